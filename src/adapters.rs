@@ -0,0 +1,461 @@
+//! The `adapters` module contains generic `Bus` combinators.
+//!
+//! Each adapter wraps another `Bus` and is itself a `Bus`, so adapters can be
+//! stacked freely. For example, a logged, mirrored RAM with a memory-mapped
+//! device on top:
+//!
+//! ```
+//! use lib6502::adapters::{LoggingBus, MirrorBus, OverlayBus};
+//! use lib6502::bus::Bus;
+//!
+//! let ram = vec![0u8; 0x0800];
+//! let device = vec![0u8; 0x10000];
+//! let mut bus = LoggingBus::new(OverlayBus::new(
+//!     MirrorBus::new(ram, 0x0000..=0x1FFF, 0x0800),
+//!     0x6000..=0x600F,
+//!     device,
+//! ));
+//!
+//! bus.write(0x0801, 0x42);
+//! assert_eq!(bus.read(0x0001), 0x42);
+//! assert_eq!(bus.log().len(), 2);
+//! ```
+
+pub use crate::bus::{AccessKind, BusAccess};
+
+use crate::bus::Bus;
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// A bus adapter that records every access made through it.
+///
+/// The log grows without bound until it is cleared with `clear` or drained
+/// with `take_log`.
+pub struct LoggingBus<B: Bus> {
+    inner: B,
+    log: Vec<BusAccess>,
+}
+
+impl<B: Bus> LoggingBus<B> {
+    /// Creates a new `LoggingBus` wrapping the given bus.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            log: Vec::new(),
+        }
+    }
+
+    /// Returns the accesses recorded so far, oldest first.
+    pub fn log(&self) -> &[BusAccess] {
+        &self.log
+    }
+
+    /// Removes and returns the accesses recorded so far.
+    pub fn take_log(&mut self) -> Vec<BusAccess> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// Discards the accesses recorded so far.
+    pub fn clear(&mut self) {
+        self.log.clear();
+    }
+
+    /// Returns a reference to the wrapped bus.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the adapter and returns the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Bus> Bus for LoggingBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.inner.read(addr);
+        self.log.push(BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Read,
+        });
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.inner.write(addr, data);
+        self.log.push(BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Write,
+        });
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        self.inner.wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.inner.peek(addr)
+    }
+}
+
+/// A bus adapter that calls a closure for every access made through it.
+///
+/// Unlike `LoggingBus`, nothing is stored, which makes it suitable for
+/// streaming accesses to a file or to the console.
+pub struct TracingBus<B: Bus, F: FnMut(&BusAccess)> {
+    inner: B,
+    tracer: F,
+}
+
+impl<B: Bus, F: FnMut(&BusAccess)> TracingBus<B, F> {
+    /// Creates a new `TracingBus` that passes every access to `tracer`.
+    pub fn new(inner: B, tracer: F) -> Self {
+        Self { inner, tracer }
+    }
+
+    /// Returns a reference to the wrapped bus.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the adapter and returns the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Bus, F: FnMut(&BusAccess)> Bus for TracingBus<B, F> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.inner.read(addr);
+        (self.tracer)(&BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Read,
+        });
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.inner.write(addr, data);
+        (self.tracer)(&BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Write,
+        });
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        self.inner.wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.inner.peek(addr)
+    }
+}
+
+/// A bus adapter that sends accesses within a range to an overlay bus and
+/// forwards everything else to the wrapped bus.
+///
+/// The overlay sees the original, unmodified address. This is the usual way
+/// to place a memory-mapped device or a ROM on top of RAM.
+pub struct OverlayBus<B: Bus, O: Bus> {
+    inner: B,
+    range: RangeInclusive<u16>,
+    overlay: O,
+}
+
+impl<B: Bus, O: Bus> OverlayBus<B, O> {
+    /// Creates a new `OverlayBus` that routes `range` to `overlay`.
+    pub fn new(inner: B, range: RangeInclusive<u16>, overlay: O) -> Self {
+        Self {
+            inner,
+            range,
+            overlay,
+        }
+    }
+
+    /// Returns the address range handled by the overlay.
+    pub fn range(&self) -> &RangeInclusive<u16> {
+        &self.range
+    }
+
+    /// Returns a reference to the wrapped bus.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Returns a reference to the overlay bus.
+    pub fn overlay(&self) -> &O {
+        &self.overlay
+    }
+
+    /// Returns a mutable reference to the overlay bus.
+    pub fn overlay_mut(&mut self) -> &mut O {
+        &mut self.overlay
+    }
+
+    /// Consumes the adapter and returns the wrapped bus and the overlay bus.
+    pub fn into_parts(self) -> (B, O) {
+        (self.inner, self.overlay)
+    }
+}
+
+impl<B: Bus, O: Bus> Bus for OverlayBus<B, O> {
+    fn read(&mut self, addr: u16) -> u8 {
+        if self.range.contains(&addr) {
+            self.overlay.read(addr)
+        } else {
+            self.inner.read(addr)
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if self.range.contains(&addr) {
+            self.overlay.write(addr, data)
+        } else {
+            self.inner.write(addr, data)
+        }
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        if self.range.contains(&addr) {
+            self.overlay.wait_states(addr, kind)
+        } else {
+            self.inner.wait_states(addr, kind)
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        if self.range.contains(&addr) {
+            self.overlay.peek(addr)
+        } else {
+            self.inner.peek(addr)
+        }
+    }
+}
+
+/// A bus adapter that folds addresses within a range onto a smaller window.
+///
+/// An address `addr` inside the range is mapped to
+/// `start + (addr - start) % size` before being passed to the wrapped bus.
+/// Addresses outside the range are passed through unchanged. For example, the
+/// NES mirrors its 2KB of RAM across $0000-$1FFF with
+/// `MirrorBus::new(ram, 0x0000..=0x1FFF, 0x0800)`.
+pub struct MirrorBus<B: Bus> {
+    inner: B,
+    range: RangeInclusive<u16>,
+    size: u16,
+}
+
+impl<B: Bus> MirrorBus<B> {
+    /// Creates a new `MirrorBus` that folds `range` onto a window of `size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(inner: B, range: RangeInclusive<u16>, size: u16) -> Self {
+        assert!(size > 0, "mirror size must be non-zero");
+        Self { inner, range, size }
+    }
+
+    /// Returns the address that `addr` is folded onto.
+    pub fn fold(&self, addr: u16) -> u16 {
+        if self.range.contains(&addr) {
+            let start = *self.range.start();
+            start + (addr - start) % self.size
+        } else {
+            addr
+        }
+    }
+
+    /// Returns a reference to the wrapped bus.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the adapter and returns the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Bus> Bus for MirrorBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let addr = self.fold(addr);
+        self.inner.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        let addr = self.fold(addr);
+        self.inner.write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        let addr = self.fold(addr);
+        self.inner.wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.inner.peek(self.fold(addr))
+    }
+}
+
+/// A bus adapter that adds a fixed number of wait states to accesses within a range.
+///
+/// This models slow devices such as EEPROMs or peripherals behind a clock
+/// stretcher. Wait states reported by the wrapped bus are added on top.
+pub struct WaitStateBus<B: Bus> {
+    inner: B,
+    range: RangeInclusive<u16>,
+    read_wait_states: u8,
+    write_wait_states: u8,
+}
+
+impl<B: Bus> WaitStateBus<B> {
+    /// Creates a new `WaitStateBus` that adds `wait_states` cycles to every
+    /// read and write within `range`.
+    pub fn new(inner: B, range: RangeInclusive<u16>, wait_states: u8) -> Self {
+        Self {
+            inner,
+            range,
+            read_wait_states: wait_states,
+            write_wait_states: wait_states,
+        }
+    }
+
+    /// Creates a new `WaitStateBus` with separate costs for reads and writes
+    /// within `range`, for devices such as EEPROMs that are slower to write.
+    pub fn with_read_write(
+        inner: B,
+        range: RangeInclusive<u16>,
+        read_wait_states: u8,
+        write_wait_states: u8,
+    ) -> Self {
+        Self {
+            inner,
+            range,
+            read_wait_states,
+            write_wait_states,
+        }
+    }
+
+    /// Returns a reference to the wrapped bus.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the adapter and returns the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Bus> Bus for WaitStateBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.inner.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.inner.write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        let inner = self.inner.wait_states(addr, kind);
+        if !self.range.contains(&addr) {
+            return inner;
+        }
+        let own = match kind {
+            AccessKind::Read => self.read_wait_states,
+            AccessKind::Write => self.write_wait_states,
+        };
+        inner.saturating_add(own)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.inner.peek(addr)
+    }
+}
+
+/// A bus shared through `Rc<RefCell<_>>`, for example between a `CPU` and a
+/// DMA engine on the same thread.
+///
+/// # Panics
+///
+/// Accesses panic if the bus is already mutably borrowed elsewhere.
+impl<B: Bus> Bus for Rc<RefCell<B>> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.borrow_mut().read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.borrow_mut().write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        self.borrow_mut().wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.borrow().peek(addr)
+    }
+}
+
+/// A bus shared through `Arc<Mutex<_>>`, for example between two `CPU`s
+/// running on different threads.
+///
+/// A poisoned lock is recovered rather than propagated, since a panic in
+/// another user of the bus does not make the memory contents invalid.
+impl<B: Bus> Bus for Arc<Mutex<B>> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        self.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .peek(addr)
+    }
+}
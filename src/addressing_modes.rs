@@ -15,11 +15,14 @@ pub type AddressingMode<B> = fn(&mut CPU<B>) -> (u16, u8);
 /// The Accumulator addressing mode. This mode is used by instructions that
 /// only operate on the Accumulator.
 ///
+/// Like the 6502, this reads the byte after the opcode and discards it.
+///
 /// # Returns
 ///
 /// A tuple containing the address (always 0) and the number of additional cycles
 /// (always 0).
-pub fn accumulator<B: Bus>(_cpu: &mut CPU<B>) -> (u16, u8) {
+pub fn accumulator<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
+    cpu.read(cpu.registers.pc);
     (0, 0)
 }
 
@@ -50,11 +53,32 @@ pub fn absolute_x<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Check if a page boundary was crossed
     let page_cross = (base & 0xFF00) != (addr & 0xFF00);
     // If a page boundary was crossed, add one cycle to the instruction
-    let additional_cycles = if page_cross { 1 } else { 0 };
+    let additional_cycles = if page_cross {
+        uncorrected_read(cpu, base, addr);
+        1
+    } else {
+        0
+    };
     // Return the address and additional cycles
     (addr, additional_cycles)
 }
 
+/// The Absolute X addressing mode for instructions that write to memory.
+///
+/// The 6502 always spends the cycle a page crossing would take, reading from
+/// the address before its high byte is corrected, so no cycles are added.
+///
+/// # Returns
+///
+/// A tuple containing the address (the absolute memory address plus the value
+/// of the X register) and the number of additional cycles (always 0).
+pub fn absolute_x_write<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
+    let base = cpu.fetch_word();
+    let addr = base.wrapping_add(cpu.registers.x as u16);
+    uncorrected_read(cpu, base, addr);
+    (addr, 0)
+}
+
 /// The Absolute Y addressing mode. This mode is used by instructions that
 /// operate on an absolute memory address plus the value of the Y register.
 ///
@@ -70,11 +94,32 @@ pub fn absolute_y<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Check if a page boundary was crossed
     let page_cross = (base & 0xFF00) != (addr & 0xFF00);
     // If a page boundary was crossed, add one cycle to the instruction
-    let additional_cycles = if page_cross { 1 } else { 0 };
+    let additional_cycles = if page_cross {
+        uncorrected_read(cpu, base, addr);
+        1
+    } else {
+        0
+    };
     // Return the address and additional cycles
     (addr, additional_cycles)
 }
 
+/// The Absolute Y addressing mode for instructions that write to memory.
+///
+/// Like `absolute_x_write`, this always reads from the uncorrected address
+/// and adds no cycles.
+///
+/// # Returns
+///
+/// A tuple containing the address (the absolute memory address plus the value
+/// of the Y register) and the number of additional cycles (always 0).
+pub fn absolute_y_write<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
+    let base = cpu.fetch_word();
+    let addr = base.wrapping_add(cpu.registers.y as u16);
+    uncorrected_read(cpu, base, addr);
+    (addr, 0)
+}
+
 /// The Immediate addressing mode. This mode is used by instructions that
 /// operate on an immediate value.
 ///
@@ -94,11 +139,14 @@ pub fn immediate<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
 /// The Implied addressing mode. This mode is used by instructions that do not
 /// use an operand.
 ///
+/// Like the 6502, this reads the byte after the opcode and discards it.
+///
 /// # Returns
 ///
 /// A tuple containing the address (always 0) and the number of additional cycles
 /// (always 0).
-pub fn implied<B: Bus>(_cpu: &mut CPU<B>) -> (u16, u8) {
+pub fn implied<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
+    cpu.read(cpu.registers.pc);
     // The implied addressing mode does not use an operand, so the address is
     // always 0. The instruction also does not add any additional cycles.
     (0, 0)
@@ -114,7 +162,7 @@ pub fn indirect<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Fetch the address of the memory address to be read
     let ptr = cpu.fetch_word();
     // Read the low byte of the memory address
-    let lo = cpu.bus.read(ptr) as u16;
+    let lo = cpu.read(ptr) as u16;
     // Read the high byte of the memory address
     // If the low byte of the pointer is 0xFF, the high byte is stored at the
     // first byte of the page. This is a bug in the original 6502.
@@ -123,7 +171,7 @@ pub fn indirect<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     } else {
         ptr + 1
     };
-    let hi = cpu.bus.read(hi_address) as u16;
+    let hi = cpu.read(hi_address) as u16;
     // Calculate the address from the low and high bytes
     let addr = (hi << 8) | lo;
     // Return the address and 0 additional cycles
@@ -139,11 +187,14 @@ pub fn indirect<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
 /// A tuple containing the address and the number of additional cycles (always 0).
 pub fn indirect_x<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Fetch the address of the memory address to be read
-    let ptr = cpu.fetch_byte().wrapping_add(cpu.registers.x);
+    let base = cpu.fetch_byte();
+    // The 6502 reads the pointer before adding X to it
+    cpu.read(base as u16);
+    let ptr = base.wrapping_add(cpu.registers.x);
     // Read the low byte of the memory address
-    let lo = cpu.bus.read(ptr as u16) as u16;
+    let lo = cpu.read(ptr as u16) as u16;
     // Read the high byte of the memory address
-    let hi = cpu.bus.read(ptr.wrapping_add(1) as u16) as u16;
+    let hi = cpu.read(ptr.wrapping_add(1) as u16) as u16;
     // Calculate the address from the low and high bytes
     let addr = (hi << 8) | lo;
     // Return the address and 0 additional cycles
@@ -162,9 +213,9 @@ pub fn indirect_y<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Fetch the address of the memory address to be read
     let ptr = cpu.fetch_byte();
     // Read the low byte of the memory address
-    let lo = cpu.bus.read(ptr as u16) as u16;
+    let lo = cpu.read(ptr as u16) as u16;
     // Read the high byte of the memory address
-    let hi = cpu.bus.read(ptr.wrapping_add(1) as u16) as u16;
+    let hi = cpu.read(ptr.wrapping_add(1) as u16) as u16;
     // Calculate the base address from the low and high bytes
     let base_addr = (hi << 8) | lo;
     // Calculate the address by adding the value of the Y register
@@ -172,11 +223,34 @@ pub fn indirect_y<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Check if a page boundary was crossed
     let page_cross = (base_addr & 0xFF00) != (addr & 0xFF00);
     // If a page boundary was crossed, add one additional cycle
-    let additional_cycles = if page_cross { 1 } else { 0 };
+    let additional_cycles = if page_cross {
+        uncorrected_read(cpu, base_addr, addr);
+        1
+    } else {
+        0
+    };
     // Return the address and additional cycles
     (addr, additional_cycles)
 }
 
+/// The Indirect Y addressing mode for instructions that write to memory.
+///
+/// Like `absolute_x_write`, this always reads from the uncorrected address
+/// and adds no cycles.
+///
+/// # Returns
+///
+/// A tuple containing the address and the number of additional cycles (always 0).
+pub fn indirect_y_write<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
+    let ptr = cpu.fetch_byte();
+    let lo = cpu.read(ptr as u16) as u16;
+    let hi = cpu.read(ptr.wrapping_add(1) as u16) as u16;
+    let base_addr = (hi << 8) | lo;
+    let addr = base_addr.wrapping_add(cpu.registers.y as u16);
+    uncorrected_read(cpu, base_addr, addr);
+    (addr, 0)
+}
+
 /// The Relative addressing mode. This mode is used by branch instructions to
 /// jump to an address relative to the current program counter.
 ///
@@ -218,7 +292,10 @@ pub fn zero_page<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
 /// and the number of additional cycles (always 0).
 pub fn zero_page_x<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Fetch the zero page address from the next byte in memory
-    let addr = cpu.fetch_byte().wrapping_add(cpu.registers.x) as u16;
+    let base = cpu.fetch_byte();
+    // The 6502 reads the zero page address before adding X to it
+    cpu.read(base as u16);
+    let addr = base.wrapping_add(cpu.registers.x) as u16;
     // Return the zero page address plus the X register and 0 additional cycles
     (addr, 0)
 }
@@ -233,7 +310,17 @@ pub fn zero_page_x<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
 /// and the number of additional cycles (always 0).
 pub fn zero_page_y<B: Bus>(cpu: &mut CPU<B>) -> (u16, u8) {
     // Fetch the zero page address from the next byte in memory
-    let addr = cpu.fetch_byte().wrapping_add(cpu.registers.y) as u16;
+    let base = cpu.fetch_byte();
+    // The 6502 reads the zero page address before adding Y to it
+    cpu.read(base as u16);
+    let addr = base.wrapping_add(cpu.registers.y) as u16;
     // Return the zero page address plus the Y register and 0 additional cycles
     (addr, 0)
 }
+
+/// Reads from the address an indexed mode forms before carrying into its high
+/// byte, as the 6502 does while it corrects the address. The value is
+/// discarded.
+fn uncorrected_read<B: Bus>(cpu: &mut CPU<B>, base: u16, addr: u16) {
+    cpu.read((base & 0xFF00) | (addr & 0x00FF));
+}
@@ -0,0 +1,89 @@
+//! `mon6502` is an interactive machine language monitor for a 6502 with
+//! 64KB of RAM.
+//!
+//! ```text
+//! mon6502 [-l labels] [file [addr]]
+//! ```
+//!
+//! The file is loaded at `addr`, or as a PRG without one, and the PC is set
+//! to its start. Without a file, the CPU is reset. Type `help` at the prompt
+//! for the commands.
+
+use lib6502::cpu::CPU;
+use lib6502::monitor::Monitor;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: mon6502 [-l labels] [file [addr]]";
+
+fn main() -> ExitCode {
+    let mut labels = None;
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" => match args.next() {
+                Some(path) => labels = Some(path),
+                None => return usage(),
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut cpu = CPU::new(vec![0u8; 0x10000]);
+    let mut monitor = Monitor::new();
+    monitor
+        .debugger_mut()
+        .set_log_output(|message| println!("{message}"));
+    if let Some(path) = labels {
+        if let Err(err) = monitor.execute(&mut cpu, &format!("ll \"{path}\"")) {
+            eprintln!("mon6502: {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    match positional.as_slice() {
+        [] => cpu.reset(),
+        [path, addr @ ..] if addr.len() <= 1 => {
+            let addr = match addr.first() {
+                Some(addr) => match u16::from_str_radix(addr.trim_start_matches('$'), 16) {
+                    Ok(addr) => Some(addr),
+                    Err(_) => return usage(),
+                },
+                None => None,
+            };
+            match monitor.load(&mut cpu, path, addr) {
+                Ok(range) => cpu.registers.pc = *range.start(),
+                Err(err) => {
+                    eprintln!("mon6502: {path}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        _ => return usage(),
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    while !monitor.is_done() {
+        print!("(C:${:04X}) ", cpu.registers.pc);
+        io::stdout().flush().ok();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            break;
+        };
+        match monitor.execute(&mut cpu, &line) {
+            Ok(output) => print!("{output}"),
+            Err(err) => println!("? {err}"),
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+    ExitCode::FAILURE
+}
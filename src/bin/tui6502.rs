@@ -0,0 +1,178 @@
+//! `tui6502` is a full-screen terminal debugger for a 6502 with 64KB of
+//! RAM.
+//!
+//! ```text
+//! tui6502 [-l labels] [file [addr]]
+//! ```
+//!
+//! The file is loaded at `addr`, or as a PRG without one, and the PC is set
+//! to its start. Without a file, the CPU is reset. The terminal is put into
+//! raw mode with `stty`, so this needs a Unix terminal, which can be over
+//! SSH.
+
+use lib6502::cpu::CPU;
+use lib6502::tui::{decode_keys, Tui};
+use std::io::{self, Read, Write};
+use std::process::{Command, ExitCode, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: tui6502 [-l labels] [file [addr]]";
+
+/// How often the screen is redrawn while the machine runs.
+const FRAME: Duration = Duration::from_millis(50);
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it
+/// when dropped, even on a panic.
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "-ixon", "min", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+        Ok(Self {
+            saved: saved.trim().to_string(),
+        })
+    }
+
+    /// Returns the size of the terminal as columns and rows, or 80 by 24
+    /// if it does not say.
+    fn size() -> (usize, usize) {
+        let size = stty(&["size"]).unwrap_or_default();
+        let mut numbers = size.split_whitespace().map(str::parse::<usize>);
+        match (numbers.next(), numbers.next()) {
+            (Some(Ok(rows @ 1..)), Some(Ok(columns @ 1..))) => (columns, rows),
+            _ => (80, 24),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().ok();
+        stty(&[self.saved.as_str()]).ok();
+    }
+}
+
+/// Runs `stty` on the terminal, returning what it prints.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed; is stdin a terminal?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn main() -> ExitCode {
+    let mut labels = None;
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-l" => match args.next() {
+                Some(path) => labels = Some(path),
+                None => return usage(),
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut cpu = CPU::new(vec![0u8; 0x10000]);
+    let mut tui = Tui::new();
+    if let Some(path) = labels {
+        if let Err(err) = tui
+            .monitor_mut()
+            .execute(&mut cpu, &format!("ll \"{path}\""))
+        {
+            eprintln!("tui6502: {path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    match positional.as_slice() {
+        [] => cpu.reset(),
+        [path, addr @ ..] if addr.len() <= 1 => {
+            let addr = match addr.first() {
+                Some(addr) => match u16::from_str_radix(addr.trim_start_matches('$'), 16) {
+                    Ok(addr) => Some(addr),
+                    Err(_) => return usage(),
+                },
+                None => None,
+            };
+            match tui.monitor_mut().load(&mut cpu, path, addr) {
+                Ok(range) => {
+                    cpu.registers.pc = *range.start();
+                    tui.show_memory(*range.start());
+                }
+                Err(err) => {
+                    eprintln!("tui6502: {path}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        _ => return usage(),
+    }
+
+    let terminal = match Terminal::enter() {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            eprintln!("tui6502: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let (keys, key_events) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok(len @ 1..) = io::stdin().read(&mut buffer) {
+            if keys.send(decode_keys(&buffer[..len])).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = io::stdout();
+    while !tui.is_done() {
+        let (width, height) = Terminal::size();
+        write!(stdout, "{}", tui.render(&cpu, width, height)).ok();
+        stdout.flush().ok();
+        if tui.is_running() {
+            // Run until the next frame is due or a key is pressed
+            let deadline = Instant::now() + FRAME;
+            let mut pressed = Vec::new();
+            tui.run(&mut cpu, || {
+                pressed.extend(key_events.try_iter().flatten());
+                pressed.is_empty() && Instant::now() < deadline
+            });
+            for key in pressed {
+                tui.handle_key(&mut cpu, key);
+            }
+        } else {
+            let Ok(pressed) = key_events.recv() else {
+                break;
+            };
+            for key in pressed {
+                tui.handle_key(&mut cpu, key);
+            }
+        }
+    }
+    drop(terminal);
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+    ExitCode::FAILURE
+}
@@ -20,4 +20,132 @@ pub trait Bus {
     /// * `addr` - The memory address to write to.
     /// * `data` - The byte to write to memory.
     fn write(&mut self, addr: u16, data: u8);
+
+    /// Returns the number of extra cycles an access to the given address costs.
+    ///
+    /// The CPU calls this after every read and write it makes and adds the
+    /// result to its cycle count. Slow ROMs, peripherals on a slower clock and
+    /// clock stretching can be modelled by returning a non-zero value here.
+    ///
+    /// The default implementation returns 0, meaning every access completes
+    /// within its normal cycle.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The memory address that was accessed.
+    /// * `kind` - Whether the access was a read or a write.
+    fn wait_states(&mut self, _addr: u16, _kind: AccessKind) -> u8 {
+        0
+    }
+
+    /// Returns the byte a read from the given address would return, without
+    /// any of the side effects a read can have on I/O devices.
+    ///
+    /// Debuggers and memory diffs use this to look at memory without
+    /// disturbing the machine. Devices whose registers cannot be inspected
+    /// without side effects return `None` for them.
+    ///
+    /// The default implementation returns `None` for every address.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The memory address to inspect.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+}
+
+/// The direction of a single bus access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AccessKind {
+    /// The CPU read a byte from the bus.
+    Read,
+    /// The CPU wrote a byte to the bus.
+    Write,
+}
+
+/// A single recorded bus access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BusAccess {
+    /// The address that was accessed.
+    pub addr: u16,
+    /// The byte that was read or written.
+    pub data: u8,
+    /// Whether the access was a read or a write.
+    pub kind: AccessKind,
+}
+
+/// A flat 64KB array is the simplest possible bus: every address is RAM.
+impl Bus for [u8; 0x10000] {
+    fn read(&mut self, addr: u16) -> u8 {
+        self[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self[addr as usize] = data;
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self[addr as usize])
+    }
+}
+
+/// A `Vec<u8>` acts as RAM starting at address 0x0000.
+///
+/// Reads past the end of the vector return 0 and writes past the end are ignored.
+impl Bus for Vec<u8> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        if let Some(byte) = self.get_mut(addr as usize) {
+            *byte = data;
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.get(addr as usize).copied().unwrap_or(0))
+    }
+}
+
+/// Borrowing a bus is also a bus, so a `CPU` can run against a bus it does not own.
+impl<B: Bus + ?Sized> Bus for &mut B {
+    fn read(&mut self, addr: u16) -> u8 {
+        (**self).read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        (**self).write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        (**self).wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        (**self).peek(addr)
+    }
+}
+
+/// A boxed bus is a bus, which allows `CPU<Box<dyn Bus>>`.
+impl<B: Bus + ?Sized> Bus for Box<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        (**self).read(addr)
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        (**self).write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        (**self).wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        (**self).peek(addr)
+    }
 }
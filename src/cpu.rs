@@ -1,7 +1,7 @@
 //! The `cpu` module contains the implementation of the 6502 CPU emulator.
 
 use crate::addressing_modes::*;
-use crate::bus::Bus;
+use crate::bus::{AccessKind, Bus, BusAccess};
 use crate::instructions::Instruction;
 use crate::registers::{Registers, StatusFlags};
 use std::collections::HashMap;
@@ -43,6 +43,27 @@ pub struct CPU<B: Bus> {
     /// The instruction table is a mapping of opcodes to their associated instruction handlers and addressing modes.
     /// The instruction table is used to decode instructions and execute them.
     instruction_table: HashMap<u8, DecodedInstruction<B>>,
+
+    /// Whether bus accesses are being recorded into `accesses`.
+    record_accesses: bool,
+
+    /// The bus accesses made by the most recent instruction or interrupt, if recording is enabled.
+    accesses: Vec<BusAccess>,
+
+    /// Whether taken interrupts are being recorded into `interrupts`.
+    record_interrupts: bool,
+
+    /// The interrupts taken since they were last collected, if recording is enabled.
+    interrupts: Vec<(u64, Interrupt)>,
+}
+
+/// An interrupt taken by the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interrupt {
+    /// A maskable interrupt request, from `CPU::irq`.
+    Irq,
+    /// A non-maskable interrupt, from `CPU::nmi`.
+    Nmi,
 }
 
 impl<B: Bus> CPU<B> {
@@ -62,6 +83,10 @@ impl<B: Bus> CPU<B> {
             bus,                         // Use the provided bus for memory operations
             cycles: 0,                   // Initialize cycle count to zero
             instruction_table: HashMap::new(), // Create an empty instruction table
+            record_accesses: false,            // Access recording is opt-in
+            accesses: Vec::new(),              // No accesses recorded yet
+            record_interrupts: false,          // Interrupt recording is opt-in
+            interrupts: Vec::new(),            // No interrupts recorded yet
         };
         cpu.init_instruction_table(); // Initialize the instruction table with opcodes
         cpu // Return the initialized CPU instance
@@ -74,8 +99,8 @@ impl<B: Bus> CPU<B> {
     /// status flags.
     pub fn reset(&mut self) {
         // Read the reset vector from the bus
-        let lo = self.bus.read(0xFFFC) as u16;
-        let hi = self.bus.read(0xFFFD) as u16;
+        let lo = self.read(0xFFFC) as u16;
+        let hi = self.read(0xFFFD) as u16;
 
         // Set the program counter to the reset vector address
         self.registers.pc = (hi << 8) | lo;
@@ -95,6 +120,8 @@ impl<B: Bus> CPU<B> {
     /// This method fetches the current opcode from memory, decodes the instruction, and executes it.
     /// If the instruction is not implemented, it will call the `unimplemented_instruction` method.
     pub fn step(&mut self) {
+        // Start a fresh access record for this instruction
+        self.accesses.clear();
         let opcode = self.fetch_byte();
         // Get the instruction from the instruction table
         if let Some(decoded_instruction) = self.instruction_table.get(&opcode) {
@@ -120,12 +147,110 @@ impl<B: Bus> CPU<B> {
         }
     }
 
+    /// Reads a byte from the bus on behalf of the CPU.
+    ///
+    /// Every read the CPU makes goes through this method, so any wait states
+    /// reported by the bus are added to the cycle count.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The memory address to read from.
+    ///
+    /// # Returns
+    ///
+    /// The byte read from the bus.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        let data = self.bus.read(addr);
+        self.cycles += self.bus.wait_states(addr, AccessKind::Read) as u64;
+        if self.record_accesses {
+            self.accesses.push(BusAccess {
+                addr,
+                data,
+                kind: AccessKind::Read,
+            });
+        }
+        data
+    }
+
+    /// Writes a byte to the bus on behalf of the CPU.
+    ///
+    /// Every write the CPU makes goes through this method, so any wait states
+    /// reported by the bus are added to the cycle count.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The memory address to write to.
+    /// * `data` - The byte to write.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        self.bus.write(addr, data);
+        self.cycles += self.bus.wait_states(addr, AccessKind::Write) as u64;
+        if self.record_accesses {
+            self.accesses.push(BusAccess {
+                addr,
+                data,
+                kind: AccessKind::Write,
+            });
+        }
+    }
+
+    /// Enables or disables recording of the CPU's bus accesses.
+    ///
+    /// While enabled, `accesses` returns the reads and writes made by the
+    /// most recent call to `step`, `irq` or `nmi`. Recording is disabled by
+    /// default, as it costs a little time on every access.
+    pub fn set_access_recording(&mut self, enabled: bool) {
+        self.record_accesses = enabled;
+        self.accesses.clear();
+    }
+
+    /// Returns `true` if bus accesses are being recorded.
+    pub fn is_recording_accesses(&self) -> bool {
+        self.record_accesses
+    }
+
+    /// Returns the bus accesses made by the most recent instruction or
+    /// interrupt, oldest first. Empty unless recording is enabled.
+    pub fn accesses(&self) -> &[BusAccess] {
+        &self.accesses
+    }
+
+    /// Enables or disables recording of the interrupts the CPU takes.
+    ///
+    /// While enabled, every IRQ or NMI taken by `irq` or `nmi` is kept,
+    /// with the cycle count at which it was taken, until `take_interrupts`
+    /// collects it. IRQs ignored because interrupts are disabled are not
+    /// recorded. Recording is disabled by default.
+    pub fn set_interrupt_recording(&mut self, enabled: bool) {
+        self.record_interrupts = enabled;
+        self.interrupts.clear();
+    }
+
+    /// Returns `true` if taken interrupts are being recorded.
+    pub fn is_recording_interrupts(&self) -> bool {
+        self.record_interrupts
+    }
+
+    /// Returns the interrupts taken since the last call, oldest first, each
+    /// with the cycle count at which it was taken. Empty unless recording is
+    /// enabled.
+    pub fn take_interrupts(&mut self) -> Vec<(u64, Interrupt)> {
+        std::mem::take(&mut self.interrupts)
+    }
+
+    /// Advances the cycle count without executing anything.
+    ///
+    /// This is used to skip over time the CPU would have spent in a loop
+    /// whose outcome is already known.
+    pub(crate) fn advance_cycles(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
     /// Fetches the next byte from the memory bus and increments the program counter.
     ///
     /// This method is used to fetch the next opcode or operand from memory.
     /// It increments the program counter after fetching the byte.
     pub fn fetch_byte(&mut self) -> u8 {
-        let byte = self.bus.read(self.registers.pc);
+        let byte = self.read(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
         byte
     }
@@ -153,7 +278,7 @@ impl<B: Bus> CPU<B> {
     /// * `data` - The byte to be pushed onto the stack.
     pub fn stack_push(&mut self, data: u8) {
         // Write the byte to the stack memory address
-        self.bus.write(0x0100 + self.registers.sp as u16, data);
+        self.write(0x0100 + self.registers.sp as u16, data);
         // Decrement the stack pointer
         self.registers.sp = self.registers.sp.wrapping_sub(1);
     }
@@ -170,7 +295,7 @@ impl<B: Bus> CPU<B> {
         // Increment the stack pointer
         self.registers.sp = self.registers.sp.wrapping_add(1);
         // Read the byte from the stack memory address
-        self.bus.read(0x0100 + self.registers.sp as u16)
+        self.read(0x0100 + self.registers.sp as u16)
     }
 
     /// Updates the zero and negative flags based on the result.
@@ -194,6 +319,9 @@ impl<B: Bus> CPU<B> {
     /// if a page boundary was crossed during the branch. If a page boundary
     /// is crossed, an additional cycle penalty is incurred.
     ///
+    /// Like the 6502, this reads the byte after the branch in its extra cycle,
+    /// and the address before its high byte is corrected in the next.
+    ///
     /// # Arguments
     ///
     /// * `addr` - The address to branch to.
@@ -204,12 +332,14 @@ impl<B: Bus> CPU<B> {
     pub fn branch(&mut self, addr: u16) -> u8 {
         // Store the current program counter
         let old_pc = self.registers.pc;
+        self.read(old_pc);
         // Update the program counter to the new address
         self.registers.pc = addr;
         // Determine if a page boundary was crossed
         let page_cross = (old_pc & 0xFF00) != (addr & 0xFF00);
         // Return the cycle penalty based on page crossing
         if page_cross {
+            self.read((old_pc & 0xFF00) | (addr & 0x00FF));
             2
         } else {
             1
@@ -219,7 +349,7 @@ impl<B: Bus> CPU<B> {
     /// Handles an interrupt (IRQ or NMI).
     ///
     /// This method will not trigger an interrupt if the Interrupt Disable flag is set
-    /// and the interrupt is not an NMI.
+    /// and the interrupt is not an NMI. A taken interrupt adds 7 cycles to the cycle count.
     ///
     /// # Arguments
     ///
@@ -228,6 +358,15 @@ impl<B: Bus> CPU<B> {
         if self.registers.status.interrupt_disable && !nmi {
             return;
         }
+        if self.record_interrupts {
+            let interrupt = if nmi { Interrupt::Nmi } else { Interrupt::Irq };
+            self.interrupts.push((self.cycles, interrupt));
+        }
+        // Start a fresh access record for the interrupt sequence
+        self.accesses.clear();
+        // The 6502 reads the next opcode twice before discarding it
+        self.read(self.registers.pc);
+        self.read(self.registers.pc);
         // Push the current program counter onto the stack
         self.stack_push((self.registers.pc >> 8) as u8);
         self.stack_push((self.registers.pc & 0xFF) as u8);
@@ -241,10 +380,12 @@ impl<B: Bus> CPU<B> {
         self.registers.status.interrupt_disable = true;
         // Read the interrupt vector address from memory
         let vector_address = if nmi { 0xFFFA } else { 0xFFFE };
-        let lo = self.bus.read(vector_address) as u16;
-        let hi = self.bus.read(vector_address + 1) as u16;
+        let lo = self.read(vector_address) as u16;
+        let hi = self.read(vector_address + 1) as u16;
         // Set the program counter to the vector address
         self.registers.pc = (hi << 8) | lo;
+        // The interrupt sequence takes 7 cycles, the same as BRK
+        self.cycles += 7;
     }
 
     /// Handles an interrupt request (IRQ).
@@ -273,8 +414,22 @@ impl<B: Bus> CPU<B> {
         panic!("Unimplemented opcode {:02X} at PC: {:04X}", opcode, self.registers.pc);
     }
 
+    /// Returns `true` if the CPU can execute `opcode`.
+    ///
+    /// Every NMOS opcode is implemented, undocumented ones included, except
+    /// the JAM opcodes that halt the 6502; `step` panics on those.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - The opcode to look up.
+    pub fn is_implemented(&self, opcode: u8) -> bool {
+        self.instruction_table.contains_key(&opcode)
+    }
+
     /// Returns the current cycle count.
     ///
+    /// This includes any wait states reported by the bus.
+    ///
     /// # Returns
     ///
     /// The current cycle count of the CPU.
@@ -282,6 +437,15 @@ impl<B: Bus> CPU<B> {
         self.cycles
     }
 
+    /// Sets the cycle count.
+    ///
+    /// # Arguments
+    ///
+    /// * `cycles` - The new cycle count.
+    pub fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
     /// Initializes the instruction dispatch table.
     fn init_instruction_table(&mut self) {
         use crate::addressing_modes::*;
@@ -314,7 +478,7 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0x06, asl, zero_page, 5); // ASL Zero Page
         self.map_opcode(0x16, asl, zero_page_x, 6); // ASL Zero Page,X
         self.map_opcode(0x0E, asl, absolute, 6); // ASL Absolute
-        self.map_opcode(0x1E, asl, absolute_x, 7); // ASL Absolute,X (+1 if page crossed)
+        self.map_opcode(0x1E, asl, absolute_x_write, 7); // ASL Absolute,X
 
         // Branch Instructions
         self.map_opcode(0x90, bcc, relative, 2); // BCC Relative
@@ -367,7 +531,7 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0xC6, dec, zero_page, 5); // DEC Zero Page
         self.map_opcode(0xD6, dec, zero_page_x, 6); // DEC Zero Page,X
         self.map_opcode(0xCE, dec, absolute, 6); // DEC Absolute
-        self.map_opcode(0xDE, dec, absolute_x, 7); // DEC Absolute,X (+1 if page crossed)
+        self.map_opcode(0xDE, dec, absolute_x_write, 7); // DEC Absolute,X
 
         // Decrement X Instruction
         self.map_opcode(0xCA, dex, implied, 2); // DEX Implied
@@ -389,7 +553,7 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0xE6, inc, zero_page, 5); // INC Zero Page
         self.map_opcode(0xF6, inc, zero_page_x, 6); // INC Zero Page,X
         self.map_opcode(0xEE, inc, absolute, 6); // INC Absolute
-        self.map_opcode(0xFE, inc, absolute_x, 7); // INC Absolute,X (+1 if page crossed)
+        self.map_opcode(0xFE, inc, absolute_x_write, 7); // INC Absolute,X
 
         // Increment X Instruction
         self.map_opcode(0xE8, inx, implied, 2); // INX Implied
@@ -402,7 +566,7 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0x6C, jmp, indirect, 5); // JMP Indirect
 
         // Jump Subroutine Instruction
-        self.map_opcode(0x20, jsr, absolute, 6); // JSR Absolute
+        self.map_opcode(0x20, jsr, immediate, 6); // JSR Absolute (reads its operand itself)
 
         // LDA Instructions
         self.map_opcode(0xA9, lda, immediate, 2); // LDA Immediate
@@ -433,7 +597,7 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0x46, lsr_memory, zero_page, 5); // LSR Zero Page
         self.map_opcode(0x56, lsr_memory, zero_page_x, 6); // LSR Zero Page,X
         self.map_opcode(0x4E, lsr_memory, absolute, 6); // LSR Absolute
-        self.map_opcode(0x5E, lsr_memory, absolute_x, 7); // LSR Absolute,X
+        self.map_opcode(0x5E, lsr_memory, absolute_x_write, 7); // LSR Absolute,X
 
         // No-op Instructions
         self.map_opcode(0xEA, nop, implied, 2); // NOP Implied
@@ -459,14 +623,14 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0x26, rol_memory, zero_page, 5); // ROL Zero Page
         self.map_opcode(0x36, rol_memory, zero_page_x, 6); // ROL Zero Page,X
         self.map_opcode(0x2E, rol_memory, absolute, 6); // ROL Absolute
-        self.map_opcode(0x3E, rol_memory, absolute_x, 7); // ROL Absolute,X
+        self.map_opcode(0x3E, rol_memory, absolute_x_write, 7); // ROL Absolute,X
 
         // ROR (Rotate Right) Instructions
         self.map_opcode(0x6A, ror_accumulator, accumulator, 2); // ROR Accumulator
         self.map_opcode(0x66, ror_memory, zero_page, 5); // ROR Zero Page
         self.map_opcode(0x76, ror_memory, zero_page_x, 6); // ROR Zero Page,X
         self.map_opcode(0x6E, ror_memory, absolute, 6); // ROR Absolute
-        self.map_opcode(0x7E, ror_memory, absolute_x, 7); // ROR Absolute,X
+        self.map_opcode(0x7E, ror_memory, absolute_x_write, 7); // ROR Absolute,X
 
         // Return Instructions
         self.map_opcode(0x40, rti, implied, 6); // RTI Implied
@@ -491,10 +655,10 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0x85, sta, zero_page, 3); // STA Zero Page
         self.map_opcode(0x95, sta, zero_page_x, 4); // STA Zero Page,X
         self.map_opcode(0x8D, sta, absolute, 4); // STA Absolute
-        self.map_opcode(0x9D, sta, absolute_x, 5); // STA Absolute,X
-        self.map_opcode(0x99, sta, absolute_y, 5); // STA Absolute,Y
+        self.map_opcode(0x9D, sta, absolute_x_write, 5); // STA Absolute,X
+        self.map_opcode(0x99, sta, absolute_y_write, 5); // STA Absolute,Y
         self.map_opcode(0x81, sta, indirect_x, 6); // STA Indirect,X
-        self.map_opcode(0x91, sta, indirect_y, 6); // STA Indirect,Y
+        self.map_opcode(0x91, sta, indirect_y_write, 6); // STA Indirect,Y
 
         // STX (Store X Register) Instructions
         self.map_opcode(0x86, stx, zero_page, 3); // STX Zero Page
@@ -513,6 +677,123 @@ impl<B: Bus> CPU<B> {
         self.map_opcode(0x8A, txa, implied, 2); // TXA Implied
         self.map_opcode(0x9A, txs, implied, 2); // TXS Implied
         self.map_opcode(0x98, tya, implied, 2); // TYA Implied
+
+        // Undocumented Instructions. The JAM opcodes, which halt the 6502,
+        // are left unimplemented.
+        // SLO (undocumented read-modify-write)
+        self.map_opcode(0x07, slo, zero_page, 5); // SLO Zero Page
+        self.map_opcode(0x17, slo, zero_page_x, 6); // SLO Zero Page,X
+        self.map_opcode(0x0F, slo, absolute, 6); // SLO Absolute
+        self.map_opcode(0x1F, slo, absolute_x_write, 7); // SLO Absolute,X
+        self.map_opcode(0x1B, slo, absolute_y_write, 7); // SLO Absolute,Y
+        self.map_opcode(0x03, slo, indirect_x, 8); // SLO Indirect,X
+        self.map_opcode(0x13, slo, indirect_y_write, 8); // SLO Indirect,Y
+
+        // RLA (undocumented read-modify-write)
+        self.map_opcode(0x27, rla, zero_page, 5); // RLA Zero Page
+        self.map_opcode(0x37, rla, zero_page_x, 6); // RLA Zero Page,X
+        self.map_opcode(0x2F, rla, absolute, 6); // RLA Absolute
+        self.map_opcode(0x3F, rla, absolute_x_write, 7); // RLA Absolute,X
+        self.map_opcode(0x3B, rla, absolute_y_write, 7); // RLA Absolute,Y
+        self.map_opcode(0x23, rla, indirect_x, 8); // RLA Indirect,X
+        self.map_opcode(0x33, rla, indirect_y_write, 8); // RLA Indirect,Y
+
+        // SRE (undocumented read-modify-write)
+        self.map_opcode(0x47, sre, zero_page, 5); // SRE Zero Page
+        self.map_opcode(0x57, sre, zero_page_x, 6); // SRE Zero Page,X
+        self.map_opcode(0x4F, sre, absolute, 6); // SRE Absolute
+        self.map_opcode(0x5F, sre, absolute_x_write, 7); // SRE Absolute,X
+        self.map_opcode(0x5B, sre, absolute_y_write, 7); // SRE Absolute,Y
+        self.map_opcode(0x43, sre, indirect_x, 8); // SRE Indirect,X
+        self.map_opcode(0x53, sre, indirect_y_write, 8); // SRE Indirect,Y
+
+        // RRA (undocumented read-modify-write)
+        self.map_opcode(0x67, rra, zero_page, 5); // RRA Zero Page
+        self.map_opcode(0x77, rra, zero_page_x, 6); // RRA Zero Page,X
+        self.map_opcode(0x6F, rra, absolute, 6); // RRA Absolute
+        self.map_opcode(0x7F, rra, absolute_x_write, 7); // RRA Absolute,X
+        self.map_opcode(0x7B, rra, absolute_y_write, 7); // RRA Absolute,Y
+        self.map_opcode(0x63, rra, indirect_x, 8); // RRA Indirect,X
+        self.map_opcode(0x73, rra, indirect_y_write, 8); // RRA Indirect,Y
+
+        // DCP (undocumented read-modify-write)
+        self.map_opcode(0xC7, dcp, zero_page, 5); // DCP Zero Page
+        self.map_opcode(0xD7, dcp, zero_page_x, 6); // DCP Zero Page,X
+        self.map_opcode(0xCF, dcp, absolute, 6); // DCP Absolute
+        self.map_opcode(0xDF, dcp, absolute_x_write, 7); // DCP Absolute,X
+        self.map_opcode(0xDB, dcp, absolute_y_write, 7); // DCP Absolute,Y
+        self.map_opcode(0xC3, dcp, indirect_x, 8); // DCP Indirect,X
+        self.map_opcode(0xD3, dcp, indirect_y_write, 8); // DCP Indirect,Y
+
+        // ISB (undocumented read-modify-write)
+        self.map_opcode(0xE7, isb, zero_page, 5); // ISB Zero Page
+        self.map_opcode(0xF7, isb, zero_page_x, 6); // ISB Zero Page,X
+        self.map_opcode(0xEF, isb, absolute, 6); // ISB Absolute
+        self.map_opcode(0xFF, isb, absolute_x_write, 7); // ISB Absolute,X
+        self.map_opcode(0xFB, isb, absolute_y_write, 7); // ISB Absolute,Y
+        self.map_opcode(0xE3, isb, indirect_x, 8); // ISB Indirect,X
+        self.map_opcode(0xF3, isb, indirect_y_write, 8); // ISB Indirect,Y
+
+        // SAX (undocumented)
+        self.map_opcode(0x87, sax, zero_page, 3); // SAX Zero Page
+        self.map_opcode(0x97, sax, zero_page_y, 4); // SAX Zero Page,Y
+        self.map_opcode(0x8F, sax, absolute, 4); // SAX Absolute
+        self.map_opcode(0x83, sax, indirect_x, 6); // SAX Indirect,X
+
+        // LAX (undocumented)
+        self.map_opcode(0xA7, lax, zero_page, 3); // LAX Zero Page
+        self.map_opcode(0xB7, lax, zero_page_y, 4); // LAX Zero Page,Y
+        self.map_opcode(0xAF, lax, absolute, 4); // LAX Absolute
+        self.map_opcode(0xBF, lax, absolute_y, 4); // LAX Absolute,Y (+1 if page crossed)
+        self.map_opcode(0xA3, lax, indirect_x, 6); // LAX Indirect,X
+        self.map_opcode(0xB3, lax, indirect_y, 5); // LAX Indirect,Y (+1 if page crossed)
+
+        // Undocumented immediate instructions
+        self.map_opcode(0x0B, anc, immediate, 2); // ANC Immediate
+        self.map_opcode(0x2B, anc, immediate, 2); // ANC Immediate
+        self.map_opcode(0x4B, alr, immediate, 2); // ALR Immediate
+        self.map_opcode(0x6B, arr, immediate, 2); // ARR Immediate
+        self.map_opcode(0x8B, xaa, immediate, 2); // XAA Immediate
+        self.map_opcode(0xAB, lxa, immediate, 2); // LXA Immediate
+        self.map_opcode(0xCB, axs, immediate, 2); // AXS Immediate
+        self.map_opcode(0xEB, sbc, immediate, 2); // SBC Immediate
+
+        // NOP (undocumented)
+        self.map_opcode(0x1A, nop, implied, 2); // NOP Implied
+        self.map_opcode(0x3A, nop, implied, 2); // NOP Implied
+        self.map_opcode(0x5A, nop, implied, 2); // NOP Implied
+        self.map_opcode(0x7A, nop, implied, 2); // NOP Implied
+        self.map_opcode(0xDA, nop, implied, 2); // NOP Implied
+        self.map_opcode(0xFA, nop, implied, 2); // NOP Implied
+        self.map_opcode(0x80, nop_memory, immediate, 2); // NOP Immediate
+        self.map_opcode(0x82, nop_memory, immediate, 2); // NOP Immediate
+        self.map_opcode(0x89, nop_memory, immediate, 2); // NOP Immediate
+        self.map_opcode(0xC2, nop_memory, immediate, 2); // NOP Immediate
+        self.map_opcode(0xE2, nop_memory, immediate, 2); // NOP Immediate
+        self.map_opcode(0x04, nop_memory, zero_page, 3); // NOP Zero Page
+        self.map_opcode(0x44, nop_memory, zero_page, 3); // NOP Zero Page
+        self.map_opcode(0x64, nop_memory, zero_page, 3); // NOP Zero Page
+        self.map_opcode(0x14, nop_memory, zero_page_x, 4); // NOP Zero Page,X
+        self.map_opcode(0x34, nop_memory, zero_page_x, 4); // NOP Zero Page,X
+        self.map_opcode(0x54, nop_memory, zero_page_x, 4); // NOP Zero Page,X
+        self.map_opcode(0x74, nop_memory, zero_page_x, 4); // NOP Zero Page,X
+        self.map_opcode(0xD4, nop_memory, zero_page_x, 4); // NOP Zero Page,X
+        self.map_opcode(0xF4, nop_memory, zero_page_x, 4); // NOP Zero Page,X
+        self.map_opcode(0x0C, nop_memory, absolute, 4); // NOP Absolute
+        self.map_opcode(0x1C, nop_memory, absolute_x, 4); // NOP Absolute,X (+1 if page crossed)
+        self.map_opcode(0x3C, nop_memory, absolute_x, 4); // NOP Absolute,X (+1 if page crossed)
+        self.map_opcode(0x5C, nop_memory, absolute_x, 4); // NOP Absolute,X (+1 if page crossed)
+        self.map_opcode(0x7C, nop_memory, absolute_x, 4); // NOP Absolute,X (+1 if page crossed)
+        self.map_opcode(0xDC, nop_memory, absolute_x, 4); // NOP Absolute,X (+1 if page crossed)
+        self.map_opcode(0xFC, nop_memory, absolute_x, 4); // NOP Absolute,X (+1 if page crossed)
+
+        // Undocumented stores ANDed with the address high byte, and LAS
+        self.map_opcode(0x93, sha, indirect_y_write, 6); // SHA Indirect,Y
+        self.map_opcode(0x9F, sha, absolute_y_write, 5); // SHA Absolute,Y
+        self.map_opcode(0x9E, shx, absolute_y_write, 5); // SHX Absolute,Y
+        self.map_opcode(0x9C, shy, absolute_x_write, 5); // SHY Absolute,X
+        self.map_opcode(0x9B, tas, absolute_y_write, 5); // TAS Absolute,Y
+        self.map_opcode(0xBB, las, absolute_y, 4); // LAS Absolute,Y (+1 if page crossed)
     }
 
     /// Helper function to map an opcode to an instruction and addressing mode.
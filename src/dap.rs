@@ -0,0 +1,900 @@
+//! The `dap` module implements a Debug Adapter Protocol server.
+//!
+//! This module is only available with the `serde` feature.
+//!
+//! A `DapServer` lets editors such as VS Code debug a machine at the source
+//! level. It speaks the protocol over any byte stream, typically standard
+//! input and output or a localhost socket, and is a thin translation layer
+//! over a `Debugger`:
+//!
+//! ```no_run
+//! use lib6502::cpu::CPU;
+//! use lib6502::dap::DapServer;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! DapServer::new().serve_stdio(&mut cpu).unwrap();
+//! ```
+//!
+//! Source lines are mapped to addresses with a cc65 debug info file, given
+//! either to `set_debug_info` or as the `debugInfo` path in the arguments of
+//! the `launch` or `attach` request. Without one, instruction breakpoints,
+//! the disassembly view and stepping by instruction still work.
+//!
+//! Breakpoint conditions and log messages use the expression language of
+//! the `expr` module, so a breakpoint condition can be `A == $FF && X > 3`
+//! and a log message `X={X:02X}`. The `evaluate` request takes the same
+//! expressions. The machine's registers are shown in a single scope, with
+//! the status flags as children of `P`.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::dbginfo::DebugInfo;
+use crate::debugger::{BreakOn, Debugger, Machine, Resume, Stop};
+use crate::disasm::{back_up, disassemble, next_addr};
+use crate::expr::{Expr, LogMessage};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// The instructions executed between checks for requests while running.
+const SLICE: u64 = 10_000;
+
+/// The most instructions a `disassemble` request returns or skips, more
+/// than there are bytes of memory.
+const MAX_INSTRUCTIONS: u64 = 0x10000;
+
+/// The longest message body accepted, room for a `writeMemory` of all 64KB
+/// encoded as base64.
+const MAX_BODY: usize = 0x20000;
+
+/// The most instructions a source line step executes before giving up.
+const LINE_STEP_LIMIT: u64 = 1_000_000;
+
+/// The only thread of the machine.
+const THREAD_ID: u64 = 1;
+
+/// The `variablesReference` of the registers scope.
+const REGISTERS_REF: u64 = 1;
+/// The `variablesReference` of the status flags, the children of `P`.
+const FLAGS_REF: u64 = 2;
+
+/// The status flags, most significant first, as shown by the flags scope.
+const FLAGS: [&str; 8] = ["N", "V", "U", "B", "D", "I", "Z", "C"];
+
+/// A Debug Adapter Protocol server for one machine.
+pub struct DapServer {
+    debugger: Debugger,
+    debug_info: Option<DebugInfo>,
+    /// The addresses of the breakpoints set for each source file, by id.
+    source_breakpoints: BTreeMap<usize, Vec<u16>>,
+    /// The addresses of the instruction breakpoints.
+    instruction_breakpoints: BTreeSet<u16>,
+    /// Log-point messages waiting to be sent as output events.
+    log: Rc<RefCell<Vec<String>>>,
+}
+
+/// The state of one session.
+#[derive(Default)]
+struct Session {
+    launched: bool,
+    configured: bool,
+    stop_on_entry: bool,
+    running: bool,
+}
+
+/// Writes messages to the client, numbering them.
+struct Output<W: Write> {
+    writer: W,
+    seq: u64,
+}
+
+impl DapServer {
+    /// Creates a new `DapServer` with a fresh `Debugger` and no debug info.
+    pub fn new() -> Self {
+        Self::with_debugger(Debugger::new())
+    }
+
+    /// Creates a new `DapServer` around an existing debugger.
+    ///
+    /// The debugger's log output is redirected to the client.
+    pub fn with_debugger(mut debugger: Debugger) -> Self {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = log.clone();
+        debugger.set_log_output(move |message| sink.borrow_mut().push(message.to_string()));
+        Self {
+            debugger,
+            debug_info: None,
+            source_breakpoints: BTreeMap::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            log,
+        }
+    }
+
+    /// Sets the debug info used to map source lines to addresses.
+    pub fn set_debug_info(&mut self, debug_info: DebugInfo) {
+        self.debug_info = Some(debug_info);
+    }
+
+    /// Returns the debug info, if any.
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Returns the debugger.
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Returns the debugger mutably.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Serves one client over standard input and output.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error writing to standard output.
+    pub fn serve_stdio<M: Machine>(&mut self, machine: &mut M) -> io::Result<()> {
+        self.serve(machine, io::stdin(), io::stdout())
+    }
+
+    /// Serves one client until it disconnects or ends the session.
+    ///
+    /// Requests are read on a separate thread, so that the client can pause
+    /// the machine while it runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to debug.
+    /// * `reader` - Where requests come from.
+    /// * `writer` - Where responses and events go.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error writing to `writer`.
+    pub fn serve<M, R, W>(&mut self, machine: &mut M, reader: R, writer: W) -> io::Result<()>
+    where
+        M: Machine,
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let requests = spawn_reader(reader);
+        let mut output = Output { writer, seq: 0 };
+        let mut session = Session::default();
+        let mut pending = None;
+        loop {
+            let request = match pending.take() {
+                Some(request) => request,
+                None => match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => return Ok(()),
+                },
+            };
+            if !self.handle(machine, &mut output, &mut session, &request)? {
+                return Ok(());
+            }
+            if session.running {
+                // One resume until a request comes in, so that a breakpoint
+                // is not skipped where a slice happens to end
+                let log = self.log.clone();
+                let mut polled = Ok(true);
+                let stop = self
+                    .debugger
+                    .resume_polling(machine, Resume::Continue, SLICE, || {
+                        polled = output.flush_log(&log).map(|()| match requests.try_recv() {
+                            Ok(request) => {
+                                pending = Some(request);
+                                false
+                            }
+                            Err(TryRecvError::Empty) => true,
+                            Err(TryRecvError::Disconnected) => false,
+                        });
+                        matches!(polled, Ok(true))
+                    });
+                polled?;
+                output.flush_log(&self.log)?;
+                if stop != Stop::Limit {
+                    session.running = false;
+                    self.stopped(&mut output, stop)?;
+                } else if pending.is_none() {
+                    // The client has gone
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Handles one request. Returns `false` if the session has ended.
+    fn handle<M: Machine, W: Write>(
+        &mut self,
+        machine: &mut M,
+        output: &mut Output<W>,
+        session: &mut Session,
+        request: &Value,
+    ) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let result = match command {
+            "initialize" => {
+                output.respond(request, Ok(capabilities()))?;
+                output.event("initialized", Value::Null)?;
+                return Ok(true);
+            }
+            "launch" | "attach" => self.launch(session, args),
+            "configurationDone" => {
+                session.configured = true;
+                Ok(Value::Null)
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setExceptionBreakpoints" => {
+                let filters = args["filters"].as_array().cloned().unwrap_or_default();
+                let enabled = |name: &str| filters.iter().any(|filter| filter == name);
+                self.debugger.set_break_on(BreakOn {
+                    brk: enabled("brk"),
+                    undocumented: enabled("undocumented"),
+                    interrupt: enabled("interrupt"),
+                    ..self.debugger.break_on()
+                });
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "6502" }] })),
+            "stackTrace" => Ok(self.stack_trace(machine.cpu())),
+            "scopes" => Ok(json!({
+                "scopes": [{
+                    "name": "Registers",
+                    "presentationHint": "registers",
+                    "variablesReference": REGISTERS_REF,
+                    "expensive": false,
+                }]
+            })),
+            "variables" => Ok(variables(machine.cpu(), args)),
+            "setVariable" => set_variable(machine.cpu_mut(), args),
+            "evaluate" => evaluate(machine.cpu(), args),
+            "readMemory" => read_memory(machine.cpu(), args),
+            "writeMemory" => write_memory(machine.cpu_mut(), args),
+            "disassemble" => self.disassemble(machine.cpu(), args),
+            "continue" => {
+                session.running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                output.respond(request, Ok(Value::Null))?;
+                let stop = self.step(machine, command, args);
+                output.flush_log(&self.log)?;
+                self.stopped(output, stop)?;
+                return Ok(true);
+            }
+            "pause" => {
+                output.respond(request, Ok(Value::Null))?;
+                if session.running {
+                    session.running = false;
+                    output.event(
+                        "stopped",
+                        json!({
+                            "reason": "pause",
+                            "threadId": THREAD_ID,
+                            "allThreadsStopped": true,
+                        }),
+                    )?;
+                }
+                return Ok(true);
+            }
+            "disconnect" | "terminate" => {
+                output.respond(request, Ok(Value::Null))?;
+                if command == "terminate" {
+                    output.event("terminated", Value::Null)?;
+                }
+                return Ok(false);
+            }
+            _ => Err(format!("unsupported request '{command}'")),
+        };
+        output.respond(request, result)?;
+        if matches!(command, "launch" | "attach" | "configurationDone")
+            && session.launched
+            && session.configured
+            && !session.running
+        {
+            // Only once
+            session.configured = false;
+            if session.stop_on_entry {
+                output.event(
+                    "stopped",
+                    json!({
+                        "reason": "entry",
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    }),
+                )?;
+            } else {
+                session.running = true;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Handles `launch` and `attach`, which take the same arguments.
+    fn launch(&mut self, session: &mut Session, args: &Value) -> Result<Value, String> {
+        if let Some(path) = args["debugInfo"].as_str() {
+            self.debug_info = Some(DebugInfo::load(path).map_err(|err| err.to_string())?);
+        }
+        session.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        session.launched = true;
+        Ok(Value::Null)
+    }
+
+    /// Handles `setBreakpoints`, replacing the breakpoints of one source.
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = args["source"]["path"].as_str().unwrap_or_default();
+        let file = self.debug_info.as_ref().and_then(|info| info.file_id(path));
+        if let Some(old) = file.and_then(|file| self.source_breakpoints.remove(&file)) {
+            for addr in old {
+                if !self.instruction_breakpoints.contains(&addr) {
+                    self.debugger.remove_breakpoint(addr);
+                }
+            }
+        }
+
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut addrs = Vec::new();
+        let results = requested
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+                let found =
+                    file.and_then(|file| self.debug_info.as_ref()?.line_address(file, line));
+                let Some((line, addr)) = found else {
+                    return json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "no code at this line",
+                    });
+                };
+                match self.insert_breakpoint(addr, breakpoint) {
+                    Ok(()) => {
+                        addrs.push(addr);
+                        json!({
+                            "verified": true,
+                            "line": line,
+                            "instructionReference": memory_reference(addr),
+                        })
+                    }
+                    Err(message) => json!({ "verified": false, "line": line, "message": message }),
+                }
+            })
+            .collect::<Vec<_>>();
+        if let Some(file) = file {
+            self.source_breakpoints.insert(file, addrs);
+        }
+        json!({ "breakpoints": results })
+    }
+
+    /// Handles `setInstructionBreakpoints`, replacing all of them.
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        let sources: BTreeSet<u16> = self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .copied()
+            .collect();
+        for addr in std::mem::take(&mut self.instruction_breakpoints) {
+            if !sources.contains(&addr) {
+                self.debugger.remove_breakpoint(addr);
+            }
+        }
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let results = requested
+            .iter()
+            .map(|breakpoint| {
+                let reference = breakpoint["instructionReference"]
+                    .as_str()
+                    .unwrap_or_default();
+                let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                let Some(addr) = parse_reference(reference, offset) else {
+                    return json!({ "verified": false, "message": "invalid address" });
+                };
+                match self.insert_breakpoint(addr, breakpoint) {
+                    Ok(()) => {
+                        self.instruction_breakpoints.insert(addr);
+                        json!({ "verified": true, "instructionReference": memory_reference(addr) })
+                    }
+                    Err(message) => json!({ "verified": false, "message": message }),
+                }
+            })
+            .collect::<Vec<_>>();
+        json!({ "breakpoints": results })
+    }
+
+    /// Sets a debugger breakpoint with the condition and log message of a
+    /// DAP breakpoint.
+    fn insert_breakpoint(&mut self, addr: u16, breakpoint: &Value) -> Result<(), String> {
+        let condition = match breakpoint["condition"].as_str() {
+            Some(condition) if !condition.trim().is_empty() => {
+                Some(Expr::parse(condition).map_err(|err| err.to_string())?)
+            }
+            _ => None,
+        };
+        let log = match breakpoint["logMessage"].as_str() {
+            Some(message) => Some(LogMessage::parse(message).map_err(|err| err.to_string())?),
+            None => None,
+        };
+        let inserted = self.debugger.set_breakpoint(addr);
+        inserted.condition = condition;
+        inserted.log = log;
+        Ok(())
+    }
+
+    /// Handles `stackTrace`. There is a single frame, for the PC.
+    fn stack_trace<B: Bus>(&self, cpu: &CPU<B>) -> Value {
+        let pc = cpu.registers.pc;
+        let info = self.debug_info.as_ref();
+        let name = info
+            .and_then(|info| info.symbol_at(pc))
+            .map_or_else(|| format!("${pc:04X}"), str::to_string);
+        let mut frame = json!({
+            "id": 0,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": memory_reference(pc),
+        });
+        if let Some((line, path)) = info.and_then(|info| {
+            let line = info.line_at(pc)?;
+            Some((line.line, info.source_path(line.file)?))
+        }) {
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+            frame["source"] = source(&path);
+        }
+        json!({ "stackFrames": [frame], "totalFrames": 1 })
+    }
+
+    /// Handles `disassemble`.
+    fn disassemble<B: Bus>(&self, cpu: &CPU<B>, args: &Value) -> Result<Value, String> {
+        let reference = args["memoryReference"].as_str().unwrap_or_default();
+        let offset = args["offset"].as_i64().unwrap_or(0);
+        let addr = parse_reference(reference, offset).ok_or("invalid memory reference")?;
+        let limit = MAX_INSTRUCTIONS as i64;
+        let skip = args["instructionOffset"]
+            .as_i64()
+            .unwrap_or(0)
+            .clamp(-limit, limit);
+        let count = args["instructionCount"]
+            .as_u64()
+            .unwrap_or(0)
+            .min(MAX_INSTRUCTIONS) as usize;
+
+        let mut addr = if skip < 0 {
+            back_up(&cpu.bus, addr, skip.unsigned_abs() as usize)
+        } else {
+            (0..skip).fold(addr, |addr, _| next_addr(&cpu.bus, addr))
+        };
+        let info = self.debug_info.as_ref();
+        let name = |addr: u16| {
+            info.and_then(|info| info.symbol_at(addr))
+                .map(str::to_string)
+        };
+        let mut instructions = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut instruction = match disassemble(&cpu.bus, addr) {
+                Some(decoded) => {
+                    let operand = decoded.operand_text(name);
+                    let bytes: Vec<String> = decoded
+                        .bytes
+                        .iter()
+                        .map(|byte| format!("{byte:02X}"))
+                        .collect();
+                    json!({
+                        "address": memory_reference(addr),
+                        "instructionBytes": bytes.join(" "),
+                        "instruction": format!("{} {operand}", decoded.mnemonic).trim_end(),
+                    })
+                }
+                None => json!({
+                    "address": memory_reference(addr),
+                    "instruction": "??",
+                    "presentationHint": "invalid",
+                }),
+            };
+            if let Some(symbol) = name(addr) {
+                instruction["symbol"] = json!(symbol);
+            }
+            if let Some((line, path)) = info.and_then(|info| {
+                let line = info.line_at(addr)?;
+                Some((line.line, info.source_path(line.file)?))
+            }) {
+                instruction["line"] = json!(line);
+                instruction["location"] = source(&path);
+            }
+            instructions.push(instruction);
+            addr = next_addr(&cpu.bus, addr);
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    /// Carries out `next`, `stepIn` or `stepOut`.
+    ///
+    /// With debug info for the current line and no `instruction`
+    /// granularity, `next` and `stepIn` step until the PC leaves the
+    /// current source line.
+    fn step<M: Machine>(&mut self, machine: &mut M, command: &str, args: &Value) -> Stop {
+        let how = match command {
+            "next" => Resume::StepOver,
+            "stepIn" => Resume::StepInto,
+            _ => Resume::StepOut,
+        };
+        let line_at = |machine: &M, info: Option<&DebugInfo>| {
+            info.and_then(|info| info.line_at(machine.cpu().registers.pc))
+        };
+        let by_line = args["granularity"].as_str() != Some("instruction") && how != Resume::StepOut;
+        let start = line_at(machine, self.debug_info.as_ref()).filter(|_| by_line);
+        let mut executed = 0;
+        loop {
+            let stop = self.debugger.resume(machine, how, LINE_STEP_LIMIT);
+            executed += 1;
+            let Some(start) = start else {
+                return stop;
+            };
+            if stop != Stop::StepComplete || executed >= LINE_STEP_LIMIT {
+                return stop;
+            }
+            // Code without source, such as a ROM routine, ends the step too
+            if line_at(machine, self.debug_info.as_ref()) != Some(start) {
+                return stop;
+            }
+        }
+    }
+
+    /// Sends the stopped event for `stop`.
+    fn stopped<W: Write>(&self, output: &mut Output<W>, stop: Stop) -> io::Result<()> {
+        let reason = match stop {
+            Stop::StepComplete | Stop::Cursor { .. } | Stop::Limit => "step",
+            Stop::Breakpoint { pc } | Stop::TemporaryBreakpoint { pc }
+                if self.instruction_breakpoints.contains(&pc) =>
+            {
+                "instruction breakpoint"
+            }
+            Stop::Breakpoint { .. } | Stop::TemporaryBreakpoint { .. } => "breakpoint",
+            Stop::Watchpoint { .. } => "data breakpoint",
+            Stop::Brk { .. } | Stop::Rti { .. } | Stop::Undocumented { .. } => "exception",
+            Stop::Interrupt { .. } => "exception",
+        };
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if !matches!(stop, Stop::StepComplete | Stop::Limit) {
+            body["description"] = json!(stop.to_string());
+        }
+        output.event("stopped", body)
+    }
+}
+
+impl Default for DapServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Write> Output<W> {
+    /// Sends a message, framed with its length.
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.writer.flush()
+    }
+
+    /// Sends the response to `request`: the body, or the error message.
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    /// Sends an event.
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    /// Sends an output event for each pending log-point message.
+    fn flush_log(&mut self, log: &RefCell<Vec<String>>) -> io::Result<()> {
+        let messages = std::mem::take(&mut *log.borrow_mut());
+        for message in messages {
+            self.event(
+                "output",
+                json!({ "category": "console", "output": format!("{message}\n") }),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Starts a thread reading requests from `reader`. The channel closes when
+/// the stream ends.
+fn spawn_reader<R: Read + Send + 'static>(reader: R) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Reads one message. Returns `None` at the end of the stream. Messages
+/// that are not valid JSON are skipped.
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("Content-Length") {
+                    length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+        if length > MAX_BODY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message is longer than any request",
+            ));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Ok(Some(message));
+        }
+    }
+}
+
+/// Returns the capabilities sent in reply to `initialize`.
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsConditionalBreakpoints": true,
+        "supportsLogPoints": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsDisassembleRequest": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsSetVariable": true,
+        "supportsSteppingGranularity": true,
+        "supportsTerminateRequest": true,
+        "supportsEvaluateForHovers": true,
+        "exceptionBreakpointFilters": [
+            { "filter": "brk", "label": "BRK" },
+            { "filter": "undocumented", "label": "JAM opcodes", "default": true },
+            { "filter": "interrupt", "label": "Interrupts" },
+        ],
+    })
+}
+
+/// Returns a `Source` for `path`.
+fn source(path: &std::path::Path) -> Value {
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    json!({ "name": name.to_string_lossy(), "path": path.to_string_lossy() })
+}
+
+/// Formats an address as a memory reference.
+fn memory_reference(addr: u16) -> String {
+    format!("0x{addr:04X}")
+}
+
+/// Parses a memory reference, as `0x` hex or decimal, and adds `offset`.
+fn parse_reference(reference: &str, offset: i64) -> Option<u16> {
+    let addr = match reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => reference.parse().ok()?,
+    };
+    u16::try_from(addr.checked_add(offset)?).ok()
+}
+
+/// Handles `variables`: the registers, or the flags under `P`.
+fn variables<B: Bus>(cpu: &CPU<B>, args: &Value) -> Value {
+    let registers = &cpu.registers;
+    let byte = |name: &str, value: u8| json!({ "name": name, "value": format!("${value:02X}"), "variablesReference": 0 });
+    let variables = match args["variablesReference"].as_u64() {
+        Some(REGISTERS_REF) => vec![
+            byte("A", registers.a),
+            byte("X", registers.x),
+            byte("Y", registers.y),
+            byte("SP", registers.sp),
+            json!({
+                "name": "PC",
+                "value": format!("${:04X}", registers.pc),
+                "memoryReference": memory_reference(registers.pc),
+                "variablesReference": 0,
+            }),
+            json!({
+                "name": "P",
+                "value": format!("${:02X}", registers.status.to_byte()),
+                "variablesReference": FLAGS_REF,
+            }),
+        ],
+        Some(FLAGS_REF) => {
+            let status = registers.status.to_byte();
+            FLAGS
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let set = status & (0x80 >> i) != 0;
+                    json!({ "name": name, "value": (set as u8).to_string(), "variablesReference": 0 })
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    json!({ "variables": variables })
+}
+
+/// Handles `setVariable`. The value may be any expression.
+fn set_variable<B: Bus>(cpu: &mut CPU<B>, args: &Value) -> Result<Value, String> {
+    let name = args["name"].as_str().unwrap_or_default();
+    let value = args["value"].as_str().unwrap_or_default();
+    let value = Expr::parse(value)
+        .map_err(|err| err.to_string())?
+        .eval(cpu, 0)
+        .map_err(|err| err.to_string())?;
+    let registers = &mut cpu.registers;
+    let shown = match name {
+        "A" | "X" | "Y" | "SP" | "P" => {
+            let byte = value as u8;
+            match name {
+                "A" => registers.a = byte,
+                "X" => registers.x = byte,
+                "Y" => registers.y = byte,
+                "SP" => registers.sp = byte,
+                _ => registers.status.from_byte(byte),
+            }
+            format!("${byte:02X}")
+        }
+        "PC" => {
+            registers.pc = value as u16;
+            format!("${:04X}", registers.pc)
+        }
+        flag => {
+            let bit = FLAGS
+                .iter()
+                .position(|candidate| *candidate == flag)
+                .ok_or_else(|| format!("unknown register '{flag}'"))?;
+            let mask = 0x80 >> bit;
+            let status = registers.status.to_byte();
+            let set = value != 0;
+            registers
+                .status
+                .from_byte(if set { status | mask } else { status & !mask });
+            (set as u8).to_string()
+        }
+    };
+    Ok(json!({ "value": shown }))
+}
+
+/// Handles `evaluate`, with the expression language of the `expr` module.
+fn evaluate<B: Bus>(cpu: &CPU<B>, args: &Value) -> Result<Value, String> {
+    let expression = args["expression"].as_str().unwrap_or_default();
+    let value = Expr::parse(expression)
+        .map_err(|err| err.to_string())?
+        .eval(cpu, 0)
+        .map_err(|err| err.to_string())?;
+    Ok(json!({ "result": format!("${value:X} ({value})"), "variablesReference": 0 }))
+}
+
+/// Handles `readMemory`, peeking so that I/O is not disturbed. Reading
+/// stops at the first byte that cannot be peeked.
+fn read_memory<B: Bus>(cpu: &CPU<B>, args: &Value) -> Result<Value, String> {
+    let reference = args["memoryReference"].as_str().unwrap_or_default();
+    let offset = args["offset"].as_i64().unwrap_or(0);
+    let addr = parse_reference(reference, offset).ok_or("invalid memory reference")?;
+    let count = args["count"].as_u64().unwrap_or(0) as usize;
+    let count = count.min(0x10000 - addr as usize);
+    let data: Vec<u8> = (0..count)
+        .map_while(|i| cpu.bus.peek(addr + i as u16))
+        .collect();
+    Ok(json!({
+        "address": memory_reference(addr),
+        "data": base64_encode(&data),
+        "unreadableBytes": count - data.len(),
+    }))
+}
+
+/// Handles `writeMemory`, writing through the bus.
+fn write_memory<B: Bus>(cpu: &mut CPU<B>, args: &Value) -> Result<Value, String> {
+    let reference = args["memoryReference"].as_str().unwrap_or_default();
+    let offset = args["offset"].as_i64().unwrap_or(0);
+    let addr = parse_reference(reference, offset).ok_or("invalid memory reference")?;
+    let data = base64_decode(args["data"].as_str().unwrap_or_default()).ok_or("invalid data")?;
+    if addr as usize + data.len() > 0x10000 {
+        return Err("write past the end of memory".to_string());
+    }
+    for (i, &byte) in data.iter().enumerate() {
+        cpu.bus.write(addr + i as u16, byte);
+    }
+    Ok(json!({ "bytesWritten": data.len() }))
+}
+
+/// The base64 alphabet.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded base64.
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes base64, with or without padding.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let digits = text
+        .trim_end_matches('=')
+        .bytes()
+        .map(|c| {
+            BASE64
+                .iter()
+                .position(|&digit| digit == c)
+                .map(|d| d as u32)
+        })
+        .collect::<Option<Vec<u32>>>()?;
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &digit)| bits | digit << (18 - 6 * i));
+        for i in 0..chunk.len() - 1 {
+            out.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
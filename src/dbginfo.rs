@@ -0,0 +1,349 @@
+//! The `dbginfo` module reads the debug info files written by the cc65 tools.
+//!
+//! Passing `-g` to ca65 and `--dbgfile` to ld65 produces a text file that
+//! maps every assembled byte back to the source line it came from, and
+//! names the labels of the program. A source-level debugger uses it to set
+//! breakpoints by line and to show where the CPU is:
+//!
+//! ```
+//! use lib6502::dbginfo::DebugInfo;
+//!
+//! let info = DebugInfo::parse(concat!(
+//!     "version\tmajor=2,minor=0\n",
+//!     "file\tid=0,name=\"main.s\",size=100,mtime=0x65000000,mod=0\n",
+//!     "line\tid=0,file=0,line=4,span=0\n",
+//!     "seg\tid=0,name=\"CODE\",start=0x008000,size=0x0002,addrsize=absolute,type=ro\n",
+//!     "span\tid=0,seg=0,start=0,size=2\n",
+//!     "sym\tid=0,name=\"main\",addrsize=absolute,val=0x8000,seg=0,type=lab\n",
+//! ))
+//! .unwrap();
+//! let file = info.file_id("main.s").unwrap();
+//! assert_eq!(info.line_address(file, 1), Some((4, 0x8000)));
+//! assert_eq!(info.line_at(0x8001).unwrap().line, 4);
+//! assert_eq!(info.symbol_at(0x8000), Some("main"));
+//! ```
+//!
+//! Only lines of assembler source are used. The lines of macro bodies and of
+//! C source are skipped, so code generated by a macro is attributed to the
+//! line that invoked it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A position in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceLine {
+    /// The id of the source file.
+    pub file: usize,
+    /// The line number, starting at 1.
+    pub line: u32,
+}
+
+/// A label of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the label.
+    pub name: String,
+    /// The address it labels.
+    pub addr: u16,
+}
+
+/// An error reading a debug info file.
+#[derive(Debug)]
+pub enum DebugInfoError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// A line of the file is malformed.
+    Parse {
+        /// The line number in the debug info file, starting at 1.
+        line: usize,
+        /// What is wrong with it.
+        message: String,
+    },
+}
+
+impl fmt::Display for DebugInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebugInfoError::Io(err) => write!(f, "cannot read debug info: {err}"),
+            DebugInfoError::Parse { line, message } => {
+                write!(f, "invalid debug info at line {line}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DebugInfoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DebugInfoError::Io(err) => Some(err),
+            DebugInfoError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DebugInfoError {
+    fn from(err: std::io::Error) -> Self {
+        DebugInfoError::Io(err)
+    }
+}
+
+/// The contents of a cc65 debug info file.
+#[derive(Debug, Clone, Default)]
+pub struct DebugInfo {
+    /// The source file names, by id.
+    files: BTreeMap<usize, String>,
+    /// The directory relative file names are resolved against.
+    base: Option<PathBuf>,
+    /// The address range of each assembled line, by start address.
+    ranges: BTreeMap<u16, Vec<(u16, SourceLine)>>,
+    /// The labels, sorted by address.
+    symbols: Vec<Symbol>,
+}
+
+/// A record of the file: its attributes by name.
+type Record<'a> = HashMap<&'a str, String>;
+
+impl DebugInfo {
+    /// Parses the text of a debug info file.
+    ///
+    /// Relative source file names are kept as they are; use `load` to have
+    /// them resolved against the directory of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DebugInfoError::Parse` for a malformed line, or one that
+    /// refers to a segment or span that does not exist.
+    pub fn parse(text: &str) -> Result<Self, DebugInfoError> {
+        let mut info = DebugInfo::default();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut lines = Vec::new();
+        for (index, text) in text.lines().enumerate() {
+            let error = |message: String| DebugInfoError::Parse {
+                line: index + 1,
+                message,
+            };
+            let Some((kind, attributes)) = text.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let record = parse_record(attributes).map_err(error)?;
+            let number = |key: &str| -> Result<u64, DebugInfoError> {
+                let value = record
+                    .get(key)
+                    .ok_or_else(|| error(format!("missing {key}")))?;
+                parse_number(value).ok_or_else(|| error(format!("invalid {key} '{value}'")))
+            };
+            match kind {
+                "file" => {
+                    let name = record
+                        .get("name")
+                        .ok_or_else(|| error("missing name".to_string()))?;
+                    info.files.insert(number("id")? as usize, name.clone());
+                }
+                "seg" => {
+                    segments.insert(number("id")?, number("start")?);
+                }
+                "span" => {
+                    spans.insert(
+                        number("id")?,
+                        (number("seg")?, number("start")?, number("size")?),
+                    );
+                }
+                // Type 0, the default, is assembler source; the others are
+                // C source and macro bodies
+                "line" if record.contains_key("span") && number("type").unwrap_or(0) == 0 => {
+                    let line = SourceLine {
+                        file: number("file")? as usize,
+                        line: number("line")? as u32,
+                    };
+                    for span in record["span"].split('+') {
+                        let span = parse_number(span)
+                            .ok_or_else(|| error(format!("invalid span '{span}'")))?;
+                        lines.push((index + 1, line, span));
+                    }
+                }
+                "sym" if record.get("type").is_some_and(|kind| kind == "lab") => {
+                    let name = record
+                        .get("name")
+                        .ok_or_else(|| error("missing name".to_string()))?;
+                    // Cheap local labels are reused throughout a program
+                    if !name.starts_with('@') {
+                        if let Ok(addr) = u16::try_from(number("val")?) {
+                            info.symbols.push(Symbol {
+                                name: name.clone(),
+                                addr,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (index, line, span) in lines {
+            let error = |message: String| DebugInfoError::Parse {
+                line: index,
+                message,
+            };
+            let &(segment, offset, size) = spans
+                .get(&span)
+                .ok_or_else(|| error(format!("unknown span {span}")))?;
+            let start = segments
+                .get(&segment)
+                .ok_or_else(|| error(format!("unknown segment {segment}")))?
+                + offset;
+            if size == 0 || start + size > 0x10000 {
+                continue;
+            }
+            let (start, end) = (start as u16, (start + size - 1) as u16);
+            info.ranges.entry(start).or_default().push((end, line));
+        }
+        info.symbols.sort_by_key(|symbol| symbol.addr);
+        Ok(info)
+    }
+
+    /// Reads a debug info file, resolving relative source file names against
+    /// its directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DebugInfoError::Io` if the file cannot be read, and
+    /// `DebugInfoError::Parse` if it is malformed.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, DebugInfoError> {
+        let path = path.as_ref();
+        let mut info = Self::parse(&std::fs::read_to_string(path)?)?;
+        info.base = path.parent().map(Path::to_path_buf);
+        Ok(info)
+    }
+
+    /// Returns the source files, as id and name.
+    pub fn files(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.files.iter().map(|(&id, name)| (id, name.as_str()))
+    }
+
+    /// Returns the id of the source file at `path`.
+    ///
+    /// A file matches if its name, resolved as by `source_path`, is `path`,
+    /// or if `path` ends with its name, so that absolute paths from an
+    /// editor find files the assembler was given relative paths to.
+    pub fn file_id<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
+        let path = path.as_ref();
+        self.files
+            .keys()
+            .find(|&&id| self.source_path(id).as_deref() == Some(path))
+            .or_else(|| {
+                self.files
+                    .iter()
+                    .find(|(_, name)| path.ends_with(name.as_str()))
+                    .map(|(id, _)| id)
+            })
+            .copied()
+    }
+
+    /// Returns the path of source file `file`, resolved against the
+    /// directory of the debug info file if it was loaded with `load`.
+    pub fn source_path(&self, file: usize) -> Option<PathBuf> {
+        let name = Path::new(self.files.get(&file)?);
+        Some(match &self.base {
+            Some(base) if name.is_relative() => base.join(name),
+            _ => name.to_path_buf(),
+        })
+    }
+
+    /// Returns the source line that assembled the byte at `addr`.
+    ///
+    /// When lines overlap, the one covering the fewest bytes wins.
+    pub fn line_at(&self, addr: u16) -> Option<SourceLine> {
+        self.ranges
+            .range(..=addr)
+            .flat_map(|(&start, lines)| lines.iter().map(move |&(end, line)| (start, end, line)))
+            .filter(|&(_, end, _)| end >= addr)
+            .min_by_key(|&(start, end, _)| end - start)
+            .map(|(_, _, line)| line)
+    }
+
+    /// Finds where to put a breakpoint for a source line.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The id of the source file.
+    /// * `line` - The line number.
+    ///
+    /// # Returns
+    ///
+    /// The first line at or after `line` that produced code, and the lowest
+    /// address of that code, or `None` if no such line exists.
+    pub fn line_address(&self, file: usize, line: u32) -> Option<(u32, u16)> {
+        self.ranges
+            .iter()
+            .flat_map(|(&start, lines)| lines.iter().map(move |&(_, source)| (source, start)))
+            .filter(|(source, _)| source.file == file && source.line >= line)
+            .min_by_key(|&(source, start)| (source.line, start))
+            .map(|(source, start)| (source.line, start))
+    }
+
+    /// Returns the labels, in address order.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Returns the address of the label `name`.
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.addr)
+    }
+
+    /// Returns the name of a label at `addr`.
+    pub fn symbol_at(&self, addr: u16) -> Option<&str> {
+        let index = self.symbols.partition_point(|symbol| symbol.addr < addr);
+        self.symbols
+            .get(index)
+            .filter(|symbol| symbol.addr == addr)
+            .map(|symbol| symbol.name.as_str())
+    }
+}
+
+/// Parses the comma-separated `key=value` attributes of a record.
+fn parse_record(text: &str) -> Result<Record<'_>, String> {
+    let mut record = HashMap::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value in '{rest}'"))?;
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next() {
+                        Some((_, '\\')) => value.extend(chars.next().map(|(_, c)| c)),
+                        Some((i, '"')) => break i + 1,
+                        Some((_, c)) => value.push(c),
+                        None => return Err(format!("unterminated string for {key}")),
+                    }
+                };
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].to_string(), &after[end..])
+            }
+        };
+        record.insert(key.trim(), value);
+        rest = after.strip_prefix(',').unwrap_or(after).trim_start();
+    }
+    Ok(record)
+}
+
+/// Parses a decimal or `0x` hex number.
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
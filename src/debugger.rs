@@ -0,0 +1,795 @@
+//! The `debugger` module contains the debugger core shared by all frontends.
+//!
+//! A `Debugger` holds breakpoints and break conditions and runs a machine
+//! until one of them is hit or the requested step completes. It has no user
+//! interface of its own: a command-line monitor, a terminal UI or a remote
+//! protocol server decides what to run and presents the resulting `Stop`.
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::debugger::{Debugger, Stop};
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! let mut debugger = Debugger::new();
+//! debugger.set_breakpoint(0x0010);
+//! assert_eq!(debugger.run(&mut cpu), Stop::Breakpoint { pc: 0x0010 });
+//! assert_eq!(debugger.step_into(&mut cpu), Stop::StepComplete);
+//! assert_eq!(cpu.registers.pc, 0x0011);
+//! ```
+//!
+//! Watchpoints stop execution when the CPU accesses watched memory. They
+//! see every access the CPU makes, including instruction fetches and the
+//! stack accesses of an interrupt, and stop once the instruction making the
+//! access has finished, reporting the address it started at.
+//!
+//! Breakpoints and watchpoints can carry a condition written in the
+//! expression language of the `expr` module, such as `A == $FF && X > 3`,
+//! and only stop when it holds. Either can instead be a log-point, which
+//! writes a message such as `"X={X:02X}"` to the log output set with
+//! `set_log_output` when hit, and keeps going.
+//!
+//! The debugger can drive a bare `CPU` or a `Scheduler`, through the
+//! `Machine` trait. With a scheduler, device events and interrupts are
+//! delivered between instructions as usual, so breaking on an interrupt
+//! being taken works too.
+//!
+//! The next opcode is looked at with `Bus::peek`. On a bus that cannot
+//! peek it, the debugger learns the opcode from the instruction's fetch
+//! once it has executed, so stepping works the same, but `BreakOn::brk` and
+//! `BreakOn::rti` stop just after the instruction instead of before it and
+//! `BreakOn::undocumented` cannot stop an unimplemented opcode from panicking.
+
+use crate::bus::{AccessKind, Bus, BusAccess};
+use crate::cpu::CPU;
+use crate::expr::{Expr, LogMessage, ParseError};
+use crate::scheduler::Scheduler;
+use crate::state::CpuState;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The opcode of `JSR`.
+const JSR: u8 = 0x20;
+/// The opcode of `RTS`.
+const RTS: u8 = 0x60;
+/// The opcode of `RTI`.
+const RTI: u8 = 0x40;
+/// The opcode of `BRK`.
+const BRK: u8 = 0x00;
+
+/// Something a `Debugger` can run: a CPU and whatever drives it.
+pub trait Machine {
+    /// The bus of the machine's CPU.
+    type Bus: Bus;
+
+    /// Returns the machine's CPU.
+    fn cpu(&self) -> &CPU<Self::Bus>;
+
+    /// Returns the machine's CPU mutably.
+    fn cpu_mut(&mut self) -> &mut CPU<Self::Bus>;
+
+    /// Delivers whatever is due before the next instruction, such as device
+    /// events and interrupts. The default does nothing.
+    fn before_instruction(&mut self) {}
+
+    /// Executes one instruction.
+    fn execute(&mut self);
+}
+
+impl<B: Bus> Machine for CPU<B> {
+    type Bus = B;
+
+    fn cpu(&self) -> &CPU<B> {
+        self
+    }
+
+    fn cpu_mut(&mut self) -> &mut CPU<B> {
+        self
+    }
+
+    fn execute(&mut self) {
+        self.step();
+    }
+}
+
+impl<B: Bus> Machine for Scheduler<B> {
+    type Bus = B;
+
+    fn cpu(&self) -> &CPU<B> {
+        &self.cpu
+    }
+
+    fn cpu_mut(&mut self) -> &mut CPU<B> {
+        &mut self.cpu
+    }
+
+    fn before_instruction(&mut self) {
+        self.dispatch_due();
+        self.service_interrupts();
+    }
+
+    fn execute(&mut self) {
+        Scheduler::execute(self);
+    }
+}
+
+/// A PC breakpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// The address of the instruction to stop before.
+    pub addr: u16,
+    /// Disabled breakpoints are kept but never stop execution.
+    pub enabled: bool,
+    /// Temporary breakpoints are removed the first time they stop execution.
+    pub temporary: bool,
+    /// The number of times the breakpoint has been reached while enabled,
+    /// whether or not its condition held.
+    pub hits: u64,
+    /// Only stop if this evaluates to non-zero. A condition that fails to
+    /// evaluate stops execution, so that the problem gets noticed.
+    pub condition: Option<Expr>,
+    /// Makes this a log-point: instead of stopping, the message is written
+    /// to the debugger's log output.
+    pub log: Option<LogMessage>,
+}
+
+/// Identifies a watchpoint within a `Debugger`.
+pub type WatchpointId = usize;
+
+/// The accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    /// Any read, including instruction fetches.
+    Read,
+    /// Any write.
+    Write,
+    /// Any read or write.
+    Access,
+    /// A write of a value different from the one already there.
+    Change,
+}
+
+impl WatchKind {
+    /// Returns `true` if an access of `kind` is of interest to this watchpoint.
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write | WatchKind::Change => kind == AccessKind::Write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+/// A memory watchpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// The addresses watched.
+    pub range: RangeInclusive<u16>,
+    /// The accesses that stop execution.
+    pub kind: WatchKind,
+    /// Disabled watchpoints are kept but never stop execution.
+    pub enabled: bool,
+    /// The number of instructions that made a matching access while the
+    /// watchpoint was enabled, whether or not its condition held.
+    pub hits: u64,
+    /// Only stop if this evaluates to non-zero, after the instruction has
+    /// finished. A condition that fails to evaluate stops execution.
+    pub condition: Option<Expr>,
+    /// Makes this a log-point: instead of stopping, the message is written
+    /// to the debugger's log output.
+    pub log: Option<LogMessage>,
+    /// The last known value of each watched address, for `WatchKind::Change`.
+    values: Vec<Option<u8>>,
+}
+
+/// Events other than breakpoints that stop execution.
+///
+/// All are off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BreakOn {
+    /// Stop before executing `BRK`, or just after it if the bus cannot
+    /// peek the opcode.
+    pub brk: bool,
+    /// Stop before executing `RTI`, or just after it if the bus cannot
+    /// peek the opcode.
+    pub rti: bool,
+    /// Stop before executing an opcode the CPU does not implement, one of
+    /// the undocumented JAM opcodes, instead of letting `CPU::step` panic. This needs a bus that can
+    /// peek the opcode.
+    pub undocumented: bool,
+    /// Stop when the machine takes an interrupt, before the first
+    /// instruction of the handler.
+    pub interrupt: bool,
+}
+
+/// How to resume execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until something stops execution.
+    Continue,
+    /// Execute one instruction.
+    StepInto,
+    /// Execute one instruction, running a `JSR` until it returns.
+    StepOver,
+    /// Run until the current subroutine returns to its caller.
+    StepOut,
+    /// Run until the PC reaches the given address.
+    RunTo(u16),
+}
+
+/// Why a `Debugger` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested step into, over or out completed.
+    StepComplete,
+    /// The PC reached the address given to `Resume::RunTo`.
+    Cursor {
+        /// The address reached.
+        pc: u16,
+    },
+    /// The PC reached a breakpoint.
+    Breakpoint {
+        /// The address of the breakpoint.
+        pc: u16,
+    },
+    /// The PC reached a temporary breakpoint, which has been removed.
+    TemporaryBreakpoint {
+        /// The address of the breakpoint.
+        pc: u16,
+    },
+    /// The next instruction is `BRK`.
+    Brk {
+        /// The address of the `BRK`.
+        pc: u16,
+    },
+    /// The next instruction is `RTI`.
+    Rti {
+        /// The address of the `RTI`.
+        pc: u16,
+    },
+    /// The next instruction has an opcode the CPU does not implement, a JAM.
+    Undocumented {
+        /// The address of the instruction.
+        pc: u16,
+        /// The opcode.
+        opcode: u8,
+    },
+    /// The CPU accessed memory covered by a watchpoint.
+    Watchpoint {
+        /// The watchpoint that was hit.
+        id: WatchpointId,
+        /// The address of the instruction that made the access.
+        pc: u16,
+        /// The access.
+        access: BusAccess,
+        /// For `WatchKind::Change`, the value before the write, if known.
+        old: Option<u8>,
+    },
+    /// The machine took an interrupt.
+    Interrupt {
+        /// The address of the interrupt handler.
+        pc: u16,
+        /// The address execution will return to.
+        return_addr: u16,
+    },
+    /// The instruction limit given to `Debugger::resume` was reached, or
+    /// `Debugger::resume_polling` was told to stop.
+    Limit,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::StepComplete => write!(f, "step complete"),
+            Stop::Cursor { pc } => write!(f, "reached ${pc:04X}"),
+            Stop::Breakpoint { pc } => write!(f, "breakpoint at ${pc:04X}"),
+            Stop::TemporaryBreakpoint { pc } => write!(f, "temporary breakpoint at ${pc:04X}"),
+            Stop::Brk { pc } => write!(f, "BRK at ${pc:04X}"),
+            Stop::Rti { pc } => write!(f, "RTI at ${pc:04X}"),
+            Stop::Undocumented { pc, opcode } => {
+                write!(f, "undocumented opcode ${opcode:02X} at ${pc:04X}")
+            }
+            Stop::Watchpoint {
+                id,
+                pc,
+                access,
+                old,
+            } => {
+                write!(f, "watchpoint {id}: ${pc:04X} ")?;
+                match access.kind {
+                    AccessKind::Read => write!(f, "read ${:02X} from", access.data)?,
+                    AccessKind::Write => write!(f, "wrote ${:02X} to", access.data)?,
+                }
+                write!(f, " ${:04X}", access.addr)?;
+                match old {
+                    Some(old) => write!(f, " (was ${old:02X})"),
+                    None => Ok(()),
+                }
+            }
+            Stop::Interrupt { pc, return_addr } => write!(
+                f,
+                "interrupt taken to ${pc:04X}, returning to ${return_addr:04X}"
+            ),
+            Stop::Limit => write!(f, "instruction limit reached"),
+        }
+    }
+}
+
+/// Breakpoints, break conditions and the stepping logic.
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    watchpoints: BTreeMap<WatchpointId, Watchpoint>,
+    next_watchpoint: WatchpointId,
+    break_on: BreakOn,
+    log_output: Box<dyn FnMut(&str)>,
+    /// The PC of the instruction not yet checked when the last resume
+    /// stopped with `Stop::Limit`.
+    unchecked: Option<u16>,
+}
+
+impl Debugger {
+    /// Creates a new `Debugger` with no breakpoints.
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            next_watchpoint: 1,
+            break_on: BreakOn::default(),
+            log_output: Box::new(|_| {}),
+            unchecked: None,
+        }
+    }
+
+    /// Sets a breakpoint at `addr`, replacing any breakpoint already there.
+    pub fn set_breakpoint(&mut self, addr: u16) -> &mut Breakpoint {
+        self.insert_breakpoint(addr, false)
+    }
+
+    /// Sets a breakpoint at `addr` that is removed the first time it is hit.
+    pub fn set_temporary_breakpoint(&mut self, addr: u16) -> &mut Breakpoint {
+        self.insert_breakpoint(addr, true)
+    }
+
+    /// Sets a breakpoint at `addr` that only stops when `condition` holds.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ParseError` if `condition` is not a valid expression, in
+    /// which case no breakpoint is set.
+    pub fn set_conditional_breakpoint(
+        &mut self,
+        addr: u16,
+        condition: &str,
+    ) -> Result<&mut Breakpoint, ParseError> {
+        let condition = Expr::parse(condition)?;
+        let breakpoint = self.insert_breakpoint(addr, false);
+        breakpoint.condition = Some(condition);
+        Ok(breakpoint)
+    }
+
+    /// Sets a log-point at `addr`, which writes `message` to the log output
+    /// every time the instruction there is about to execute.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ParseError` if `message` is not a valid log message, in
+    /// which case no log-point is set.
+    pub fn set_log_point(
+        &mut self,
+        addr: u16,
+        message: &str,
+    ) -> Result<&mut Breakpoint, ParseError> {
+        let message = LogMessage::parse(message)?;
+        let breakpoint = self.insert_breakpoint(addr, false);
+        breakpoint.log = Some(message);
+        Ok(breakpoint)
+    }
+
+    /// Sets where log-point messages go. By default they are discarded, so
+    /// a frontend that shows them must set this.
+    pub fn set_log_output<F: FnMut(&str) + 'static>(&mut self, output: F) {
+        self.log_output = Box::new(output);
+    }
+
+    /// Removes the breakpoint at `addr`. Returns `false` if there was none.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    /// Removes every breakpoint.
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Returns the breakpoint at `addr`, if any.
+    pub fn breakpoint(&self, addr: u16) -> Option<&Breakpoint> {
+        self.breakpoints.get(&addr)
+    }
+
+    /// Returns the breakpoint at `addr` mutably, for example to disable it.
+    pub fn breakpoint_mut(&mut self, addr: u16) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&addr)
+    }
+
+    /// Returns every breakpoint, in address order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.values()
+    }
+
+    /// Adds a watchpoint on `range` and returns its id.
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, kind: WatchKind) -> WatchpointId {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        self.watchpoints.insert(
+            id,
+            Watchpoint {
+                range,
+                kind,
+                enabled: true,
+                hits: 0,
+                condition: None,
+                log: None,
+                values: Vec::new(),
+            },
+        );
+        id
+    }
+
+    /// Removes a watchpoint. Returns `false` if there was none with that id.
+    pub fn remove_watchpoint(&mut self, id: WatchpointId) -> bool {
+        self.watchpoints.remove(&id).is_some()
+    }
+
+    /// Removes every watchpoint.
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Returns the watchpoint with the given id, if any.
+    pub fn watchpoint(&self, id: WatchpointId) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    /// Returns the watchpoint with the given id mutably, for example to disable it.
+    pub fn watchpoint_mut(&mut self, id: WatchpointId) -> Option<&mut Watchpoint> {
+        self.watchpoints.get_mut(&id)
+    }
+
+    /// Returns every watchpoint with its id, in the order they were added.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (WatchpointId, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    /// Returns the events that stop execution besides breakpoints.
+    pub fn break_on(&self) -> BreakOn {
+        self.break_on
+    }
+
+    /// Sets the events that stop execution besides breakpoints.
+    pub fn set_break_on(&mut self, break_on: BreakOn) {
+        self.break_on = break_on;
+    }
+
+    /// Executes one instruction.
+    pub fn step_into<M: Machine>(&mut self, machine: &mut M) -> Stop {
+        self.resume(machine, Resume::StepInto, u64::MAX)
+    }
+
+    /// Executes one instruction, or a whole subroutine call if it is a `JSR`.
+    pub fn step_over<M: Machine>(&mut self, machine: &mut M) -> Stop {
+        self.resume(machine, Resume::StepOver, u64::MAX)
+    }
+
+    /// Runs until the current subroutine returns to its caller.
+    pub fn step_out<M: Machine>(&mut self, machine: &mut M) -> Stop {
+        self.resume(machine, Resume::StepOut, u64::MAX)
+    }
+
+    /// Runs until the PC reaches `addr`.
+    pub fn run_to<M: Machine>(&mut self, machine: &mut M, addr: u16) -> Stop {
+        self.resume(machine, Resume::RunTo(addr), u64::MAX)
+    }
+
+    /// Runs until a breakpoint or another break condition stops execution.
+    pub fn run<M: Machine>(&mut self, machine: &mut M) -> Stop {
+        self.resume(machine, Resume::Continue, u64::MAX)
+    }
+
+    /// Resumes execution and runs until it stops.
+    ///
+    /// Breakpoints and break conditions stop execution during every kind of
+    /// resume, including steps over and out. They are checked before each
+    /// instruction, except that a breakpoint at the PC execution resumes
+    /// from does not stop it again straight away. After `Stop::Limit` the
+    /// instruction at the PC has not been checked yet, so it is checked
+    /// when execution resumes.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to run.
+    /// * `how` - How to resume.
+    /// * `limit` - The most instructions to execute before returning
+    ///   `Stop::Limit`, so that frontends stay responsive.
+    ///
+    /// While there are watchpoints, access recording is enabled on the CPU.
+    pub fn resume<M: Machine>(&mut self, machine: &mut M, how: Resume, limit: u64) -> Stop {
+        self.resume_polling(machine, how, limit, || false)
+    }
+
+    /// Resumes execution like `resume`, asking `keep_going` every `slice`
+    /// instructions whether to carry on.
+    ///
+    /// Unlike calling `resume` again after `Stop::Limit`, which starts a new
+    /// step, this finishes one step over or out however long it takes, while
+    /// letting a frontend check for a request to stop it.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to run.
+    /// * `how` - How to resume.
+    /// * `slice` - The instructions to execute between calls to
+    ///   `keep_going`.
+    /// * `keep_going` - Returns `false` to stop with `Stop::Limit`.
+    pub fn resume_polling<M: Machine, F: FnMut() -> bool>(
+        &mut self,
+        machine: &mut M,
+        how: Resume,
+        slice: u64,
+        mut keep_going: F,
+    ) -> Stop {
+        if !self.watchpoints.is_empty() {
+            self.prepare_watchpoints(machine.cpu_mut());
+        }
+        let start = machine.cpu().registers;
+        let unchecked = self.unchecked.take();
+        let mut call_return = match how {
+            Resume::StepOver if opcode_at(machine.cpu(), start.pc) == Some(JSR) => {
+                Some(start.pc.wrapping_add(3))
+            }
+            _ => None,
+        };
+
+        let mut executed = 0;
+        let mut poll_at = slice;
+        loop {
+            if executed == poll_at {
+                if !keep_going() {
+                    self.unchecked = Some(machine.cpu().registers.pc);
+                    return Stop::Limit;
+                }
+                poll_at = poll_at.saturating_add(slice);
+            }
+            let before = machine.cpu().snapshot();
+            machine.before_instruction();
+            let cpu = machine.cpu();
+            let interrupted = took_interrupt(&before, &cpu.snapshot());
+            if interrupted {
+                if let Some(stop) = self.check_watchpoints(cpu, before.registers.pc) {
+                    return stop;
+                }
+            }
+            if self.break_on.interrupt && interrupted {
+                return Stop::Interrupt {
+                    pc: cpu.registers.pc,
+                    return_addr: before.registers.pc,
+                };
+            }
+
+            let pc = cpu.registers.pc;
+            let opcode = opcode_at(cpu, pc);
+            if let Some(opcode) = opcode {
+                // Executing it would panic, so there is no moving past this one
+                if self.break_on.undocumented && !cpu.is_implemented(opcode) {
+                    return Stop::Undocumented { pc, opcode };
+                }
+            }
+            let resuming = executed == 0 && pc == start.pc && unchecked != Some(pc);
+            if !resuming {
+                if let Some(stop) = self.check_before(cpu, opcode, how) {
+                    return stop;
+                }
+            }
+
+            if opcode.is_none() && !machine.cpu().is_recording_accesses() {
+                // The fetch is the only way left to learn the opcode
+                machine.cpu_mut().set_access_recording(true);
+            }
+            machine.execute();
+            executed += 1;
+
+            let cpu = machine.cpu();
+            if let Some(stop) = self.check_watchpoints(cpu, pc) {
+                return stop;
+            }
+            let peeked = opcode.is_some();
+            let opcode = opcode.or_else(|| fetched_opcode(cpu, pc));
+            if !peeked {
+                if how == Resume::StepOver && executed == 1 && pc == start.pc && opcode == Some(JSR)
+                {
+                    call_return = Some(pc.wrapping_add(3));
+                }
+                match opcode {
+                    Some(BRK) if self.break_on.brk => return Stop::Brk { pc },
+                    Some(RTI) if self.break_on.rti => return Stop::Rti { pc },
+                    _ => {}
+                }
+            }
+            let done = match how {
+                Resume::StepInto => true,
+                Resume::StepOver => match call_return {
+                    Some(addr) => cpu.registers.pc == addr && cpu.registers.sp == start.sp,
+                    None => true,
+                },
+                // The return that pops this frame leaves the stack above
+                // where it was when the step started
+                Resume::StepOut => matches!(opcode, Some(RTS | RTI)) && cpu.registers.sp > start.sp,
+                Resume::Continue | Resume::RunTo(_) => false,
+            };
+            if done {
+                return Stop::StepComplete;
+            }
+        }
+    }
+
+    /// Checks the conditions that stop execution before the instruction at
+    /// the PC, counting breakpoint hits and writing log-point messages.
+    fn check_before<B: Bus>(
+        &mut self,
+        cpu: &CPU<B>,
+        opcode: Option<u8>,
+        how: Resume,
+    ) -> Option<Stop> {
+        let pc = cpu.registers.pc;
+        if let Some(breakpoint) = self.breakpoints.get_mut(&pc) {
+            if breakpoint.enabled {
+                breakpoint.hits += 1;
+                if condition_holds(&breakpoint.condition, cpu, breakpoint.hits) {
+                    let temporary = breakpoint.temporary;
+                    let stop = match &breakpoint.log {
+                        Some(message) => {
+                            (self.log_output)(&message.format(cpu, breakpoint.hits));
+                            None
+                        }
+                        None if temporary => Some(Stop::TemporaryBreakpoint { pc }),
+                        None => Some(Stop::Breakpoint { pc }),
+                    };
+                    if temporary {
+                        self.breakpoints.remove(&pc);
+                    }
+                    if stop.is_some() {
+                        return stop;
+                    }
+                }
+            }
+        }
+        if how == Resume::RunTo(pc) {
+            return Some(Stop::Cursor { pc });
+        }
+        match opcode {
+            Some(BRK) if self.break_on.brk => Some(Stop::Brk { pc }),
+            Some(RTI) if self.break_on.rti => Some(Stop::Rti { pc }),
+            _ => None,
+        }
+    }
+
+    /// Turns on access recording and captures the current values of the
+    /// memory watched for changes, which the host may have modified since
+    /// the last resume.
+    fn prepare_watchpoints<B: Bus>(&mut self, cpu: &mut CPU<B>) {
+        if !cpu.is_recording_accesses() {
+            cpu.set_access_recording(true);
+        }
+        for watchpoint in self.watchpoints.values_mut() {
+            if watchpoint.kind == WatchKind::Change {
+                watchpoint.values = watchpoint
+                    .range
+                    .clone()
+                    .map(|addr| cpu.bus.peek(addr))
+                    .collect();
+            }
+        }
+    }
+
+    /// Checks the accesses made by the instruction at `pc` against the
+    /// watchpoints, returning the first hit whose condition holds.
+    fn check_watchpoints<B: Bus>(&mut self, cpu: &CPU<B>, pc: u16) -> Option<Stop> {
+        let mut hit = None;
+        // Each watchpoint is counted, and its condition evaluated, once per
+        // instruction
+        let mut seen = Vec::new();
+        for access in cpu.accesses() {
+            for (&id, watchpoint) in self.watchpoints.iter_mut() {
+                if !watchpoint.range.contains(&access.addr) || !watchpoint.kind.matches(access.kind)
+                {
+                    continue;
+                }
+                let mut old = None;
+                if watchpoint.kind == WatchKind::Change {
+                    let index = (access.addr - watchpoint.range.start()) as usize;
+                    old = watchpoint.values[index];
+                    watchpoint.values[index] = Some(access.data);
+                    if old == Some(access.data) {
+                        continue;
+                    }
+                }
+                if !watchpoint.enabled || seen.contains(&id) {
+                    continue;
+                }
+                seen.push(id);
+                watchpoint.hits += 1;
+                if !condition_holds(&watchpoint.condition, cpu, watchpoint.hits) {
+                    continue;
+                }
+                match &watchpoint.log {
+                    Some(message) => (self.log_output)(&message.format(cpu, watchpoint.hits)),
+                    None if hit.is_none() => {
+                        hit = Some(Stop::Watchpoint {
+                            id,
+                            pc,
+                            access: *access,
+                            old,
+                        })
+                    }
+                    None => {}
+                }
+            }
+        }
+        hit
+    }
+
+    /// Inserts a fresh breakpoint at `addr`.
+    fn insert_breakpoint(&mut self, addr: u16, temporary: bool) -> &mut Breakpoint {
+        self.breakpoints.insert(
+            addr,
+            Breakpoint {
+                addr,
+                enabled: true,
+                temporary,
+                hits: 0,
+                condition: None,
+                log: None,
+            },
+        );
+        self.breakpoints.get_mut(&addr).expect("just inserted")
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the opcode at `pc`, if it can be read without side effects.
+fn opcode_at<B: Bus>(cpu: &CPU<B>, pc: u16) -> Option<u8> {
+    cpu.bus.peek(pc)
+}
+
+/// Returns the opcode the instruction at `pc` fetched, from the recorded
+/// accesses.
+fn fetched_opcode<B: Bus>(cpu: &CPU<B>, pc: u16) -> Option<u8> {
+    cpu.accesses()
+        .first()
+        .filter(|access| access.addr == pc && access.kind == AccessKind::Read)
+        .map(|access| access.data)
+}
+
+/// Returns `true` if the change from `before` to `after` is an interrupt
+/// being taken: three bytes pushed, interrupts disabled and at least the
+/// seven cycles of the interrupt sequence used.
+fn took_interrupt(before: &CpuState, after: &CpuState) -> bool {
+    before != after
+        && after.registers.sp == before.registers.sp.wrapping_sub(3)
+        && after.registers.status.interrupt_disable
+        && after.cycles >= before.cycles + 7
+}
+
+/// Returns `true` if there is no condition, or it evaluates to non-zero or
+/// fails to evaluate.
+fn condition_holds<B: Bus>(condition: &Option<Expr>, cpu: &CPU<B>, hits: u64) -> bool {
+    condition
+        .as_ref()
+        .is_none_or(|condition| condition.eval(cpu, hits) != Ok(0))
+}
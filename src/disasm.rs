@@ -0,0 +1,491 @@
+//! The `disasm` module turns machine code back into assembly language.
+//!
+//! Disassembly reads memory with `Bus::peek`, so it can be used on a live
+//! machine without disturbing its I/O devices:
+//!
+//! ```
+//! use lib6502::disasm::disassemble;
+//!
+//! let mut bus = vec![0u8; 0x10000];
+//! bus[0x8000..0x8005].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x02]);
+//! let lda = disassemble(&bus, 0x8000).unwrap();
+//! assert_eq!(lda.to_string(), "LDA #$42");
+//! let sta = disassemble(&bus, lda.next_addr()).unwrap();
+//! assert_eq!(sta.to_string(), "STA $0200");
+//! ```
+//!
+//! Opcodes the CPU does not implement are shown as `.byte` directives.
+
+use crate::bus::Bus;
+use std::fmt;
+
+/// The addressing mode of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// No operand, as in `NOP`.
+    Implied,
+    /// The accumulator, as in `ASL A`.
+    Accumulator,
+    /// `#$nn`
+    Immediate,
+    /// `$nn`
+    ZeroPage,
+    /// `$nn,X`
+    ZeroPageX,
+    /// `$nn,Y`
+    ZeroPageY,
+    /// `$nnnn`
+    Absolute,
+    /// `$nnnn,X`
+    AbsoluteX,
+    /// `$nnnn,Y`
+    AbsoluteY,
+    /// `($nnnn)`
+    Indirect,
+    /// `($nn,X)`
+    IndirectX,
+    /// `($nn),Y`
+    IndirectY,
+    /// A branch offset, shown as its target address.
+    Relative,
+}
+
+impl Mode {
+    /// Returns the number of operand bytes following the opcode.
+    pub fn operand_len(self) -> u16 {
+        match self {
+            Mode::Implied | Mode::Accumulator => 0,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Returns the mnemonic and addressing mode of `opcode`, or `None` if the
+/// CPU does not implement it.
+///
+/// Undocumented opcodes go by the names the NESdev wiki gives them, except
+/// `ISB`, which is what Nintendulator and the nestest log call `ISC`.
+pub fn lookup(opcode: u8) -> Option<(&'static str, Mode)> {
+    Some(match opcode {
+        0x00 => ("BRK", Mode::Implied),
+        0x01 => ("ORA", Mode::IndirectX),
+        0x03 => ("SLO", Mode::IndirectX),
+        0x04 => ("NOP", Mode::ZeroPage),
+        0x05 => ("ORA", Mode::ZeroPage),
+        0x06 => ("ASL", Mode::ZeroPage),
+        0x07 => ("SLO", Mode::ZeroPage),
+        0x08 => ("PHP", Mode::Implied),
+        0x09 => ("ORA", Mode::Immediate),
+        0x0A => ("ASL", Mode::Accumulator),
+        0x0B => ("ANC", Mode::Immediate),
+        0x0C => ("NOP", Mode::Absolute),
+        0x0D => ("ORA", Mode::Absolute),
+        0x0E => ("ASL", Mode::Absolute),
+        0x0F => ("SLO", Mode::Absolute),
+        0x10 => ("BPL", Mode::Relative),
+        0x11 => ("ORA", Mode::IndirectY),
+        0x13 => ("SLO", Mode::IndirectY),
+        0x14 => ("NOP", Mode::ZeroPageX),
+        0x15 => ("ORA", Mode::ZeroPageX),
+        0x16 => ("ASL", Mode::ZeroPageX),
+        0x17 => ("SLO", Mode::ZeroPageX),
+        0x18 => ("CLC", Mode::Implied),
+        0x19 => ("ORA", Mode::AbsoluteY),
+        0x1A => ("NOP", Mode::Implied),
+        0x1B => ("SLO", Mode::AbsoluteY),
+        0x1C => ("NOP", Mode::AbsoluteX),
+        0x1D => ("ORA", Mode::AbsoluteX),
+        0x1E => ("ASL", Mode::AbsoluteX),
+        0x1F => ("SLO", Mode::AbsoluteX),
+        0x20 => ("JSR", Mode::Absolute),
+        0x21 => ("AND", Mode::IndirectX),
+        0x23 => ("RLA", Mode::IndirectX),
+        0x24 => ("BIT", Mode::ZeroPage),
+        0x25 => ("AND", Mode::ZeroPage),
+        0x26 => ("ROL", Mode::ZeroPage),
+        0x27 => ("RLA", Mode::ZeroPage),
+        0x28 => ("PLP", Mode::Implied),
+        0x29 => ("AND", Mode::Immediate),
+        0x2A => ("ROL", Mode::Accumulator),
+        0x2B => ("ANC", Mode::Immediate),
+        0x2C => ("BIT", Mode::Absolute),
+        0x2D => ("AND", Mode::Absolute),
+        0x2E => ("ROL", Mode::Absolute),
+        0x2F => ("RLA", Mode::Absolute),
+        0x30 => ("BMI", Mode::Relative),
+        0x31 => ("AND", Mode::IndirectY),
+        0x33 => ("RLA", Mode::IndirectY),
+        0x34 => ("NOP", Mode::ZeroPageX),
+        0x35 => ("AND", Mode::ZeroPageX),
+        0x36 => ("ROL", Mode::ZeroPageX),
+        0x37 => ("RLA", Mode::ZeroPageX),
+        0x38 => ("SEC", Mode::Implied),
+        0x39 => ("AND", Mode::AbsoluteY),
+        0x3A => ("NOP", Mode::Implied),
+        0x3B => ("RLA", Mode::AbsoluteY),
+        0x3C => ("NOP", Mode::AbsoluteX),
+        0x3D => ("AND", Mode::AbsoluteX),
+        0x3E => ("ROL", Mode::AbsoluteX),
+        0x3F => ("RLA", Mode::AbsoluteX),
+        0x40 => ("RTI", Mode::Implied),
+        0x41 => ("EOR", Mode::IndirectX),
+        0x43 => ("SRE", Mode::IndirectX),
+        0x44 => ("NOP", Mode::ZeroPage),
+        0x45 => ("EOR", Mode::ZeroPage),
+        0x46 => ("LSR", Mode::ZeroPage),
+        0x47 => ("SRE", Mode::ZeroPage),
+        0x48 => ("PHA", Mode::Implied),
+        0x49 => ("EOR", Mode::Immediate),
+        0x4A => ("LSR", Mode::Accumulator),
+        0x4B => ("ALR", Mode::Immediate),
+        0x4C => ("JMP", Mode::Absolute),
+        0x4D => ("EOR", Mode::Absolute),
+        0x4E => ("LSR", Mode::Absolute),
+        0x4F => ("SRE", Mode::Absolute),
+        0x50 => ("BVC", Mode::Relative),
+        0x51 => ("EOR", Mode::IndirectY),
+        0x53 => ("SRE", Mode::IndirectY),
+        0x54 => ("NOP", Mode::ZeroPageX),
+        0x55 => ("EOR", Mode::ZeroPageX),
+        0x56 => ("LSR", Mode::ZeroPageX),
+        0x57 => ("SRE", Mode::ZeroPageX),
+        0x58 => ("CLI", Mode::Implied),
+        0x59 => ("EOR", Mode::AbsoluteY),
+        0x5A => ("NOP", Mode::Implied),
+        0x5B => ("SRE", Mode::AbsoluteY),
+        0x5C => ("NOP", Mode::AbsoluteX),
+        0x5D => ("EOR", Mode::AbsoluteX),
+        0x5E => ("LSR", Mode::AbsoluteX),
+        0x5F => ("SRE", Mode::AbsoluteX),
+        0x60 => ("RTS", Mode::Implied),
+        0x61 => ("ADC", Mode::IndirectX),
+        0x63 => ("RRA", Mode::IndirectX),
+        0x64 => ("NOP", Mode::ZeroPage),
+        0x65 => ("ADC", Mode::ZeroPage),
+        0x66 => ("ROR", Mode::ZeroPage),
+        0x67 => ("RRA", Mode::ZeroPage),
+        0x68 => ("PLA", Mode::Implied),
+        0x69 => ("ADC", Mode::Immediate),
+        0x6A => ("ROR", Mode::Accumulator),
+        0x6B => ("ARR", Mode::Immediate),
+        0x6C => ("JMP", Mode::Indirect),
+        0x6D => ("ADC", Mode::Absolute),
+        0x6E => ("ROR", Mode::Absolute),
+        0x6F => ("RRA", Mode::Absolute),
+        0x70 => ("BVS", Mode::Relative),
+        0x71 => ("ADC", Mode::IndirectY),
+        0x73 => ("RRA", Mode::IndirectY),
+        0x74 => ("NOP", Mode::ZeroPageX),
+        0x75 => ("ADC", Mode::ZeroPageX),
+        0x76 => ("ROR", Mode::ZeroPageX),
+        0x77 => ("RRA", Mode::ZeroPageX),
+        0x78 => ("SEI", Mode::Implied),
+        0x79 => ("ADC", Mode::AbsoluteY),
+        0x7A => ("NOP", Mode::Implied),
+        0x7B => ("RRA", Mode::AbsoluteY),
+        0x7C => ("NOP", Mode::AbsoluteX),
+        0x7D => ("ADC", Mode::AbsoluteX),
+        0x7E => ("ROR", Mode::AbsoluteX),
+        0x7F => ("RRA", Mode::AbsoluteX),
+        0x80 => ("NOP", Mode::Immediate),
+        0x81 => ("STA", Mode::IndirectX),
+        0x82 => ("NOP", Mode::Immediate),
+        0x83 => ("SAX", Mode::IndirectX),
+        0x84 => ("STY", Mode::ZeroPage),
+        0x85 => ("STA", Mode::ZeroPage),
+        0x86 => ("STX", Mode::ZeroPage),
+        0x87 => ("SAX", Mode::ZeroPage),
+        0x88 => ("DEY", Mode::Implied),
+        0x89 => ("NOP", Mode::Immediate),
+        0x8A => ("TXA", Mode::Implied),
+        0x8B => ("XAA", Mode::Immediate),
+        0x8C => ("STY", Mode::Absolute),
+        0x8D => ("STA", Mode::Absolute),
+        0x8E => ("STX", Mode::Absolute),
+        0x8F => ("SAX", Mode::Absolute),
+        0x90 => ("BCC", Mode::Relative),
+        0x91 => ("STA", Mode::IndirectY),
+        0x93 => ("SHA", Mode::IndirectY),
+        0x94 => ("STY", Mode::ZeroPageX),
+        0x95 => ("STA", Mode::ZeroPageX),
+        0x96 => ("STX", Mode::ZeroPageY),
+        0x97 => ("SAX", Mode::ZeroPageY),
+        0x98 => ("TYA", Mode::Implied),
+        0x99 => ("STA", Mode::AbsoluteY),
+        0x9A => ("TXS", Mode::Implied),
+        0x9B => ("TAS", Mode::AbsoluteY),
+        0x9C => ("SHY", Mode::AbsoluteX),
+        0x9D => ("STA", Mode::AbsoluteX),
+        0x9E => ("SHX", Mode::AbsoluteY),
+        0x9F => ("SHA", Mode::AbsoluteY),
+        0xA0 => ("LDY", Mode::Immediate),
+        0xA1 => ("LDA", Mode::IndirectX),
+        0xA2 => ("LDX", Mode::Immediate),
+        0xA3 => ("LAX", Mode::IndirectX),
+        0xA4 => ("LDY", Mode::ZeroPage),
+        0xA5 => ("LDA", Mode::ZeroPage),
+        0xA6 => ("LDX", Mode::ZeroPage),
+        0xA7 => ("LAX", Mode::ZeroPage),
+        0xA8 => ("TAY", Mode::Implied),
+        0xA9 => ("LDA", Mode::Immediate),
+        0xAA => ("TAX", Mode::Implied),
+        0xAB => ("LXA", Mode::Immediate),
+        0xAC => ("LDY", Mode::Absolute),
+        0xAD => ("LDA", Mode::Absolute),
+        0xAE => ("LDX", Mode::Absolute),
+        0xAF => ("LAX", Mode::Absolute),
+        0xB0 => ("BCS", Mode::Relative),
+        0xB1 => ("LDA", Mode::IndirectY),
+        0xB3 => ("LAX", Mode::IndirectY),
+        0xB4 => ("LDY", Mode::ZeroPageX),
+        0xB5 => ("LDA", Mode::ZeroPageX),
+        0xB6 => ("LDX", Mode::ZeroPageY),
+        0xB7 => ("LAX", Mode::ZeroPageY),
+        0xB8 => ("CLV", Mode::Implied),
+        0xB9 => ("LDA", Mode::AbsoluteY),
+        0xBA => ("TSX", Mode::Implied),
+        0xBB => ("LAS", Mode::AbsoluteY),
+        0xBC => ("LDY", Mode::AbsoluteX),
+        0xBD => ("LDA", Mode::AbsoluteX),
+        0xBE => ("LDX", Mode::AbsoluteY),
+        0xBF => ("LAX", Mode::AbsoluteY),
+        0xC0 => ("CPY", Mode::Immediate),
+        0xC1 => ("CMP", Mode::IndirectX),
+        0xC2 => ("NOP", Mode::Immediate),
+        0xC3 => ("DCP", Mode::IndirectX),
+        0xC4 => ("CPY", Mode::ZeroPage),
+        0xC5 => ("CMP", Mode::ZeroPage),
+        0xC6 => ("DEC", Mode::ZeroPage),
+        0xC7 => ("DCP", Mode::ZeroPage),
+        0xC8 => ("INY", Mode::Implied),
+        0xC9 => ("CMP", Mode::Immediate),
+        0xCA => ("DEX", Mode::Implied),
+        0xCB => ("AXS", Mode::Immediate),
+        0xCC => ("CPY", Mode::Absolute),
+        0xCD => ("CMP", Mode::Absolute),
+        0xCE => ("DEC", Mode::Absolute),
+        0xCF => ("DCP", Mode::Absolute),
+        0xD0 => ("BNE", Mode::Relative),
+        0xD1 => ("CMP", Mode::IndirectY),
+        0xD3 => ("DCP", Mode::IndirectY),
+        0xD4 => ("NOP", Mode::ZeroPageX),
+        0xD5 => ("CMP", Mode::ZeroPageX),
+        0xD6 => ("DEC", Mode::ZeroPageX),
+        0xD7 => ("DCP", Mode::ZeroPageX),
+        0xD8 => ("CLD", Mode::Implied),
+        0xD9 => ("CMP", Mode::AbsoluteY),
+        0xDA => ("NOP", Mode::Implied),
+        0xDB => ("DCP", Mode::AbsoluteY),
+        0xDC => ("NOP", Mode::AbsoluteX),
+        0xDD => ("CMP", Mode::AbsoluteX),
+        0xDE => ("DEC", Mode::AbsoluteX),
+        0xDF => ("DCP", Mode::AbsoluteX),
+        0xE0 => ("CPX", Mode::Immediate),
+        0xE1 => ("SBC", Mode::IndirectX),
+        0xE2 => ("NOP", Mode::Immediate),
+        0xE3 => ("ISB", Mode::IndirectX),
+        0xE4 => ("CPX", Mode::ZeroPage),
+        0xE5 => ("SBC", Mode::ZeroPage),
+        0xE6 => ("INC", Mode::ZeroPage),
+        0xE7 => ("ISB", Mode::ZeroPage),
+        0xE8 => ("INX", Mode::Implied),
+        0xE9 => ("SBC", Mode::Immediate),
+        0xEA => ("NOP", Mode::Implied),
+        0xEB => ("SBC", Mode::Immediate),
+        0xEC => ("CPX", Mode::Absolute),
+        0xED => ("SBC", Mode::Absolute),
+        0xEE => ("INC", Mode::Absolute),
+        0xEF => ("ISB", Mode::Absolute),
+        0xF0 => ("BEQ", Mode::Relative),
+        0xF1 => ("SBC", Mode::IndirectY),
+        0xF3 => ("ISB", Mode::IndirectY),
+        0xF4 => ("NOP", Mode::ZeroPageX),
+        0xF5 => ("SBC", Mode::ZeroPageX),
+        0xF6 => ("INC", Mode::ZeroPageX),
+        0xF7 => ("ISB", Mode::ZeroPageX),
+        0xF8 => ("SED", Mode::Implied),
+        0xF9 => ("SBC", Mode::AbsoluteY),
+        0xFA => ("NOP", Mode::Implied),
+        0xFB => ("ISB", Mode::AbsoluteY),
+        0xFC => ("NOP", Mode::AbsoluteX),
+        0xFD => ("SBC", Mode::AbsoluteX),
+        0xFE => ("INC", Mode::AbsoluteX),
+        0xFF => ("ISB", Mode::AbsoluteX),
+        _ => return None,
+    })
+}
+
+/// The undocumented opcodes whose low two bits are not both set: the JAMs,
+/// the NOPs with an operand, SHY and SHX.
+const UNDOCUMENTED_GAPS: [u8; 41] = [
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2, 0x80, 0x82, 0x89, 0xC2,
+    0xE2, 0x04, 0x44, 0x64, 0x14, 0x34, 0x54, 0x74, 0xD4, 0xF4, 0x0C, 0x1C, 0x3C, 0x5C, 0x7C, 0xDC,
+    0xFC, 0x1A, 0x3A, 0x5A, 0x7A, 0xDA, 0xFA, 0x9C, 0x9E,
+];
+
+/// Returns `true` if `opcode` is one of the 151 opcodes documented by MOS.
+pub fn is_documented(opcode: u8) -> bool {
+    // Every opcode whose low two bits are set is undocumented
+    opcode & 0x03 != 0x03 && !UNDOCUMENTED_GAPS.contains(&opcode)
+}
+
+/// A disassembled instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembled {
+    /// The address of the opcode.
+    pub addr: u16,
+    /// The opcode and operand bytes.
+    pub bytes: Vec<u8>,
+    /// The mnemonic, or `.byte` for an opcode the CPU does not implement.
+    pub mnemonic: &'static str,
+    /// The addressing mode, or `None` for a `.byte` directive.
+    pub mode: Option<Mode>,
+    /// The operand value: a byte, an address, or a branch target.
+    pub operand: Option<u16>,
+}
+
+impl Disassembled {
+    /// Returns the address of the instruction that follows this one.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// Returns the address the instruction refers to, if its operand is an
+    /// address: a memory operand, a jump or a branch target.
+    pub fn target(&self) -> Option<u16> {
+        match self.mode? {
+            Mode::Implied | Mode::Accumulator | Mode::Immediate => None,
+            _ => self.operand,
+        }
+    }
+
+    /// Formats the operand, writing addresses with `name` where it returns
+    /// a symbol for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Returns the symbol for an address, if there is one.
+    ///
+    /// # Returns
+    ///
+    /// The operand as it would be written in source, such as `($10),Y`, or
+    /// an empty string if there is none.
+    pub fn operand_text<F: Fn(u16) -> Option<String>>(&self, name: F) -> String {
+        let value = match (self.mode, self.operand) {
+            (Some(Mode::Accumulator), _) => return "A".to_string(),
+            (Some(mode), Some(value)) => (mode, value),
+            (None, _) => return format!("${:02X}", self.bytes[0]),
+            _ => return String::new(),
+        };
+        let addr = |value: u16, digits: usize| {
+            name(value).unwrap_or_else(|| format!("${value:0digits$X}"))
+        };
+        match value {
+            (Mode::Immediate, value) => format!("#${value:02X}"),
+            (Mode::ZeroPage, value) => addr(value, 2),
+            (Mode::ZeroPageX, value) => format!("{},X", addr(value, 2)),
+            (Mode::ZeroPageY, value) => format!("{},Y", addr(value, 2)),
+            (Mode::Absolute | Mode::Relative, value) => addr(value, 4),
+            (Mode::AbsoluteX, value) => format!("{},X", addr(value, 4)),
+            (Mode::AbsoluteY, value) => format!("{},Y", addr(value, 4)),
+            (Mode::Indirect, value) => format!("({})", addr(value, 4)),
+            (Mode::IndirectX, value) => format!("({},X)", addr(value, 2)),
+            (Mode::IndirectY, value) => format!("({}),Y", addr(value, 2)),
+            (Mode::Implied | Mode::Accumulator, _) => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for Disassembled {
+    /// Formats the instruction as `LDA #$42`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = self.operand_text(|_| None);
+        if operand.is_empty() {
+            f.write_str(self.mnemonic)
+        } else {
+            write!(f, "{} {operand}", self.mnemonic)
+        }
+    }
+}
+
+/// Disassembles the instruction at `addr`.
+///
+/// # Arguments
+///
+/// * `bus` - The memory to read, with `Bus::peek`.
+/// * `addr` - The address of the opcode.
+///
+/// # Returns
+///
+/// The instruction, a one-byte `.byte` directive if the opcode is not
+/// implemented or its operand cannot be peeked, or `None` if the opcode
+/// itself cannot be peeked.
+pub fn disassemble<B: Bus + ?Sized>(bus: &B, addr: u16) -> Option<Disassembled> {
+    let opcode = bus.peek(addr)?;
+    let byte = Disassembled {
+        addr,
+        bytes: vec![opcode],
+        mnemonic: ".byte",
+        mode: None,
+        operand: None,
+    };
+    let Some((mnemonic, mode)) = lookup(opcode) else {
+        return Some(byte);
+    };
+    let mut bytes = vec![opcode];
+    for offset in 1..=mode.operand_len() {
+        match bus.peek(addr.wrapping_add(offset)) {
+            Some(operand) => bytes.push(operand),
+            None => return Some(byte),
+        }
+    }
+    let operand = match bytes[1..] {
+        [] => None,
+        [offset] if mode == Mode::Relative => {
+            Some(addr.wrapping_add(2).wrapping_add(offset as i8 as u16))
+        }
+        [value] => Some(value as u16),
+        [lo, hi] => Some(u16::from_le_bytes([lo, hi])),
+        _ => unreachable!(),
+    };
+    Some(Disassembled {
+        addr,
+        bytes,
+        mnemonic,
+        mode: Some(mode),
+        operand,
+    })
+}
+
+/// Returns the address of the instruction after the one at `addr`, taking
+/// an opcode that cannot be peeked as one byte long.
+pub fn next_addr<B: Bus + ?Sized>(bus: &B, addr: u16) -> u16 {
+    disassemble(bus, addr).map_or(addr.wrapping_add(1), |decoded| decoded.next_addr())
+}
+
+/// Finds the address `count` instructions before `addr`.
+///
+/// Instructions have no marker of where they start, so this disassembles
+/// forward from far enough back and hopes to fall into step, falling back to
+/// one byte per instruction.
+pub fn back_up<B: Bus + ?Sized>(bus: &B, addr: u16, count: usize) -> u16 {
+    if count == 0 {
+        return addr;
+    }
+    let mut starts = Vec::new();
+    let mut at = addr.saturating_sub((count * 3) as u16);
+    while at < addr {
+        starts.push(at);
+        let next = next_addr(bus, at);
+        if next <= at {
+            break;
+        }
+        at = next;
+    }
+    if at == addr && starts.len() >= count {
+        starts[starts.len() - count]
+    } else {
+        addr.saturating_sub(count as u16)
+    }
+}
@@ -0,0 +1,670 @@
+//! The `expr` module contains the expression language used by the debugger.
+//!
+//! Expressions give breakpoints and watchpoints their conditions, and fill
+//! in the messages of log-points. They look like Rust integer expressions
+//! over the machine state:
+//!
+//! ```text
+//! A == $FF && X > 3          registers: A X Y SP PC, and P for the status byte
+//! [$0200] & $80              a memory byte, read with Bus::peek
+//! w[$FFFC] == $8000          a little-endian memory word
+//! C && !Z                    flags: N V U B D I Z C, as 0 or 1
+//! cycles > 100000            the cycle count
+//! hits == 5                  how often the breakpoint has been reached
+//! ```
+//!
+//! Numbers are decimal, or hex with a `$` or `0x` prefix, or binary with a
+//! `%` or `0b` prefix. A `%` straight after a value is the remainder
+//! operator instead, so `X%10` is the remainder by ten and `X % %10` the
+//! remainder by two. The operators are those of Rust, with the same
+//! precedence: `* / %`, `+ -`, `<< >>`, `&`, `^`, `|`, comparisons, `&&` and
+//! `||`, plus unary `-`, `!` (logical not) and `~` (bitwise not). Names are
+//! not case sensitive. Values are 64-bit signed integers, and any non-zero
+//! value counts as true.
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::expr::Expr;
+//!
+//! let mut cpu = CPU::new(vec![0u8; 0x10000]);
+//! cpu.registers.a = 0xFF;
+//! cpu.registers.x = 4;
+//! let expr = Expr::parse("A == $FF && X > 3").unwrap();
+//! assert_eq!(expr.eval(&cpu, 0), Ok(1));
+//! ```
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::state::Field;
+use std::fmt;
+use std::str::FromStr;
+
+/// An error found while parsing an expression or a log message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The character offset of the error in the source.
+    pub position: usize,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An error found while evaluating an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    /// The bus returned `None` from `peek` for a memory operand.
+    Unreadable {
+        /// The address that could not be read.
+        addr: u16,
+    },
+    /// The expression divided by zero.
+    DivisionByZero,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Unreadable { addr } => {
+                write!(f, "${addr:04X} cannot be read without side effects")
+            }
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A unary operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+/// A binary operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    /// Returns the operator spelled `token` and its binding power, where
+    /// higher binds tighter.
+    fn from_token(token: &str) -> Option<(BinaryOp, u8)> {
+        Some(match token {
+            "||" => (BinaryOp::Or, 1),
+            "&&" => (BinaryOp::And, 2),
+            "==" => (BinaryOp::Eq, 3),
+            "!=" => (BinaryOp::Ne, 3),
+            "<" => (BinaryOp::Lt, 3),
+            "<=" => (BinaryOp::Le, 3),
+            ">" => (BinaryOp::Gt, 3),
+            ">=" => (BinaryOp::Ge, 3),
+            "|" => (BinaryOp::BitOr, 4),
+            "^" => (BinaryOp::BitXor, 5),
+            "&" => (BinaryOp::BitAnd, 6),
+            "<<" => (BinaryOp::Shl, 7),
+            ">>" => (BinaryOp::Shr, 7),
+            "+" => (BinaryOp::Add, 8),
+            "-" => (BinaryOp::Sub, 8),
+            "*" => (BinaryOp::Mul, 9),
+            "/" => (BinaryOp::Div, 9),
+            "%" => (BinaryOp::Rem, 9),
+            _ => return None,
+        })
+    }
+}
+
+/// A node of a parsed expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Field(Field),
+    Status,
+    Hits,
+    Byte(Box<Node>),
+    Word(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl Expr {
+    /// Parses an expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ParseError` pointing at the first thing that is not valid.
+    pub fn parse(source: &str) -> Result<Expr, ParseError> {
+        let mut parser = Parser::new(source)?;
+        let root = parser.expression(0)?;
+        match parser.peek() {
+            Token::End => Ok(Expr {
+                source: source.trim().to_string(),
+                root,
+            }),
+            _ => Err(parser.error("expected an operator")),
+        }
+    }
+
+    /// Returns the expression as it was written.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the expression against the current state of `cpu`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - The CPU whose registers, cycle count and memory are used.
+    /// * `hits` - The value of `hits`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `EvalError` if a memory operand cannot be peeked or the
+    /// expression divides by zero.
+    pub fn eval<B: Bus>(&self, cpu: &CPU<B>, hits: u64) -> Result<i64, EvalError> {
+        eval(&self.root, cpu, hits)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Expr, ParseError> {
+        Expr::parse(source)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Evaluates a node.
+fn eval<B: Bus>(node: &Node, cpu: &CPU<B>, hits: u64) -> Result<i64, EvalError> {
+    let peek = |addr: i64| {
+        let addr = addr as u16;
+        cpu.bus
+            .peek(addr)
+            .map(i64::from)
+            .ok_or(EvalError::Unreadable { addr })
+    };
+    Ok(match node {
+        Node::Number(value) => *value,
+        Node::Field(Field::Cycles) => cpu.cycles() as i64,
+        Node::Field(field) => cpu.snapshot().get(*field) as i64,
+        Node::Status => cpu.registers.status.to_byte() as i64,
+        Node::Hits => hits as i64,
+        Node::Byte(addr) => peek(eval(addr, cpu, hits)?)?,
+        Node::Word(addr) => {
+            let addr = eval(addr, cpu, hits)?;
+            peek(addr)? | peek((addr as u16).wrapping_add(1) as i64)? << 8
+        }
+        Node::Unary(op, operand) => {
+            let value = eval(operand, cpu, hits)?;
+            match op {
+                UnaryOp::Neg => value.wrapping_neg(),
+                UnaryOp::Not => (value == 0) as i64,
+                UnaryOp::BitNot => !value,
+            }
+        }
+        Node::Binary(BinaryOp::And, left, right) => {
+            (eval(left, cpu, hits)? != 0 && eval(right, cpu, hits)? != 0) as i64
+        }
+        Node::Binary(BinaryOp::Or, left, right) => {
+            (eval(left, cpu, hits)? != 0 || eval(right, cpu, hits)? != 0) as i64
+        }
+        Node::Binary(op, left, right) => {
+            let (left, right) = (eval(left, cpu, hits)?, eval(right, cpu, hits)?);
+            let shift = |value: i64, f: fn(i64, u32) -> Option<i64>| {
+                u32::try_from(right)
+                    .ok()
+                    .and_then(|amount| f(value, amount))
+                    .unwrap_or(0)
+            };
+            match op {
+                BinaryOp::Eq => (left == right) as i64,
+                BinaryOp::Ne => (left != right) as i64,
+                BinaryOp::Lt => (left < right) as i64,
+                BinaryOp::Le => (left <= right) as i64,
+                BinaryOp::Gt => (left > right) as i64,
+                BinaryOp::Ge => (left >= right) as i64,
+                BinaryOp::BitOr => left | right,
+                BinaryOp::BitXor => left ^ right,
+                BinaryOp::BitAnd => left & right,
+                BinaryOp::Shl => shift(left, i64::checked_shl),
+                BinaryOp::Shr => shift(left, i64::checked_shr),
+                BinaryOp::Add => left.wrapping_add(right),
+                BinaryOp::Sub => left.wrapping_sub(right),
+                BinaryOp::Mul => left.wrapping_mul(right),
+                BinaryOp::Div | BinaryOp::Rem if right == 0 => {
+                    return Err(EvalError::DivisionByZero)
+                }
+                BinaryOp::Div => left.wrapping_div(right),
+                BinaryOp::Rem => left.wrapping_rem(right),
+                BinaryOp::And | BinaryOp::Or => unreachable!(),
+            }
+        }
+    })
+}
+
+/// A token of the expression language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+    End,
+}
+
+/// The operators, longest first so that `<=` is not read as `<`.
+const OPERATORS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "<", ">", "|", "^", "&", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+/// A recursive descent parser with precedence climbing for binary operators.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    /// Splits `source` into tokens.
+    fn new(source: &str) -> Result<Self, ParseError> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let rest: String = chars[i..].iter().take(2).collect();
+            // After a value, `%` is the remainder operator, as in `X%10`
+            let value_expected = !matches!(
+                tokens.last(),
+                Some((Token::Number(_) | Token::Name(_) | Token::Op(")" | "]"), _))
+            );
+            let radix = match c {
+                '$' => Some((16, 1)),
+                '%' if value_expected
+                    && chars.get(i + 1).is_some_and(|c| *c == '0' || *c == '1') =>
+                {
+                    Some((2, 1))
+                }
+                '0' if rest.eq_ignore_ascii_case("0x") => Some((16, 2)),
+                '0' if rest.eq_ignore_ascii_case("0b") => Some((2, 2)),
+                c if c.is_ascii_digit() => Some((10, 0)),
+                _ => None,
+            };
+            if let Some((radix, prefix)) = radix {
+                i += prefix;
+                let digits_start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let digits: String = chars[digits_start..i]
+                    .iter()
+                    .filter(|c| **c != '_')
+                    .collect();
+                let value = i64::from_str_radix(&digits, radix).map_err(|_| ParseError {
+                    position: start,
+                    message: "invalid number".to_string(),
+                })?;
+                tokens.push((Token::Number(value), start));
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                tokens.push((Token::Name(name.to_ascii_lowercase()), start));
+            } else if let Some(op) = OPERATORS
+                .iter()
+                .find(|op| chars[i..].iter().take(op.len()).copied().eq(op.chars()))
+            {
+                i += op.len();
+                tokens.push((Token::Op(op), start));
+            } else {
+                return Err(ParseError {
+                    position: start,
+                    message: format!("unexpected character '{c}'"),
+                });
+            }
+        }
+        tokens.push((Token::End, chars.len()));
+        Ok(Self { tokens, next: 0 })
+    }
+
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    /// Consumes and returns the next token.
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    /// Returns an error at the position of the next token.
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            position: self.tokens[self.next].1,
+            message: message.to_string(),
+        }
+    }
+
+    /// Consumes the operator `op` or fails.
+    fn expect(&mut self, op: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Token::Op(found) if *found == op => {
+                self.advance();
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected '{op}'"))),
+        }
+    }
+
+    /// Parses binary operators binding tighter than `min_power`.
+    fn expression(&mut self, min_power: u8) -> Result<Node, ParseError> {
+        let mut left = self.unary()?;
+        while let Some((op, power)) = self.binary_op(min_power) {
+            self.advance();
+            let right = self.expression(power)?;
+            left = Node::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// Returns the next token as a binary operator if it binds tighter than
+    /// `min_power`.
+    fn binary_op(&self, min_power: u8) -> Option<(BinaryOp, u8)> {
+        match self.peek() {
+            Token::Op(token) => BinaryOp::from_token(token).filter(|(_, power)| *power > min_power),
+            _ => None,
+        }
+    }
+
+    /// Parses a unary operator or a primary expression.
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        let op = match self.peek() {
+            Token::Op("-") => UnaryOp::Neg,
+            Token::Op("!") => UnaryOp::Not,
+            Token::Op("~") => UnaryOp::BitNot,
+            _ => return self.primary(),
+        };
+        self.advance();
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    /// Parses a number, a name, a memory operand or a parenthesised expression.
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        let position = self.next;
+        match self.advance() {
+            Token::Number(value) => Ok(Node::Number(value)),
+            Token::Op("(") => {
+                let node = self.expression(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Token::Op("[") => Ok(Node::Byte(Box::new(self.address()?))),
+            Token::Name(name) if name == "w" && *self.peek() == Token::Op("[") => {
+                self.advance();
+                Ok(Node::Word(Box::new(self.address()?)))
+            }
+            Token::Name(name) => {
+                let field = match name.as_str() {
+                    "a" => Field::A,
+                    "x" => Field::X,
+                    "y" => Field::Y,
+                    "sp" => Field::Sp,
+                    "pc" => Field::Pc,
+                    "n" => Field::Negative,
+                    "v" => Field::Overflow,
+                    "u" => Field::Unused,
+                    "b" => Field::Break,
+                    "d" => Field::Decimal,
+                    "i" => Field::InterruptDisable,
+                    "z" => Field::Zero,
+                    "c" => Field::Carry,
+                    "cycles" => Field::Cycles,
+                    "p" => return Ok(Node::Status),
+                    "hits" => return Ok(Node::Hits),
+                    _ => {
+                        self.next = position;
+                        return Err(self.error(&format!("unknown name '{name}'")));
+                    }
+                };
+                Ok(Node::Field(field))
+            }
+            _ => {
+                self.next = position;
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    /// Parses the address inside `[...]`, after the opening bracket.
+    fn address(&mut self) -> Result<Node, ParseError> {
+        let node = self.expression(0)?;
+        self.expect("]")?;
+        Ok(node)
+    }
+}
+
+/// How a value is written into a log message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Format {
+    radix: u32,
+    uppercase: bool,
+    width: usize,
+    zero_pad: bool,
+}
+
+/// A piece of a log message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Value(Expr, Format),
+}
+
+/// The message of a log-point: text with embedded expressions.
+///
+/// Expressions go in braces, optionally followed by a format of the form
+/// `:[0][width][x|X|b|d]`, as in `"A={A:02X} at {cycles}"`. Write `{{` and
+/// `}}` for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMessage {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl LogMessage {
+    /// Parses a log message.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ParseError` for an unterminated brace, an invalid format
+    /// or an invalid expression, with the position within the whole message.
+    pub fn parse(source: &str) -> Result<LogMessage, ParseError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.char_indices().peekable();
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().is_some_and(|(_, c)| *c == '{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek().is_some_and(|(_, c)| *c == '}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let start = position + 1;
+                    let end = source[start..]
+                        .find('}')
+                        .map(|offset| start + offset)
+                        .ok_or(ParseError {
+                            position,
+                            message: "unterminated '{'".to_string(),
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Self::value(&source[start..end], start)?);
+                    while chars.next_if(|(i, _)| *i <= end).is_some() {}
+                }
+                '}' => {
+                    return Err(ParseError {
+                        position,
+                        message: "unmatched '}'".to_string(),
+                    })
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(LogMessage {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    /// Returns the message as it was written.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Formats the message against the current state of `cpu`.
+    ///
+    /// Expressions that fail to evaluate are replaced by the error in angle
+    /// brackets, so a log-point never stops execution.
+    pub fn format<B: Bus>(&self, cpu: &CPU<B>, hits: u64) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Value(expr, format) => match expr.eval(cpu, hits) {
+                    Ok(value) => out.push_str(&format.apply(value)),
+                    Err(err) => out.push_str(&format!("<{err}>")),
+                },
+            }
+        }
+        out
+    }
+
+    /// Parses `expr[:format]` found at byte offset `offset` of the message.
+    fn value(inner: &str, offset: usize) -> Result<Part, ParseError> {
+        let (expr, spec) = match inner.rfind(':') {
+            Some(colon) => (&inner[..colon], &inner[colon + 1..]),
+            None => (inner, ""),
+        };
+        let expr = Expr::parse(expr).map_err(|err| ParseError {
+            position: offset + err.position,
+            message: err.message,
+        })?;
+        let format = Format::parse(spec).ok_or(ParseError {
+            position: offset + expr.source.len(),
+            message: format!("invalid format '{spec}'"),
+        })?;
+        Ok(Part::Value(expr, format))
+    }
+}
+
+impl FromStr for LogMessage {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<LogMessage, ParseError> {
+        LogMessage::parse(source)
+    }
+}
+
+impl fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Format {
+    /// Parses a format such as `02X`. An empty format means decimal.
+    fn parse(spec: &str) -> Option<Format> {
+        let (digits, kind) = match spec.chars().last() {
+            Some(c) if c.is_ascii_alphabetic() => (&spec[..spec.len() - 1], Some(c)),
+            _ => (spec, None),
+        };
+        let (radix, uppercase) = match kind {
+            None | Some('d') => (10, false),
+            Some('x') => (16, false),
+            Some('X') => (16, true),
+            Some('b') => (2, false),
+            Some(_) => return None,
+        };
+        let width = match digits {
+            "" => 0,
+            digits => digits.parse().ok()?,
+        };
+        Some(Format {
+            radix,
+            uppercase,
+            width,
+            zero_pad: digits.starts_with('0'),
+        })
+    }
+
+    /// Writes `value` in this format.
+    fn apply(&self, value: i64) -> String {
+        let (width, fill) = (self.width, if self.zero_pad { '0' } else { ' ' });
+        let digits = match (self.radix, self.uppercase) {
+            (16, true) => format!("{value:X}"),
+            (16, false) => format!("{value:x}"),
+            (2, _) => format!("{value:b}"),
+            _ => value.to_string(),
+        };
+        let padding = width.saturating_sub(digits.len());
+        std::iter::repeat_n(fill, padding)
+            .chain(digits.chars())
+            .collect()
+    }
+}
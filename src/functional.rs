@@ -0,0 +1,239 @@
+//! The `functional` module runs self-checking test programs, such as Klaus
+//! Dormann's functional tests and Bruce Clark's decimal mode test.
+//!
+//! These programs run from a fixed address until they trap, executing an
+//! instruction that jumps or branches to itself. Where they trap tells
+//! whether they passed. Each `Suite` describes one program: where its image
+//! is loaded, where it starts and how it signals success.
+//!
+//! The binaries are not vendored in this repository, so the tests that run
+//! them are ignored by default. CI fetches the functional test from the
+//! `bin_files` directory of
+//! <https://github.com/Klaus2m5/6502_65C02_functional_tests> and runs it.
+//! The interrupt and decimal tests have to be assembled from the same
+//! repository for the addresses used here, the decimal test with its
+//! `end_of_test` macro as `jmp *`; nothing builds them yet. Bruce Clark's
+//! model of decimal mode is also checked directly, for every operand, by a
+//! test that runs by default. To run the binaries:
+//!
+//! ```text
+//! KLAUS_DIR=path/to/bin_files cargo test --release suite -- --ignored
+//! ```
+//!
+//! `EXTENDED_65C02` describes the 65C02 test for a 65C02 core, which this
+//! crate does not have yet.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// How a test program signals that it passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Success {
+    /// It traps at this address, and anywhere else on failure.
+    Trap(u16),
+    /// It traps when done, with this byte zero if it passed.
+    Zero(u16),
+}
+
+/// A self-checking test program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suite {
+    /// The name of the test.
+    pub name: &'static str,
+    /// The name of the binary.
+    pub file: &'static str,
+    /// Where the image is loaded.
+    pub load: u16,
+    /// Where execution starts.
+    pub start: u16,
+    /// How the program signals success.
+    pub success: Success,
+    /// The address of the port the program writes to raise interrupts: bit
+    /// 0 holds IRQ low and a rising bit 1 triggers an NMI.
+    pub feedback: Option<u16>,
+}
+
+/// Klaus Dormann's test of every documented NMOS instruction.
+pub const FUNCTIONAL: Suite = Suite {
+    name: "6502 functional test",
+    file: "6502_functional_test.bin",
+    load: 0x0000,
+    start: 0x0400,
+    success: Success::Trap(0x3469),
+    feedback: None,
+};
+
+/// Klaus Dormann's test of the 65C02 instructions and addressing modes.
+pub const EXTENDED_65C02: Suite = Suite {
+    name: "65C02 extended opcodes test",
+    file: "65C02_extended_opcodes_test.bin",
+    load: 0x0000,
+    start: 0x0400,
+    success: Success::Trap(0x24F1),
+    feedback: None,
+};
+
+/// Klaus Dormann's test of IRQ, NMI and BRK.
+pub const INTERRUPT: Suite = Suite {
+    name: "6502 interrupt test",
+    file: "6502_interrupt_test.bin",
+    load: 0x0000,
+    start: 0x0400,
+    success: Success::Trap(0x06F5),
+    feedback: Some(0xBFFC),
+};
+
+/// Bruce Clark's test of ADC and SBC in decimal mode, for all operands and
+/// the NMOS flags, as adapted by Klaus Dormann.
+pub const DECIMAL: Suite = Suite {
+    name: "decimal mode test",
+    file: "6502_decimal_test.bin",
+    load: 0x0200,
+    start: 0x0200,
+    // The ERROR variable
+    success: Success::Zero(0x000B),
+    feedback: None,
+};
+
+/// How a test program ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// It passed.
+    Passed,
+    /// It trapped somewhere other than its success address, or with a
+    /// nonzero error byte.
+    Failed {
+        /// The address of the trap.
+        pc: u16,
+    },
+    /// It reached an opcode the CPU does not implement.
+    Unimplemented {
+        /// The address of the opcode.
+        pc: u16,
+        /// The opcode.
+        opcode: u8,
+    },
+    /// It did not trap within the instruction limit.
+    TimedOut,
+}
+
+/// The result of running a test program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// How it ended.
+    pub outcome: Outcome,
+    /// The number of instructions executed.
+    pub instructions: u64,
+    /// The number of cycles used, including interrupts.
+    pub cycles: u64,
+}
+
+impl Report {
+    /// Returns `true` if the program passed.
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.outcome {
+            Outcome::Passed => write!(f, "passed")?,
+            Outcome::Failed { pc } => write!(f, "failed at ${pc:04X}")?,
+            Outcome::Unimplemented { pc, opcode } => {
+                write!(f, "opcode ${opcode:02X} at ${pc:04X} is not implemented")?
+            }
+            Outcome::TimedOut => write!(f, "did not finish")?,
+        }
+        write!(
+            f,
+            " after {} instructions and {} cycles",
+            self.instructions, self.cycles
+        )
+    }
+}
+
+impl Suite {
+    /// Reads the binary from a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the file.
+    pub fn read(&self, dir: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(dir.join(self.file))
+    }
+
+    /// Creates a CPU with 64KB of RAM holding the image, ready to run it.
+    ///
+    /// # Returns
+    ///
+    /// The CPU, or `None` if the image does not fit above its load
+    /// address.
+    pub fn machine(&self, image: &[u8]) -> Option<CPU<Vec<u8>>> {
+        let load = self.load as usize;
+        if load + image.len() > 0x10000 {
+            return None;
+        }
+        let mut cpu = CPU::new(vec![0u8; 0x10000]);
+        cpu.bus[load..load + image.len()].copy_from_slice(image);
+        cpu.registers.pc = self.start;
+        Some(cpu)
+    }
+
+    /// Runs the program until it traps.
+    ///
+    /// If the suite has a feedback port, it is read after every instruction
+    /// to raise interrupts.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - The CPU, usually from `machine`.
+    /// * `limit` - The most instructions to execute.
+    pub fn run<B: Bus>(&self, cpu: &mut CPU<B>, limit: u64) -> Report {
+        let start_cycles = cpu.cycles();
+        let mut instructions = 0;
+        let mut nmi_line = false;
+        let outcome = loop {
+            if instructions == limit {
+                break Outcome::TimedOut;
+            }
+            let pc = cpu.registers.pc;
+            if let Some(opcode) = cpu.bus.peek(pc) {
+                if !cpu.is_implemented(opcode) {
+                    break Outcome::Unimplemented { pc, opcode };
+                }
+            }
+            cpu.step();
+            instructions += 1;
+            if cpu.registers.pc == pc {
+                let passed = match self.success {
+                    Success::Trap(addr) => pc == addr,
+                    Success::Zero(addr) => cpu.bus.peek(addr) == Some(0),
+                };
+                break if passed {
+                    Outcome::Passed
+                } else {
+                    Outcome::Failed { pc }
+                };
+            }
+            if let Some(port) = self.feedback {
+                let lines = cpu.bus.peek(port).unwrap_or(0);
+                // NMI is taken on an edge, IRQ for as long as it is held
+                if lines & 0x02 != 0 && !nmi_line {
+                    cpu.nmi();
+                } else if lines & 0x01 != 0 {
+                    cpu.irq();
+                }
+                nmi_line = lines & 0x02 != 0;
+            }
+        };
+        Report {
+            outcome,
+            instructions,
+            cycles: cpu.cycles() - start_cycles,
+        }
+    }
+}
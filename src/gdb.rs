@@ -0,0 +1,610 @@
+//! The `gdb` module implements the GDB remote serial protocol.
+//!
+//! A `GdbStub` lets gdb, lldb or any other client of the protocol debug a
+//! machine over TCP. It is a thin translation layer over a `Debugger`:
+//! breakpoints set by the client become debugger breakpoints, and `continue`
+//! and `step` become debugger resumes.
+//!
+//! ```no_run
+//! use lib6502::cpu::CPU;
+//! use lib6502::gdb::GdbStub;
+//! use std::net::TcpListener;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! let listener = TcpListener::bind("127.0.0.1:6502").unwrap();
+//! let (stream, _) = listener.accept().unwrap();
+//! GdbStub::new().serve(&mut cpu, stream).unwrap();
+//! ```
+//!
+//! The registers, in the order of the target description, are `a`, `x`,
+//! `y`, `sp`, `pc` (16 bits) and `p`. Memory reads use `Bus::peek`, so
+//! reading an I/O register from the client has no side effects, and fail
+//! for addresses that cannot be peeked; memory writes go through
+//! `Bus::write`. While the machine runs, the client can stop it by sending
+//! an interrupt (Ctrl-C in gdb).
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::debugger::{Debugger, Machine, Resume, Stop, WatchKind, WatchpointId};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// The instructions executed between checks for an interrupt from the client.
+const SLICE: u64 = 10_000;
+
+/// The largest packet the stub accepts, as advertised to the client.
+const PACKET_SIZE: usize = 0x1000;
+
+/// The byte a client sends to interrupt a running target.
+const INTERRUPT: u8 = 0x03;
+
+/// The target description, telling the client about the register set.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.lib6502.cpu">
+    <flags id="status_flags" size="1">
+      <field name="C" start="0" end="0"/>
+      <field name="Z" start="1" end="1"/>
+      <field name="I" start="2" end="2"/>
+      <field name="D" start="3" end="3"/>
+      <field name="B" start="4" end="4"/>
+      <field name="U" start="5" end="5"/>
+      <field name="V" start="6" end="6"/>
+      <field name="N" start="7" end="7"/>
+    </flags>
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="p" bitsize="8" type="status_flags"/>
+  </feature>
+</target>
+"#;
+
+/// The register numbers used by `p` and `P` packets.
+const REGISTERS: usize = 6;
+/// The register number of the PC, the only 16-bit register.
+const PC: usize = 4;
+
+/// What to do after handling a packet.
+enum Reply {
+    /// Send the packet and keep serving.
+    Packet(String),
+    /// Send the packet and end the session.
+    Last(String),
+    /// End the session without a reply.
+    Close,
+}
+
+/// A GDB remote serial protocol server for one machine.
+pub struct GdbStub {
+    debugger: Debugger,
+    /// Addresses with a software (`Z0`) breakpoint.
+    software: BTreeSet<u16>,
+    /// Addresses with a hardware (`Z1`) breakpoint.
+    hardware: BTreeSet<u16>,
+    /// The watchpoints set by the client, by type, address and length.
+    watchpoints: BTreeMap<(u8, u16, usize), WatchpointId>,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    /// Creates a new `GdbStub` with a fresh `Debugger`.
+    pub fn new() -> Self {
+        Self::with_debugger(Debugger::new())
+    }
+
+    /// Creates a new `GdbStub` around an existing debugger, for example one
+    /// set up to break on `BRK`.
+    pub fn with_debugger(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            software: BTreeSet::new(),
+            hardware: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            no_ack: false,
+        }
+    }
+
+    /// Returns the debugger.
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Returns the debugger mutably.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Serves one client until it detaches, kills the target or disconnects.
+    ///
+    /// Killing the target only ends the session; the machine is left as it
+    /// is. Breakpoints and watchpoints set by the client stay in the
+    /// debugger, so a later session sees them too.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to debug.
+    /// * `stream` - The connection to the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error on the connection.
+    pub fn serve<M: Machine>(&mut self, machine: &mut M, stream: TcpStream) -> io::Result<()> {
+        self.no_ack = false;
+        // Packets are small and each waits for an answer
+        stream.set_nodelay(true)?;
+        let mut connection = Connection::new(stream);
+        while let Some(packet) = connection.read_packet(self.no_ack)? {
+            match self.handle(machine, &mut connection, &packet)? {
+                Reply::Packet(reply) => {
+                    connection.write_packet(reply.as_bytes(), self.no_ack)?;
+                    // The reply to this one is still acknowledged
+                    if packet == b"QStartNoAckMode" {
+                        self.no_ack = true;
+                    }
+                }
+                Reply::Last(reply) => {
+                    return connection.write_packet(reply.as_bytes(), self.no_ack)
+                }
+                Reply::Close => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles one packet.
+    fn handle<M: Machine>(
+        &mut self,
+        machine: &mut M,
+        connection: &mut Connection,
+        packet: &[u8],
+    ) -> io::Result<Reply> {
+        let text = String::from_utf8_lossy(packet);
+        let cpu = machine.cpu_mut();
+        let reply = match packet.first() {
+            Some(b'?') => "S05".to_string(),
+            Some(b'g') => read_registers(cpu),
+            Some(b'G') => ok_or_error(write_registers(cpu, &text[1..])),
+            Some(b'p') => parse_hex(&text[1..])
+                .and_then(|reg| read_register(cpu, reg as usize))
+                .unwrap_or_default(),
+            Some(b'P') => ok_or_error(text[1..].split_once('=').and_then(|(reg, value)| {
+                write_register(cpu, parse_hex(reg)? as usize, &decode_hex(value)?)
+            })),
+            Some(b'm') => read_memory(cpu, &text[1..]).unwrap_or_else(|| "E0E".to_string()),
+            Some(b'M') => ok_or_error(text[1..].split_once(':').and_then(|(range, data)| {
+                write_memory(cpu, range, &decode_hex(data)?, PACKET_SIZE / 2)
+            })),
+            Some(b'X') => {
+                let colon = packet.iter().position(|&byte| byte == b':');
+                ok_or_error(colon.and_then(|colon| {
+                    // The lossy text is no guide to byte offsets in the packet
+                    let range = std::str::from_utf8(&packet[1..colon]).ok()?;
+                    write_memory(cpu, range, &packet[colon + 1..], PACKET_SIZE)
+                }))
+            }
+            Some(b'c' | b's' | b'C' | b'S') => {
+                let step = matches!(packet[0], b's' | b'S');
+                // The signal of `C` and `S` is ignored; a resume address may
+                // follow `c` and `s`
+                if let (b'c' | b's', Some(addr)) = (packet[0], parse_hex(&text[1..])) {
+                    cpu.registers.pc = addr as u16;
+                }
+                return self.run(machine, connection, step);
+            }
+            Some(b'Z' | b'z') => {
+                let insert = packet[0] == b'Z';
+                self.set_point(&text[1..], insert)
+                    .unwrap_or_else(|| "E16".to_string())
+            }
+            Some(b'H' | b'T') => "OK".to_string(),
+            Some(b'D') => return Ok(Reply::Last("OK".to_string())),
+            Some(b'k') => return Ok(Reply::Close),
+            _ => match text.as_ref() {
+                "vKill" | "vKill;1" => return Ok(Reply::Last("OK".to_string())),
+                "vCont?" => "vCont;c;C;s;S".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                "qAttached" => "1".to_string(),
+                "qSymbol::" | "QStartNoAckMode" => "OK".to_string(),
+                text if text.starts_with("qSupported") => format!(
+                    "PacketSize={PACKET_SIZE:x};QStartNoAckMode+;qXfer:features:read+;\
+                     swbreak+;hwbreak+;vContSupported+"
+                ),
+                text if text.starts_with("qXfer:features:read:") => {
+                    read_target_xml(&text["qXfer:features:read:".len()..])
+                        .unwrap_or_else(|| "E00".to_string())
+                }
+                text if text.starts_with("vCont;") => {
+                    // All actions apply to the only thread, so the first
+                    // one decides
+                    let step = text[6..].starts_with(['s', 'S']);
+                    return self.run(machine, connection, step);
+                }
+                _ => String::new(),
+            },
+        };
+        Ok(Reply::Packet(reply))
+    }
+
+    /// Steps or continues the machine and returns the stop reply. Closes the
+    /// session if the client disconnected while the machine was running.
+    fn run<M: Machine>(
+        &mut self,
+        machine: &mut M,
+        connection: &mut Connection,
+        step: bool,
+    ) -> io::Result<Reply> {
+        let stop = if step {
+            Some(self.debugger.step_into(machine))
+        } else {
+            // One resume throughout, so that a breakpoint is not skipped
+            // where a slice happens to end
+            let mut polled = Ok(Some(false));
+            let stop = self
+                .debugger
+                .resume_polling(machine, Resume::Continue, SLICE, || {
+                    polled = connection.poll_interrupt();
+                    matches!(polled, Ok(Some(false)))
+                });
+            match (stop, polled?) {
+                (_, None) => return Ok(Reply::Close),
+                (Stop::Limit, _) => None,
+                (stop, _) => Some(stop),
+            }
+        };
+        let reply = match stop {
+            None => "S02".to_string(),
+            Some(stop) => self.stop_reply(stop),
+        };
+        Ok(Reply::Packet(reply))
+    }
+
+    /// Returns the stop reply packet for `stop`.
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Breakpoint { pc } | Stop::TemporaryBreakpoint { pc }
+                if self.hardware.contains(&pc) && !self.software.contains(&pc) =>
+            {
+                "T05hwbreak:;".to_string()
+            }
+            Stop::Breakpoint { .. } | Stop::TemporaryBreakpoint { .. } => {
+                "T05swbreak:;".to_string()
+            }
+            Stop::Watchpoint { id, access, .. } => {
+                let kind = self
+                    .watchpoints
+                    .iter()
+                    .find(|(_, &watchpoint)| watchpoint == id)
+                    .map_or("awatch", |(&(kind, _, _), _)| match kind {
+                        2 => "watch",
+                        3 => "rwatch",
+                        _ => "awatch",
+                    });
+                format!("T05{kind}:{:x};", access.addr)
+            }
+            Stop::Undocumented { .. } => "S04".to_string(),
+            _ => "S05".to_string(),
+        }
+    }
+
+    /// Inserts or removes a breakpoint or watchpoint from the arguments of a
+    /// `Z` or `z` packet. Returns `None` for malformed packets.
+    fn set_point(&mut self, args: &str, insert: bool) -> Option<String> {
+        let mut fields = args.split([',', ';']);
+        let kind = fields.next()?.parse::<u8>().ok()?;
+        let addr = parse_hex(fields.next()?)?;
+        let len = parse_hex(fields.next()?)?;
+        if addr > 0xFFFF || len > 0x10000 {
+            return None;
+        }
+        let addr = addr as u16;
+        match kind {
+            0 | 1 => {
+                let (set, other) = if kind == 0 {
+                    (&mut self.software, &self.hardware)
+                } else {
+                    (&mut self.hardware, &self.software)
+                };
+                if insert {
+                    set.insert(addr);
+                    if self.debugger.breakpoint(addr).is_none() {
+                        self.debugger.set_breakpoint(addr);
+                    }
+                } else if set.remove(&addr) && !other.contains(&addr) {
+                    self.debugger.remove_breakpoint(addr);
+                }
+            }
+            2..=4 => {
+                let key = (kind, addr, len as usize);
+                if insert {
+                    let end = (addr as u32 + len.max(1) - 1).min(0xFFFF) as u16;
+                    let watch = match kind {
+                        2 => WatchKind::Write,
+                        3 => WatchKind::Read,
+                        _ => WatchKind::Access,
+                    };
+                    let id = self.debugger.add_watchpoint(addr..=end, watch);
+                    if let Some(old) = self.watchpoints.insert(key, id) {
+                        self.debugger.remove_watchpoint(old);
+                    }
+                } else if let Some(id) = self.watchpoints.remove(&key) {
+                    self.debugger.remove_watchpoint(id);
+                }
+            }
+            _ => return Some(String::new()),
+        }
+        Some("OK".to_string())
+    }
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A connection to a client, with packet framing.
+struct Connection {
+    stream: TcpStream,
+    /// The last packet sent, for retransmission when the client asks.
+    last: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            last: Vec::new(),
+        }
+    }
+
+    /// Reads one byte, or `None` at the end of the stream.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Reads the next packet, acknowledging it unless acks are off, and
+    /// returns its unescaped contents. Returns `None` at the end of the
+    /// stream.
+    fn read_packet(&mut self, no_ack: bool) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => {
+                    let last = self.last.clone();
+                    self.stream.write_all(&last)?;
+                    continue;
+                }
+                // Acks and interrupts while stopped need no answer
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => {
+                        sum = sum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(sum);
+            if !no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid || no_ack {
+                return Ok(Some(unescape(&data)));
+            }
+        }
+    }
+
+    /// Sends a packet, waiting for the client's ack unless acks are off.
+    fn write_packet(&mut self, data: &[u8], no_ack: bool) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        for &byte in data {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                packet.extend([b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let sum = packet[1..]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        packet.extend(format!("#{sum:02x}").bytes());
+        self.stream.write_all(&packet)?;
+        self.last = packet;
+        if no_ack {
+            return Ok(());
+        }
+        loop {
+            match self.read_byte()? {
+                None | Some(b'+') => return Ok(()),
+                Some(b'-') => {
+                    let last = self.last.clone();
+                    self.stream.write_all(&last)?;
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Checks, without blocking, whether the client sent an interrupt.
+    /// Returns `None` if the client disconnected.
+    fn poll_interrupt(&mut self) -> io::Result<Option<bool>> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0] == INTERRUPT)),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(Some(false)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Undoes the `}` escaping of binary data.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => out.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            byte => out.push(byte),
+        }
+    }
+    out
+}
+
+/// Parses a hex number.
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// Decodes pairs of hex digits into bytes.
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    // An odd digit out fails to parse as a pair
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encodes bytes as pairs of hex digits.
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Replies `OK` on success and with an error otherwise.
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => "OK".to_string(),
+        None => "E16".to_string(),
+    }
+}
+
+/// Returns the bytes of register `reg`, little-endian.
+fn register_bytes<B: Bus>(cpu: &CPU<B>, reg: usize) -> Option<Vec<u8>> {
+    let registers = &cpu.registers;
+    Some(match reg {
+        0 => vec![registers.a],
+        1 => vec![registers.x],
+        2 => vec![registers.y],
+        3 => vec![registers.sp],
+        PC => registers.pc.to_le_bytes().to_vec(),
+        5 => vec![registers.status.to_byte()],
+        _ => return None,
+    })
+}
+
+/// Returns the reply to `g`: every register, in order.
+fn read_registers<B: Bus>(cpu: &CPU<B>) -> String {
+    (0..REGISTERS)
+        .filter_map(|reg| register_bytes(cpu, reg))
+        .map(|bytes| encode_hex(&bytes))
+        .collect()
+}
+
+/// Returns the reply to `p`.
+fn read_register<B: Bus>(cpu: &CPU<B>, reg: usize) -> Option<String> {
+    register_bytes(cpu, reg).map(|bytes| encode_hex(&bytes))
+}
+
+/// Sets register `reg` from its little-endian bytes.
+fn write_register<B: Bus>(cpu: &mut CPU<B>, reg: usize, bytes: &[u8]) -> Option<()> {
+    let registers = &mut cpu.registers;
+    match (reg, bytes) {
+        (0, [a]) => registers.a = *a,
+        (1, [x]) => registers.x = *x,
+        (2, [y]) => registers.y = *y,
+        (3, [sp]) => registers.sp = *sp,
+        (PC, [lo, hi]) => registers.pc = u16::from_le_bytes([*lo, *hi]),
+        (5, [p]) => registers.status.from_byte(*p),
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Handles `G`: sets every register from the hex of the `g` layout.
+fn write_registers<B: Bus>(cpu: &mut CPU<B>, text: &str) -> Option<()> {
+    let bytes = decode_hex(text)?;
+    if bytes.len() != 7 {
+        return None;
+    }
+    let mut offset = 0;
+    for reg in 0..REGISTERS {
+        let len = if reg == PC { 2 } else { 1 };
+        write_register(cpu, reg, &bytes[offset..offset + len])?;
+        offset += len;
+    }
+    Some(())
+}
+
+/// Parses the `addr,length` of a memory packet, allowing at most `limit`
+/// bytes.
+fn parse_range(text: &str, limit: usize) -> Option<(u16, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    let (addr, len) = (parse_hex(addr)?, parse_hex(len)? as usize);
+    if addr > 0xFFFF || len > limit {
+        return None;
+    }
+    Some((addr as u16, len))
+}
+
+/// Handles `m`, peeking each byte. Returns `None` if any byte cannot be read.
+fn read_memory<B: Bus>(cpu: &CPU<B>, text: &str) -> Option<String> {
+    // Each byte is sent as two hex digits
+    let (addr, len) = parse_range(text, PACKET_SIZE / 2)?;
+    let bytes = (0..len)
+        .map(|offset| cpu.bus.peek(addr.wrapping_add(offset as u16)))
+        .collect::<Option<Vec<u8>>>()?;
+    Some(encode_hex(&bytes))
+}
+
+/// Handles `M` and `X`, writing `data` through the bus. `limit` is the
+/// most bytes the packet can carry: `M` sends each as two hex digits, `X`
+/// as one binary byte.
+fn write_memory<B: Bus>(cpu: &mut CPU<B>, range: &str, data: &[u8], limit: usize) -> Option<()> {
+    let (addr, len) = parse_range(range, limit)?;
+    if data.len() != len {
+        return None;
+    }
+    for (offset, &byte) in data.iter().enumerate() {
+        cpu.bus.write(addr.wrapping_add(offset as u16), byte);
+    }
+    Some(())
+}
+
+/// Handles `qXfer:features:read:annex:offset,length`.
+fn read_target_xml(args: &str) -> Option<String> {
+    let (annex, range) = args.split_once(':')?;
+    if annex != "target.xml" {
+        return None;
+    }
+    let (offset, len) = range.split_once(',')?;
+    let (offset, len) = (parse_hex(offset)? as usize, parse_hex(len)? as usize);
+    let start = offset.min(TARGET_XML.len());
+    let end = offset.saturating_add(len).min(TARGET_XML.len());
+    let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+    Some(format!("{more}{}", &TARGET_XML[start..end]))
+}
@@ -0,0 +1,264 @@
+//! The `idle` module detects side-effect-free polling loops and skips over them.
+//!
+//! Firmware often waits for a device in a loop such as
+//!
+//! ```text
+//! wait:  LDA $6000
+//!        BEQ wait
+//! ```
+//!
+//! Once such a loop has gone round twice with no writes, reading the same
+//! values from the same addresses and coming back to its head with exactly
+//! the same registers, every further iteration is assumed to be identical
+//! until a device changes a value the loop reads. An `IdleDetector` spots
+//! this and, when told the cycle of the next device event, advances the
+//! cycle count by a whole number of iterations in one go. The CPU stays at
+//! the loop head, so the cycle count is exactly what stepping would give.
+//!
+//! Anything that changes the machine from outside the CPU, such as a device
+//! event or the host writing to the bus, must be followed by a call to
+//! `IdleDetector::reset`. The `Scheduler` does this after every event.
+//!
+//! Skipping assumes the polled reads have no side effects and that their
+//! values only change through device events. A loop that polls a register
+//! which changes on its own, such as a free-running counter, is not
+//! considered idle while the values it reads keep changing, even if it masks
+//! them off. A register that changes more slowly than the loop goes round
+//! can still read the same twice in a row, and the change would then be
+//! skipped over, so such registers must be driven by scheduled events.
+
+use crate::bus::{AccessKind, Bus};
+use crate::cpu::CPU;
+use crate::run::{RunResult, StopReason};
+
+/// A detected idle loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdleLoop {
+    /// The address of the first instruction of the loop.
+    pub head: u16,
+    /// The number of cycles one iteration takes.
+    pub period: u64,
+    /// The number of instructions in one iteration.
+    pub instructions: usize,
+    /// The addresses read by one iteration, other than instruction fetches,
+    /// in the order they were read.
+    pub polled: Vec<u16>,
+}
+
+/// The register values compared between visits to a loop head.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HeadState {
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    status: u8,
+}
+
+impl HeadState {
+    fn of<B: Bus>(cpu: &CPU<B>) -> Self {
+        Self {
+            a: cpu.registers.a,
+            x: cpu.registers.x,
+            y: cpu.registers.y,
+            sp: cpu.registers.sp,
+            status: cpu.registers.status.to_byte(),
+        }
+    }
+}
+
+/// Watches the instructions a CPU executes and recognises idle loops.
+pub struct IdleDetector {
+    /// The longest loop, in instructions, that is considered.
+    max_instructions: usize,
+    /// The current candidate loop head, if any.
+    head: Option<u16>,
+    /// The registers and cycle count at the last visit to `head`.
+    last_visit: Option<(HeadState, u64)>,
+    /// Instructions executed since the last visit to `head`.
+    instructions: usize,
+    /// Whether anything was written since the last visit to `head`.
+    wrote: bool,
+    /// Data addresses read since the last visit to `head`, and the values read.
+    polled: Vec<(u16, u8)>,
+    /// The data reads of the previous iteration.
+    last_polled: Vec<(u16, u8)>,
+    /// Where the PC and cycle count should be if nothing happened between steps.
+    expected: Option<(u16, u64)>,
+    /// The loop the CPU is currently idling in, if any.
+    idle: Option<IdleLoop>,
+}
+
+impl IdleDetector {
+    /// Creates a new `IdleDetector` that considers loops of up to 16 instructions.
+    pub fn new() -> Self {
+        Self::with_max_instructions(16)
+    }
+
+    /// Creates a new `IdleDetector` that considers loops of up to `max_instructions` instructions.
+    pub fn with_max_instructions(max_instructions: usize) -> Self {
+        Self {
+            max_instructions,
+            head: None,
+            last_visit: None,
+            instructions: 0,
+            wrote: false,
+            polled: Vec::new(),
+            last_polled: Vec::new(),
+            expected: None,
+            idle: None,
+        }
+    }
+
+    /// Returns the loop the CPU is idling in, if one has been detected.
+    pub fn idle_loop(&self) -> Option<&IdleLoop> {
+        self.idle.as_ref()
+    }
+
+    /// Forgets everything observed so far.
+    pub fn reset(&mut self) {
+        self.head = None;
+        self.last_visit = None;
+        self.expected = None;
+        self.idle = None;
+        self.last_polled.clear();
+        self.start_iteration();
+    }
+
+    /// Executes one instruction and updates the detector.
+    ///
+    /// Access recording is enabled on the CPU, since the detector needs to
+    /// see every read and write.
+    pub fn step<B: Bus>(&mut self, cpu: &mut CPU<B>) {
+        if !cpu.is_recording_accesses() {
+            cpu.set_access_recording(true);
+        }
+        // Anything that moved the CPU between steps, such as an interrupt or
+        // the host changing registers, invalidates what has been seen
+        if self.expected != Some((cpu.registers.pc, cpu.cycles())) {
+            self.reset();
+        }
+
+        let pc = cpu.registers.pc;
+        cpu.step();
+        self.observe(cpu, pc);
+        self.expected = Some((cpu.registers.pc, cpu.cycles()));
+    }
+
+    /// Skips whole iterations of the idle loop, as long as the CPU stays at
+    /// or before `until_cycle`. Returns the number of cycles skipped.
+    ///
+    /// Nothing is skipped unless an idle loop has been detected and the CPU
+    /// is at its head.
+    pub fn fast_forward<B: Bus>(&mut self, cpu: &mut CPU<B>, until_cycle: u64) -> u64 {
+        let idle = match &self.idle {
+            Some(idle) if idle.head == cpu.registers.pc => idle,
+            _ => return 0,
+        };
+        if self.expected != Some((cpu.registers.pc, cpu.cycles())) {
+            return 0;
+        }
+        let iterations = until_cycle.saturating_sub(cpu.cycles()) / idle.period;
+        let skipped = iterations * idle.period;
+        cpu.advance_cycles(skipped);
+        if let Some((state, cycles)) = self.last_visit {
+            self.last_visit = Some((state, cycles + skipped));
+        }
+        self.expected = Some((cpu.registers.pc, cpu.cycles()));
+        skipped
+    }
+
+    /// Runs until the CPU's cycle count reaches `cycle`, skipping idle
+    /// iterations on the way.
+    ///
+    /// This is the way to run a machine whose next device event is known to
+    /// be at `cycle`: the result is the same as stepping, only faster.
+    pub fn run_until<B: Bus>(&mut self, cpu: &mut CPU<B>, cycle: u64) -> RunResult {
+        let start = cpu.cycles();
+        let mut instructions = 0;
+        while cpu.cycles() < cycle {
+            if self.fast_forward(cpu, cycle) == 0 || cpu.cycles() < cycle {
+                self.step(cpu);
+                instructions += 1;
+            }
+        }
+        RunResult {
+            reason: StopReason::CyclesElapsed,
+            cycles: cpu.cycles() - start,
+            instructions,
+        }
+    }
+
+    /// Updates the loop tracking after an instruction that started at `pc`.
+    fn observe<B: Bus>(&mut self, cpu: &CPU<B>, pc: u16) {
+        let next = cpu.registers.pc;
+        // Instruction fetches come from around the PC; anything else is data
+        let fetch_end = pc.wrapping_add(3);
+        for access in cpu.accesses() {
+            match access.kind {
+                AccessKind::Write => self.wrote = true,
+                AccessKind::Read => {
+                    let is_fetch = if pc <= fetch_end {
+                        (pc..fetch_end).contains(&access.addr)
+                    } else {
+                        access.addr >= pc || access.addr < fetch_end
+                    };
+                    if !is_fetch {
+                        self.polled.push((access.addr, access.data));
+                    }
+                }
+            }
+        }
+        self.instructions += 1;
+
+        if Some(next) == self.head {
+            self.visit_head(cpu);
+        } else if next <= pc {
+            // A backward jump or branch starts a new candidate loop
+            self.head = Some(next);
+            self.last_visit = Some((HeadState::of(cpu), cpu.cycles()));
+            self.idle = None;
+            self.last_polled.clear();
+            self.start_iteration();
+        } else if self.instructions > self.max_instructions {
+            self.head = None;
+            self.last_visit = None;
+            self.idle = None;
+        }
+    }
+
+    /// Handles a return to the loop head at the end of an iteration.
+    fn visit_head<B: Bus>(&mut self, cpu: &CPU<B>) {
+        let state = HeadState::of(cpu);
+        let cycles = cpu.cycles();
+        let repeated = !self.wrote
+            && self.instructions <= self.max_instructions
+            && self.polled == self.last_polled
+            && matches!(self.last_visit, Some((last, _)) if last == state);
+        self.idle = match (repeated, self.last_visit, self.head) {
+            (true, Some((_, last_cycles)), Some(head)) => Some(IdleLoop {
+                head,
+                period: cycles - last_cycles,
+                instructions: self.instructions,
+                polled: self.polled.iter().map(|&(addr, _)| addr).collect(),
+            }),
+            _ => None,
+        };
+        self.last_visit = Some((state, cycles));
+        self.last_polled = std::mem::take(&mut self.polled);
+        self.start_iteration();
+    }
+
+    /// Clears the per-iteration tracking.
+    fn start_iteration(&mut self) {
+        self.instructions = 0;
+        self.wrote = false;
+        self.polled.clear();
+    }
+}
+
+impl Default for IdleDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
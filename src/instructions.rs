@@ -18,64 +18,57 @@ pub type Instruction<B> = fn(&mut CPU<B>, u16) -> u8;
 /// accumulator, taking into account the carry flag.
 ///
 /// If the decimal mode flag is set, the instruction adds the values as BCD
-/// values. Otherwise it adds the values as binary values.
+/// values. Otherwise it adds the values as binary values. In decimal mode the
+/// flags behave as on the NMOS 6502, including for values that are not valid
+/// BCD, and no cycle is added.
 ///
 /// # Returns
 ///
 /// The number of additional cycles that the instruction adds to the instruction's
-/// base cycle count.
+/// base cycle count (always 0).
 pub fn adc<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
+    add_with_carry(cpu, value);
+    0
+}
+
+/// Adds `value` and the carry flag to the accumulator, as ADC does.
+fn add_with_carry<B: Bus>(cpu: &mut CPU<B>, value: u8) {
     let a = cpu.registers.a;
     let carry_in = if cpu.registers.status.carry { 1 } else { 0 };
-    let mut additional_cycles = 0;
+
+    // Add the values as binary values
+    let sum = (a as u16) + (value as u16) + (carry_in as u16);
+    let result = sum as u8;
 
     if cpu.registers.status.decimal_mode {
-        // Add the values as BCD values
-        let mut al = (a & 0x0F) + (value & 0x0F) + carry_in;
-        let mut ah = (a >> 4) + (value >> 4);
+        // The NMOS 6502 sets Z from the binary sum, and N and V from the
+        // sum after only the low digit has been adjusted
+        cpu.registers.status.zero = result == 0;
 
-        // If the lower nibble is greater than 9, add 6 to carry the value
-        // to the next digit. This is done because the range of the lower nibble
-        // is 0-9, not 0-F.
+        // If the low digit is greater than 9, add 6 to carry it into the
+        // high digit
+        let mut al = (a & 0x0F) + (value & 0x0F) + carry_in;
         if al > 9 {
-            al += 6;
-        }
-
-        // If the lower nibble is greater than 0xF, add 1 to the higher nibble
-        // and mask the lower nibble to 0-9.
-        if al > 0x0F {
-            ah += 1;
-            al &= 0x0F;
+            al = ((al + 6) & 0x0F) + 0x10;
         }
+        let mut bcd = (a & 0xF0) as u16 + (value & 0xF0) as u16 + al as u16;
+        cpu.registers.status.negative = (bcd & 0x80) != 0;
+        cpu.registers.status.overflow = (!(a ^ value) as u16 & (a as u16 ^ bcd) & 0x80) != 0;
 
-        // If the higher nibble is greater than 9, add 6 to carry the value
-        // to the next digit. This is done because the range of the higher nibble
-        // is 0-9, not 0-F.
-        if ah > 9 {
-            ah += 6;
+        // Then the same for the high digit, which carries out of the byte
+        if bcd >= 0xA0 {
+            bcd += 0x60;
         }
-
-        let result = ((ah << 4) | (al & 0x0F)) as u8;
-        cpu.registers.status.carry = ah > 0x0F;
-        cpu.registers.status.zero = result == 0;
-        cpu.registers.status.negative = (result & 0x80) != 0;
-        // Note: The overflow flag in decimal mode is undefined on the 6502 and can be ignored
-        cpu.registers.a = result;
-        additional_cycles = 1;
+        cpu.registers.status.carry = bcd > 0xFF;
+        cpu.registers.a = bcd as u8;
     } else {
-        // Add the values as binary values
-        let sum = (a as u16) + (value as u16) + (carry_in as u16);
-        let result = sum as u8;
-
         cpu.registers.status.carry = sum > 0xFF;
         cpu.registers.status.zero = result == 0;
         cpu.registers.status.negative = (result & 0x80) != 0;
         cpu.registers.status.overflow = ((!(a ^ value) & (a ^ result)) & 0x80) != 0;
         cpu.registers.a = result;
     }
-
-    additional_cycles
 }
 
 /// AND - Logical AND
@@ -88,7 +81,7 @@ pub fn adc<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles that the instruction adds to the
 /// instruction's base cycle count.
 pub fn and<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
     cpu.registers.a &= value;
     cpu.update_zero_and_negative_flags(cpu.registers.a);
     0
@@ -108,11 +101,13 @@ pub fn and<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// instruction's base cycle count.
 pub fn asl<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
+    // The 6502 writes the value back unchanged while it modifies it
+    cpu.write(addr, value);
     // Shift the value left by one bit
     let result = value << 1;
     // Write the result back to the specified address
-    cpu.bus.write(addr, result);
+    cpu.write(addr, result);
     // Set the carry flag if the high bit of the original value was set
     cpu.registers.status.carry = (value & 0x80) != 0;
     // Update the zero and negative flags based on the result
@@ -216,7 +211,7 @@ pub fn beq<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles that the instruction adds to the
 /// instruction's base cycle count.
 pub fn bit<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
     let result = cpu.registers.a & value;
     cpu.registers.status.zero = result == 0;
     cpu.registers.status.overflow = (value & 0x40) != 0;
@@ -318,7 +313,7 @@ pub fn bpl<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles that the instruction adds to the instruction's
 /// base cycle count (always 0 for BRK).
 pub fn brk<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
-    // Increment the program counter
+    // Skip the padding byte, which the implied mode has already read
     cpu.registers.pc = cpu.registers.pc.wrapping_add(1);
     
     // Push the program counter onto the stack (high byte first)
@@ -335,8 +330,8 @@ pub fn brk<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
     cpu.registers.status.interrupt_disable = true;
     
     // Jump to the interrupt vector address
-    let lo = cpu.bus.read(0xFFFE) as u16;
-    let hi = cpu.bus.read(0xFFFF) as u16;
+    let lo = cpu.read(0xFFFE) as u16;
+    let hi = cpu.read(0xFFFF) as u16;
     cpu.registers.pc = (hi << 8) | lo;
     
     // Return 0 additional cycles
@@ -465,7 +460,7 @@ pub fn clv<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (0).
 pub fn cmp<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the given address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
     // Calculate the result of the comparison
     let result = cpu.registers.a.wrapping_sub(m);
     // Set the carry flag if a > m
@@ -494,7 +489,7 @@ pub fn cmp<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (0).
 pub fn cpx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the given address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
     // Calculate the result of the comparison
     let result = cpu.registers.x.wrapping_sub(m);
     // Set the carry flag if x >= m
@@ -523,7 +518,7 @@ pub fn cpx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (0).
 pub fn cpy<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the given address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
     // Calculate the result of the comparison
     let result = cpu.registers.y.wrapping_sub(m);
     // Set the carry flag if y >= m
@@ -550,11 +545,13 @@ pub fn cpy<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (0).
 pub fn dec<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the given address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
+    // The 6502 writes the value back unchanged while it modifies it
+    cpu.write(addr, m);
     // Decrement the value
     let result = m.wrapping_sub(1);
     // Write the result back to the given address
-    cpu.bus.write(addr, result);
+    cpu.write(addr, result);
     // Update the zero and negative flags
     cpu.update_zero_and_negative_flags(result);
     // Return 0 additional cycles
@@ -621,7 +618,7 @@ pub fn dey<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn eor<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
     // Perform XOR operation with the accumulator
     let result = cpu.registers.a ^ m;
     // Store the result back into the accumulator
@@ -646,11 +643,13 @@ pub fn eor<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn inc<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the given address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
+    // The 6502 writes the value back unchanged while it modifies it
+    cpu.write(addr, m);
     // Increment the value
     let result = m.wrapping_add(1);
     // Write the result back to the given address
-    cpu.bus.write(addr, result);
+    cpu.write(addr, result);
     // Update the zero and negative flags based on the result
     cpu.update_zero_and_negative_flags(result);
     // Return 0 additional cycles
@@ -721,19 +720,27 @@ pub fn jmp<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The JSR instruction pushes the current program counter onto the stack and
 /// sets the program counter to the given address.
 ///
+/// `addr` is the address of the target's low byte. As on the 6502, the high
+/// byte is read only after the return address has been pushed, so the pushed
+/// address is that of the high byte.
+///
 /// # Returns
 ///
 /// The number of additional cycles that the instruction adds to the instruction's
 /// base cycle count (always 0).
 pub fn jsr<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let target_lo = cpu.read(addr) as u16;
+    // The 6502 reads the stack while it waits for the push
+    cpu.read(0x0100 + cpu.registers.sp as u16);
     // Push the current program counter onto the stack
-    let pc = cpu.registers.pc.wrapping_sub(1);
+    let pc = cpu.registers.pc;
     let hi = (pc >> 8) as u8;
     let lo = pc as u8;
     cpu.stack_push(hi);
     cpu.stack_push(lo);
+    let target_hi = cpu.read(pc) as u16;
     // Set the program counter to the given address
-    cpu.registers.pc = addr;
+    cpu.registers.pc = (target_hi << 8) | target_lo;
     // Return 0 additional cycles
     0
 }
@@ -753,7 +760,7 @@ pub fn jsr<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn lda<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
     // Load the value into the accumulator
     cpu.registers.a = value;
     // Update the zero and negative flags based on the accumulator's value
@@ -777,7 +784,7 @@ pub fn lda<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn ldx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
     // Load the value into the X register
     cpu.registers.x = value;
     // Update the zero and negative flags based on the X register's value
@@ -801,7 +808,7 @@ pub fn ldx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn ldy<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let value = cpu.bus.read(addr);
+    let value = cpu.read(addr);
     // Load the value into the Y register
     cpu.registers.y = value;
     // Update the zero and negative flags based on the Y register's value
@@ -861,13 +868,15 @@ pub fn lsr_accumulator<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn lsr_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
+    // The 6502 writes the value back unchanged while it modifies it
+    cpu.write(addr, m);
     // Shift the value to the right by one bit
     let result = m >> 1;
     // Set the carry flag if the least significant bit of the original value was set
     cpu.registers.status.carry = (m & 0x01) != 0;
     // Write the result back to the specified address
-    cpu.bus.write(addr, result);
+    cpu.write(addr, result);
     // Update the zero and negative flags based on the result
     cpu.update_zero_and_negative_flags(result);
     // Return 0 additional cycles
@@ -902,7 +911,7 @@ pub fn nop<B: Bus>(_cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn ora<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
     // Perform OR operation with the accumulator
     cpu.registers.a |= m;
     // Update the zero and negative flags based on the result
@@ -911,27 +920,75 @@ pub fn ora<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     0
 }
 
-pub fn pha<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
-    cpu.unimplemented_instruction(value);
+/// PHA - Push Accumulator
+///
+/// This instruction pushes a copy of the accumulator onto the stack.
+///
+/// # Returns
+///
+/// The number of additional cycles that the instruction adds to the
+/// instruction's base cycle count (always 0).
+pub fn pha<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
+    // Push the accumulator onto the stack
+    cpu.stack_push(cpu.registers.a);
+    // Return 0 additional cycles
     0
 }
 
-pub fn php<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
-    cpu.unimplemented_instruction(value);
+/// PHP - Push Processor Status
+///
+/// This instruction pushes a copy of the status flags onto the stack. The B
+/// and U flags are always set in the pushed copy.
+///
+/// # Returns
+///
+/// The number of additional cycles that the instruction adds to the
+/// instruction's base cycle count (always 0).
+pub fn php<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
+    // Push the status register with the B and U flags set
+    let status = cpu.registers.status.to_byte() | 0x30;
+    cpu.stack_push(status);
+    // Return 0 additional cycles
     0
 }
 
-pub fn pla<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
-    cpu.unimplemented_instruction(value);
+/// PLA - Pull Accumulator
+///
+/// This instruction pulls a byte from the stack into the accumulator. The
+/// zero and negative flags are updated based on the pulled value.
+///
+/// # Returns
+///
+/// The number of additional cycles that the instruction adds to the
+/// instruction's base cycle count (always 0).
+pub fn pla<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
+    // The 6502 reads the stack before incrementing the stack pointer
+    cpu.read(0x0100 + cpu.registers.sp as u16);
+    // Pop the value from the stack into the accumulator
+    cpu.registers.a = cpu.stack_pop();
+    // Update the zero and negative flags based on the accumulator's value
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    // Return 0 additional cycles
     0
 }
 
-pub fn plp<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let value = cpu.bus.read(addr);
-    cpu.unimplemented_instruction(value);
+/// PLP - Pull Processor Status
+///
+/// This instruction pulls the status flags from the stack. The B flag only
+/// exists on the stack, so it is cleared, and the U flag is always set.
+///
+/// # Returns
+///
+/// The number of additional cycles that the instruction adds to the
+/// instruction's base cycle count (always 0).
+pub fn plp<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
+    // The 6502 reads the stack before incrementing the stack pointer
+    cpu.read(0x0100 + cpu.registers.sp as u16);
+    // Pop the status register from the stack
+    let status = cpu.stack_pop();
+    // Restore the flags, ignoring B and forcing U
+    cpu.registers.status.from_byte((status & !0x10) | 0x20);
+    // Return 0 additional cycles
     0
 }
 
@@ -980,7 +1037,9 @@ pub fn rol_accumulator<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// instruction's base cycle count.
 pub fn rol_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the memory value
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
+    // The 6502 writes the value back unchanged while it modifies it
+    cpu.write(addr, m);
 
     // Save the current carry flag
     let old_carry = if cpu.registers.status.carry { 1 } else { 0 };
@@ -993,7 +1052,7 @@ pub fn rol_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     let result = (m << 1) | old_carry;
 
     // Write the result back to the memory
-    cpu.bus.write(addr, result);
+    cpu.write(addr, result);
 
     // Update the zero and negative flags
     cpu.update_zero_and_negative_flags(result);
@@ -1047,7 +1106,9 @@ pub fn ror_accumulator<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles incurred by this instruction (always 0).
 pub fn ror_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Read the value from the specified address
-    let m = cpu.bus.read(addr);
+    let m = cpu.read(addr);
+    // The 6502 writes the value back unchanged while it modifies it
+    cpu.write(addr, m);
     // Save the current carry flag as a bit value
     let old_carry = if cpu.registers.status.carry { 1 } else { 0 };
     // Set the carry flag to the value of the least significant bit of the original value
@@ -1055,7 +1116,7 @@ pub fn ror_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Rotate the value one position to the right, inserting the old carry as the new high bit
     let result = (m >> 1) | (old_carry << 7);
     // Write the result back to the specified address
-    cpu.bus.write(addr, result);
+    cpu.write(addr, result);
     // Update the zero and negative flags based on the result
     cpu.update_zero_and_negative_flags(result);
     // Return 0 additional cycles
@@ -1072,6 +1133,8 @@ pub fn ror_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// The number of additional cycles that the instruction adds to the
 /// instruction's base cycle count (always 0).
 pub fn rti<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
+    // The 6502 reads the stack before incrementing the stack pointer
+    cpu.read(0x0100 + cpu.registers.sp as u16);
     // Pop the status register from the stack
     let status = cpu.stack_pop();
     // Restore the status flags from the popped value
@@ -1100,12 +1163,15 @@ pub fn rti<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles that the instruction adds to the
 /// instruction's base cycle count (always 0).
 pub fn rts<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
+    // The 6502 reads the stack before incrementing the stack pointer
+    cpu.read(0x0100 + cpu.registers.sp as u16);
     // Pop the low and high bytes of the program counter from the stack
     let lo = cpu.stack_pop();
     let hi = cpu.stack_pop();
     // Combine the low and high bytes to form the program counter
     let pc = (hi as u16) << 8 | lo as u16;
-    // Increment the program counter by one
+    // Read the pulled address while incrementing it by one
+    cpu.read(pc);
     cpu.registers.pc = pc.wrapping_add(1);
     
     // Return 0 additional cycles
@@ -1117,7 +1183,8 @@ pub fn rts<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// This instruction subtracts the value of the memory at the given address
 /// from the accumulator, taking into account the carry flag. If the decimal
 /// mode flag is set, the instruction subtracts the values as BCD values.
-/// Otherwise it subtracts the values as binary values.
+/// Otherwise it subtracts the values as binary values. The flags are always
+/// set from the binary difference, as on the NMOS 6502.
 ///
 /// # Arguments
 ///
@@ -1129,46 +1196,45 @@ pub fn rts<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// The number of additional cycles that the instruction adds to the
 /// instruction's base cycle count (always 0).
 pub fn sbc<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
-    let m = cpu.bus.read(addr);
-    let value = m;
-    let carry = if cpu.registers.status.carry { 1 } else { 0 };
+    let value = cpu.read(addr);
+    subtract_with_carry(cpu, value);
+    0
+}
+
+/// Subtracts `value` and the borrow from the accumulator, as SBC does.
+fn subtract_with_carry<B: Bus>(cpu: &mut CPU<B>, value: u8) {
+    let borrow = if cpu.registers.status.carry { 0 } else { 1 };
     let a = cpu.registers.a;
+
+    // The NMOS 6502 sets every flag from the binary difference, even in
+    // decimal mode
+    let difference = a as i16 - value as i16 - borrow;
+    let result = difference as u8;
+    // Set the carry flag if no borrow was needed
+    cpu.registers.status.carry = difference >= 0;
+    // Set the zero flag if the result is zero
+    cpu.registers.status.zero = result == 0;
+    // Set the negative flag if the result has the high bit set
+    cpu.registers.status.negative = (result & 0x80) != 0;
+    // Set the overflow flag if the operands had different signs and the
+    // result's sign differs from the accumulator's
+    cpu.registers.status.overflow = ((a ^ result) & (a ^ value) & 0x80) != 0;
+
     if cpu.registers.status.decimal_mode {
-        let mut temp = a as i16 - value as i16 - (1 - carry) as i16;
-        // Set the carry flag if the result is negative
-        cpu.registers.status.carry = temp >= 0;
-        // Set the zero flag if the result is zero
-        cpu.registers.status.zero = (temp & 0xFF) == 0;
-        // Set the negative flag if the result has the high bit set
-        cpu.registers.status.negative = (temp & 0x80) != 0;
-        // Set the overflow flag if the result is negative and the carry flag was set
-        cpu.registers.status.overflow = ((a ^ temp as u8) & (a ^ value) & 0x80) != 0;
-        // If the lower nibble of A is less than the lower nibble of M plus the carry
-        // flag, subtract 6 from the result
-        if (a & 0x0F) < ((value & 0x0F) + (1 - carry as u8)) {
-            temp -= 6;
+        // If the low digit borrowed, subtract 6 to bring it back to 0-9
+        let mut al = (a & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+        if al < 0 {
+            al = ((al - 6) & 0x0F) - 0x10;
         }
-        // If the result is negative, subtract 0x60 from the result
-        if temp < 0 {
-            temp -= 0x60;
+        // Then the same for the high digit
+        let mut bcd = (a & 0xF0) as i16 - (value & 0xF0) as i16 + al;
+        if bcd < 0 {
+            bcd -= 0x60;
         }
-        // Store the result in A
-        cpu.registers.a = (temp & 0xFF) as u8;
+        cpu.registers.a = bcd as u8;
     } else {
-        let temp = a as u16 - value as u16 - (1 - carry) as u16;
-        // Store the result in A
-        cpu.registers.a = temp as u8;
-        // Set the carry flag if the result is positive (no borrow)
-        cpu.registers.status.carry = temp < 0x100;
-        // Set the zero flag if the result is zero
-        cpu.registers.status.zero = cpu.registers.a == 0;
-        // Set the negative flag if the result has the high bit set
-        cpu.registers.status.negative = (cpu.registers.a & 0x80) != 0;
-        // Set the overflow flag if the result has the high bit set and the carry
-        // flag was set
-        cpu.registers.status.overflow = ((a ^ cpu.registers.a) & (a ^ value) & 0x80) != 0;
+        cpu.registers.a = result;
     }
-    0
 }
 
 /// SEC - Set Carry Flag
@@ -1230,7 +1296,7 @@ pub fn sei<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
 /// instruction's base cycle count (always 0).
 pub fn sta<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Store the value of the accumulator at the given address
-    cpu.bus.write(addr, cpu.registers.a);
+    cpu.write(addr, cpu.registers.a);
     // Return 0 additional cycles
     0
 }
@@ -1246,7 +1312,7 @@ pub fn sta<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// instruction's base cycle count (always 0).
 pub fn stx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Store the value of the X register at the given address
-    cpu.bus.write(addr, cpu.registers.x);
+    cpu.write(addr, cpu.registers.x);
     // Return 0 additional cycles
     0
 }
@@ -1261,7 +1327,7 @@ pub fn stx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
 /// instruction's base cycle count (always 0).
 pub fn sty<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
     // Store the value of the Y register at the given address
-    cpu.bus.write(addr, cpu.registers.y);
+    cpu.write(addr, cpu.registers.y);
     // Return 0 additional cycles
     0
 }
@@ -1398,3 +1464,375 @@ pub fn tya<B: Bus>(cpu: &mut CPU<B>, _addr: u16) -> u8 {
     // Return 0 additional cycles
     0
 }
+
+// Undocumented instructions
+//
+// The NMOS 6502 decodes every opcode, and most of those left out of the
+// documented instruction set do something predictable, usually two
+// documented instructions at once. The opcodes that halt the CPU (JAM) are
+// not implemented.
+
+/// The "magic" constant XAA and LXA OR into the accumulator. It varies
+/// between chips; this is the value SingleStepTests assume.
+const MAGIC: u8 = 0xEE;
+
+/// Reads the value at `addr`, writes it back unchanged as the 6502 does,
+/// and writes the result of `op` on it.
+///
+/// # Returns
+///
+/// The value written.
+fn read_modify_write<B: Bus>(cpu: &mut CPU<B>, addr: u16, op: fn(&mut CPU<B>, u8) -> u8) -> u8 {
+    let m = cpu.read(addr);
+    cpu.write(addr, m);
+    let result = op(cpu, m);
+    cpu.write(addr, result);
+    result
+}
+
+/// Stores `value` ANDed with the high byte of the base address plus one, as
+/// SHA, SHX, SHY and TAS do. When adding `index` crossed a page, the stored
+/// value also replaces the high byte of the address.
+fn store_and_high<B: Bus>(cpu: &mut CPU<B>, addr: u16, index: u8, value: u8) {
+    let crossed = (addr as u8) < index;
+    let high = (addr >> 8) as u8;
+    let value = value & if crossed { high } else { high.wrapping_add(1) };
+    let addr = if crossed {
+        (value as u16) << 8 | (addr & 0x00FF)
+    } else {
+        addr
+    };
+    cpu.write(addr, value);
+}
+
+/// ALR - AND then Logical Shift Right (undocumented)
+///
+/// ANDs the accumulator with the value at the given address, then shifts
+/// the accumulator right by one bit.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn alr<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.read(addr) & cpu.registers.a;
+    cpu.registers.status.carry = (value & 0x01) != 0;
+    cpu.registers.a = value >> 1;
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    0
+}
+
+/// ANC - AND with Carry (undocumented)
+///
+/// ANDs the accumulator with the value at the given address, then copies
+/// the negative flag into the carry flag.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn anc<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    cpu.registers.a &= cpu.read(addr);
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    cpu.registers.status.carry = cpu.registers.status.negative;
+    0
+}
+
+/// ARR - AND then Rotate Right (undocumented)
+///
+/// ANDs the accumulator with the value at the given address, then rotates
+/// the accumulator right through the carry flag. The carry and overflow
+/// flags come from bits 6 and 5 of the result. In decimal mode the result
+/// is adjusted as the NMOS 6502 does, with the flags set along the way.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn arr<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.read(addr) & cpu.registers.a;
+    let carry_in = if cpu.registers.status.carry { 0x80 } else { 0 };
+    let result = (value >> 1) | carry_in;
+    cpu.update_zero_and_negative_flags(result);
+    if cpu.registers.status.decimal_mode {
+        cpu.registers.status.overflow = ((value ^ result) & 0x40) != 0;
+        let (hi, lo) = (value >> 4, value & 0x0F);
+        let mut result = result;
+        // Each digit is adjusted as if it were being added to itself
+        if lo + (lo & 0x01) > 5 {
+            result = (result & 0xF0) | (result.wrapping_add(6) & 0x0F);
+        }
+        cpu.registers.status.carry = hi + (hi & 0x01) > 5;
+        if cpu.registers.status.carry {
+            result = result.wrapping_add(0x60);
+        }
+        cpu.registers.a = result;
+    } else {
+        cpu.registers.status.carry = (result & 0x40) != 0;
+        cpu.registers.status.overflow = ((result ^ (result << 1)) & 0x40) != 0;
+        cpu.registers.a = result;
+    }
+    0
+}
+
+/// AXS - AND X with Accumulator then Subtract (undocumented)
+///
+/// Sets X to the accumulator ANDed with X, minus the value at the given
+/// address. The carry flag is set as by CMP; the borrow and decimal mode
+/// are ignored.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn axs<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.read(addr);
+    let and = cpu.registers.a & cpu.registers.x;
+    cpu.registers.status.carry = and >= value;
+    cpu.registers.x = and.wrapping_sub(value);
+    cpu.update_zero_and_negative_flags(cpu.registers.x);
+    0
+}
+
+/// DCP - Decrement then Compare (undocumented)
+///
+/// Decrements the value at the given address, then compares the
+/// accumulator with it as CMP does.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn dcp<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let m = read_modify_write(cpu, addr, |_, m| m.wrapping_sub(1));
+    let a = cpu.registers.a;
+    cpu.registers.status.carry = a >= m;
+    cpu.update_zero_and_negative_flags(a.wrapping_sub(m));
+    0
+}
+
+/// ISB - Increment then Subtract with Carry (undocumented)
+///
+/// Increments the value at the given address, then subtracts it from the
+/// accumulator as SBC does.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn isb<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let m = read_modify_write(cpu, addr, |_, m| m.wrapping_add(1));
+    subtract_with_carry(cpu, m);
+    0
+}
+
+/// LAS - Load Accumulator, X and Stack Pointer (undocumented)
+///
+/// ANDs the value at the given address with the stack pointer, and loads
+/// the result into the accumulator, X and the stack pointer.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn las<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.read(addr) & cpu.registers.sp;
+    cpu.registers.a = value;
+    cpu.registers.x = value;
+    cpu.registers.sp = value;
+    cpu.update_zero_and_negative_flags(value);
+    0
+}
+
+/// LAX - Load Accumulator and X (undocumented)
+///
+/// Loads the value at the given address into both the accumulator and X.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn lax<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.read(addr);
+    cpu.registers.a = value;
+    cpu.registers.x = value;
+    cpu.update_zero_and_negative_flags(value);
+    0
+}
+
+/// LXA - Load Accumulator and X, unstable (undocumented)
+///
+/// ORs the accumulator with a chip-dependent constant, ANDs it with the
+/// value at the given address, and loads the result into the accumulator
+/// and X.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn lxa<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = (cpu.registers.a | MAGIC) & cpu.read(addr);
+    cpu.registers.a = value;
+    cpu.registers.x = value;
+    cpu.update_zero_and_negative_flags(value);
+    0
+}
+
+/// NOP - No Operation, reading memory (undocumented)
+///
+/// The undocumented NOPs with an operand read the value at the given
+/// address and discard it.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn nop_memory<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    cpu.read(addr);
+    0
+}
+
+/// RLA - Rotate Left then AND (undocumented)
+///
+/// Rotates the value at the given address left through the carry flag, as
+/// ROL does, then ANDs the accumulator with it.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn rla<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let m = read_modify_write(cpu, addr, |cpu, m| {
+        let carry_in = if cpu.registers.status.carry { 1 } else { 0 };
+        cpu.registers.status.carry = (m & 0x80) != 0;
+        (m << 1) | carry_in
+    });
+    cpu.registers.a &= m;
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    0
+}
+
+/// RRA - Rotate Right then Add with Carry (undocumented)
+///
+/// Rotates the value at the given address right through the carry flag, as
+/// ROR does, then adds it to the accumulator as ADC does.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn rra<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let m = read_modify_write(cpu, addr, |cpu, m| {
+        let carry_in = if cpu.registers.status.carry { 0x80 } else { 0 };
+        cpu.registers.status.carry = (m & 0x01) != 0;
+        (m >> 1) | carry_in
+    });
+    add_with_carry(cpu, m);
+    0
+}
+
+/// SAX - Store Accumulator AND X (undocumented)
+///
+/// Stores the accumulator ANDed with X at the given address. No flags are
+/// changed.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn sax<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    cpu.write(addr, cpu.registers.a & cpu.registers.x);
+    0
+}
+
+/// SHA - Store Accumulator AND X AND High Byte (undocumented)
+///
+/// Stores the accumulator ANDed with X and with the high byte of the base
+/// address plus one. Used with Y indexing only.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn sha<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.registers.a & cpu.registers.x;
+    store_and_high(cpu, addr, cpu.registers.y, value);
+    0
+}
+
+/// SHX - Store X AND High Byte (undocumented)
+///
+/// Stores X ANDed with the high byte of the base address plus one. Used
+/// with Y indexing.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn shx<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    store_and_high(cpu, addr, cpu.registers.y, cpu.registers.x);
+    0
+}
+
+/// SHY - Store Y AND High Byte (undocumented)
+///
+/// Stores Y ANDed with the high byte of the base address plus one. Used
+/// with X indexing.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn shy<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    store_and_high(cpu, addr, cpu.registers.x, cpu.registers.y);
+    0
+}
+
+/// SLO - Shift Left then OR (undocumented)
+///
+/// Shifts the value at the given address left, as ASL does, then ORs the
+/// accumulator with it.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn slo<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let m = read_modify_write(cpu, addr, |cpu, m| {
+        cpu.registers.status.carry = (m & 0x80) != 0;
+        m << 1
+    });
+    cpu.registers.a |= m;
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    0
+}
+
+/// SRE - Shift Right then Exclusive OR (undocumented)
+///
+/// Shifts the value at the given address right, as LSR does, then
+/// exclusive ORs the accumulator with it.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn sre<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let m = read_modify_write(cpu, addr, |cpu, m| {
+        cpu.registers.status.carry = (m & 0x01) != 0;
+        m >> 1
+    });
+    cpu.registers.a ^= m;
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    0
+}
+
+/// TAS - Transfer Accumulator AND X to Stack Pointer, then store (undocumented)
+///
+/// Sets the stack pointer to the accumulator ANDed with X, then stores it
+/// ANDed with the high byte of the base address plus one, as SHA does.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn tas<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    cpu.registers.sp = cpu.registers.a & cpu.registers.x;
+    store_and_high(cpu, addr, cpu.registers.y, cpu.registers.sp);
+    0
+}
+
+/// XAA - Transfer X to Accumulator then AND, unstable (undocumented)
+///
+/// ORs the accumulator with a chip-dependent constant, then ANDs it with X
+/// and the value at the given address.
+///
+/// # Returns
+///
+/// The number of additional cycles (always 0).
+pub fn xaa<B: Bus>(cpu: &mut CPU<B>, addr: u16) -> u8 {
+    let value = cpu.read(addr);
+    cpu.registers.a = (cpu.registers.a | MAGIC) & cpu.registers.x & value;
+    cpu.update_zero_and_negative_flags(cpu.registers.a);
+    0
+}
@@ -5,11 +5,38 @@
 //! CPU's registers and memory.
 
 #![warn(missing_docs)]
+pub mod adapters;
 pub mod addressing_modes;
 pub mod bus;
 pub mod cpu;
+#[cfg(feature = "serde")]
+pub mod dap;
+pub mod dbginfo;
+pub mod debugger;
+pub mod disasm;
+pub mod expr;
+pub mod functional;
+pub mod gdb;
+pub mod idle;
 pub mod instructions;
+pub mod lockstep;
+pub mod memdiff;
+pub mod mock;
+pub mod monitor;
+pub mod nestest;
 pub mod registers;
+pub mod replay;
+pub mod rewind;
+pub mod run;
+#[cfg(feature = "serde")]
+pub mod savestate;
+pub mod scheduler;
+pub mod singlestep;
+pub mod state;
+pub mod throttle;
+pub mod trace;
+pub mod tui;
+pub mod vice;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,294 @@
+//! The `lockstep` module runs two CPUs side by side, one instruction at a
+//! time, and finds the first instruction at which they disagree.
+//!
+//! Either side is a `StepSource`: a `CPU` with any bus, or a `Recorded`
+//! trace of steps from somewhere else, such as another emulator. After each
+//! instruction the registers, the cycles it took and its bus accesses are
+//! compared. A mismatch is reported as a `Divergence`, with the last few
+//! instructions before it.
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::lockstep::Lockstep;
+//!
+//! let mut program = vec![0u8; 0x10000];
+//! // loop: INX; JMP loop
+//! program[0x8000..0x8004].copy_from_slice(&[0xE8, 0x4C, 0x00, 0x80]);
+//! let mut reference = CPU::new(program.clone());
+//! let mut candidate = CPU::new(program);
+//! reference.registers.pc = 0x8000;
+//! candidate.registers.pc = 0x8000;
+//! candidate.registers.x = 0x10;
+//!
+//! let divergence = Lockstep::new()
+//!     .run(&mut reference, &mut candidate, 100)
+//!     .unwrap_err();
+//! assert_eq!(divergence.step, 0);
+//! assert_eq!(divergence.fields, ["X"]);
+//! ```
+
+use crate::bus::{Bus, BusAccess};
+use crate::cpu::CPU;
+use crate::disasm::{disassemble, Disassembled};
+use crate::registers::Registers;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// The number of steps shown before a divergence by default.
+const WINDOW: usize = 8;
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// The address of the instruction.
+    pub pc: u16,
+    /// The instruction, if known.
+    pub instruction: Option<Disassembled>,
+    /// The registers after it.
+    pub registers: Registers,
+    /// The cycles it took, if known.
+    pub cycles: Option<u64>,
+    /// Its bus accesses, oldest first, if known.
+    pub accesses: Option<Vec<BusAccess>>,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match &self.instruction {
+            Some(instruction) => instruction.to_string(),
+            None => String::new(),
+        };
+        let registers = &self.registers;
+        write!(
+            f,
+            "{:04X}  {text:<14}-> PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X}",
+            self.pc,
+            registers.pc,
+            registers.a,
+            registers.x,
+            registers.y,
+            registers.sp,
+            registers.status.to_byte()
+        )?;
+        if let Some(cycles) = self.cycles {
+            write!(f, " CYC:{cycles}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Something that executes, or replays, one instruction at a time.
+pub trait StepSource {
+    /// Executes the next instruction.
+    ///
+    /// # Returns
+    ///
+    /// What it did, or `None` if there are no more instructions.
+    fn next_step(&mut self) -> Option<Step>;
+}
+
+impl<B: Bus> StepSource for CPU<B> {
+    /// Executes the next instruction, recording its bus accesses.
+    ///
+    /// Returns `None` at an opcode the CPU does not implement, rather than
+    /// panicking.
+    fn next_step(&mut self) -> Option<Step> {
+        if !self.is_recording_accesses() {
+            self.set_access_recording(true);
+        }
+        let pc = self.registers.pc;
+        let instruction = disassemble(&self.bus, pc);
+        if let Some(opcode) = self.bus.peek(pc) {
+            if !self.is_implemented(opcode) {
+                return None;
+            }
+        }
+        let start_cycles = self.cycles();
+        self.step();
+        Some(Step {
+            pc,
+            instruction,
+            registers: self.registers,
+            cycles: Some(self.cycles() - start_cycles),
+            accesses: Some(self.accesses().to_vec()),
+        })
+    }
+}
+
+/// A recorded trace of steps, replayed in order.
+#[derive(Debug, Clone)]
+pub struct Recorded<I: Iterator<Item = Step>> {
+    steps: I,
+}
+
+impl<I: Iterator<Item = Step>> Recorded<I> {
+    /// Creates a new `Recorded` that replays `steps`.
+    pub fn new<T: IntoIterator<IntoIter = I>>(steps: T) -> Self {
+        Self {
+            steps: steps.into_iter(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Step>> StepSource for Recorded<I> {
+    fn next_step(&mut self) -> Option<Step> {
+        self.steps.next()
+    }
+}
+
+/// The first step at which the two sides disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The number of steps that matched before it.
+    pub step: u64,
+    /// What differs: `PC`, `A`, `X`, `Y`, `SP`, `P`, `cycles`, `bus`, or
+    /// `end` if only one side ran out of instructions.
+    pub fields: Vec<&'static str>,
+    /// The left side's step, or `None` if it had ended.
+    pub left: Option<Step>,
+    /// The right side's step, or `None` if it had ended.
+    pub right: Option<Step>,
+    /// The matching steps before it, oldest first.
+    pub window: Vec<Step>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "diverged at step {} ({}):",
+            self.step,
+            self.fields.join(", ")
+        )?;
+        for step in &self.window {
+            writeln!(f, "        {step}")?;
+        }
+        let side = |step: &Option<Step>| match step {
+            Some(step) => step.to_string(),
+            None => "(ended)".to_string(),
+        };
+        writeln!(f, "  left  {}", side(&self.left))?;
+        write!(f, "  right {}", side(&self.right))?;
+        // The accesses are only worth showing when they are what differs
+        if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            if let (true, Some(left), Some(right)) = (
+                self.fields.contains(&"bus"),
+                &left.accesses,
+                &right.accesses,
+            ) {
+                write!(f, "\n  left  bus {left:?}\n  right bus {right:?}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares two steps.
+///
+/// # Returns
+///
+/// The names of the fields that differ. Cycles and bus accesses are only
+/// compared when both sides know them.
+fn compare(left: &Step, right: &Step) -> Vec<&'static str> {
+    let (l, r) = (&left.registers, &right.registers);
+    let mut fields = Vec::new();
+    let checks = [
+        ("PC", l.pc == r.pc),
+        ("A", l.a == r.a),
+        ("X", l.x == r.x),
+        ("Y", l.y == r.y),
+        ("SP", l.sp == r.sp),
+        ("P", l.status.to_byte() == r.status.to_byte()),
+    ];
+    for (name, same) in checks {
+        if !same {
+            fields.push(name);
+        }
+    }
+    if let (Some(l), Some(r)) = (left.cycles, right.cycles) {
+        if l != r {
+            fields.push("cycles");
+        }
+    }
+    if let (Some(l), Some(r)) = (&left.accesses, &right.accesses) {
+        if l != r {
+            fields.push("bus");
+        }
+    }
+    fields
+}
+
+/// Runs two step sources in lockstep.
+#[derive(Debug, Clone)]
+pub struct Lockstep {
+    window: usize,
+}
+
+impl Default for Lockstep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lockstep {
+    /// Creates a new `Lockstep` that shows the last 8 steps before a
+    /// divergence.
+    pub fn new() -> Self {
+        Self { window: WINDOW }
+    }
+
+    /// Sets how many steps before a divergence are shown.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Steps both sides until they diverge, both end, or `limit` steps
+    /// have run.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - One side, usually the reference.
+    /// * `right` - The other side.
+    /// * `limit` - The most steps to run.
+    ///
+    /// # Returns
+    ///
+    /// The number of steps run.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first `Divergence`.
+    pub fn run<L: StepSource, R: StepSource>(
+        &self,
+        left: &mut L,
+        right: &mut R,
+        limit: u64,
+    ) -> Result<u64, Box<Divergence>> {
+        let mut window = VecDeque::with_capacity(self.window);
+        for step in 0..limit {
+            let (left, right) = (left.next_step(), right.next_step());
+            let fields = match (&left, &right) {
+                (None, None) => return Ok(step),
+                (Some(l), Some(r)) => compare(l, r),
+                _ => vec!["end"],
+            };
+            if !fields.is_empty() {
+                return Err(Box::new(Divergence {
+                    step,
+                    fields,
+                    left,
+                    right,
+                    window: window.into_iter().collect(),
+                }));
+            }
+            if self.window > 0 {
+                if window.len() == self.window {
+                    window.pop_front();
+                }
+                window.extend(left);
+            }
+        }
+        Ok(limit)
+    }
+}
@@ -0,0 +1,232 @@
+//! The `memdiff` module compares the memory of two machine states.
+//!
+//! A `MemorySnapshot` is a copy of everything the CPU can see, taken with
+//! `Bus::peek` so that capturing it has no effect on I/O devices. Two
+//! snapshots, or a snapshot and a live bus, can be compared to find out
+//! exactly which bytes a routine changed:
+//!
+//! ```
+//! use lib6502::bus::Bus;
+//! use lib6502::memdiff::MemorySnapshot;
+//!
+//! let mut bus = vec![0u8; 0x10000];
+//! let before = MemorySnapshot::capture(&bus);
+//! bus.write(0x0010, 0x12);
+//! bus.write(0x0011, 0x34);
+//! bus.write(0x01FF, 0x80);
+//! let diff = before.diff_live(&bus);
+//! assert_eq!(
+//!     diff.to_string(),
+//!     "zero page:\n  $0010-$0011: 00 00 -> 12 34\nstack:\n  $01FF: 00 -> 80\n"
+//! );
+//! ```
+//!
+//! Addresses for which the bus returns `None` from `peek` are not captured,
+//! and are never reported as changed.
+
+use crate::bus::Bus;
+use std::fmt;
+
+/// The number of bytes shown on each line of a `MemoryDiff`.
+const BYTES_PER_LINE: usize = 8;
+
+/// The part of the address space a change falls in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Region {
+    /// $0000-$00FF.
+    ZeroPage,
+    /// $0100-$01FF.
+    Stack,
+    /// $0200-$FFFF.
+    Other,
+}
+
+impl Region {
+    /// Returns the region `addr` falls in.
+    pub fn of(addr: u16) -> Self {
+        match addr {
+            0x0000..=0x00FF => Region::ZeroPage,
+            0x0100..=0x01FF => Region::Stack,
+            _ => Region::Other,
+        }
+    }
+
+    /// Returns the name used for the region in diffs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::ZeroPage => "zero page",
+            Region::Stack => "stack",
+            Region::Other => "other",
+        }
+    }
+}
+
+/// A side-effect-free copy of the 64KB address space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    /// One entry per address; `None` where the bus could not be peeked.
+    bytes: Vec<Option<u8>>,
+}
+
+impl MemorySnapshot {
+    /// Captures the address space of `bus` using `Bus::peek`.
+    pub fn capture<B: Bus + ?Sized>(bus: &B) -> Self {
+        Self {
+            bytes: (0..=0xFFFF).map(|addr| bus.peek(addr)).collect(),
+        }
+    }
+
+    /// Creates a snapshot from a memory image starting at $0000.
+    ///
+    /// Addresses past the end of `image` are treated as not captured.
+    pub fn from_bytes(image: &[u8]) -> Self {
+        let mut bytes: Vec<Option<u8>> = image.iter().take(0x10000).copied().map(Some).collect();
+        bytes.resize(0x10000, None);
+        Self { bytes }
+    }
+
+    /// Returns the captured byte at `addr`, or `None` if it was not captured.
+    pub fn get(&self, addr: u16) -> Option<u8> {
+        self.bytes[addr as usize]
+    }
+
+    /// Compares this snapshot, as the old state, with `other`, as the new one.
+    pub fn diff(&self, other: &MemorySnapshot) -> MemoryDiff {
+        MemoryDiff::between(|addr| (self.get(addr), other.get(addr)))
+    }
+
+    /// Compares this snapshot, as the old state, with the current contents of
+    /// `bus`, without triggering any I/O side effects.
+    pub fn diff_live<B: Bus + ?Sized>(&self, bus: &B) -> MemoryDiff {
+        MemoryDiff::between(|addr| (self.get(addr), bus.peek(addr)))
+    }
+}
+
+/// A run of consecutive changed bytes within one region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedRange {
+    /// The first address of the run.
+    pub start: u16,
+    /// The region the run lies in.
+    pub region: Region,
+    /// The bytes before the change.
+    pub old: Vec<u8>,
+    /// The bytes after the change.
+    pub new: Vec<u8>,
+}
+
+impl ChangedRange {
+    /// Returns the last address of the run.
+    pub fn end(&self) -> u16 {
+        self.start + (self.old.len() - 1) as u16
+    }
+}
+
+impl fmt::Display for ChangedRange {
+    /// Formats the run as `$0010-$0011: 00 00 -> 12 34`, one line per eight
+    /// bytes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = self
+            .old
+            .chunks(BYTES_PER_LINE)
+            .zip(self.new.chunks(BYTES_PER_LINE));
+        for (i, (old, new)) in lines.enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let start = self.start + (i * BYTES_PER_LINE) as u16;
+            let end = start + (old.len() - 1) as u16;
+            if start == end {
+                write!(f, "${start:04X}: ")?;
+            } else {
+                write!(f, "${start:04X}-${end:04X}: ")?;
+            }
+            write!(f, "{} -> {}", hex_bytes(old), hex_bytes(new))?;
+        }
+        Ok(())
+    }
+}
+
+/// The changes between two memory states, in address order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MemoryDiff {
+    /// The changed runs, in address order.
+    pub ranges: Vec<ChangedRange>,
+}
+
+impl MemoryDiff {
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the total number of changed bytes.
+    pub fn changed_bytes(&self) -> usize {
+        self.ranges.iter().map(|range| range.old.len()).sum()
+    }
+
+    /// Returns the changed runs within `region`.
+    pub fn in_region(&self, region: Region) -> impl Iterator<Item = &ChangedRange> {
+        self.ranges
+            .iter()
+            .filter(move |range| range.region == region)
+    }
+
+    /// Builds a diff from a function returning the old and new byte at each
+    /// address.
+    fn between<F: Fn(u16) -> (Option<u8>, Option<u8>)>(bytes: F) -> Self {
+        let mut ranges: Vec<ChangedRange> = Vec::new();
+        for addr in 0..=0xFFFF {
+            let (old, new) = match bytes(addr) {
+                (Some(old), Some(new)) if old != new => (old, new),
+                _ => continue,
+            };
+            let region = Region::of(addr);
+            match ranges.last_mut() {
+                Some(range) if range.region == region && range.end().wrapping_add(1) == addr => {
+                    range.old.push(old);
+                    range.new.push(new);
+                }
+                _ => ranges.push(ChangedRange {
+                    start: addr,
+                    region,
+                    old: vec![old],
+                    new: vec![new],
+                }),
+            }
+        }
+        Self { ranges }
+    }
+}
+
+impl fmt::Display for MemoryDiff {
+    /// Formats the diff grouped by region, one heading per region with
+    /// changes, then one indented line per run or eight bytes of a run.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for region in [Region::ZeroPage, Region::Stack, Region::Other] {
+            let mut ranges = self.in_region(region).peekable();
+            if ranges.peek().is_none() {
+                continue;
+            }
+            writeln!(f, "{}:", region.name())?;
+            for range in ranges {
+                for line in range.to_string().lines() {
+                    writeln!(f, "  {line}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats bytes as space-separated hex pairs.
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
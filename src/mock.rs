@@ -0,0 +1,200 @@
+//! The `mock` module provides `MockBus`, a bus with scripted expectations for unit tests.
+//!
+//! A `MockBus` is given an ordered list of the accesses it expects to see. Each
+//! read returns the scripted value, and any access that does not match the next
+//! expectation panics with a description of what was expected, what happened
+//! and the accesses seen so far.
+//!
+//! The CPU also reads opcodes and operands through the bus, so a mock is usually
+//! placed over a device's address range with an `OverlayBus` and the program is
+//! kept in ordinary RAM:
+//!
+//! ```
+//! use lib6502::adapters::OverlayBus;
+//! use lib6502::cpu::CPU;
+//! use lib6502::mock::MockBus;
+//!
+//! let mut ram = vec![0u8; 0x10000];
+//! // LDA $6001 ; STA $6000
+//! ram[0x8000..0x8006].copy_from_slice(&[0xAD, 0x01, 0x60, 0x8D, 0x00, 0x60]);
+//! ram[0xFFFD] = 0x80;
+//!
+//! let mut mock = MockBus::new();
+//! mock.expect_read(0x6001, 0x80).expect_write(0x6000, 0x80);
+//!
+//! let mut cpu = CPU::new(OverlayBus::new(ram, 0x6000..=0x600F, mock));
+//! cpu.reset();
+//! cpu.step();
+//! cpu.step();
+//! cpu.bus.overlay().verify();
+//! ```
+
+use crate::bus::{AccessKind, Bus, BusAccess};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+
+/// A bus that checks every access against a script of expected accesses.
+///
+/// When a `MockBus` is dropped with expectations still outstanding it panics,
+/// unless the thread is already panicking.
+pub struct MockBus {
+    /// The accesses still expected, in order.
+    expected: VecDeque<BusAccess>,
+    /// The accesses seen so far, in order.
+    history: Vec<BusAccess>,
+}
+
+impl MockBus {
+    /// Creates a new `MockBus` with no expectations.
+    pub fn new() -> Self {
+        Self {
+            expected: VecDeque::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Expects a read of `addr`, which will return `data`.
+    pub fn expect_read(&mut self, addr: u16, data: u8) -> &mut Self {
+        self.expected.push_back(BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Read,
+        });
+        self
+    }
+
+    /// Expects a write of `data` to `addr`.
+    pub fn expect_write(&mut self, addr: u16, data: u8) -> &mut Self {
+        self.expected.push_back(BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Write,
+        });
+        self
+    }
+
+    /// Returns the accesses seen so far, oldest first.
+    pub fn history(&self) -> &[BusAccess] {
+        &self.history
+    }
+
+    /// Returns `true` if every expectation has been met.
+    pub fn is_satisfied(&self) -> bool {
+        self.expected.is_empty()
+    }
+
+    /// Checks that every expectation has been met.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the list of outstanding expectations if any remain.
+    pub fn verify(&self) {
+        if !self.is_satisfied() {
+            panic!(
+                "{}",
+                self.report("MockBus: expected accesses did not happen", None)
+            );
+        }
+    }
+
+    /// Checks an access against the next expectation and returns the matched expectation.
+    fn check(&mut self, actual: BusAccess) -> BusAccess {
+        let expected = match self.expected.front() {
+            Some(expected) => *expected,
+            None => panic!(
+                "{}",
+                self.report(
+                    "MockBus: unexpected access after all expectations were met",
+                    Some(actual)
+                )
+            ),
+        };
+        let matches = expected.kind == actual.kind
+            && expected.addr == actual.addr
+            && (actual.kind == AccessKind::Read || expected.data == actual.data);
+        if !matches {
+            panic!(
+                "{}",
+                self.report("MockBus: unexpected access", Some(actual))
+            );
+        }
+        self.expected.pop_front();
+        self.history.push(expected);
+        expected
+    }
+
+    /// Builds a readable report of the mock's state for a failure message.
+    fn report(&self, headline: &str, actual: Option<BusAccess>) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{headline}");
+        if let Some(actual) = actual {
+            match self.expected.front() {
+                Some(expected) => {
+                    let _ = writeln!(out, "  expected: {}", describe(expected));
+                }
+                None => {
+                    let _ = writeln!(out, "  expected: no further accesses");
+                }
+            }
+            let _ = writeln!(out, "  actual:   {}", describe_actual(&actual));
+        }
+        let _ = writeln!(out, "accesses so far ({}):", self.history.len());
+        for (i, access) in self.history.iter().enumerate() {
+            let _ = writeln!(out, "  #{:<4} {}", i + 1, describe(access));
+        }
+        let _ = writeln!(out, "remaining expectations ({}):", self.expected.len());
+        for access in &self.expected {
+            let _ = writeln!(out, "         {}", describe(access));
+        }
+        out
+    }
+}
+
+impl Default for MockBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for MockBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.check(BusAccess {
+            addr,
+            data: 0,
+            kind: AccessKind::Read,
+        })
+        .data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.check(BusAccess {
+            addr,
+            data,
+            kind: AccessKind::Write,
+        });
+    }
+}
+
+impl Drop for MockBus {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            self.verify();
+        }
+    }
+}
+
+/// Formats an expected (or matched) access.
+fn describe(access: &BusAccess) -> String {
+    match access.kind {
+        AccessKind::Read => format!("read  ${:04X} -> ${:02X}", access.addr, access.data),
+        AccessKind::Write => format!("write ${:04X} <- ${:02X}", access.addr, access.data),
+    }
+}
+
+/// Formats an actual access, where the value of a read is not yet known.
+fn describe_actual(access: &BusAccess) -> String {
+    match access.kind {
+        AccessKind::Read => format!("read  ${:04X}", access.addr),
+        AccessKind::Write => describe(access),
+    }
+}
@@ -0,0 +1,862 @@
+//! The `monitor` module implements a machine language monitor in the style
+//! of VICE and Supermon.
+//!
+//! A `Monitor` executes one command line at a time against a machine and
+//! returns the text to show, which makes it easy to put behind any kind of
+//! input, such as the `mon6502` binary:
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::monitor::Monitor;
+//!
+//! let mut cpu = CPU::new(vec![0u8; 0x10000]);
+//! let mut monitor = Monitor::new();
+//! monitor.execute(&mut cpu, "> 8000 a9 42 ea").unwrap();
+//! monitor.execute(&mut cpu, "al 8000 .main").unwrap();
+//! assert_eq!(
+//!     monitor.execute(&mut cpu, "d main 8002").unwrap(),
+//!     "main:\n.8000  A9 42     LDA #$42\n.8002  EA        NOP\n"
+//! );
+//! ```
+//!
+//! Numbers are hex, with or without a `$`. Addresses can also be labels,
+//! written with a leading `.` where the name would read as hex, and may
+//! have a hex offset, as in `.table+10`. Byte lists mix hex bytes and
+//! quoted strings. An empty line repeats the last `d`, `m`, `z` or `n`,
+//! continuing where it left off, and `!!`, `!n` and `!prefix` recall lines
+//! from the history.
+//!
+//! Memory is looked at with `Bus::peek`, so that looking never disturbs
+//! I/O devices, and changed with `Bus::write`.
+
+use crate::bus::Bus;
+use crate::dbginfo::DebugInfo;
+use crate::debugger::{Debugger, Machine, Resume, Stop};
+use crate::disasm::disassemble;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// The instructions `d` shows when not given an end address.
+const DISASSEMBLE_LINES: usize = 16;
+
+/// The bytes `m` shows when not given an end address.
+const DUMP_BYTES: u16 = 128;
+
+/// The bytes on each line of a memory dump.
+const DUMP_WIDTH: u16 = 16;
+
+/// The most instructions `g` executes before giving control back, as there
+/// is no other way to stop a program that never hits a breakpoint.
+const GO_LIMIT: u64 = 100_000_000;
+
+/// The addresses on each line of `h` and `c` output.
+const ADDRESSES_PER_LINE: usize = 8;
+
+/// The text of the `help` command.
+const HELP: &str = "\
+d [start [end]]          disassemble
+m [start [end]]          dump memory
+> addr bytes...          edit memory
+r [reg=value ...]        show or set registers (a, x, y, sp, pc, p)
+b [addr [[if] cond]]     list breakpoints, or set one with a condition
+bc [addr]                clear one breakpoint, or all
+z [count]                step into
+n [count]                step over subroutines
+g [addr]                 go, from addr if given
+f start end bytes...     fill memory with a pattern
+h start end bytes...     hunt for bytes
+c start end dest         compare memory ranges
+t start end dest         transfer memory
+l \"file\" [addr]          load a binary file, or a PRG without addr
+s \"file\" start end       save memory to a binary file
+al addr .name            add a label
+ll \"file\"                load labels from a VICE label or ca65 debug file
+shl                      show labels
+history                  show the command history
+x                        exit
+";
+
+/// An error from a monitor command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    /// What went wrong.
+    pub message: String,
+}
+
+impl CommandError {
+    fn new<S: Into<String>>(message: S) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<std::io::Error> for CommandError {
+    fn from(err: std::io::Error) -> Self {
+        Self::new(err.to_string())
+    }
+}
+
+type CommandResult = Result<String, CommandError>;
+
+/// A machine language monitor.
+pub struct Monitor {
+    debugger: Debugger,
+    /// Label addresses, by name.
+    labels: BTreeMap<String, u16>,
+    /// Label names, by address.
+    names: BTreeMap<u16, String>,
+    history: Vec<String>,
+    /// Where `d` and `m` continue when given no address.
+    next_disassemble: Option<u16>,
+    next_dump: Option<u16>,
+    /// The command an empty line repeats.
+    repeat: Option<&'static str>,
+    done: bool,
+}
+
+impl Monitor {
+    /// Creates a new `Monitor` with a fresh `Debugger`.
+    pub fn new() -> Self {
+        Self::with_debugger(Debugger::new())
+    }
+
+    /// Creates a new `Monitor` around an existing debugger.
+    pub fn with_debugger(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            labels: BTreeMap::new(),
+            names: BTreeMap::new(),
+            history: Vec::new(),
+            next_disassemble: None,
+            next_dump: None,
+            repeat: None,
+            done: false,
+        }
+    }
+
+    /// Returns the debugger.
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Returns the debugger mutably.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Returns `true` once the `x` command has been given.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns the command lines executed so far, oldest first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Adds a label, replacing any other label with the same name.
+    pub fn add_label(&mut self, name: &str, addr: u16) {
+        if let Some(old) = self.labels.insert(name.to_string(), addr) {
+            if self.names.get(&old).is_some_and(|old| old == name) {
+                self.names.remove(&old);
+            }
+        }
+        self.names.insert(addr, name.to_string());
+    }
+
+    /// Returns the name of a label at `addr`.
+    pub fn label_at(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// Executes one command line.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to work on.
+    /// * `line` - The command line.
+    ///
+    /// # Returns
+    ///
+    /// The output of the command, with each line ending in a newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CommandError` for an unknown command, bad arguments, or a
+    /// file that cannot be read or written.
+    pub fn execute<M: Machine>(
+        &mut self,
+        machine: &mut M,
+        line: &str,
+    ) -> Result<String, CommandError> {
+        let line = line.trim();
+        let line = if line.is_empty() {
+            match self.repeat {
+                Some(command) => command.to_string(),
+                None => return Ok(String::new()),
+            }
+        } else {
+            let line = self.expand_history(line)?;
+            self.history.push(line.clone());
+            line
+        };
+        self.repeat = None;
+
+        let (command, rest) = match line.strip_prefix('>') {
+            Some(rest) => (">".to_string(), rest.trim()),
+            None => match line.split_once(char::is_whitespace) {
+                Some((command, rest)) => (command.to_lowercase(), rest.trim()),
+                None => (line.to_lowercase(), ""),
+            },
+        };
+        let args = tokenize(rest)?;
+        match command.as_str() {
+            "d" => self.disassemble(machine, &args),
+            "m" => self.dump(machine, &args),
+            ">" => self.edit(machine, &args),
+            "r" => self.registers(machine, rest),
+            "b" => self.breakpoint(rest),
+            "bc" => self.clear_breakpoint(&args),
+            "z" => self.step(machine, &args, Resume::StepInto, "z"),
+            "n" => self.step(machine, &args, Resume::StepOver, "n"),
+            "g" => self.go(machine, &args),
+            "f" => self.fill(machine, &args),
+            "h" => self.hunt(machine, &args),
+            "c" => self.compare(machine, &args),
+            "t" => self.transfer(machine, &args),
+            "l" => {
+                let (path, addr) = match args.as_slice() {
+                    [path] => (path, None),
+                    [path, addr] => (path, Some(self.parse_addr(addr)?)),
+                    _ => return Err(usage("l \"file\" [addr]")),
+                };
+                let range = self.load(machine, unquote(path), addr)?;
+                Ok(format!(
+                    "loaded ${:04X}-${:04X}\n",
+                    range.start(),
+                    range.end()
+                ))
+            }
+            "s" => self.save(machine, &args),
+            "al" => match args.as_slice() {
+                [addr, name] => {
+                    let addr = self.parse_addr(addr)?;
+                    self.add_label(name.strip_prefix('.').unwrap_or(name), addr);
+                    Ok(String::new())
+                }
+                _ => Err(usage("al addr .name")),
+            },
+            "ll" => match args.as_slice() {
+                [path] => self.load_labels(unquote(path)),
+                _ => Err(usage("ll \"file\"")),
+            },
+            "shl" => {
+                let mut output = String::new();
+                for (addr, name) in &self.names {
+                    writeln!(output, "{addr:04X} .{name}").unwrap();
+                }
+                Ok(output)
+            }
+            "history" => {
+                let mut output = String::new();
+                for (index, line) in self.history.iter().enumerate() {
+                    writeln!(output, "{:4}  {line}", index + 1).unwrap();
+                }
+                Ok(output)
+            }
+            "help" | "?" => Ok(HELP.to_string()),
+            "x" | "q" => {
+                self.done = true;
+                Ok(String::new())
+            }
+            _ => Err(CommandError::new(format!("unknown command '{command}'"))),
+        }
+    }
+
+    /// Loads a binary file into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to load into.
+    /// * `path` - The file to load.
+    /// * `addr` - Where to load it. Without one, the file is a PRG: its
+    ///   first two bytes are the load address, low byte first.
+    ///
+    /// # Returns
+    ///
+    /// The addresses written.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CommandError` if the file cannot be read, is empty, or
+    /// does not fit below $10000.
+    pub fn load<M: Machine, P: AsRef<Path>>(
+        &mut self,
+        machine: &mut M,
+        path: P,
+        addr: Option<u16>,
+    ) -> Result<RangeInclusive<u16>, CommandError> {
+        let data = std::fs::read(path)?;
+        let (start, data) = match addr {
+            Some(addr) => (addr, data.as_slice()),
+            None => match data.as_slice() {
+                [lo, hi, data @ ..] => (u16::from_le_bytes([*lo, *hi]), data),
+                _ => return Err(CommandError::new("file has no load address")),
+            },
+        };
+        if data.is_empty() {
+            return Err(CommandError::new("file is empty"));
+        }
+        if start as usize + data.len() > 0x10000 {
+            return Err(CommandError::new("file does not fit in memory"));
+        }
+        let bus = &mut machine.cpu_mut().bus;
+        for (addr, &byte) in (start..).zip(data) {
+            bus.write(addr, byte);
+        }
+        Ok(start..=start + (data.len() - 1) as u16)
+    }
+
+    /// Loads labels from a ca65 debug info file, if the name ends in `.dbg`,
+    /// or a VICE label file of `al C:addr .name` lines.
+    fn load_labels(&mut self, path: &str) -> CommandResult {
+        let before = self.labels.len();
+        if path.ends_with(".dbg") {
+            let info = DebugInfo::load(path).map_err(|err| CommandError::new(err.to_string()))?;
+            for symbol in info.symbols() {
+                self.add_label(&symbol.name, symbol.addr);
+            }
+        } else {
+            for (index, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+                let error = || CommandError::new(format!("invalid label at line {}", index + 1));
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (None, ..) => {}
+                    (Some("al"), Some(addr), Some(name)) => {
+                        let addr = addr.split_once(':').map_or(addr, |(_, addr)| addr);
+                        let addr = parse_number(addr).ok_or_else(error)?;
+                        self.add_label(name.strip_prefix('.').unwrap_or(name), addr);
+                    }
+                    _ => return Err(error()),
+                }
+            }
+        }
+        Ok(format!("loaded {} labels\n", self.labels.len() - before))
+    }
+
+    /// Expands a history reference: `!!` for the last line, `!n` for line
+    /// `n`, or `!prefix` for the last line starting with `prefix`.
+    fn expand_history(&self, line: &str) -> Result<String, CommandError> {
+        let Some(reference) = line.strip_prefix('!') else {
+            return Ok(line.to_string());
+        };
+        let found = if reference == "!" {
+            self.history.last()
+        } else if let Ok(index) = reference.parse::<usize>() {
+            index
+                .checked_sub(1)
+                .and_then(|index| self.history.get(index))
+        } else {
+            self.history
+                .iter()
+                .rev()
+                .find(|line| line.starts_with(reference))
+        };
+        found
+            .cloned()
+            .ok_or_else(|| CommandError::new(format!("no history for '{line}'")))
+    }
+
+    /// Parses an address as commands do: a hex number or a label, with an
+    /// optional hex offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `CommandError` if `text` is neither.
+    pub fn parse_addr(&self, text: &str) -> Result<u16, CommandError> {
+        let (base, offset) = match text.rfind(['+', '-']) {
+            Some(index) if index > 0 => (&text[..index], Some(&text[index..])),
+            _ => (text, None),
+        };
+        let name = base.strip_prefix('.');
+        let value = name
+            .is_none()
+            .then(|| parse_number(base))
+            .flatten()
+            .or_else(|| self.labels.get(name.unwrap_or(base)).copied())
+            .ok_or_else(|| CommandError::new(format!("unknown address '{text}'")))?;
+        match offset {
+            None => Ok(value),
+            Some(offset) => {
+                let amount = parse_number(&offset[1..])
+                    .ok_or_else(|| CommandError::new(format!("invalid offset in '{text}'")))?;
+                Ok(if offset.starts_with('+') {
+                    value.wrapping_add(amount)
+                } else {
+                    value.wrapping_sub(amount)
+                })
+            }
+        }
+    }
+
+    /// Parses a `start end` range.
+    fn parse_range(&self, start: &str, end: &str) -> Result<RangeInclusive<u16>, CommandError> {
+        let (start, end) = (self.parse_addr(start)?, self.parse_addr(end)?);
+        if end < start {
+            return Err(CommandError::new("the range ends before it starts"));
+        }
+        Ok(start..=end)
+    }
+
+    /// Formats one disassembled instruction, returning the address of the
+    /// next.
+    fn disassemble_line<B: Bus>(&self, bus: &B, addr: u16, output: &mut String) -> u16 {
+        if let Some(name) = self.label_at(addr) {
+            writeln!(output, "{name}:").unwrap();
+        }
+        let Some(instruction) = disassemble(bus, addr) else {
+            writeln!(output, ".{addr:04X}  ??").unwrap();
+            return addr.wrapping_add(1);
+        };
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let operand = instruction.operand_text(|addr| self.label_at(addr).map(str::to_string));
+        let text = format!("{} {operand}", instruction.mnemonic);
+        writeln!(
+            output,
+            ".{addr:04X}  {:<9} {}",
+            bytes.join(" "),
+            text.trim_end()
+        )
+        .unwrap();
+        instruction.next_addr()
+    }
+
+    fn disassemble<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let cpu = machine.cpu();
+        let (start, end) = match args {
+            [] => (self.next_disassemble.unwrap_or(cpu.registers.pc), None),
+            [start] => (self.parse_addr(start)?, None),
+            [start, end] => {
+                let range = self.parse_range(start, end)?;
+                (*range.start(), Some(*range.end()))
+            }
+            _ => return Err(usage("d [start [end]]")),
+        };
+        let mut output = String::new();
+        let mut addr = start;
+        let mut lines = 0;
+        loop {
+            let next = self.disassemble_line(&cpu.bus, addr, &mut output);
+            lines += 1;
+            let finished = match end {
+                Some(end) => next > end || next <= addr,
+                None => lines == DISASSEMBLE_LINES,
+            };
+            addr = next;
+            if finished {
+                break;
+            }
+        }
+        self.next_disassemble = Some(addr);
+        self.repeat = Some("d");
+        Ok(output)
+    }
+
+    fn dump<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let (start, end) = match args {
+            [] => {
+                let start = self.next_dump.unwrap_or(machine.cpu().registers.pc);
+                (start, start.saturating_add(DUMP_BYTES - 1))
+            }
+            [start] => {
+                let start = self.parse_addr(start)?;
+                (start, start.saturating_add(DUMP_BYTES - 1))
+            }
+            [start, end] => {
+                let range = self.parse_range(start, end)?;
+                (*range.start(), *range.end())
+            }
+            _ => return Err(usage("m [start [end]]")),
+        };
+        let bus = &machine.cpu().bus;
+        let mut output = String::new();
+        for line in (start as u32..=end as u32).step_by(DUMP_WIDTH as usize) {
+            let line = line as u16;
+            let last = line.saturating_add(DUMP_WIDTH - 1).min(end);
+            let bytes: Vec<Option<u8>> = (line..=last).map(|addr| bus.peek(addr)).collect();
+            let hex: Vec<String> = bytes
+                .iter()
+                .map(|byte| byte.map_or("??".to_string(), |byte| format!("{byte:02X}")))
+                .collect();
+            let text: String = bytes
+                .iter()
+                .map(|byte| match byte {
+                    Some(byte @ 0x20..=0x7E) => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+            let width = DUMP_WIDTH as usize * 3 - 1;
+            writeln!(output, ">{line:04X}  {:<width$}  {text}", hex.join(" ")).unwrap();
+        }
+        self.next_dump = Some(end.wrapping_add(1));
+        self.repeat = Some("m");
+        Ok(output)
+    }
+
+    fn edit<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let [addr, bytes @ ..] = args else {
+            return Err(usage("> addr bytes..."));
+        };
+        let addr = self.parse_addr(addr)?;
+        let bytes = parse_bytes(bytes)?;
+        if bytes.is_empty() {
+            return Err(usage("> addr bytes..."));
+        }
+        let bus = &mut machine.cpu_mut().bus;
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            bus.write(addr.wrapping_add(offset as u16), byte);
+        }
+        Ok(String::new())
+    }
+
+    fn registers<M: Machine>(&mut self, machine: &mut M, rest: &str) -> CommandResult {
+        let spaced = rest.replace('=', " = ").replace(',', " ");
+        let words: Vec<&str> = spaced.split_whitespace().collect();
+        let mut assignments = Vec::new();
+        for assignment in words.chunks(3) {
+            let [name, "=", value] = assignment else {
+                return Err(usage("r [reg=value ...]"));
+            };
+            let name = name.to_lowercase();
+            let value = if name == "pc" {
+                self.parse_addr(value)?
+            } else {
+                parse_number(value)
+                    .filter(|&value| value <= 0xFF)
+                    .ok_or_else(|| CommandError::new(format!("invalid value '{value}'")))?
+            };
+            if !["a", "x", "y", "sp", "pc", "p"].contains(&name.as_str()) {
+                return Err(CommandError::new(format!("unknown register '{name}'")));
+            }
+            assignments.push((name, value));
+        }
+        let registers = &mut machine.cpu_mut().registers;
+        for (name, value) in assignments {
+            match name.as_str() {
+                "a" => registers.a = value as u8,
+                "x" => registers.x = value as u8,
+                "y" => registers.y = value as u8,
+                "sp" => registers.sp = value as u8,
+                "pc" => registers.pc = value,
+                _ => registers.status.from_byte(value as u8),
+            }
+        }
+        Ok(registers_text(machine))
+    }
+
+    fn breakpoint(&mut self, rest: &str) -> CommandResult {
+        if rest.is_empty() {
+            let mut output = String::new();
+            for breakpoint in self.debugger.breakpoints() {
+                write!(output, "{:04X}", breakpoint.addr).unwrap();
+                if let Some(name) = self.label_at(breakpoint.addr) {
+                    write!(output, " .{name}").unwrap();
+                }
+                write!(output, "  hits {}", breakpoint.hits).unwrap();
+                if let Some(condition) = &breakpoint.condition {
+                    write!(output, "  if {condition}").unwrap();
+                }
+                output.push('\n');
+            }
+            return Ok(output);
+        }
+        let (addr, condition) = match rest.split_once(char::is_whitespace) {
+            Some((addr, condition)) => (addr, condition.trim()),
+            None => (rest, ""),
+        };
+        let addr = self.parse_addr(addr)?;
+        let condition = condition.strip_prefix("if ").unwrap_or(condition).trim();
+        if condition.is_empty() {
+            self.debugger.set_breakpoint(addr);
+        } else {
+            self.debugger
+                .set_conditional_breakpoint(addr, condition)
+                .map_err(|err| CommandError::new(err.to_string()))?;
+        }
+        Ok(format!("breakpoint at ${addr:04X}\n"))
+    }
+
+    fn clear_breakpoint(&mut self, args: &[String]) -> CommandResult {
+        match args {
+            [] => self.debugger.clear_breakpoints(),
+            [addr] => {
+                let addr = self.parse_addr(addr)?;
+                if !self.debugger.remove_breakpoint(addr) {
+                    return Err(CommandError::new(format!("no breakpoint at ${addr:04X}")));
+                }
+            }
+            _ => return Err(usage("bc [addr]")),
+        }
+        Ok(String::new())
+    }
+
+    fn step<M: Machine>(
+        &mut self,
+        machine: &mut M,
+        args: &[String],
+        how: Resume,
+        command: &'static str,
+    ) -> CommandResult {
+        let count = match args {
+            [] => 1,
+            [count] => parse_number(count)
+                .ok_or_else(|| CommandError::new(format!("invalid count '{count}'")))?,
+            _ => return Err(usage(&format!("{command} [count]"))),
+        };
+        let mut output = String::new();
+        for _ in 0..count {
+            let stop = self.debugger.resume(machine, how, GO_LIMIT);
+            let cpu = machine.cpu();
+            if stop != Stop::StepComplete {
+                writeln!(output, "{}", stop_text(stop)).unwrap();
+                self.disassemble_line(&cpu.bus, cpu.registers.pc, &mut output);
+                break;
+            }
+            self.disassemble_line(&cpu.bus, cpu.registers.pc, &mut output);
+        }
+        self.next_disassemble = None;
+        self.repeat = Some(command);
+        Ok(output)
+    }
+
+    fn go<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        match args {
+            [] => {}
+            [addr] => machine.cpu_mut().registers.pc = self.parse_addr(addr)?,
+            _ => return Err(usage("g [addr]")),
+        }
+        let stop = self.debugger.resume(machine, Resume::Continue, GO_LIMIT);
+        let mut output = format!("{}\n", stop_text(stop));
+        output.push_str(&registers_text(machine));
+        let cpu = machine.cpu();
+        self.disassemble_line(&cpu.bus, cpu.registers.pc, &mut output);
+        self.next_disassemble = None;
+        Ok(output)
+    }
+
+    fn fill<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let [start, end, pattern @ ..] = args else {
+            return Err(usage("f start end bytes..."));
+        };
+        let range = self.parse_range(start, end)?;
+        let pattern = parse_bytes(pattern)?;
+        if pattern.is_empty() {
+            return Err(usage("f start end bytes..."));
+        }
+        let bus = &mut machine.cpu_mut().bus;
+        for (addr, &byte) in range.zip(pattern.iter().cycle()) {
+            bus.write(addr, byte);
+        }
+        Ok(String::new())
+    }
+
+    fn hunt<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let [start, end, pattern @ ..] = args else {
+            return Err(usage("h start end bytes..."));
+        };
+        let range = self.parse_range(start, end)?;
+        let pattern = parse_bytes(pattern)?;
+        if pattern.is_empty() {
+            return Err(usage("h start end bytes..."));
+        }
+        let memory = peek_range(&machine.cpu().bus, range.clone());
+        let found: Vec<u16> = memory
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, window)| {
+                window
+                    .iter()
+                    .zip(&pattern)
+                    .all(|(byte, wanted)| *byte == Some(*wanted))
+            })
+            .map(|(offset, _)| range.start() + offset as u16)
+            .collect();
+        Ok(address_list(&found))
+    }
+
+    fn compare<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let [start, end, dest] = args else {
+            return Err(usage("c start end dest"));
+        };
+        let range = self.parse_range(start, end)?;
+        let dest = self.parse_addr(dest)?;
+        let bus = &machine.cpu().bus;
+        let base = *range.start();
+        let differing: Vec<u16> = range
+            .filter(|&addr| bus.peek(addr) != bus.peek(dest.wrapping_add(addr - base)))
+            .collect();
+        Ok(address_list(&differing))
+    }
+
+    fn transfer<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let [start, end, dest] = args else {
+            return Err(usage("t start end dest"));
+        };
+        let range = self.parse_range(start, end)?;
+        let dest = self.parse_addr(dest)?;
+        let bus = &mut machine.cpu_mut().bus;
+        let data = peek_range(bus, range.clone());
+        if let Some(offset) = data.iter().position(Option::is_none) {
+            let addr = range.start() + offset as u16;
+            return Err(CommandError::new(format!("${addr:04X} cannot be read")));
+        }
+        for (offset, byte) in data.into_iter().flatten().enumerate() {
+            bus.write(dest.wrapping_add(offset as u16), byte);
+        }
+        Ok(String::new())
+    }
+
+    fn save<M: Machine>(&mut self, machine: &mut M, args: &[String]) -> CommandResult {
+        let [path, start, end] = args else {
+            return Err(usage("s \"file\" start end"));
+        };
+        let range = self.parse_range(start, end)?;
+        let data: Option<Vec<u8>> = peek_range(&machine.cpu().bus, range.clone())
+            .into_iter()
+            .collect();
+        let data = data.ok_or_else(|| CommandError::new("the range cannot be read"))?;
+        std::fs::write(unquote(path), data)?;
+        Ok(format!(
+            "saved ${:04X}-${:04X}\n",
+            range.start(),
+            range.end()
+        ))
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits arguments at whitespace and commas, keeping quoted strings whole
+/// and with their quotes.
+fn tokenize(text: &str) -> Result<Vec<String>, CommandError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '"' {
+            let mut token = String::from(chars.next().unwrap());
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err(CommandError::new("unterminated string")),
+                }
+            }
+            token.push('"');
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == '"' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Removes the quotes from a quoted token.
+fn unquote(token: &str) -> &str {
+    token
+        .strip_prefix('"')
+        .and_then(|token| token.strip_suffix('"'))
+        .unwrap_or(token)
+}
+
+/// Parses a hex number, with or without a `$`.
+fn parse_number(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.strip_prefix('$').unwrap_or(text), 16).ok()
+}
+
+/// Parses a list of hex bytes and quoted strings.
+fn parse_bytes(tokens: &[String]) -> Result<Vec<u8>, CommandError> {
+    let mut bytes = Vec::new();
+    for token in tokens {
+        if token.starts_with('"') {
+            bytes.extend(unquote(token).bytes());
+        } else {
+            let byte = parse_number(token)
+                .and_then(|byte| u8::try_from(byte).ok())
+                .ok_or_else(|| CommandError::new(format!("invalid byte '{token}'")))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Peeks every byte of a range.
+fn peek_range<B: Bus>(bus: &B, range: RangeInclusive<u16>) -> Vec<Option<u8>> {
+    range.map(|addr| bus.peek(addr)).collect()
+}
+
+/// Formats addresses, a few to a line.
+fn address_list(addresses: &[u16]) -> String {
+    let mut output = String::new();
+    for line in addresses.chunks(ADDRESSES_PER_LINE) {
+        let line: Vec<String> = line.iter().map(|addr| format!("{addr:04X}")).collect();
+        writeln!(output, "{}", line.join(" ")).unwrap();
+    }
+    output
+}
+
+/// Formats the registers as VICE does.
+fn registers_text<M: Machine>(machine: &M) -> String {
+    let cpu = machine.cpu();
+    let registers = &cpu.registers;
+    format!(
+        "  ADDR A  X  Y  SP NV-BDIZC CYCLES\n.;{:04X} {:02X} {:02X} {:02X} {:02X} {:08b} {}\n",
+        registers.pc,
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.sp,
+        registers.status.to_byte(),
+        cpu.cycles()
+    )
+}
+
+/// Describes why execution stopped.
+fn stop_text(stop: Stop) -> String {
+    match stop {
+        Stop::Limit => format!("stopped after {GO_LIMIT} instructions"),
+        stop => format!("stopped: {stop}"),
+    }
+}
+
+fn usage(text: &str) -> CommandError {
+    CommandError::new(format!("usage: {text}"))
+}
@@ -0,0 +1,307 @@
+//! The `nestest` module runs the nestest CPU test ROM against its reference
+//! log.
+//!
+//! nestest, by kevtris, exercises the whole NMOS instruction set, including
+//! the undocumented opcodes. Started at `$C000` instead of its reset vector,
+//! it runs without a PPU, and the reference log made with Nintendulator
+//! gives the registers and cycle count before every instruction. `compare`
+//! steps a CPU through the log and stops at the first instruction that does
+//! not match, with the lines before it for context.
+//!
+//! The CPU implements the undocumented opcodes, so the whole log is
+//! checked. Should the log reach an opcode the CPU does not implement, a
+//! JAM, `compare` stops there if the log marks it as undocumented, and
+//! reports where in the `Comparison`.
+//!
+//! The ROM and log are not distributed with this crate. To run the test
+//! against them:
+//!
+//! ```text
+//! NESTEST_DIR=path/to/dir cargo test nestest -- --ignored
+//! ```
+//!
+//! where the directory holds `nestest.nes` and `nestest.log`.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::trace::{Nestest, TraceFormat, TraceRecord};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Where nestest starts in automation mode.
+pub const START: u16 = 0xC000;
+
+/// The number of matching lines shown before a divergence.
+const CONTEXT: usize = 5;
+
+/// An error running nestest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NestestError {
+    /// The ROM is not an iNES file.
+    NotInes,
+    /// The ROM uses a mapper other than NROM (mapper 0).
+    UnsupportedMapper(u8),
+    /// The ROM is shorter than its header says.
+    Truncated,
+    /// A line of the reference log is malformed.
+    BadLogLine {
+        /// The line number in the log, starting at 1.
+        line: usize,
+    },
+    /// The CPU did not do what the log says.
+    Diverged(Divergence),
+}
+
+/// How far a comparison got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comparison {
+    /// The number of lines that matched.
+    pub matched: usize,
+    /// The line number of the undocumented opcode where the comparison
+    /// stopped, or `None` if it reached the end of the log.
+    pub stopped_at: Option<usize>,
+}
+
+impl fmt::Display for NestestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NestestError::NotInes => write!(f, "not an iNES ROM"),
+            NestestError::UnsupportedMapper(mapper) => {
+                write!(f, "mapper {mapper} is not supported; only NROM is")
+            }
+            NestestError::Truncated => write!(f, "the ROM is truncated"),
+            NestestError::BadLogLine { line } => write!(f, "malformed log line {line}"),
+            NestestError::Diverged(divergence) => write!(f, "{divergence}"),
+        }
+    }
+}
+
+impl std::error::Error for NestestError {}
+
+/// The first instruction at which the CPU and the reference log disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The line number in the log, starting at 1.
+    pub line: usize,
+    /// The fields that differ, such as `"PC"`, `"A"` or `"CYC"`.
+    pub fields: Vec<&'static str>,
+    /// The line of the log.
+    pub expected: String,
+    /// The same line for the CPU, or why there is none.
+    pub actual: String,
+    /// The matching log lines before it, oldest first.
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "diverged from the log at line {} ({}):",
+            self.line,
+            self.fields.join(", ")
+        )?;
+        for line in &self.context {
+            writeln!(f, "  {line}")?;
+        }
+        writeln!(f, "- {}", self.expected)?;
+        write!(f, "+ {}", self.actual)
+    }
+}
+
+/// Creates a CPU with 64KB of RAM and an NROM cartridge, ready to run
+/// nestest in automation mode.
+///
+/// The PRG ROM is mapped at `$8000`, mirrored at `$C000` if it is 16KB.
+/// The CPU starts at `$C000` as it would be after reset: SP `$FD`, P `$24`
+/// and 7 cycles.
+///
+/// # Arguments
+///
+/// * `rom` - The contents of an iNES file.
+///
+/// # Errors
+///
+/// Returns an error if the ROM is not an iNES file with mapper 0.
+pub fn machine(rom: &[u8]) -> Result<CPU<Vec<u8>>, NestestError> {
+    if rom.len() < 16 || &rom[..4] != b"NES\x1A" {
+        return Err(NestestError::NotInes);
+    }
+    let mapper = (rom[6] >> 4) | (rom[7] & 0xF0);
+    if mapper != 0 {
+        return Err(NestestError::UnsupportedMapper(mapper));
+    }
+    // A 512 byte trainer may come between the header and the PRG ROM
+    let start = if rom[6] & 0x04 != 0 { 16 + 512 } else { 16 };
+    let banks = match rom[4] {
+        1 | 2 => rom[4] as usize,
+        _ => return Err(NestestError::UnsupportedMapper(mapper)),
+    };
+    let prg = rom
+        .get(start..start + banks * 0x4000)
+        .ok_or(NestestError::Truncated)?;
+
+    let mut cpu = CPU::new(vec![0u8; 0x10000]);
+    cpu.bus[0x8000..0x8000 + prg.len()].copy_from_slice(prg);
+    if banks == 1 {
+        cpu.bus[0xC000..].copy_from_slice(prg);
+    }
+    cpu.registers.pc = START;
+    cpu.registers.sp = 0xFD;
+    cpu.registers.status.from_byte(0x24);
+    cpu.set_cycles(7);
+    Ok(cpu)
+}
+
+/// What a line of the log says.
+#[derive(Debug, PartialEq, Eq)]
+struct Expected {
+    pc: u16,
+    bytes: Vec<u8>,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    sp: u8,
+    cycles: u64,
+}
+
+/// Returns `true` if a line of the log is for an undocumented opcode, which
+/// Nintendulator marks with a `*` before the mnemonic.
+fn is_undocumented(line: &str) -> bool {
+    line.as_bytes().get(15) == Some(&b'*')
+}
+
+/// Parses a line of a Nintendulator log, ignoring the disassembly and the
+/// PPU column.
+fn parse_line(line: &str) -> Option<Expected> {
+    let pc = u16::from_str_radix(line.get(..4)?, 16).ok()?;
+    let bytes = line
+        .get(6..15)?
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    let registers = &line[line.find("A:")?..];
+    let field = |name: &str| {
+        registers
+            .split_whitespace()
+            .find_map(|token| token.strip_prefix(name))
+    };
+    let byte = |name: &str| u8::from_str_radix(field(name)?, 16).ok();
+    Some(Expected {
+        pc,
+        bytes,
+        a: byte("A:")?,
+        x: byte("X:")?,
+        y: byte("Y:")?,
+        p: byte("P:")?,
+        sp: byte("SP:")?,
+        cycles: field("CYC:")?.parse().ok()?,
+    })
+}
+
+/// Formats a record as a log line.
+fn format_line(record: &TraceRecord) -> String {
+    let mut line = Vec::new();
+    // Writing to a Vec cannot fail
+    Nestest.write(record, &mut line).ok();
+    String::from_utf8_lossy(&line).trim_end().to_string()
+}
+
+/// Steps the CPU through the reference log, checking the PC, the
+/// instruction bytes, the registers and the cycle count before each
+/// instruction.
+///
+/// # Arguments
+///
+/// * `cpu` - The CPU, usually from `machine`.
+/// * `log` - The reference log.
+///
+/// # Returns
+///
+/// How far it got: to the end of the log, or to the first undocumented
+/// opcode the CPU does not implement, which is left unexecuted.
+///
+/// # Errors
+///
+/// Returns `NestestError::Diverged` at the first line that does not
+/// match, including a documented opcode the CPU does not implement, and
+/// `NestestError::BadLogLine` for a line that cannot be parsed.
+pub fn compare<B: Bus>(cpu: &mut CPU<B>, log: &str) -> Result<Comparison, NestestError> {
+    let mut context = VecDeque::with_capacity(CONTEXT);
+    let mut matched = 0;
+    for (index, line) in log
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+    {
+        let line = line.trim_end();
+        let expected = parse_line(line).ok_or(NestestError::BadLogLine { line: index + 1 })?;
+        let diverged = |fields, actual| {
+            NestestError::Diverged(Divergence {
+                line: index + 1,
+                fields,
+                expected: line.to_string(),
+                actual,
+                context: context.iter().cloned().collect(),
+            })
+        };
+        let Some(record) = TraceRecord::capture(cpu, matched as u64) else {
+            return Err(diverged(
+                vec!["PC"],
+                "the opcode cannot be read".to_string(),
+            ));
+        };
+        let registers = &record.registers;
+        let opcode = record.instruction.bytes[0];
+        let implemented = cpu.is_implemented(opcode);
+        // Only the opcode of an unknown instruction is disassembled
+        let bytes = if implemented {
+            record.instruction.bytes == expected.bytes
+        } else {
+            expected.bytes.first() == Some(&opcode)
+        };
+        let checks = [
+            ("PC", record.instruction.addr == expected.pc),
+            ("bytes", bytes),
+            ("A", registers.a == expected.a),
+            ("X", registers.x == expected.x),
+            ("Y", registers.y == expected.y),
+            ("P", registers.status.to_byte() == expected.p),
+            ("SP", registers.sp == expected.sp),
+            ("CYC", record.cycles == expected.cycles),
+        ];
+        let fields: Vec<&'static str> = checks
+            .iter()
+            .filter(|(_, ok)| !ok)
+            .map(|(name, _)| *name)
+            .collect();
+        if !fields.is_empty() {
+            return Err(diverged(fields, format_line(&record)));
+        }
+        if !implemented && is_undocumented(line) {
+            return Ok(Comparison {
+                matched,
+                stopped_at: Some(index + 1),
+            });
+        }
+        if !implemented {
+            let actual = format!(
+                "{}  (opcode ${opcode:02X} is not implemented)",
+                format_line(&record)
+            );
+            return Err(diverged(vec!["opcode"], actual));
+        }
+        cpu.step();
+        if context.len() == CONTEXT {
+            context.pop_front();
+        }
+        context.push_back(line.to_string());
+        matched += 1;
+    }
+    Ok(Comparison {
+        matched,
+        stopped_at: None,
+    })
+}
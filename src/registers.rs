@@ -1,6 +1,8 @@
 //! The `registers` module defines the CPU registers for the 6502.
 
 /// The `Registers` struct represents the 6502 CPU registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers {
     /// Accumulator (A)
     pub a: u8,
@@ -30,7 +32,15 @@ impl Registers {
     }
 }
 
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// The `StatusFlags` struct represents the status flags for the 6502.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StatusFlags {
     /// N flag (bit 7)
     pub negative: bool,
@@ -126,3 +136,9 @@ impl StatusFlags {
         self.carry = byte & 1 != 0;
     }
 }
+
+impl Default for StatusFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,424 @@
+//! The `replay` module records the inputs a machine receives and replays them.
+//!
+//! Given the same program and the same inputs, `CPU::step` always does the
+//! same thing. What differs between two sessions is what comes from outside:
+//! values read from input devices, interrupts, and bytes the host feeds to
+//! devices, such as key presses into a UART. An `InputRecorder` captures
+//! these, each stamped with `CPU::cycles()`, into an `InputLog`.
+//!
+//! Device inputs are the reads from the address ranges marked as inputs when
+//! the recorder is created, typically the registers of every device fed from
+//! the host. Interrupts are recorded by the CPU itself, so those raised by a
+//! `Scheduler`'s devices or by calling `CPU::irq` directly are captured as
+//! well as those delivered through the recorder. To record a machine driven
+//! by a `Scheduler`, hand it the recorder with `Scheduler::set_recorder`.
+//!
+//! To replay a log, wrap the machine's bus in a `ReplayBus` and
+//! call `CPU::replay_step` instead of `CPU::step`. Reads from the input
+//! ranges are answered from the log without touching the devices, and
+//! interrupts and host inputs are delivered at their recorded cycles, so the
+//! session is reproduced exactly. The log stands in for the devices, so a
+//! replay runs the CPU on its own rather than under the `Scheduler`.
+//!
+//! ```
+//! use lib6502::bus::Bus;
+//! use lib6502::cpu::CPU;
+//! use lib6502::replay::{InputRecorder, ReplayBus};
+//!
+//! // LDA $6000 ; STA $0200 ; JMP $8000
+//! let program = [0xAD, 0x00, 0x60, 0x8D, 0x00, 0x02, 0x4C, 0x00, 0x80];
+//! let mut memory = vec![0u8; 0x10000];
+//! memory[0x8000..0x8009].copy_from_slice(&program);
+//! memory[0xFFFD] = 0x80;
+//!
+//! let mut cpu = CPU::new(memory.clone());
+//! cpu.reset();
+//! let mut recorder = InputRecorder::new(vec![0x6000..=0x600F]);
+//! cpu.bus.write(0x6000, 0x41); // a key press
+//! for _ in 0..3 {
+//!     recorder.step(&mut cpu);
+//! }
+//!
+//! let mut replay = CPU::new(ReplayBus::new(memory, recorder.into_log()));
+//! replay.reset();
+//! for _ in 0..3 {
+//!     replay.replay_step().unwrap();
+//! }
+//! assert_eq!(replay.bus.inner()[0x0200], 0x41);
+//! ```
+
+use crate::bus::{AccessKind, Bus};
+use crate::cpu::{Interrupt, CPU};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// A single input to the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Input {
+    /// The CPU read `data` from an input address.
+    Read {
+        /// The address read.
+        addr: u16,
+        /// The value the device returned.
+        data: u8,
+    },
+    /// The CPU took an IRQ.
+    Irq,
+    /// The CPU took an NMI.
+    Nmi,
+    /// The host fed a byte to a device, such as a key press into a UART.
+    Host {
+        /// Identifies the device, as chosen by the board.
+        channel: u32,
+        /// The byte fed to the device.
+        data: u8,
+    },
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Read { addr, data } => write!(f, "read ${addr:04X} = ${data:02X}"),
+            Input::Irq => write!(f, "IRQ"),
+            Input::Nmi => write!(f, "NMI"),
+            Input::Host { channel, data } => write!(f, "host input {channel} = ${data:02X}"),
+        }
+    }
+}
+
+/// An input stamped with the cycle at which it happened.
+///
+/// Reads are stamped with the cycle at which the instruction that made them
+/// started. Interrupts and host inputs are stamped with the cycle between
+/// instructions at which they were delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputEvent {
+    /// The value of `CPU::cycles()` when the input happened.
+    pub cycle: u64,
+    /// The input.
+    pub input: Input,
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at cycle {}", self.input, self.cycle)
+    }
+}
+
+/// A recorded session: the input address ranges and every input, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputLog {
+    /// The address ranges whose reads are inputs.
+    pub ranges: Vec<RangeInclusive<u16>>,
+    /// The inputs, in the order they happened.
+    pub events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    /// Returns `true` if `addr` lies in one of the input ranges.
+    pub fn is_input(&self, addr: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&addr))
+    }
+}
+
+/// Executes instructions and records every input the machine receives.
+pub struct InputRecorder {
+    log: InputLog,
+}
+
+impl InputRecorder {
+    /// Creates a new `InputRecorder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ranges` - The address ranges of the input devices. Reads from them
+    ///   are recorded; reads from anywhere else are assumed to be
+    ///   deterministic.
+    pub fn new(ranges: Vec<RangeInclusive<u16>>) -> Self {
+        Self {
+            log: InputLog {
+                ranges,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    /// Returns the log recorded so far.
+    pub fn log(&self) -> &InputLog {
+        &self.log
+    }
+
+    /// Consumes the recorder and returns the log.
+    pub fn into_log(self) -> InputLog {
+        self.log
+    }
+
+    /// Records the interrupts taken since the last step, executes one
+    /// instruction and records the inputs it read.
+    ///
+    /// Access and interrupt recording are enabled on the CPU, since the
+    /// recorder needs to see every read and every interrupt.
+    pub fn step<B: Bus>(&mut self, cpu: &mut CPU<B>) {
+        self.collect_interrupts(cpu);
+        let cycle = cpu.cycles();
+        cpu.step();
+        for access in cpu.accesses() {
+            if access.kind == AccessKind::Read && self.log.is_input(access.addr) {
+                self.log.events.push(InputEvent {
+                    cycle,
+                    input: Input::Read {
+                        addr: access.addr,
+                        data: access.data,
+                    },
+                });
+            }
+        }
+    }
+
+    /// Delivers an IRQ to the CPU and records it if it was taken.
+    pub fn irq<B: Bus>(&mut self, cpu: &mut CPU<B>) {
+        self.collect_interrupts(cpu);
+        cpu.irq();
+        self.collect_interrupts(cpu);
+    }
+
+    /// Delivers an NMI to the CPU and records it.
+    pub fn nmi<B: Bus>(&mut self, cpu: &mut CPU<B>) {
+        self.collect_interrupts(cpu);
+        cpu.nmi();
+        self.collect_interrupts(cpu);
+    }
+
+    /// Records a byte fed by the host to a device.
+    ///
+    /// The caller still delivers the byte to the device itself. On replay,
+    /// the byte is handed to the callback set with `ReplayBus::on_host_input`.
+    pub fn host_input<B: Bus>(&mut self, cpu: &mut CPU<B>, channel: u32, data: u8) {
+        self.collect_interrupts(cpu);
+        self.record(cpu, Input::Host { channel, data });
+    }
+
+    /// Appends an input stamped with the CPU's current cycle.
+    fn record<B: Bus>(&mut self, cpu: &CPU<B>, input: Input) {
+        self.log.events.push(InputEvent {
+            cycle: cpu.cycles(),
+            input,
+        });
+    }
+
+    /// Makes sure the CPU records what the recorder needs, and appends the
+    /// interrupts it has taken since the last call.
+    pub(crate) fn collect_interrupts<B: Bus>(&mut self, cpu: &mut CPU<B>) {
+        if !cpu.is_recording_accesses() {
+            cpu.set_access_recording(true);
+        }
+        if !cpu.is_recording_interrupts() {
+            cpu.set_interrupt_recording(true);
+        }
+        for (cycle, interrupt) in cpu.take_interrupts() {
+            let input = match interrupt {
+                Interrupt::Irq => Input::Irq,
+                Interrupt::Nmi => Input::Nmi,
+            };
+            self.log.events.push(InputEvent { cycle, input });
+        }
+    }
+}
+
+/// The point at which a replay stopped matching its log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The CPU's cycle count when the mismatch was found.
+    pub cycle: u64,
+    /// The next input in the log, if any.
+    pub expected: Option<InputEvent>,
+    /// The input the replayed machine produced instead, if any.
+    pub found: Option<Input>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay diverged at cycle {}: expected ", self.cycle)?;
+        match &self.expected {
+            Some(event) => write!(f, "{event}")?,
+            None => write!(f, "end of log")?,
+        }
+        match &self.found {
+            Some(input) => write!(f, ", found {input}"),
+            None => write!(f, ", found nothing"),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// The callback that delivers host inputs to the devices during replay.
+type HostInput<B> = Box<dyn FnMut(&mut B, u32, u8)>;
+
+/// A bus adapter that answers input reads from a recorded `InputLog`.
+///
+/// Reads from the log's input ranges return the recorded values without
+/// reaching the wrapped bus. Everything else, including writes to the input
+/// ranges, goes to the wrapped bus as usual.
+pub struct ReplayBus<B: Bus> {
+    inner: B,
+    ranges: Vec<RangeInclusive<u16>>,
+    events: VecDeque<InputEvent>,
+    host_input: Option<HostInput<B>>,
+    /// The cycle at which the current instruction started.
+    now: u64,
+    divergence: Option<Divergence>,
+}
+
+impl<B: Bus> ReplayBus<B> {
+    /// Creates a new `ReplayBus` replaying `log` on top of `inner`.
+    pub fn new(inner: B, log: InputLog) -> Self {
+        Self {
+            inner,
+            ranges: log.ranges,
+            events: log.events.into(),
+            host_input: None,
+            now: 0,
+            divergence: None,
+        }
+    }
+
+    /// Sets the callback that delivers host inputs to the devices.
+    ///
+    /// Without one, host inputs are skipped. That is fine as long as the
+    /// devices they feed are read only through the input ranges.
+    pub fn on_host_input<F: FnMut(&mut B, u32, u8) + 'static>(&mut self, callback: F) {
+        self.host_input = Some(Box::new(callback));
+    }
+
+    /// Returns the inputs not yet replayed, oldest first.
+    pub fn remaining(&self) -> impl Iterator<Item = &InputEvent> {
+        self.events.iter()
+    }
+
+    /// Returns `true` once every input in the log has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns a reference to the wrapped bus.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the adapter and returns the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Returns `true` if `addr` lies in one of the input ranges.
+    fn is_input(&self, addr: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&addr))
+    }
+}
+
+impl<B: Bus> Bus for ReplayBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        if !self.is_input(addr) || self.divergence.is_some() {
+            return self.inner.read(addr);
+        }
+        match self.events.front() {
+            Some(&InputEvent {
+                cycle,
+                input:
+                    Input::Read {
+                        addr: expected,
+                        data,
+                    },
+            }) if cycle == self.now && expected == addr => {
+                self.events.pop_front();
+                data
+            }
+            expected => {
+                // Off the recorded path, so fall back to the real device
+                let expected = expected.copied();
+                let data = self.inner.read(addr);
+                self.divergence = Some(Divergence {
+                    cycle: self.now,
+                    expected,
+                    found: Some(Input::Read { addr, data }),
+                });
+                data
+            }
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.inner.write(addr, data)
+    }
+
+    fn wait_states(&mut self, addr: u16, kind: AccessKind) -> u8 {
+        self.inner.wait_states(addr, kind)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.inner.peek(addr)
+    }
+}
+
+impl<B: Bus> CPU<ReplayBus<B>> {
+    /// Delivers the interrupts and host inputs due now, then executes one
+    /// instruction, answering input reads from the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Divergence` if the machine did something the log does not
+    /// account for, such as reading an input that was not recorded or
+    /// passing the cycle of a recorded input without consuming it. The
+    /// instruction is still executed, with input reads falling back to the
+    /// wrapped bus, but replaying further is not meaningful.
+    pub fn replay_step(&mut self) -> Result<(), Divergence> {
+        while let Some(event) = self.bus.events.front().copied() {
+            if event.cycle > self.cycles() || matches!(event.input, Input::Read { .. }) {
+                break;
+            }
+            if event.cycle < self.cycles() {
+                return Err(self.missed(event));
+            }
+            self.bus.events.pop_front();
+            match event.input {
+                Input::Irq => self.irq(),
+                Input::Nmi => self.nmi(),
+                Input::Host { channel, data } => {
+                    if let Some(callback) = self.bus.host_input.as_mut() {
+                        callback(&mut self.bus.inner, channel, data);
+                    }
+                }
+                Input::Read { .. } => unreachable!(),
+            }
+        }
+
+        self.bus.now = self.cycles();
+        self.step();
+        if let Some(divergence) = self.bus.divergence.take() {
+            return Err(divergence);
+        }
+        match self.bus.events.front().copied() {
+            Some(event) if event.cycle < self.cycles() => Err(self.missed(event)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reports an input the replay went past without consuming.
+    fn missed(&self, event: InputEvent) -> Divergence {
+        Divergence {
+            cycle: self.cycles(),
+            expected: Some(event),
+            found: None,
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! The `rewind` module lets a debugger go back in time.
+//!
+//! A `Rewind` buffer executes instructions on behalf of the caller and keeps
+//! a history of them. Every `interval` cycles it takes a checkpoint: a
+//! snapshot of the CPU and of the bus. Between checkpoints it keeps a journal
+//! of the CPU state before each instruction and of every write made.
+//!
+//! Going back restores the nearest checkpoint at or before the target and
+//! re-executes instructions from there until the target is reached. Since
+//! `CPU::step` is deterministic, the machine ends up exactly as it was,
+//! provided everything that affects it is captured by the bus's `Snapshot`
+//! implementation. Devices that are not part of the bus, or that the host
+//! pokes between steps without telling the buffer, break this assumption.
+//!
+//! Interrupts and other changes made to the CPU between steps are detected
+//! and start a new checkpoint, so they are replayed correctly.
+//!
+//! Old checkpoints are dropped once the history outgrows its memory budget,
+//! so how far back one can go depends on the budget, the interval and the
+//! size of the bus snapshot.
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::rewind::Rewind;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! let mut rewind = Rewind::new(1_000, 1 << 20);
+//! for _ in 0..10 {
+//!     rewind.step(&mut cpu);
+//! }
+//! assert!(rewind.step_back(&mut cpu));
+//! assert_eq!(cpu.registers.pc, 9);
+//! assert_eq!(cpu.cycles(), 18);
+//! ```
+
+use crate::bus::{AccessKind, Bus};
+use crate::cpu::CPU;
+use crate::state::{CpuState, Snapshot};
+use std::collections::VecDeque;
+
+/// A write recorded in the rewind journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JournaledWrite {
+    /// The index of the instruction that made the write.
+    pub step: u64,
+    /// The address of the instruction that made the write.
+    pub pc: u16,
+    /// The address written.
+    pub addr: u16,
+    /// The value written.
+    pub data: u8,
+}
+
+/// A checkpoint and the instructions executed after it.
+struct Segment<S> {
+    /// The index of the first instruction executed after the checkpoint.
+    first_step: u64,
+    /// The CPU at the checkpoint.
+    cpu: CpuState,
+    /// The bus at the checkpoint.
+    bus: S,
+    /// The CPU state before each instruction executed after the checkpoint.
+    states: Vec<CpuState>,
+    /// The writes made by those instructions.
+    writes: Vec<JournaledWrite>,
+}
+
+/// Executes instructions while keeping enough history to go back.
+pub struct Rewind<B: Bus + Snapshot> {
+    /// The number of cycles between checkpoints.
+    interval: u64,
+    /// The number of bytes the history may occupy.
+    budget: usize,
+    /// The checkpoints, oldest first.
+    segments: VecDeque<Segment<B::State>>,
+    /// The index of the next instruction to execute.
+    position: u64,
+    /// The CPU state after the last instruction, if nothing happened since.
+    expected: Option<CpuState>,
+}
+
+impl<B: Bus + Snapshot> Rewind<B> {
+    /// Creates a new `Rewind` buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The number of cycles between checkpoints. Shorter
+    ///   intervals make going back faster but use more memory.
+    /// * `budget` - The approximate number of bytes the history may occupy.
+    ///   The most recent checkpoint is always kept, even if it alone exceeds
+    ///   the budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn new(interval: u64, budget: usize) -> Self {
+        assert!(interval > 0, "checkpoint interval must be non-zero");
+        Self {
+            interval,
+            budget,
+            segments: VecDeque::new(),
+            position: 0,
+            expected: None,
+        }
+    }
+
+    /// Returns the index of the next instruction to execute, counting every
+    /// instruction executed through this buffer.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the index of the oldest instruction that can be gone back to.
+    pub fn oldest(&self) -> u64 {
+        self.segments
+            .front()
+            .map_or(self.position, |segment| segment.first_step)
+    }
+
+    /// Returns the approximate number of bytes the history occupies.
+    pub fn memory_usage(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| {
+                B::state_size(&segment.bus)
+                    + segment.states.len() * std::mem::size_of::<CpuState>()
+                    + segment.writes.len() * std::mem::size_of::<JournaledWrite>()
+            })
+            .sum()
+    }
+
+    /// Returns the journaled writes, oldest first.
+    pub fn writes(&self) -> impl Iterator<Item = &JournaledWrite> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.writes.iter())
+    }
+
+    /// Returns the most recent journaled write to `addr`, if any.
+    ///
+    /// This answers the usual debugging question of who clobbered a variable.
+    pub fn last_write_to(&self, addr: u16) -> Option<&JournaledWrite> {
+        self.segments
+            .iter()
+            .rev()
+            .flat_map(|segment| segment.writes.iter().rev())
+            .find(|write| write.addr == addr)
+    }
+
+    /// Forgets all history. The next step takes a new checkpoint.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.expected = None;
+    }
+
+    /// Executes one instruction and records it.
+    ///
+    /// Access recording is enabled on the CPU, since the journal needs to see
+    /// every write.
+    pub fn step(&mut self, cpu: &mut CPU<B>) {
+        if !cpu.is_recording_accesses() {
+            cpu.set_access_recording(true);
+        }
+        let before = cpu.snapshot();
+        let due = match self.segments.back() {
+            Some(segment) => before.cycles.saturating_sub(segment.cpu.cycles) >= self.interval,
+            None => true,
+        };
+        // Anything that moved the CPU between steps, such as an interrupt,
+        // cannot be replayed from the journal, so it needs a checkpoint
+        if due || self.expected != Some(before) {
+            self.checkpoint(cpu);
+        }
+
+        cpu.step();
+
+        let step = self.position;
+        let segment = self.segments.back_mut().expect("a checkpoint was taken");
+        segment.states.push(before);
+        segment.writes.extend(
+            cpu.accesses()
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| JournaledWrite {
+                    step,
+                    pc: before.registers.pc,
+                    addr: access.addr,
+                    data: access.data,
+                }),
+        );
+        self.position += 1;
+        self.expected = Some(cpu.snapshot());
+    }
+
+    /// Goes back to just before the instruction with index `step`.
+    ///
+    /// Everything recorded after that point is discarded. Returns `false`,
+    /// leaving the machine unchanged, if `step` is older than `oldest` or
+    /// newer than `position`.
+    pub fn goto(&mut self, cpu: &mut CPU<B>, step: u64) -> bool {
+        if step > self.position || step < self.oldest() {
+            return false;
+        }
+        if step == self.position {
+            return true;
+        }
+        while self
+            .segments
+            .back()
+            .is_some_and(|segment| segment.first_step > step)
+        {
+            self.segments.pop_back();
+        }
+        let segment = self
+            .segments
+            .back_mut()
+            .expect("step is not older than oldest");
+        let replay = (step - segment.first_step) as usize;
+
+        cpu.restore(&segment.cpu);
+        cpu.bus.restore(&segment.bus);
+        for expected in &segment.states[..replay] {
+            debug_assert_eq!(
+                cpu.snapshot(),
+                *expected,
+                "replay diverged; is the whole machine captured by Snapshot?"
+            );
+            cpu.step();
+        }
+
+        segment.states.truncate(replay);
+        segment.writes.retain(|write| write.step < step);
+        self.position = step;
+        self.expected = Some(cpu.snapshot());
+        true
+    }
+
+    /// Goes back one instruction. Returns `false` if there is no history.
+    pub fn step_back(&mut self, cpu: &mut CPU<B>) -> bool {
+        self.position > self.oldest() && self.goto(cpu, self.position - 1)
+    }
+
+    /// Goes back to the most recent instruction at `addr`, so that it is the
+    /// next one to execute.
+    ///
+    /// Returns the index of that instruction, or `None`, leaving the machine
+    /// unchanged, if no recorded instruction was at `addr`.
+    pub fn run_back_to_pc(&mut self, cpu: &mut CPU<B>, addr: u16) -> Option<u64> {
+        let step = self.find_back(|state| state.registers.pc == addr)?;
+        self.goto(cpu, step);
+        Some(step)
+    }
+
+    /// Goes back at least `cycles` cycles, to the most recent instruction
+    /// boundary at or before that point.
+    ///
+    /// Returns the index of the instruction gone back to, or `None`, leaving
+    /// the machine unchanged, if the history does not reach back that far.
+    pub fn rewind_cycles(&mut self, cpu: &mut CPU<B>, cycles: u64) -> Option<u64> {
+        if cycles == 0 {
+            return Some(self.position);
+        }
+        let target = cpu.cycles().checked_sub(cycles)?;
+        let step = self.find_back(|state| state.cycles <= target)?;
+        self.goto(cpu, step);
+        Some(step)
+    }
+
+    /// Returns the index of the most recent recorded instruction whose
+    /// starting state matches `predicate`.
+    fn find_back<F: Fn(&CpuState) -> bool>(&self, predicate: F) -> Option<u64> {
+        self.segments.iter().rev().find_map(|segment| {
+            segment
+                .states
+                .iter()
+                .rposition(&predicate)
+                .map(|index| segment.first_step + index as u64)
+        })
+    }
+
+    /// Starts a new segment at the current state and drops old segments
+    /// that no longer fit in the budget.
+    fn checkpoint(&mut self, cpu: &CPU<B>) {
+        self.segments.push_back(Segment {
+            first_step: self.position,
+            cpu: cpu.snapshot(),
+            bus: cpu.bus.snapshot(),
+            states: Vec::new(),
+            writes: Vec::new(),
+        });
+        while self.segments.len() > 1 && self.memory_usage() > self.budget {
+            self.segments.pop_front();
+        }
+    }
+}
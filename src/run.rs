@@ -0,0 +1,126 @@
+//! The `run` module contains run-loop helpers built on `CPU::step`.
+//!
+//! Each helper executes whole instructions until its stop condition is met and
+//! returns a `RunResult` describing why it stopped and how much it ran.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+
+/// The reason a run-loop helper stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested number of cycles has elapsed.
+    CyclesElapsed,
+    /// The requested number of instructions has been executed.
+    InstructionsExecuted,
+    /// The requested amount of wall-clock time has passed.
+    TimeElapsed,
+    /// The predicate passed to `run_until` returned `true`.
+    Predicate,
+    /// The program counter reached the requested address.
+    PcReached,
+    /// The CPU executed an instruction that jumps or branches to itself,
+    /// such as `JMP *` or `BNE *`.
+    Trap {
+        /// The address of the trapping instruction.
+        pc: u16,
+    },
+}
+
+/// The result of a run-loop helper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunResult {
+    /// Why the run stopped.
+    pub reason: StopReason,
+    /// The number of cycles used, including wait states and interrupts.
+    pub cycles: u64,
+    /// The number of instructions executed.
+    pub instructions: u64,
+}
+
+impl<B: Bus> CPU<B> {
+    /// Runs until at least `cycles` cycles have elapsed.
+    ///
+    /// Instructions are never split, so the run may overshoot by up to one
+    /// instruction's worth of cycles.
+    pub fn run_cycles(&mut self, cycles: u64) -> RunResult {
+        let target = self.cycles() + cycles;
+        self.run_while(StopReason::CyclesElapsed, |cpu, _| cpu.cycles() < target)
+    }
+
+    /// Runs exactly `count` instructions.
+    pub fn run_instructions(&mut self, count: u64) -> RunResult {
+        self.run_while(StopReason::InstructionsExecuted, |_, executed| {
+            executed < count
+        })
+    }
+
+    /// Runs until `predicate` returns `true`.
+    ///
+    /// The predicate is checked before each instruction, so no instruction is
+    /// executed if it is already `true`.
+    pub fn run_until<F: FnMut(&CPU<B>) -> bool>(&mut self, mut predicate: F) -> RunResult {
+        self.run_while(StopReason::Predicate, |cpu, _| !predicate(cpu))
+    }
+
+    /// Runs until the program counter equals `addr`.
+    ///
+    /// Returns immediately if the program counter is already `addr`.
+    pub fn run_until_pc(&mut self, addr: u16) -> RunResult {
+        self.run_while(StopReason::PcReached, |cpu, _| cpu.registers.pc != addr)
+    }
+
+    /// Runs until the CPU executes an instruction that leaves the program
+    /// counter unchanged, such as `JMP *` or a taken `BNE *`.
+    ///
+    /// This is the convention used by test suites such as Klaus Dormann's to
+    /// signal success or failure: the trap address tells which. The trap
+    /// instruction itself is executed once.
+    ///
+    /// A program that never traps stops after `max_instructions`
+    /// instructions, with `StopReason::InstructionsExecuted`.
+    pub fn run_until_trap(&mut self, max_instructions: u64) -> RunResult {
+        let start_cycles = self.cycles();
+        let mut instructions = 0;
+        loop {
+            if instructions == max_instructions {
+                return RunResult {
+                    reason: StopReason::InstructionsExecuted,
+                    cycles: self.cycles() - start_cycles,
+                    instructions,
+                };
+            }
+            let pc = self.registers.pc;
+            self.step();
+            instructions += 1;
+            if self.registers.pc == pc {
+                return RunResult {
+                    reason: StopReason::Trap { pc },
+                    cycles: self.cycles() - start_cycles,
+                    instructions,
+                };
+            }
+        }
+    }
+
+    /// Steps while `keep_going` returns `true`, then reports `reason`.
+    ///
+    /// The closure receives the CPU and the number of instructions executed so far.
+    fn run_while<F: FnMut(&CPU<B>, u64) -> bool>(
+        &mut self,
+        reason: StopReason,
+        mut keep_going: F,
+    ) -> RunResult {
+        let start_cycles = self.cycles();
+        let mut instructions = 0;
+        while keep_going(self, instructions) {
+            self.step();
+            instructions += 1;
+        }
+        RunResult {
+            reason,
+            cycles: self.cycles() - start_cycles,
+            instructions,
+        }
+    }
+}
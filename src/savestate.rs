@@ -0,0 +1,436 @@
+//! The `savestate` module writes and reads versioned machine save states.
+//!
+//! This module is only available with the `serde` feature.
+//!
+//! A save state file is a JSON document with a header, the `CpuState`, and
+//! the state of the rest of the machine:
+//!
+//! ```text
+//! {
+//!   "magic": "lib6502-savestate",
+//!   "version": 1,
+//!   "variant": "NMOS 6502",
+//!   "cpu": { "registers": { ... }, "cycles": 1234 },
+//!   "machine": ...
+//! }
+//! ```
+//!
+//! The machine part comes from the `SaveState` trait, which buses and devices
+//! implement to save their own state. It is implemented for the plain memory
+//! buses and the adapters in this crate, so a `CPU<Vec<u8>>` can be saved
+//! as is:
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! cpu.step();
+//! let mut file = Vec::new();
+//! cpu.save_state(&mut file).unwrap();
+//!
+//! let mut restored = CPU::new(vec![0u8; 0x10000]);
+//! restored.load_state(file.as_slice()).unwrap();
+//! assert_eq!(restored.snapshot(), cpu.snapshot());
+//! ```
+//!
+//! Files written by an older version of the format, back to
+//! `OLDEST_VERSION`, are migrated when they are loaded. Files from outside
+//! that range, or for a different CPU variant, are rejected with a
+//! `SaveStateError` saying why.
+
+use crate::adapters::{LoggingBus, MirrorBus, OverlayBus, WaitStateBus};
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::state::CpuState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+/// The string every save state file starts with, to recognise the format.
+pub const MAGIC: &str = "lib6502-savestate";
+
+/// The version of the save state format written by this crate.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The oldest version of the format this crate can read, migrating it to
+/// `FORMAT_VERSION`.
+pub const OLDEST_VERSION: u32 = 1;
+
+/// The CPU variant written into save states by this crate.
+pub const CPU_VARIANT: &str = "NMOS 6502";
+
+/// A type whose state can be stored in a save state file.
+///
+/// Buses and devices implement this to take part in save states. The state
+/// is an ordinary serde type, so it can be a plain struct with derived
+/// `Serialize` and `Deserialize` impls.
+pub trait SaveState {
+    /// The serialisable form of the state.
+    type State: Serialize + DeserializeOwned;
+
+    /// Captures the current state.
+    fn save_state(&self) -> Self::State;
+
+    /// Restores a state captured by `save_state`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state cannot be applied, for example because
+    /// a memory image has the wrong size.
+    fn load_state(&mut self, state: Self::State) -> Result<(), SaveStateError>;
+}
+
+/// An error from writing or reading a save state.
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// Reading or writing the file failed.
+    Io(std::io::Error),
+    /// The file is not valid JSON or does not have the expected structure.
+    Format(serde_json::Error),
+    /// The file does not start with the save state header.
+    NotASaveState,
+    /// The file was written by a version of the format this crate cannot read.
+    UnsupportedVersion {
+        /// The version in the file.
+        found: u32,
+        /// The oldest version this crate can read.
+        oldest: u32,
+        /// The newest version this crate can read.
+        newest: u32,
+    },
+    /// The file was saved from a different CPU variant.
+    VariantMismatch {
+        /// The variant in the file.
+        found: String,
+        /// The variant of this CPU.
+        expected: String,
+    },
+    /// A bus or device could not apply its saved state.
+    InvalidState(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(err) => write!(f, "save state I/O error: {err}"),
+            SaveStateError::Format(err) => write!(f, "malformed save state: {err}"),
+            SaveStateError::NotASaveState => write!(f, "not a lib6502 save state"),
+            SaveStateError::UnsupportedVersion { found, oldest, .. } if found < oldest => write!(
+                f,
+                "save state format version {found} is older than the oldest supported version {oldest}"
+            ),
+            SaveStateError::UnsupportedVersion { found, newest, .. } => write!(
+                f,
+                "save state format version {found} is not supported (this build reads up to version {newest})"
+            ),
+            SaveStateError::VariantMismatch { found, expected } => write!(
+                f,
+                "save state is for a {found} CPU, but this CPU is a {expected}"
+            ),
+            SaveStateError::InvalidState(reason) => write!(f, "invalid save state: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveStateError::Io(err) => Some(err),
+            SaveStateError::Format(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveStateError {
+    fn from(err: std::io::Error) -> Self {
+        SaveStateError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SaveStateError {
+    fn from(err: serde_json::Error) -> Self {
+        SaveStateError::Format(err)
+    }
+}
+
+/// The identifying header of a save state file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    /// Always `MAGIC`.
+    pub magic: String,
+    /// The format version the file was written with.
+    pub version: u32,
+    /// The CPU variant the state was saved from.
+    pub variant: String,
+}
+
+impl Header {
+    /// Returns the header written by this crate.
+    pub fn current() -> Self {
+        Self {
+            magic: MAGIC.to_string(),
+            version: FORMAT_VERSION,
+            variant: CPU_VARIANT.to_string(),
+        }
+    }
+}
+
+/// A complete save state: header, CPU state and machine state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveFile<M> {
+    /// The identifying header.
+    #[serde(flatten)]
+    pub header: Header,
+    /// The CPU's registers and cycle count.
+    pub cpu: CpuState,
+    /// The state of the bus and devices.
+    pub machine: M,
+}
+
+/// Writes a save state containing `cpu` and the state of `machine`.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn write_state<M: SaveState, W: Write>(
+    writer: W,
+    cpu: &CpuState,
+    machine: &M,
+) -> Result<(), SaveStateError> {
+    let file = SaveFile {
+        header: Header::current(),
+        cpu: *cpu,
+        machine: machine.save_state(),
+    };
+    serde_json::to_writer(writer, &file)?;
+    Ok(())
+}
+
+/// Reads a save state, restores `machine` from it and returns the CPU state.
+///
+/// Older format versions are migrated first.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, is not a save state, has an
+/// unsupported version or CPU variant, or the machine rejects its state.
+pub fn read_state<M: SaveState, R: Read>(
+    reader: R,
+    machine: &mut M,
+) -> Result<CpuState, SaveStateError> {
+    let value: Value = serde_json::from_reader(reader)?;
+    let value = migrate(value)?;
+    let file: SaveFile<M::State> = serde_json::from_value(value)?;
+    machine.load_state(file.machine)?;
+    Ok(file.cpu)
+}
+
+/// The header of a parsed file, borrowed from it.
+#[derive(Deserialize)]
+struct HeaderRef<'a> {
+    magic: &'a str,
+    version: u32,
+    variant: &'a str,
+}
+
+/// Checks the header of a parsed file and upgrades it to `FORMAT_VERSION`.
+fn migrate(value: Value) -> Result<Value, SaveStateError> {
+    let header = HeaderRef::deserialize(&value).map_err(|_| SaveStateError::NotASaveState)?;
+    if header.magic != MAGIC {
+        return Err(SaveStateError::NotASaveState);
+    }
+    if header.variant != CPU_VARIANT {
+        return Err(SaveStateError::VariantMismatch {
+            found: header.variant.to_string(),
+            expected: CPU_VARIANT.to_string(),
+        });
+    }
+    // Each version from OLDEST_VERSION up gets an arm here that upgrades it
+    // by one version
+    match header.version {
+        FORMAT_VERSION => Ok(value),
+        found => Err(SaveStateError::UnsupportedVersion {
+            found,
+            oldest: OLDEST_VERSION,
+            newest: FORMAT_VERSION,
+        }),
+    }
+}
+
+impl<B: Bus + SaveState> CPU<B> {
+    /// Writes a save state of the CPU and its bus.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    pub fn save_state<W: Write>(&self, writer: W) -> Result<(), SaveStateError> {
+        write_state(writer, &self.snapshot(), &self.bus)
+    }
+
+    /// Restores the CPU and its bus from a save state.
+    ///
+    /// Nothing is changed if an error is returned before the bus accepts its state.
+    ///
+    /// # Errors
+    ///
+    /// See `read_state`.
+    pub fn load_state<R: Read>(&mut self, reader: R) -> Result<(), SaveStateError> {
+        let state = read_state(reader, &mut self.bus)?;
+        self.restore(&state);
+        Ok(())
+    }
+}
+
+/// A block of memory, stored in save states as a hex string to keep files small.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MemoryImage(pub Vec<u8>);
+
+impl Serialize for MemoryImage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut hex = String::with_capacity(self.0.len() * 2);
+        for byte in &self.0 {
+            hex.push_str(&format!("{byte:02x}"));
+        }
+        serializer.serialize_str(&hex)
+    }
+}
+
+impl<'de> Deserialize<'de> for MemoryImage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(serde::de::Error::custom("memory image is not a hex string"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map(MemoryImage)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl SaveState for Vec<u8> {
+    type State = MemoryImage;
+
+    fn save_state(&self) -> MemoryImage {
+        MemoryImage(self.clone())
+    }
+
+    fn load_state(&mut self, state: MemoryImage) -> Result<(), SaveStateError> {
+        *self = state.0;
+        Ok(())
+    }
+}
+
+impl SaveState for [u8; 0x10000] {
+    type State = MemoryImage;
+
+    fn save_state(&self) -> MemoryImage {
+        MemoryImage(self.to_vec())
+    }
+
+    fn load_state(&mut self, state: MemoryImage) -> Result<(), SaveStateError> {
+        if state.0.len() != self.len() {
+            return Err(SaveStateError::InvalidState(format!(
+                "expected a 65536-byte memory image, found {} bytes",
+                state.0.len()
+            )));
+        }
+        self.copy_from_slice(&state.0);
+        Ok(())
+    }
+}
+
+impl<S: SaveState + ?Sized> SaveState for Box<S> {
+    type State = S::State;
+
+    fn save_state(&self) -> S::State {
+        (**self).save_state()
+    }
+
+    fn load_state(&mut self, state: S::State) -> Result<(), SaveStateError> {
+        (**self).load_state(state)
+    }
+}
+
+impl<S: SaveState> SaveState for Rc<RefCell<S>> {
+    type State = S::State;
+
+    fn save_state(&self) -> S::State {
+        self.borrow().save_state()
+    }
+
+    fn load_state(&mut self, state: S::State) -> Result<(), SaveStateError> {
+        self.borrow_mut().load_state(state)
+    }
+}
+
+/// A machine made of several parts saves each part in turn.
+impl<S1: SaveState, S2: SaveState> SaveState for (S1, S2) {
+    type State = (S1::State, S2::State);
+
+    fn save_state(&self) -> Self::State {
+        (self.0.save_state(), self.1.save_state())
+    }
+
+    fn load_state(&mut self, state: Self::State) -> Result<(), SaveStateError> {
+        self.0.load_state(state.0)?;
+        self.1.load_state(state.1)
+    }
+}
+
+/// The access log is debugging data, so only the wrapped bus is saved.
+impl<B: Bus + SaveState> SaveState for LoggingBus<B> {
+    type State = B::State;
+
+    fn save_state(&self) -> B::State {
+        self.inner().save_state()
+    }
+
+    fn load_state(&mut self, state: B::State) -> Result<(), SaveStateError> {
+        self.inner_mut().load_state(state)
+    }
+}
+
+impl<B: Bus + SaveState> SaveState for MirrorBus<B> {
+    type State = B::State;
+
+    fn save_state(&self) -> B::State {
+        self.inner().save_state()
+    }
+
+    fn load_state(&mut self, state: B::State) -> Result<(), SaveStateError> {
+        self.inner_mut().load_state(state)
+    }
+}
+
+impl<B: Bus + SaveState> SaveState for WaitStateBus<B> {
+    type State = B::State;
+
+    fn save_state(&self) -> B::State {
+        self.inner().save_state()
+    }
+
+    fn load_state(&mut self, state: B::State) -> Result<(), SaveStateError> {
+        self.inner_mut().load_state(state)
+    }
+}
+
+impl<B: Bus + SaveState, O: Bus + SaveState> SaveState for OverlayBus<B, O> {
+    type State = (B::State, O::State);
+
+    fn save_state(&self) -> Self::State {
+        (self.inner().save_state(), self.overlay().save_state())
+    }
+
+    fn load_state(&mut self, state: Self::State) -> Result<(), SaveStateError> {
+        self.inner_mut().load_state(state.0)?;
+        self.overlay_mut().load_state(state.1)
+    }
+}
@@ -0,0 +1,446 @@
+//! The `scheduler` module runs a `CPU` together with timed devices.
+//!
+//! Devices register events for a future cycle instead of polling on every
+//! instruction. The `Scheduler` steps the CPU, dispatches each event once the
+//! CPU's cycle count reaches it, and services the IRQ and NMI lines that
+//! devices drive.
+//!
+//! The CPU executes whole instructions, so an event is dispatched at the first
+//! instruction boundary at or after its cycle. The event still sees the cycle
+//! it was scheduled for through `DeviceContext::scheduled`, so periodic timers
+//! that reschedule relative to that cycle never drift.
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::scheduler::{Clock, DeviceContext, Scheduler};
+//!
+//! let mut memory = vec![0u8; 0x10000];
+//! memory[0x8000..0xA000].fill(0xEA); // NOPs
+//! memory[0xFFFD] = 0x80; // Reset vector: $8000
+//! memory[0xFFFB] = 0x90; // NMI vector: $9000
+//!
+//! let mut cpu = CPU::new(memory);
+//! cpu.reset();
+//! let mut scheduler = Scheduler::new(cpu);
+//!
+//! // Fire an NMI 100 cycles from now
+//! let timer = scheduler.add_device(
+//!     |_tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| ctx.trigger_nmi(),
+//!     Clock::CPU,
+//! );
+//! scheduler.schedule_at(timer, 100, 0);
+//! scheduler.run_until(200);
+//! assert!(scheduler.cpu.registers.pc >= 0x9000);
+//! ```
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::idle::IdleDetector;
+use crate::replay::InputRecorder;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Identifies a device registered with a `Scheduler`.
+pub type DeviceId = usize;
+
+/// The clock a device runs on, as a fraction of the CPU clock.
+///
+/// A device with `Clock::new(3, 2)` ticks three times for every two CPU
+/// cycles. Conversions between device ticks and CPU cycles round towards the
+/// later cycle, so an event scheduled in device ticks never fires early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    /// Device ticks per `den` CPU cycles.
+    num: u64,
+    /// CPU cycles per `num` device ticks.
+    den: u64,
+}
+
+impl Clock {
+    /// A clock running at exactly the CPU clock.
+    pub const CPU: Clock = Clock { num: 1, den: 1 };
+
+    /// Creates a clock that ticks `num` times for every `den` CPU cycles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either `num` or `den` is zero.
+    pub fn new(num: u64, den: u64) -> Self {
+        assert!(num > 0 && den > 0, "clock ratio must be non-zero");
+        Self { num, den }
+    }
+
+    /// Creates a clock from the device and CPU frequencies in Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either frequency is zero.
+    pub fn from_frequencies(device_hz: u64, cpu_hz: u64) -> Self {
+        Self::new(device_hz, cpu_hz)
+    }
+
+    /// Returns the number of whole device ticks that have elapsed by `cycle`.
+    pub fn ticks_at(&self, cycle: u64) -> u64 {
+        ((cycle as u128 * self.num as u128) / self.den as u128) as u64
+    }
+
+    /// Returns the first CPU cycle at which `ticks` device ticks have elapsed.
+    pub fn cycle_of_tick(&self, ticks: u64) -> u64 {
+        (ticks as u128 * self.den as u128).div_ceil(self.num as u128) as u64
+    }
+}
+
+/// A device driven by the scheduler.
+///
+/// The `tag` is the value given when the event was scheduled, which lets a
+/// device with several timers tell them apart.
+///
+/// Closures with the matching signature implement `Device` as well.
+pub trait Device<B: Bus> {
+    /// Handles an event that has come due.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - The tag the event was scheduled with.
+    /// * `ctx` - Access to the CPU and the scheduler's services for this device.
+    fn event(&mut self, tag: u32, ctx: &mut DeviceContext<'_, B>);
+}
+
+impl<B: Bus, F: FnMut(u32, &mut DeviceContext<'_, B>)> Device<B> for F {
+    fn event(&mut self, tag: u32, ctx: &mut DeviceContext<'_, B>) {
+        self(tag, ctx)
+    }
+}
+
+/// The view a device gets of the machine while handling an event.
+pub struct DeviceContext<'a, B: Bus> {
+    /// The CPU, and through `cpu.bus`, the bus.
+    pub cpu: &'a mut CPU<B>,
+    id: DeviceId,
+    scheduled: u64,
+    clock: Clock,
+    queue: &'a mut EventQueue,
+    irq_line: &'a mut bool,
+    nmi_pending: &'a mut bool,
+}
+
+impl<B: Bus> DeviceContext<'_, B> {
+    /// Returns the id of the device handling the event.
+    pub fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    /// Returns the CPU cycle the event was scheduled for.
+    pub fn scheduled(&self) -> u64 {
+        self.scheduled
+    }
+
+    /// Returns the current CPU cycle, which may be later than `scheduled`.
+    pub fn now(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    /// Returns the device's own tick count at the scheduled cycle.
+    pub fn ticks(&self) -> u64 {
+        self.clock.ticks_at(self.scheduled)
+    }
+
+    /// Schedules an event for this device at an absolute CPU cycle.
+    ///
+    /// An event at or before the cycle the current event was scheduled for
+    /// is moved to the cycle after it, so a handler that keeps scheduling
+    /// itself cannot stop the CPU from advancing.
+    pub fn schedule_at(&mut self, cycle: u64, tag: u32) {
+        let cycle = cycle.max(self.scheduled + 1);
+        self.queue.push(cycle, self.id, tag);
+    }
+
+    /// Schedules an event for this device `cycles` CPU cycles after the
+    /// cycle the current event was scheduled for. A `cycles` of 0 is
+    /// treated as 1, as in `schedule_at`.
+    pub fn schedule_in(&mut self, cycles: u64, tag: u32) {
+        self.schedule_at(self.scheduled + cycles, tag);
+    }
+
+    /// Schedules an event for this device `ticks` ticks of its own clock
+    /// after the cycle the current event was scheduled for.
+    pub fn schedule_in_ticks(&mut self, ticks: u64, tag: u32) {
+        let cycle = self.clock.cycle_of_tick(self.ticks() + ticks);
+        self.schedule_at(cycle, tag);
+    }
+
+    /// Cancels every pending event for this device with the given tag.
+    pub fn cancel(&mut self, tag: u32) {
+        self.queue.cancel(self.id, Some(tag));
+    }
+
+    /// Sets this device's IRQ output. The IRQ line is the wired-OR of all devices.
+    pub fn set_irq(&mut self, asserted: bool) {
+        *self.irq_line = asserted;
+    }
+
+    /// Requests a non-maskable interrupt before the next instruction.
+    pub fn trigger_nmi(&mut self) {
+        *self.nmi_pending = true;
+    }
+}
+
+/// A pending event in the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Event {
+    /// The CPU cycle the event is due at.
+    cycle: u64,
+    /// The order the event was scheduled in, to keep dispatch order stable.
+    seq: u64,
+    /// The device the event belongs to.
+    device: DeviceId,
+    /// The device's tag for the event.
+    tag: u32,
+}
+
+/// A priority queue of events ordered by cycle, then by scheduling order.
+#[derive(Default)]
+struct EventQueue {
+    heap: BinaryHeap<Reverse<Event>>,
+    next_seq: u64,
+}
+
+impl EventQueue {
+    fn push(&mut self, cycle: u64, device: DeviceId, tag: u32) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.heap.push(Reverse(Event {
+            cycle,
+            seq,
+            device,
+            tag,
+        }));
+    }
+
+    fn peek_cycle(&self) -> Option<u64> {
+        self.heap.peek().map(|Reverse(event)| event.cycle)
+    }
+
+    fn pop_due(&mut self, now: u64) -> Option<Event> {
+        match self.heap.peek() {
+            Some(Reverse(event)) if event.cycle <= now => self.heap.pop().map(|Reverse(e)| e),
+            _ => None,
+        }
+    }
+
+    fn cancel(&mut self, device: DeviceId, tag: Option<u32>) {
+        self.heap
+            .retain(|Reverse(e)| e.device != device || tag.is_some_and(|t| t != e.tag));
+    }
+}
+
+/// A registered device and its interrupt output.
+struct Slot<B: Bus> {
+    device: Box<dyn Device<B>>,
+    clock: Clock,
+    irq: bool,
+}
+
+/// Runs a `CPU` and a set of devices against a shared timeline.
+pub struct Scheduler<B: Bus> {
+    /// The CPU being driven.
+    pub cpu: CPU<B>,
+    devices: Vec<Slot<B>>,
+    queue: EventQueue,
+    nmi_pending: bool,
+    idle: Option<IdleDetector>,
+    recorder: Option<InputRecorder>,
+}
+
+impl<B: Bus> Scheduler<B> {
+    /// Creates a new `Scheduler` driving the given CPU.
+    pub fn new(cpu: CPU<B>) -> Self {
+        Self {
+            cpu,
+            devices: Vec::new(),
+            queue: EventQueue::default(),
+            nmi_pending: false,
+            idle: None,
+            recorder: None,
+        }
+    }
+
+    /// Enables or disables skipping of idle polling loops.
+    ///
+    /// When enabled, `run_until` and `run_for` detect side-effect-free
+    /// polling loops and jump straight to the next device event, keeping the
+    /// cycle count exact. See the `idle` module for the conditions.
+    ///
+    /// Nothing is skipped while a recorder is set, since the inputs read by
+    /// skipped iterations would be missing from the recording.
+    pub fn set_idle_skipping(&mut self, enabled: bool) {
+        self.idle = if enabled {
+            Some(IdleDetector::new())
+        } else {
+            None
+        };
+    }
+
+    /// Records the machine's inputs with `recorder` from now on.
+    ///
+    /// Every instruction is then executed through the recorder, so the reads
+    /// from its input ranges and the interrupts the devices raise all end up
+    /// in its log.
+    pub fn set_recorder(&mut self, recorder: InputRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Stops recording and returns the recorder, if one was set.
+    ///
+    /// An interrupt taken since the last instruction is added to its log
+    /// first.
+    pub fn take_recorder(&mut self) -> Option<InputRecorder> {
+        let mut recorder = self.recorder.take()?;
+        recorder.collect_interrupts(&mut self.cpu);
+        Some(recorder)
+    }
+
+    /// Records a byte fed by the host to a device, if a recorder is set.
+    ///
+    /// The caller still delivers the byte to the device itself.
+    pub fn record_host_input(&mut self, channel: u32, data: u8) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.host_input(&mut self.cpu, channel, data);
+        }
+    }
+
+    /// Registers a device running on the given clock and returns its id.
+    pub fn add_device<D: Device<B> + 'static>(&mut self, device: D, clock: Clock) -> DeviceId {
+        self.devices.push(Slot {
+            device: Box::new(device),
+            clock,
+            irq: false,
+        });
+        self.devices.len() - 1
+    }
+
+    /// Schedules an event for a device at an absolute CPU cycle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `device` was not returned by `add_device`.
+    pub fn schedule_at(&mut self, device: DeviceId, cycle: u64, tag: u32) {
+        assert!(device < self.devices.len(), "unknown device id {device}");
+        self.queue.push(cycle, device, tag);
+    }
+
+    /// Schedules an event for a device `cycles` CPU cycles from now.
+    pub fn schedule_in(&mut self, device: DeviceId, cycles: u64, tag: u32) {
+        let cycle = self.cpu.cycles() + cycles;
+        self.schedule_at(device, cycle, tag);
+    }
+
+    /// Cancels every pending event for a device.
+    pub fn cancel_all(&mut self, device: DeviceId) {
+        self.queue.cancel(device, None);
+    }
+
+    /// Returns the cycle of the earliest pending event, if there is one.
+    pub fn next_event_cycle(&self) -> Option<u64> {
+        self.queue.peek_cycle()
+    }
+
+    /// Sets a device's IRQ output from outside an event handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `device` was not returned by `add_device`.
+    pub fn set_irq(&mut self, device: DeviceId, asserted: bool) {
+        assert!(device < self.devices.len(), "unknown device id {device}");
+        self.devices[device].irq = asserted;
+    }
+
+    /// Requests a non-maskable interrupt before the next instruction.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Returns `true` if any device is asserting the IRQ line.
+    pub fn irq_asserted(&self) -> bool {
+        self.devices.iter().any(|slot| slot.irq)
+    }
+
+    /// Dispatches all due events, services pending interrupts and executes one instruction.
+    pub fn step(&mut self) {
+        self.dispatch_due();
+        self.service_interrupts();
+        self.execute();
+    }
+
+    /// Runs until the CPU's cycle count reaches `cycle`, then dispatches any
+    /// events that are due.
+    pub fn run_until(&mut self, cycle: u64) {
+        while self.cpu.cycles() < cycle {
+            self.dispatch_due();
+            self.service_interrupts();
+            if let (Some(idle), None) = (&mut self.idle, &self.recorder) {
+                // Nothing can change what the loop reads before the next event
+                let limit = self
+                    .queue
+                    .peek_cycle()
+                    .map_or(cycle, |next| next.min(cycle));
+                idle.fast_forward(&mut self.cpu, limit);
+                if self.cpu.cycles() >= cycle {
+                    break;
+                }
+            }
+            self.execute();
+        }
+        self.dispatch_due();
+    }
+
+    /// Runs for at least `cycles` CPU cycles from now.
+    pub fn run_for(&mut self, cycles: u64) {
+        let target = self.cpu.cycles() + cycles;
+        self.run_until(target);
+    }
+
+    /// Dispatches every event whose cycle has been reached, in order.
+    ///
+    /// Events a handler schedules are always later than the event being
+    /// handled, so this returns even if handlers keep rescheduling.
+    pub fn dispatch_due(&mut self) {
+        while let Some(event) = self.queue.pop_due(self.cpu.cycles()) {
+            let slot = &mut self.devices[event.device];
+            let mut ctx = DeviceContext {
+                cpu: &mut self.cpu,
+                id: event.device,
+                scheduled: event.cycle,
+                clock: slot.clock,
+                queue: &mut self.queue,
+                irq_line: &mut slot.irq,
+                nmi_pending: &mut self.nmi_pending,
+            };
+            slot.device.event(event.tag, &mut ctx);
+            // The device may have changed what an idle loop is waiting on
+            if let Some(idle) = &mut self.idle {
+                idle.reset();
+            }
+        }
+    }
+
+    /// Executes one instruction, through the recorder or the idle detector
+    /// if either is in use.
+    pub(crate) fn execute(&mut self) {
+        match (&mut self.recorder, &mut self.idle) {
+            (Some(recorder), _) => recorder.step(&mut self.cpu),
+            (None, Some(idle)) => idle.step(&mut self.cpu),
+            (None, None) => self.cpu.step(),
+        }
+    }
+
+    /// Takes a pending NMI, or an IRQ if the line is asserted and IRQs are enabled.
+    pub(crate) fn service_interrupts(&mut self) {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.cpu.nmi();
+        } else if self.irq_asserted() && !self.cpu.registers.status.interrupt_disable {
+            self.cpu.irq();
+        }
+    }
+}
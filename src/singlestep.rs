@@ -0,0 +1,369 @@
+//! The `singlestep` module runs single instruction test vectors in the
+//! format of Tom Harte's SingleStepTests (formerly ProcessorTests).
+//!
+//! Each test case gives the registers and some RAM before one instruction,
+//! the registers and RAM after it, and every bus cycle in between:
+//!
+//! ```text
+//! {
+//!   "name": "a9 42 00",
+//!   "initial": { "pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+//!                "ram": [[512, 169], [513, 66]] },
+//!   "final": { "pc": 514, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+//!              "ram": [[512, 169], [513, 66]] },
+//!   "cycles": [[512, 169, "read"], [513, 66, "read"]]
+//! }
+//! ```
+//!
+//! `run_case` checks the final registers and RAM, the cycle count and each
+//! bus access, and returns every `Mismatch`. It works on anything that
+//! implements `Subject`, which `CPU` does for any bus, so the same vectors
+//! can check other CPU variants. Reading the JSON files with `read_cases`
+//! needs the `serde` feature.
+
+use crate::bus::{AccessKind, Bus, BusAccess};
+use crate::cpu::CPU;
+use crate::registers::Registers;
+use std::fmt;
+
+/// The state of the machine before or after a test case.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct TestState {
+    /// The program counter.
+    pub pc: u16,
+    /// The stack pointer.
+    pub s: u8,
+    /// The accumulator.
+    pub a: u8,
+    /// The X register.
+    pub x: u8,
+    /// The Y register.
+    pub y: u8,
+    /// The status register.
+    pub p: u8,
+    /// The bytes of RAM that matter, as address and value.
+    pub ram: Vec<(u16, u8)>,
+}
+
+/// A single instruction test vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct TestCase {
+    /// The name of the case, usually the instruction bytes.
+    pub name: String,
+    /// The state before the instruction.
+    pub initial: TestState,
+    /// The state after it.
+    #[cfg_attr(feature = "serde", serde(rename = "final"))]
+    pub final_state: TestState,
+    /// The bus access in each cycle, in order.
+    pub cycles: Vec<BusAccess>,
+}
+
+/// Reads the test cases in a JSON file, such as `v1/a9.json`.
+///
+/// This function is only available with the `serde` feature.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or is not a list of test
+/// cases.
+#[cfg(feature = "serde")]
+pub fn read_cases<R: std::io::Read>(reader: R) -> serde_json::Result<Vec<TestCase>> {
+    serde_json::from_reader(std::io::BufReader::new(reader))
+}
+
+/// Something that can execute a test case: a CPU and its memory.
+pub trait Subject {
+    /// Puts the registers and RAM into `state`, and starts recording bus
+    /// accesses.
+    fn set_up(&mut self, state: &TestState);
+
+    /// Returns `true` if the subject can execute `opcode`.
+    fn is_implemented(&self, opcode: u8) -> bool;
+
+    /// Executes one instruction.
+    fn execute(&mut self);
+
+    /// Returns the registers.
+    fn registers(&self) -> Registers;
+
+    /// Returns the cycle count.
+    fn cycles(&self) -> u64;
+
+    /// Returns the bus accesses made by the last instruction.
+    fn accesses(&self) -> &[BusAccess];
+
+    /// Returns the byte at `addr` without side effects, if it can be seen.
+    fn peek(&self, addr: u16) -> Option<u8>;
+}
+
+impl<B: Bus> Subject for CPU<B> {
+    fn set_up(&mut self, state: &TestState) {
+        self.registers.pc = state.pc;
+        self.registers.sp = state.s;
+        self.registers.a = state.a;
+        self.registers.x = state.x;
+        self.registers.y = state.y;
+        self.registers.status.from_byte(state.p);
+        for &(addr, value) in &state.ram {
+            self.bus.write(addr, value);
+        }
+        self.set_access_recording(true);
+    }
+
+    fn is_implemented(&self, opcode: u8) -> bool {
+        CPU::is_implemented(self, opcode)
+    }
+
+    fn execute(&mut self) {
+        self.step();
+    }
+
+    fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    fn cycles(&self) -> u64 {
+        CPU::cycles(self)
+    }
+
+    fn accesses(&self) -> &[BusAccess] {
+        CPU::accesses(self)
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.bus.peek(addr)
+    }
+}
+
+/// One way the subject differed from a test case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The subject does not implement the opcode, so nothing was executed.
+    Unimplemented {
+        /// The opcode.
+        opcode: u8,
+    },
+    /// A register ended with the wrong value.
+    Register {
+        /// The register's name: `PC`, `S`, `A`, `X`, `Y` or `P`.
+        name: &'static str,
+        /// The value in the test case.
+        expected: u16,
+        /// The subject's value.
+        actual: u16,
+    },
+    /// A byte of RAM ended with the wrong value.
+    Memory {
+        /// The address.
+        addr: u16,
+        /// The value in the test case.
+        expected: u8,
+        /// The subject's value, if it can be peeked.
+        actual: Option<u8>,
+    },
+    /// The instruction took the wrong number of cycles.
+    CycleCount {
+        /// The number of cycles in the test case.
+        expected: u64,
+        /// The number the subject counted.
+        actual: u64,
+    },
+    /// The first bus access that differs. Either side is `None` if it made
+    /// fewer accesses.
+    Cycle {
+        /// The cycle, from 0.
+        index: usize,
+        /// The access in the test case.
+        expected: Option<BusAccess>,
+        /// The subject's access.
+        actual: Option<BusAccess>,
+    },
+}
+
+/// Formats a bus access for a mismatch message.
+fn format_access(access: Option<BusAccess>) -> String {
+    match access {
+        Some(access) => format!(
+            "${:04X} {} ${:02X}",
+            access.addr,
+            match access.kind {
+                AccessKind::Read => "read",
+                AccessKind::Write => "write",
+            },
+            access.data
+        ),
+        None => "nothing".to_string(),
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Mismatch::Unimplemented { opcode } => {
+                write!(f, "opcode ${opcode:02X} is not implemented")
+            }
+            Mismatch::Register {
+                name,
+                expected,
+                actual,
+            } => write!(f, "{name} is ${actual:02X}, expected ${expected:02X}"),
+            Mismatch::Memory {
+                addr,
+                expected,
+                actual,
+            } => match actual {
+                Some(actual) => write!(f, "${addr:04X} is ${actual:02X}, expected ${expected:02X}"),
+                None => write!(f, "${addr:04X} cannot be read, expected ${expected:02X}"),
+            },
+            Mismatch::CycleCount { expected, actual } => {
+                write!(f, "took {actual} cycles, expected {expected}")
+            }
+            Mismatch::Cycle {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "cycle {index} was {}, expected {}",
+                format_access(actual),
+                format_access(expected)
+            ),
+        }
+    }
+}
+
+/// Runs one test case.
+///
+/// # Arguments
+///
+/// * `subject` - The CPU. Only the RAM the case lists is set up, so the
+///   rest should be fresh for each case.
+/// * `case` - The test case.
+///
+/// # Returns
+///
+/// Every mismatch, registers first, or nothing if the case passed.
+pub fn run_case<S: Subject>(subject: &mut S, case: &TestCase) -> Vec<Mismatch> {
+    subject.set_up(&case.initial);
+    let opcode = subject.peek(case.initial.pc).unwrap_or_default();
+    if !subject.is_implemented(opcode) {
+        return vec![Mismatch::Unimplemented { opcode }];
+    }
+    let start_cycles = subject.cycles();
+    subject.execute();
+
+    let mut mismatches = Vec::new();
+    let registers = subject.registers();
+    let expected = &case.final_state;
+    let pairs = [
+        ("PC", expected.pc, registers.pc),
+        ("S", expected.s as u16, registers.sp as u16),
+        ("A", expected.a as u16, registers.a as u16),
+        ("X", expected.x as u16, registers.x as u16),
+        ("Y", expected.y as u16, registers.y as u16),
+        ("P", expected.p as u16, registers.status.to_byte() as u16),
+    ];
+    for (name, expected, actual) in pairs {
+        if expected != actual {
+            mismatches.push(Mismatch::Register {
+                name,
+                expected,
+                actual,
+            });
+        }
+    }
+    for &(addr, expected) in &expected.ram {
+        let actual = subject.peek(addr);
+        if actual != Some(expected) {
+            mismatches.push(Mismatch::Memory {
+                addr,
+                expected,
+                actual,
+            });
+        }
+    }
+    let cycles = subject.cycles() - start_cycles;
+    if cycles != case.cycles.len() as u64 {
+        mismatches.push(Mismatch::CycleCount {
+            expected: case.cycles.len() as u64,
+            actual: cycles,
+        });
+    }
+    let accesses = subject.accesses();
+    let count = accesses.len().max(case.cycles.len());
+    if let Some(index) = (0..count).find(|&i| accesses.get(i) != case.cycles.get(i)) {
+        mismatches.push(Mismatch::Cycle {
+            index,
+            expected: case.cycles.get(index).copied(),
+            actual: accesses.get(index).copied(),
+        });
+    }
+    mismatches
+}
+
+/// The results of running many test cases.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of cases run.
+    pub cases: usize,
+    /// The number that passed.
+    pub passed: usize,
+    /// The number that failed on registers or RAM, as opposed to only on
+    /// bus timing.
+    pub wrong_results: usize,
+    /// The name and mismatches of each failing case.
+    pub failures: Vec<(String, Vec<Mismatch>)>,
+}
+
+impl Summary {
+    /// Returns `true` if every case passed.
+    pub fn all_passed(&self) -> bool {
+        self.passed == self.cases
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} cases passed, {} with wrong results",
+            self.passed, self.cases, self.wrong_results
+        )?;
+        if let Some((name, mismatches)) = self.failures.first() {
+            write!(f, "; first failure \"{name}\":")?;
+            for mismatch in mismatches {
+                write!(f, " {mismatch};")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs test cases, each on a fresh subject.
+///
+/// # Arguments
+///
+/// * `new_subject` - Creates a subject, with empty RAM.
+/// * `cases` - The test cases.
+pub fn run_cases<S: Subject, F: FnMut() -> S>(mut new_subject: F, cases: &[TestCase]) -> Summary {
+    let mut summary = Summary::default();
+    for case in cases {
+        summary.cases += 1;
+        let mismatches = run_case(&mut new_subject(), case);
+        if mismatches.is_empty() {
+            summary.passed += 1;
+            continue;
+        }
+        let timing_only = mismatches
+            .iter()
+            .all(|m| matches!(m, Mismatch::CycleCount { .. } | Mismatch::Cycle { .. }));
+        if !timing_only {
+            summary.wrong_results += 1;
+        }
+        summary.failures.push((case.name.clone(), mismatches));
+    }
+    summary
+}
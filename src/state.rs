@@ -0,0 +1,391 @@
+//! The `state` module captures the complete state of a `CPU` as a plain value,
+//! and defines the `Snapshot` trait for capturing the state of buses and devices.
+//!
+//! A `CpuState` holds everything `CPU::step` depends on apart from the bus:
+//! the registers and the cycle count. It can be taken with `CPU::snapshot`,
+//! put back with `CPU::restore`, compared, and diffed field by field for
+//! test failure messages:
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! let before = cpu.snapshot();
+//! cpu.step();
+//! let diff = before.diff(&cpu.snapshot());
+//! assert_eq!(diff.to_string(), "PC: $0000 -> $0001\ncycles: 0 -> 2\n");
+//! cpu.restore(&before);
+//! assert_eq!(cpu.snapshot(), before);
+//! ```
+
+use crate::adapters::{LoggingBus, MirrorBus, OverlayBus, WaitStateBus};
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::registers::Registers;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// The complete architectural and internal state of a `CPU`, excluding the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    /// The CPU registers, including the status flags.
+    pub registers: Registers,
+    /// The number of cycles elapsed since reset.
+    pub cycles: u64,
+}
+
+impl CpuState {
+    /// Compares this state with another, field by field.
+    ///
+    /// # Returns
+    ///
+    /// A `StateDiff` listing each field whose value differs, in the order
+    /// A, X, Y, SP, PC, the flags from N to C, then the cycle count.
+    pub fn diff(&self, other: &CpuState) -> StateDiff {
+        let changes = Field::ALL
+            .iter()
+            .filter_map(|&field| {
+                let (left, right) = (self.get(field), other.get(field));
+                (left != right).then_some(FieldChange { field, left, right })
+            })
+            .collect();
+        StateDiff { changes }
+    }
+
+    /// Returns the value of a single field, with flags as 0 or 1.
+    pub fn get(&self, field: Field) -> u64 {
+        let registers = &self.registers;
+        let status = &registers.status;
+        match field {
+            Field::A => registers.a as u64,
+            Field::X => registers.x as u64,
+            Field::Y => registers.y as u64,
+            Field::Sp => registers.sp as u64,
+            Field::Pc => registers.pc as u64,
+            Field::Negative => status.negative as u64,
+            Field::Overflow => status.overflow as u64,
+            Field::Unused => status.unused as u64,
+            Field::Break => status.break_mode as u64,
+            Field::Decimal => status.decimal_mode as u64,
+            Field::InterruptDisable => status.interrupt_disable as u64,
+            Field::Zero => status.zero as u64,
+            Field::Carry => status.carry as u64,
+            Field::Cycles => self.cycles,
+        }
+    }
+}
+
+/// A field of a `CpuState`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// The accumulator.
+    A,
+    /// The X register.
+    X,
+    /// The Y register.
+    Y,
+    /// The stack pointer.
+    Sp,
+    /// The program counter.
+    Pc,
+    /// The N flag.
+    Negative,
+    /// The V flag.
+    Overflow,
+    /// The U flag.
+    Unused,
+    /// The B flag.
+    Break,
+    /// The D flag.
+    Decimal,
+    /// The I flag.
+    InterruptDisable,
+    /// The Z flag.
+    Zero,
+    /// The C flag.
+    Carry,
+    /// The cycle count.
+    Cycles,
+}
+
+impl Field {
+    /// Every field, in the order used by diffs.
+    pub const ALL: [Field; 14] = [
+        Field::A,
+        Field::X,
+        Field::Y,
+        Field::Sp,
+        Field::Pc,
+        Field::Negative,
+        Field::Overflow,
+        Field::Unused,
+        Field::Break,
+        Field::Decimal,
+        Field::InterruptDisable,
+        Field::Zero,
+        Field::Carry,
+        Field::Cycles,
+    ];
+
+    /// Returns the name used for the field in diffs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::A => "A",
+            Field::X => "X",
+            Field::Y => "Y",
+            Field::Sp => "SP",
+            Field::Pc => "PC",
+            Field::Negative => "N",
+            Field::Overflow => "V",
+            Field::Unused => "U",
+            Field::Break => "B",
+            Field::Decimal => "D",
+            Field::InterruptDisable => "I",
+            Field::Zero => "Z",
+            Field::Carry => "C",
+            Field::Cycles => "cycles",
+        }
+    }
+}
+
+/// A single field that differs between two states.
+///
+/// Values are widened to `u64`. Flags are 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldChange {
+    /// The field that differs.
+    pub field: Field,
+    /// The value in the state `diff` was called on.
+    pub left: u64,
+    /// The value in the state passed to `diff`.
+    pub right: u64,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.field.name();
+        match self.field {
+            Field::Pc => write!(f, "{name}: ${:04X} -> ${:04X}", self.left, self.right),
+            Field::A | Field::X | Field::Y | Field::Sp => {
+                write!(f, "{name}: ${:02X} -> ${:02X}", self.left, self.right)
+            }
+            _ => write!(f, "{name}: {} -> {}", self.left, self.right),
+        }
+    }
+}
+
+/// The differences between two `CpuState`s.
+///
+/// Displays as one line per differing field.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StateDiff {
+    /// The fields that differ, in a fixed order.
+    pub changes: Vec<FieldChange>,
+}
+
+impl StateDiff {
+    /// Returns `true` if the two states were identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    /// Captures the CPU's current state.
+    pub fn snapshot(&self) -> CpuState {
+        CpuState {
+            registers: self.registers,
+            cycles: self.cycles(),
+        }
+    }
+
+    /// Restores a state captured by `snapshot`.
+    ///
+    /// The bus is left untouched.
+    pub fn restore(&mut self, state: &CpuState) {
+        self.registers = state.registers;
+        self.set_cycles(state.cycles);
+    }
+}
+
+/// A bus or device whose state can be captured in memory and put back.
+///
+/// This is what the rewind buffer uses to restore the machine behind the
+/// CPU. Unlike `savestate::SaveState`, it needs no serialisation, so
+/// snapshots are cheap to take. It is implemented for the memory buses and
+/// the adapters in this crate.
+pub trait Snapshot {
+    /// The captured state.
+    type State: Clone;
+
+    /// Captures the current state without side effects.
+    fn snapshot(&self) -> Self::State;
+
+    /// Puts back a state captured by `snapshot`.
+    fn restore(&mut self, state: &Self::State);
+
+    /// Returns the approximate number of bytes a captured state occupies,
+    /// used to keep rewind buffers within their memory budget.
+    fn state_size(state: &Self::State) -> usize {
+        std::mem::size_of_val(state)
+    }
+}
+
+impl Snapshot for Vec<u8> {
+    type State = Vec<u8>;
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn restore(&mut self, state: &Vec<u8>) {
+        self.clone_from(state);
+    }
+
+    fn state_size(state: &Vec<u8>) -> usize {
+        state.len()
+    }
+}
+
+impl Snapshot for [u8; 0x10000] {
+    type State = Vec<u8>;
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn restore(&mut self, state: &Vec<u8>) {
+        self.copy_from_slice(state);
+    }
+
+    fn state_size(state: &Vec<u8>) -> usize {
+        state.len()
+    }
+}
+
+impl<S: Snapshot + ?Sized> Snapshot for Box<S> {
+    type State = S::State;
+
+    fn snapshot(&self) -> S::State {
+        (**self).snapshot()
+    }
+
+    fn restore(&mut self, state: &S::State) {
+        (**self).restore(state)
+    }
+
+    fn state_size(state: &S::State) -> usize {
+        S::state_size(state)
+    }
+}
+
+impl<S: Snapshot> Snapshot for Rc<RefCell<S>> {
+    type State = S::State;
+
+    fn snapshot(&self) -> S::State {
+        self.borrow().snapshot()
+    }
+
+    fn restore(&mut self, state: &S::State) {
+        self.borrow_mut().restore(state)
+    }
+
+    fn state_size(state: &S::State) -> usize {
+        S::state_size(state)
+    }
+}
+
+/// A machine made of several parts captures each part in turn.
+impl<S1: Snapshot, S2: Snapshot> Snapshot for (S1, S2) {
+    type State = (S1::State, S2::State);
+
+    fn snapshot(&self) -> Self::State {
+        (self.0.snapshot(), self.1.snapshot())
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.0.restore(&state.0);
+        self.1.restore(&state.1);
+    }
+
+    fn state_size(state: &Self::State) -> usize {
+        S1::state_size(&state.0) + S2::state_size(&state.1)
+    }
+}
+
+/// The access log is debugging data, so only the wrapped bus is captured.
+impl<B: Bus + Snapshot> Snapshot for LoggingBus<B> {
+    type State = B::State;
+
+    fn snapshot(&self) -> B::State {
+        self.inner().snapshot()
+    }
+
+    fn restore(&mut self, state: &B::State) {
+        self.inner_mut().restore(state)
+    }
+
+    fn state_size(state: &B::State) -> usize {
+        B::state_size(state)
+    }
+}
+
+impl<B: Bus + Snapshot> Snapshot for MirrorBus<B> {
+    type State = B::State;
+
+    fn snapshot(&self) -> B::State {
+        self.inner().snapshot()
+    }
+
+    fn restore(&mut self, state: &B::State) {
+        self.inner_mut().restore(state)
+    }
+
+    fn state_size(state: &B::State) -> usize {
+        B::state_size(state)
+    }
+}
+
+impl<B: Bus + Snapshot> Snapshot for WaitStateBus<B> {
+    type State = B::State;
+
+    fn snapshot(&self) -> B::State {
+        self.inner().snapshot()
+    }
+
+    fn restore(&mut self, state: &B::State) {
+        self.inner_mut().restore(state)
+    }
+
+    fn state_size(state: &B::State) -> usize {
+        B::state_size(state)
+    }
+}
+
+impl<B: Bus + Snapshot, O: Bus + Snapshot> Snapshot for OverlayBus<B, O> {
+    type State = (B::State, O::State);
+
+    fn snapshot(&self) -> Self::State {
+        (self.inner().snapshot(), self.overlay().snapshot())
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.inner_mut().restore(&state.0);
+        self.overlay_mut().restore(&state.1);
+    }
+
+    fn state_size(state: &Self::State) -> usize {
+        B::state_size(&state.0) + O::state_size(&state.1)
+    }
+}
@@ -0,0 +1,106 @@
+// src/tests/adapters.rs
+
+use crate::adapters::*;
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_logging_bus_records_cpu_accesses() {
+    let mut memory = [0u8; 0x10000];
+    // LDA #$42 ; STA $0200
+    memory[0x8000..0x8005].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x02]);
+    memory[0xFFFC] = 0x00;
+    memory[0xFFFD] = 0x80;
+
+    let mut cpu = CPU::new(LoggingBus::new(memory));
+    cpu.reset();
+    cpu.bus.clear();
+    cpu.step();
+    cpu.step();
+
+    let log = cpu.bus.log();
+    assert_eq!(log.len(), 6);
+    assert_eq!(
+        log[5],
+        BusAccess {
+            addr: 0x0200,
+            data: 0x42,
+            kind: AccessKind::Write
+        }
+    );
+    assert_eq!(cpu.bus.inner()[0x0200], 0x42);
+}
+
+#[test]
+fn test_tracing_bus_calls_closure() {
+    let mut seen = Vec::new();
+    let mut bus = TracingBus::new(vec![0u8; 16], |access: &BusAccess| seen.push(*access));
+    bus.write(3, 7);
+    assert_eq!(bus.read(3), 7);
+    drop(bus);
+    assert_eq!(seen.len(), 2);
+    assert_eq!(seen[1].kind, AccessKind::Read);
+}
+
+#[test]
+fn test_overlay_bus_routes_range() {
+    let mut bus = OverlayBus::new(vec![0u8; 0x10000], 0x6000..=0x6001, vec![0u8; 0x10000]);
+    bus.write(0x6000, 0x11);
+    bus.write(0x6002, 0x22);
+    assert_eq!(bus.overlay()[0x6000], 0x11);
+    assert_eq!(bus.inner()[0x6000], 0x00);
+    assert_eq!(bus.inner()[0x6002], 0x22);
+}
+
+#[test]
+fn test_mirror_bus_folds_addresses() {
+    let mut bus = MirrorBus::new(vec![0u8; 0x0800], 0x0000..=0x1FFF, 0x0800);
+    bus.write(0x1801, 0x5A);
+    assert_eq!(bus.read(0x0001), 0x5A);
+    assert_eq!(bus.read(0x0801), 0x5A);
+    assert_eq!(bus.fold(0x2000), 0x2000);
+}
+
+#[test]
+fn test_vec_bus_out_of_range() {
+    let mut bus = vec![0u8; 4];
+    bus.write(0x1000, 0xFF);
+    assert_eq!(bus.read(0x1000), 0x00);
+}
+
+#[test]
+fn test_borrowed_bus() {
+    let mut memory = vec![0u8; 0x10000];
+    memory[0xFFFC] = 0x34;
+    memory[0xFFFD] = 0x12;
+    {
+        let mut cpu = CPU::new(&mut memory);
+        cpu.reset();
+        assert_eq!(cpu.registers.pc, 0x1234);
+        cpu.bus.write(0x0000, 0x99);
+    }
+    assert_eq!(memory[0x0000], 0x99);
+}
+
+#[test]
+fn test_shared_bus_between_two_cpus() {
+    let shared = Rc::new(RefCell::new(vec![0u8; 0x10000]));
+    let mut writer = CPU::new(shared.clone());
+    let mut reader = CPU::new(shared.clone());
+    writer.bus.write(0x0300, 0xAB);
+    assert_eq!(reader.bus.read(0x0300), 0xAB);
+    assert_eq!(shared.borrow()[0x0300], 0xAB);
+}
+
+#[test]
+fn test_sync_shared_bus() {
+    let shared = Arc::new(Mutex::new(vec![0u8; 0x10000]));
+    let mut bus = shared.clone();
+    std::thread::spawn(move || bus.write(0x0010, 0x01))
+        .join()
+        .unwrap();
+    assert_eq!(shared.clone().read(0x0010), 0x01);
+}
@@ -0,0 +1,388 @@
+// src/tests/dap.rs
+
+use super::create_ram_cpu_with_program;
+use crate::cpu::CPU;
+use crate::dap::DapServer;
+use crate::dbginfo::DebugInfo;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+/// main.s:
+///  3  main:  LDA #$42      ; $8000
+///  4         JSR sub       ; $8002
+///  5  loop:  JMP loop      ; $8005
+///  8  sub:   INX           ; $8010
+///  9         RTS           ; $8011
+const DBG: &str = "\
+version\tmajor=2,minor=0
+file\tid=0,name=\"main.s\",size=100,mtime=0x65000000,mod=0
+line\tid=0,file=0,line=3,span=0
+line\tid=1,file=0,line=4,span=1
+line\tid=2,file=0,line=5,span=2
+line\tid=3,file=0,line=8,span=3
+line\tid=4,file=0,line=9,span=4
+seg\tid=0,name=\"CODE\",start=0x008000,size=0x0012,addrsize=absolute,type=ro
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+span\tid=2,seg=0,start=5,size=3
+span\tid=3,seg=0,start=16,size=1
+span\tid=4,seg=0,start=17,size=1
+sym\tid=0,name=\"main\",addrsize=absolute,val=0x8000,seg=0,type=lab
+sym\tid=1,name=\"loop\",addrsize=absolute,val=0x8005,seg=0,type=lab
+sym\tid=2,name=\"sub\",addrsize=absolute,val=0x8010,seg=0,type=lab
+";
+
+fn create_cpu() -> CPU<Vec<u8>> {
+    let mut cpu = create_ram_cpu_with_program(&[0xA9, 0x42, 0x20, 0x10, 0x80, 0x4C, 0x05, 0x80]);
+    cpu.bus[0x8010..0x8012].copy_from_slice(&[0xE8, 0x60]);
+    cpu.registers.sp = 0xFF;
+    cpu
+}
+
+/// A scripted protocol client, standing in for an editor.
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: u64,
+    events: VecDeque<Value>,
+}
+
+impl Client {
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.reader.read_line(&mut header).unwrap();
+            match header.trim_end().split_once(": ") {
+                Some(("Content-Length", value)) => length = value.parse().unwrap(),
+                _ => break,
+            }
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Sends a request and returns the body of its successful response.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let response = self.try_request(command, arguments);
+        assert_eq!(response["success"], true, "{response}");
+        response["body"].clone()
+    }
+
+    /// Sends a request and returns the whole response.
+    fn try_request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let seq = self.seq;
+        self.send(json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }));
+        loop {
+            let message = self.receive();
+            if message["type"] == "response" && message["request_seq"] == seq {
+                assert_eq!(message["command"], command);
+                return message;
+            }
+            self.events.push_back(message);
+        }
+    }
+
+    /// Returns the body of the next event, which must be `event`.
+    fn event(&mut self, event: &str) -> Value {
+        let message = match self.events.pop_front() {
+            Some(message) => message,
+            None => self.receive(),
+        };
+        assert_eq!(message["event"], event, "{message}");
+        message["body"].clone()
+    }
+
+    /// Returns the PC, line and name of the only stack frame.
+    fn frame(&mut self) -> (String, u64, String) {
+        let trace = self.request("stackTrace", json!({ "threadId": 1 }));
+        let frame = &trace["stackFrames"][0];
+        (
+            frame["instructionPointerReference"]
+                .as_str()
+                .unwrap()
+                .to_string(),
+            frame["line"].as_u64().unwrap(),
+            frame["name"].as_str().unwrap().to_string(),
+        )
+    }
+}
+
+/// Serves `cpu` to a client running `script` on another thread.
+fn session<F: FnOnce(&mut Client) + Send + 'static>(
+    server: &mut DapServer,
+    cpu: &mut CPU<Vec<u8>>,
+    script: F,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            seq: 0,
+            events: VecDeque::new(),
+        };
+        script(&mut client)
+    });
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nodelay(true).unwrap();
+    server
+        .serve(cpu, stream.try_clone().unwrap(), stream)
+        .unwrap();
+    client.join().unwrap();
+}
+
+fn create_server() -> DapServer {
+    let mut server = DapServer::new();
+    server.set_debug_info(DebugInfo::parse(DBG).unwrap());
+    server
+}
+
+#[test]
+fn test_source_breakpoints_and_stepping() {
+    let mut cpu = create_cpu();
+    session(&mut create_server(), &mut cpu, |client| {
+        let capabilities = client.request("initialize", json!({ "adapterID": "lib6502" }));
+        assert_eq!(capabilities["supportsDisassembleRequest"], true);
+        client.event("initialized");
+        client.request("launch", json!({ "stopOnEntry": true }));
+        let breakpoints = client.request(
+            "setBreakpoints",
+            json!({
+                "source": { "path": "/home/user/project/main.s" },
+                "breakpoints": [{ "line": 4 }, { "line": 7 }, { "line": 20 }],
+            }),
+        );
+        let lines: Vec<_> = breakpoints["breakpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|breakpoint| (breakpoint["verified"].clone(), breakpoint["line"].clone()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (json!(true), json!(4)),
+                (json!(true), json!(8)),
+                (json!(false), json!(20))
+            ]
+        );
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.event("stopped")["reason"], "entry");
+        assert_eq!(
+            client.frame(),
+            ("0x8000".to_string(), 3, "main".to_string())
+        );
+
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "breakpoint");
+        assert_eq!(client.frame().1, 4);
+
+        client.request("stepIn", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "step");
+        assert_eq!(client.frame(), ("0x8010".to_string(), 8, "sub".to_string()));
+        client.request("next", json!({ "threadId": 1 }));
+        client.event("stopped");
+        assert_eq!(client.frame().1, 9);
+        client.request("stepOut", json!({ "threadId": 1 }));
+        client.event("stopped");
+        assert_eq!(client.frame().1, 5);
+
+        client.request("disconnect", json!({}));
+    });
+    assert_eq!(cpu.registers.pc, 0x8005);
+    assert_eq!(cpu.registers.x, 1);
+}
+
+#[test]
+fn test_registers_memory_and_disassembly() {
+    let mut cpu = create_cpu();
+    cpu.registers.a = 0x42;
+    session(&mut create_server(), &mut cpu, |client| {
+        client.request("initialize", json!({}));
+        client.request("launch", json!({ "stopOnEntry": true }));
+        client.request("configurationDone", json!({}));
+        client.event("initialized");
+        client.event("stopped");
+
+        let scopes = client.request("scopes", json!({ "frameId": 0 }));
+        let registers = scopes["scopes"][0]["variablesReference"].clone();
+        let variables = client.request("variables", json!({ "variablesReference": registers }));
+        let variables = variables["variables"].as_array().unwrap();
+        assert_eq!(variables[0]["name"], "A");
+        assert_eq!(variables[0]["value"], "$42");
+        assert_eq!(variables[4]["value"], "$8000");
+        let flags = variables[5]["variablesReference"].clone();
+        let flags = client.request("variables", json!({ "variablesReference": flags }));
+        assert_eq!(flags["variables"].as_array().unwrap().len(), 8);
+
+        let set = client.request(
+            "setVariable",
+            json!({ "variablesReference": registers, "name": "X", "value": "A + 1" }),
+        );
+        assert_eq!(set["value"], "$43");
+        let set = client.request(
+            "setVariable",
+            json!({ "variablesReference": flags, "name": "C", "value": "1" }),
+        );
+        assert_eq!(set["value"], "1");
+        let result = client.request("evaluate", json!({ "expression": "X + C" }));
+        assert_eq!(result["result"], "$44 (68)");
+        let error = client.try_request("evaluate", json!({ "expression": "X +" }));
+        assert_eq!(error["success"], false);
+
+        let memory = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x8000", "count": 3 }),
+        );
+        assert_eq!(memory["data"], "qUIg");
+        client.request(
+            "writeMemory",
+            json!({ "memoryReference": "0x0300", "data": "AQI=" }),
+        );
+        let memory = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0300", "offset": 1, "count": 1 }),
+        );
+        assert_eq!(
+            (memory["address"].clone(), memory["data"].clone()),
+            (json!("0x0301"), json!("Ag=="))
+        );
+
+        let listing = client.request(
+            "disassemble",
+            json!({ "memoryReference": "0x8002", "instructionOffset": -1, "instructionCount": 3 }),
+        );
+        let listing: Vec<_> = listing["instructions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|instruction| {
+                format!(
+                    "{} {} {}",
+                    instruction["address"].as_str().unwrap(),
+                    instruction["instruction"].as_str().unwrap(),
+                    instruction["line"]
+                )
+            })
+            .collect();
+        assert_eq!(
+            listing,
+            vec!["0x8000 LDA #$42 3", "0x8002 JSR sub 4", "0x8005 JMP loop 5"]
+        );
+        client.request("disconnect", json!({}));
+    });
+    assert_eq!(cpu.registers.x, 0x43);
+    assert!(cpu.registers.status.carry);
+    assert_eq!(cpu.bus[0x0300..0x0302], [1, 2]);
+}
+
+#[test]
+fn test_log_points_conditions_and_pause() {
+    let mut cpu = create_cpu();
+    cpu.bus[0x8FFF] = 0xEA;
+    session(&mut create_server(), &mut cpu, |client| {
+        client.request("initialize", json!({}));
+        client.event("initialized");
+        client.request("launch", json!({}));
+        let breakpoints = client.request(
+            "setBreakpoints",
+            json!({
+                "source": { "path": "main.s" },
+                "breakpoints": [
+                    { "line": 8, "logMessage": "in sub with X={X}" },
+                    { "line": 5, "condition": "X == 2" },
+                    { "line": 3, "condition": "X ==" },
+                ],
+            }),
+        );
+        assert_eq!(breakpoints["breakpoints"][2]["verified"], false);
+        client.request(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x9000" }] }),
+        );
+        client.request("configurationDone", json!({}));
+
+        let output = client.event("output");
+        assert_eq!(output["output"], "in sub with X=0\n");
+        // The loop never satisfies the condition
+        client.request("pause", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "pause");
+        assert_eq!(client.frame().1, 5);
+
+        // Run into the instruction breakpoint from a NOP
+        client.request(
+            "setVariable",
+            json!({ "variablesReference": 1, "name": "PC", "value": "$8FFF" }),
+        );
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "instruction breakpoint");
+
+        let unknown = client.try_request("restartFrame", json!({}));
+        assert_eq!(unknown["success"], false);
+        client.request("terminate", json!({}));
+        client.event("terminated");
+    });
+    assert_eq!(cpu.registers.pc, 0x9000);
+}
+
+#[test]
+fn test_breakpoint_at_end_of_slice() {
+    // The server checks for requests every 10000 instructions, and this
+    // breakpoint is reached just as the first slice ends
+    let mut cpu = create_ram_cpu_with_program(&[0xEA; 0x3000]);
+    session(&mut DapServer::new(), &mut cpu, |client| {
+        client.request("initialize", json!({}));
+        client.event("initialized");
+        client.request("launch", json!({}));
+        client.request(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0xA710" }] }),
+        );
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.event("stopped")["reason"], "instruction breakpoint");
+        client.request("disconnect", json!({}));
+    });
+    assert_eq!(cpu.registers.pc, 0xA710);
+}
+
+#[test]
+fn test_client_sizes_are_capped() {
+    let mut cpu = create_cpu();
+    session(&mut create_server(), &mut cpu, |client| {
+        client.request("initialize", json!({}));
+        client.event("initialized");
+        let listing = client.request(
+            "disassemble",
+            json!({
+                "memoryReference": "0x8000",
+                "instructionOffset": i64::MIN,
+                "instructionCount": u64::MAX,
+            }),
+        );
+        assert_eq!(listing["instructions"].as_array().unwrap().len(), 0x10000);
+
+        // A body longer than any request ends the session rather than
+        // being allocated
+        write!(client.writer, "Content-Length: 1000000000000\r\n\r\n").unwrap();
+        assert_eq!(client.reader.read(&mut [0]).unwrap(), 0);
+    });
+}
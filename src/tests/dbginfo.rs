@@ -0,0 +1,106 @@
+// src/tests/dbginfo.rs
+
+use crate::dbginfo::{DebugInfo, DebugInfoError, SourceLine};
+use std::path::PathBuf;
+
+/// main.s: a main program at $8000 with a subroutine at $8010, one line
+/// that is a macro body and one line with no code.
+const DBG: &str = "\
+version\tmajor=2,minor=0
+info\tcsym=0,file=2,lib=0,line=7,mod=1,scope=1,seg=1,span=5,sym=4,type=4
+file\tid=0,name=\"src/main.s\",size=200,mtime=0x65000000,mod=0
+file\tid=1,name=\"macros.inc\",size=50,mtime=0x65000000,mod=0
+line\tid=0,file=0,line=3,span=0
+line\tid=1,file=0,line=4,span=1
+line\tid=2,file=0,line=5,span=2
+line\tid=3,file=0,line=6
+line\tid=4,file=0,line=8,span=3+4
+line\tid=5,file=1,line=2,type=2,count=1,span=4
+mod\tid=0,name=\"main.o\",file=0
+seg\tid=0,name=\"CODE\",start=0x008000,size=0x0012,addrsize=absolute,type=ro,oname=\"main.bin\",ooffs=0
+span\tid=0,seg=0,start=0,size=2,type=0
+span\tid=1,seg=0,start=2,size=3
+span\tid=2,seg=0,start=5,size=3
+span\tid=3,seg=0,start=16,size=1
+span\tid=4,seg=0,start=17,size=1
+scope\tid=0,name=\"\",mod=0,size=18,span=0+1+2+3+4
+sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=0,val=0x8000,seg=0,type=lab
+sym\tid=1,name=\"sub\",addrsize=absolute,scope=0,def=4,val=0x8010,seg=0,type=lab
+sym\tid=2,name=\"@loop\",addrsize=absolute,scope=0,def=2,val=0x8005,seg=0,type=lab
+sym\tid=3,name=\"SCREEN\",addrsize=absolute,scope=0,def=1,val=0x400,type=equ
+";
+
+#[test]
+fn test_lines_and_addresses() {
+    let info = DebugInfo::parse(DBG).unwrap();
+    let main = info.file_id("/home/user/project/src/main.s").unwrap();
+    assert_eq!(info.file_id("main.s"), None);
+    assert_eq!(info.file_id("src/main.s"), Some(main));
+    assert_eq!(info.files().count(), 2);
+
+    assert_eq!(
+        info.line_at(0x8003),
+        Some(SourceLine {
+            file: main,
+            line: 4
+        })
+    );
+    assert_eq!(info.line_at(0x8011).unwrap().line, 8);
+    assert_eq!(info.line_at(0x8008), None);
+
+    assert_eq!(info.line_address(main, 4), Some((4, 0x8002)));
+    // Line 6 has no code, so the breakpoint moves to line 8
+    assert_eq!(info.line_address(main, 6), Some((8, 0x8010)));
+    assert_eq!(info.line_address(main, 9), None);
+}
+
+#[test]
+fn test_symbols() {
+    let info = DebugInfo::parse(DBG).unwrap();
+    assert_eq!(info.symbol("sub"), Some(0x8010));
+    assert_eq!(info.symbol_at(0x8000), Some("main"));
+    // Neither cheap locals nor equates name addresses
+    assert_eq!(info.symbol_at(0x8005), None);
+    assert_eq!(info.symbol("SCREEN"), None);
+    assert_eq!(info.symbols().len(), 2);
+}
+
+#[test]
+fn test_load_resolves_paths() {
+    let dir = std::env::temp_dir().join(format!("lib6502-dbginfo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.dbg");
+    std::fs::write(&path, DBG).unwrap();
+    let info = DebugInfo::load(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let main = info.file_id(dir.join("src/main.s")).unwrap();
+    assert_eq!(info.source_path(main), Some(dir.join("src/main.s")));
+    assert_eq!(
+        DebugInfo::parse(DBG).unwrap().source_path(main),
+        Some(PathBuf::from("src/main.s"))
+    );
+    assert!(matches!(
+        DebugInfo::load(dir.join("missing.dbg")),
+        Err(DebugInfoError::Io(_))
+    ));
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = DebugInfo::parse("file\tid=0,name=\"main.s").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid debug info at line 1: unterminated string for name"
+    );
+    let err = DebugInfo::parse("version\tmajor=2\nline\tid=0,file=0,line=1,span=9").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid debug info at line 2: unknown span 9"
+    );
+    let err = DebugInfo::parse("seg\tid=0,start=zero").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid debug info at line 1: invalid start 'zero'"
+    );
+}
@@ -0,0 +1,423 @@
+// src/tests/debugger.rs
+
+use super::{create_cpu_with_program, create_ram_cpu_with_program, TestBus};
+use crate::bus::{AccessKind, BusAccess};
+use crate::cpu::CPU;
+use crate::debugger::{BreakOn, Debugger, Resume, Stop, WatchKind};
+use crate::expr::{Expr, LogMessage};
+use crate::scheduler::{Clock, DeviceContext, Scheduler};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// main:  JSR sub ; NOP ; BRK
+/// sub:   LDA #1 ; JSR inner ; RTS
+/// inner: INX ; RTS
+/// irq:   RTI
+fn create_cpu() -> CPU<Vec<u8>> {
+    let mut cpu = create_ram_cpu_with_program(&[0x20, 0x10, 0x80, 0xEA, 0x00]);
+    cpu.bus[0x8010..0x8016].copy_from_slice(&[0xA9, 0x01, 0x20, 0x20, 0x80, 0x60]);
+    cpu.bus[0x8020..0x8022].copy_from_slice(&[0xE8, 0x60]);
+    cpu.bus[0x9000] = 0x40;
+    cpu.bus[0xFFFE] = 0x00;
+    cpu.bus[0xFFFF] = 0x90;
+    cpu.registers.sp = 0xFF;
+    cpu
+}
+
+/// The program of `create_cpu` on a bus that cannot peek.
+fn create_unpeekable_cpu() -> CPU<TestBus> {
+    let ram = create_cpu();
+    let mut cpu = create_cpu_with_program(&[]);
+    cpu.bus.memory.copy_from_slice(&ram.bus);
+    cpu.registers.sp = 0xFF;
+    cpu
+}
+
+#[test]
+fn test_breakpoint_stops_before_instruction() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    debugger.set_breakpoint(0x8020);
+    assert_eq!(debugger.run(&mut cpu), Stop::Breakpoint { pc: 0x8020 });
+    assert_eq!(cpu.registers.pc, 0x8020);
+    assert_eq!(cpu.registers.x, 0);
+    assert_eq!(debugger.breakpoint(0x8020).unwrap().hits, 1);
+
+    // Resuming from a breakpoint does not stop on it again
+    debugger.set_break_on(BreakOn {
+        brk: true,
+        ..BreakOn::default()
+    });
+    assert_eq!(debugger.run(&mut cpu), Stop::Brk { pc: 0x8004 });
+    assert_eq!(cpu.registers.x, 1);
+}
+
+#[test]
+fn test_disabled_and_temporary_breakpoints() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    debugger.set_breakpoint(0x8010).enabled = false;
+    debugger.set_temporary_breakpoint(0x8020);
+    assert_eq!(
+        debugger.run(&mut cpu),
+        Stop::TemporaryBreakpoint { pc: 0x8020 }
+    );
+    assert!(debugger.breakpoint(0x8020).is_none());
+    assert_eq!(debugger.breakpoint(0x8010).unwrap().hits, 0);
+    assert_eq!(debugger.breakpoints().count(), 1);
+    assert!(debugger.remove_breakpoint(0x8010));
+    assert!(!debugger.remove_breakpoint(0x8010));
+}
+
+#[test]
+fn test_step_into_and_over() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.step_into(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8010);
+
+    let mut cpu = create_cpu();
+    assert_eq!(debugger.step_over(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8003);
+    assert_eq!(cpu.registers.a, 1);
+    assert_eq!(cpu.registers.x, 1);
+    assert_eq!(cpu.registers.sp, 0xFF);
+
+    // Not a JSR, so just one instruction
+    assert_eq!(debugger.step_over(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8004);
+}
+
+#[test]
+fn test_step_over_stops_at_breakpoint_inside_call() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    debugger.set_breakpoint(0x8020);
+    assert_eq!(
+        debugger.step_over(&mut cpu),
+        Stop::Breakpoint { pc: 0x8020 }
+    );
+}
+
+#[test]
+fn test_step_out() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    debugger.step_into(&mut cpu);
+    // The nested call to inner returns to this frame, so it does not count
+    assert_eq!(debugger.step_out(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8003);
+    assert_eq!(cpu.registers.x, 1);
+
+    let mut cpu = create_cpu();
+    debugger.run_to(&mut cpu, 0x8020);
+    assert_eq!(debugger.step_out(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8015);
+}
+
+#[test]
+fn test_run_to_cursor() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.run_to(&mut cpu, 0x8015),
+        Stop::Cursor { pc: 0x8015 }
+    );
+    assert_eq!(cpu.registers.x, 1);
+    assert_eq!(debugger.breakpoints().count(), 0);
+}
+
+#[test]
+fn test_break_on_rti_and_undocumented() {
+    let mut cpu = create_cpu();
+    cpu.bus[0x8003] = 0x02;
+    let mut debugger = Debugger::new();
+    debugger.set_break_on(BreakOn {
+        undocumented: true,
+        rti: true,
+        ..BreakOn::default()
+    });
+    let stop = debugger.run(&mut cpu);
+    assert_eq!(
+        stop,
+        Stop::Undocumented {
+            pc: 0x8003,
+            opcode: 0x02
+        }
+    );
+    assert_eq!(stop.to_string(), "undocumented opcode $02 at $8003");
+    // There is no getting past it
+    assert_eq!(debugger.step_into(&mut cpu), stop);
+
+    // NOP, then fall into the RTI of the handler
+    cpu.bus[0x8FFF] = 0xEA;
+    cpu.registers.pc = 0x8FFF;
+    assert_eq!(debugger.run(&mut cpu), Stop::Rti { pc: 0x9000 });
+}
+
+#[test]
+fn test_break_on_interrupt_taken() {
+    let mut cpu = create_cpu();
+    cpu.registers.status.interrupt_disable = false;
+    // Loop forever at $8003
+    cpu.bus[0x8003..0x8006].copy_from_slice(&[0x4C, 0x03, 0x80]);
+    let mut scheduler = Scheduler::new(cpu);
+    let device = scheduler.add_device(
+        |_tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| ctx.set_irq(true),
+        Clock::CPU,
+    );
+    scheduler.schedule_at(device, 100, 0);
+
+    let mut debugger = Debugger::new();
+    debugger.set_break_on(BreakOn {
+        interrupt: true,
+        ..BreakOn::default()
+    });
+    let stop = debugger.run(&mut scheduler);
+    assert_eq!(
+        stop,
+        Stop::Interrupt {
+            pc: 0x9000,
+            return_addr: 0x8003
+        }
+    );
+    assert_eq!(scheduler.cpu.registers.pc, 0x9000);
+}
+
+#[test]
+fn test_instruction_limit() {
+    let mut cpu = create_cpu();
+    cpu.bus[0x8003..0x8006].copy_from_slice(&[0x4C, 0x03, 0x80]);
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.resume(&mut cpu, Resume::Continue, 1_000),
+        Stop::Limit
+    );
+    assert_eq!(cpu.registers.pc, 0x8003);
+
+    // A breakpoint where the limit was reached has not been checked yet
+    let mut cpu = create_cpu();
+    debugger.set_breakpoint(0x8010);
+    assert_eq!(debugger.resume(&mut cpu, Resume::Continue, 1), Stop::Limit);
+    assert_eq!(debugger.run(&mut cpu), Stop::Breakpoint { pc: 0x8010 });
+    assert_eq!(debugger.step_into(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8012);
+}
+
+#[test]
+fn test_resume_polling() {
+    // One step over the call, however many slices it takes
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    let mut polls = 0;
+    let stop = debugger.resume_polling(&mut cpu, Resume::StepOver, 1, || {
+        polls += 1;
+        true
+    });
+    assert_eq!(stop, Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8003);
+    assert_eq!(polls, 5);
+
+    let mut cpu = create_cpu();
+    let stop = debugger.resume_polling(&mut cpu, Resume::StepOver, 2, || false);
+    assert_eq!(stop, Stop::Limit);
+    assert_eq!(cpu.registers.pc, 0x8012);
+}
+
+#[test]
+fn test_write_watchpoint_reports_instruction() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    let id = debugger.add_watchpoint(0x01FE..=0x01FF, WatchKind::Write);
+    let stop = debugger.run(&mut cpu);
+    assert_eq!(
+        stop,
+        Stop::Watchpoint {
+            id,
+            pc: 0x8000,
+            access: BusAccess {
+                addr: 0x01FF,
+                data: 0x80,
+                kind: AccessKind::Write
+            },
+            old: None
+        }
+    );
+    assert_eq!(stop.to_string(), "watchpoint 1: $8000 wrote $80 to $01FF");
+    // The instruction has finished
+    assert_eq!(cpu.registers.pc, 0x8010);
+    assert_eq!(debugger.watchpoint(id).unwrap().hits, 1);
+}
+
+#[test]
+fn test_read_watchpoint_sees_fetches() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    debugger.add_watchpoint(0x8021..=0x8021, WatchKind::Read);
+    let stop = debugger.run(&mut cpu);
+    // INX reads the byte after it before the RTS there is fetched
+    assert_eq!(stop.to_string(), "watchpoint 1: $8020 read $60 from $8021");
+    assert_eq!(cpu.registers.pc, 0x8021);
+}
+
+#[test]
+fn test_change_watchpoint() {
+    // LDA #5 ; STA $10 ; STA $10 ; LDA #6 ; STA $10
+    let mut cpu = create_cpu();
+    cpu.bus[0x8000..0x800A]
+        .copy_from_slice(&[0xA9, 0x05, 0x85, 0x10, 0x85, 0x10, 0xA9, 0x06, 0x85, 0x10]);
+    let mut debugger = Debugger::new();
+    debugger.add_watchpoint(0x0010..=0x0010, WatchKind::Change);
+
+    let stop = debugger.run(&mut cpu);
+    assert_eq!(
+        stop.to_string(),
+        "watchpoint 1: $8002 wrote $05 to $0010 (was $00)"
+    );
+    // Writing the same value again is not a change
+    let stop = debugger.run(&mut cpu);
+    assert_eq!(
+        stop.to_string(),
+        "watchpoint 1: $8008 wrote $06 to $0010 (was $05)"
+    );
+}
+
+#[test]
+fn test_disabled_and_removed_watchpoints() {
+    let mut cpu = create_cpu();
+    let mut debugger = Debugger::new();
+    let disabled = debugger.add_watchpoint(0x0000..=0xFFFF, WatchKind::Access);
+    debugger.watchpoint_mut(disabled).unwrap().enabled = false;
+    let removed = debugger.add_watchpoint(0x01FF..=0x01FF, WatchKind::Write);
+    assert!(debugger.remove_watchpoint(removed));
+    assert_eq!(debugger.watchpoints().count(), 1);
+    assert_eq!(
+        debugger.run_to(&mut cpu, 0x8003),
+        Stop::Cursor { pc: 0x8003 }
+    );
+    assert_eq!(debugger.watchpoint(disabled).unwrap().hits, 0);
+}
+
+#[test]
+fn test_watchpoint_hit_by_interrupt() {
+    let mut cpu = create_cpu();
+    cpu.registers.status.interrupt_disable = false;
+    cpu.registers.pc = 0x8003;
+    cpu.bus[0x8003..0x8006].copy_from_slice(&[0x4C, 0x03, 0x80]);
+    let mut scheduler = Scheduler::new(cpu);
+    let device = scheduler.add_device(
+        |_tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| ctx.set_irq(true),
+        Clock::CPU,
+    );
+    scheduler.schedule_at(device, 50, 0);
+
+    let mut debugger = Debugger::new();
+    debugger.add_watchpoint(0x01FD..=0x01FD, WatchKind::Write);
+    match debugger.run(&mut scheduler) {
+        Stop::Watchpoint { pc, access, .. } => {
+            assert_eq!(pc, 0x8003);
+            assert_eq!(access.addr, 0x01FD);
+        }
+        stop => panic!("unexpected stop: {stop}"),
+    }
+    assert_eq!(scheduler.cpu.registers.pc, 0x9000);
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    // inner: INX ; RTS, called in a loop: JSR inner ; JMP $8000
+    let mut cpu = create_cpu();
+    cpu.bus[0x8000..0x8006].copy_from_slice(&[0x20, 0x20, 0x80, 0x4C, 0x00, 0x80]);
+    let mut debugger = Debugger::new();
+    debugger
+        .set_conditional_breakpoint(0x8020, "X == 3")
+        .unwrap();
+    assert_eq!(debugger.run(&mut cpu), Stop::Breakpoint { pc: 0x8020 });
+    assert_eq!(cpu.registers.x, 3);
+    // Hits count every time it was reached
+    assert_eq!(debugger.breakpoint(0x8020).unwrap().hits, 4);
+
+    debugger
+        .set_conditional_breakpoint(0x8003, "hits == 2")
+        .unwrap();
+    assert_eq!(debugger.run(&mut cpu), Stop::Breakpoint { pc: 0x8003 });
+    assert_eq!(cpu.registers.x, 5);
+
+    assert!(debugger.set_conditional_breakpoint(0x8000, "X ==").is_err());
+    assert!(debugger.breakpoint(0x8000).is_none());
+}
+
+#[test]
+fn test_log_point_keeps_going() {
+    let mut cpu = create_cpu();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut debugger = Debugger::new();
+    let sink = log.clone();
+    debugger.set_log_output(move |message| sink.borrow_mut().push(message.to_string()));
+    debugger
+        .set_log_point(0x8020, "inner: A={A:02X} X={X} hit {hits}")
+        .unwrap();
+    debugger.set_break_on(BreakOn {
+        brk: true,
+        ..BreakOn::default()
+    });
+    assert_eq!(debugger.run(&mut cpu), Stop::Brk { pc: 0x8004 });
+    assert_eq!(*log.borrow(), vec!["inner: A=01 X=0 hit 1"]);
+}
+
+#[test]
+fn test_conditional_watchpoint_and_log_point() {
+    // LDA #5 ; STA $10 ; LDA #$85 ; STA $10
+    let mut cpu = create_cpu();
+    cpu.bus[0x8000..0x8008].copy_from_slice(&[0xA9, 0x05, 0x85, 0x10, 0xA9, 0x85, 0x85, 0x10]);
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut debugger = Debugger::new();
+    let sink = log.clone();
+    debugger.set_log_output(move |message| sink.borrow_mut().push(message.to_string()));
+    let logged = debugger.add_watchpoint(0x0010..=0x0010, WatchKind::Write);
+    debugger.watchpoint_mut(logged).unwrap().log =
+        Some(LogMessage::parse("$10 = {[$10]:02X}").unwrap());
+    let id = debugger.add_watchpoint(0x0010..=0x0010, WatchKind::Write);
+    debugger.watchpoint_mut(id).unwrap().condition = Some(Expr::parse("[$10] & $80").unwrap());
+
+    match debugger.run(&mut cpu) {
+        Stop::Watchpoint { id: hit, pc, .. } => {
+            assert_eq!(hit, id);
+            assert_eq!(pc, 0x8006);
+        }
+        stop => panic!("unexpected stop: {stop}"),
+    }
+    assert_eq!(*log.borrow(), vec!["$10 = 05", "$10 = 85"]);
+    assert_eq!(debugger.watchpoint(id).unwrap().hits, 2);
+}
+
+#[test]
+fn test_stepping_without_peek() {
+    let mut cpu = create_unpeekable_cpu();
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.step_over(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8003);
+    assert_eq!(cpu.registers.x, 1);
+    assert_eq!(cpu.registers.sp, 0xFF);
+
+    let mut cpu = create_unpeekable_cpu();
+    debugger.step_into(&mut cpu);
+    assert_eq!(debugger.step_out(&mut cpu), Stop::StepComplete);
+    assert_eq!(cpu.registers.pc, 0x8003);
+}
+
+#[test]
+fn test_break_on_brk_and_rti_without_peek() {
+    let mut cpu = create_unpeekable_cpu();
+    let mut debugger = Debugger::new();
+    debugger.set_break_on(BreakOn {
+        brk: true,
+        rti: true,
+        ..BreakOn::default()
+    });
+    // The stops come just after the instruction, in the handler and back
+    assert_eq!(debugger.run(&mut cpu), Stop::Brk { pc: 0x8004 });
+    assert_eq!(cpu.registers.pc, 0x9000);
+    assert_eq!(debugger.run(&mut cpu), Stop::Rti { pc: 0x9000 });
+    assert_eq!(cpu.registers.pc, 0x8006);
+}
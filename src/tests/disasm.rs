@@ -0,0 +1,81 @@
+// src/tests/disasm.rs
+
+use crate::adapters::OverlayBus;
+use crate::cpu::CPU;
+use crate::disasm::{disassemble, is_documented, lookup, Mode};
+use crate::mock::MockBus;
+
+fn disassemble_bytes(bytes: &[u8]) -> String {
+    let mut bus = vec![0u8; 0x10000];
+    bus[0x8000..0x8000 + bytes.len()].copy_from_slice(bytes);
+    disassemble(&bus, 0x8000).unwrap().to_string()
+}
+
+#[test]
+fn test_table_matches_cpu() {
+    let cpu = CPU::new(vec![0u8; 0x10000]);
+    for opcode in 0..=0xFF {
+        assert_eq!(
+            lookup(opcode).is_some(),
+            cpu.is_implemented(opcode),
+            "opcode ${opcode:02X}"
+        );
+    }
+}
+
+#[test]
+fn test_documented_opcodes() {
+    assert_eq!((0..=0xFF).filter(|&op| is_documented(op)).count(), 151);
+    assert!(is_documented(0xEA));
+    assert!(!is_documented(0x1A));
+    assert!(!is_documented(0xA7));
+    assert_eq!(disassemble_bytes(&[0xA7, 0x10]), "LAX $10");
+    assert_eq!(disassemble_bytes(&[0xFB, 0x34, 0x12]), "ISB $1234,Y");
+    assert_eq!(disassemble_bytes(&[0x1C, 0x34, 0x12]), "NOP $1234,X");
+}
+
+#[test]
+fn test_addressing_modes() {
+    assert_eq!(disassemble_bytes(&[0xEA]), "NOP");
+    assert_eq!(disassemble_bytes(&[0x0A]), "ASL A");
+    assert_eq!(disassemble_bytes(&[0xA9, 0x42]), "LDA #$42");
+    assert_eq!(disassemble_bytes(&[0xA5, 0x10]), "LDA $10");
+    assert_eq!(disassemble_bytes(&[0xB5, 0x10]), "LDA $10,X");
+    assert_eq!(disassemble_bytes(&[0xB6, 0x10]), "LDX $10,Y");
+    assert_eq!(disassemble_bytes(&[0xAD, 0x34, 0x12]), "LDA $1234");
+    assert_eq!(disassemble_bytes(&[0xBD, 0x34, 0x12]), "LDA $1234,X");
+    assert_eq!(disassemble_bytes(&[0xB9, 0x34, 0x12]), "LDA $1234,Y");
+    assert_eq!(disassemble_bytes(&[0x6C, 0xFC, 0xFF]), "JMP ($FFFC)");
+    assert_eq!(disassemble_bytes(&[0xA1, 0x10]), "LDA ($10,X)");
+    assert_eq!(disassemble_bytes(&[0xB1, 0x10]), "LDA ($10),Y");
+    assert_eq!(disassemble_bytes(&[0xD0, 0xFE]), "BNE $8000");
+    assert_eq!(disassemble_bytes(&[0x10, 0x10]), "BPL $8012");
+    assert_eq!(disassemble_bytes(&[0x02]), ".byte $02");
+}
+
+#[test]
+fn test_targets_and_symbols() {
+    let mut bus = vec![0u8; 0x10000];
+    bus[0x8000..0x8003].copy_from_slice(&[0x20, 0x10, 0x80]);
+    let jsr = disassemble(&bus, 0x8000).unwrap();
+    assert_eq!(jsr.mode, Some(Mode::Absolute));
+    assert_eq!(jsr.target(), Some(0x8010));
+    assert_eq!(jsr.next_addr(), 0x8003);
+    assert_eq!(jsr.bytes, vec![0x20, 0x10, 0x80]);
+    let named = jsr.operand_text(|addr| (addr == 0x8010).then(|| "sub".to_string()));
+    assert_eq!(named, "sub");
+
+    bus[0x8003..0x8005].copy_from_slice(&[0xA9, 0x10]);
+    assert_eq!(disassemble(&bus, 0x8003).unwrap().target(), None);
+}
+
+#[test]
+fn test_unpeekable_memory() {
+    // JMP $6000 runs into memory that cannot be peeked
+    let mut ram = vec![0u8; 0x10000];
+    ram[0x5FFE] = 0x4C;
+    ram[0x5FFF] = 0x00;
+    let bus = OverlayBus::new(ram, 0x6000..=0x600F, MockBus::new());
+    assert_eq!(disassemble(&bus, 0x6000), None);
+    assert_eq!(disassemble(&bus, 0x5FFE).unwrap().to_string(), ".byte $4C");
+}
@@ -0,0 +1,108 @@
+// src/tests/expr.rs
+
+use super::create_ram_cpu_with_program;
+use crate::adapters::OverlayBus;
+use crate::cpu::CPU;
+use crate::expr::{EvalError, Expr, LogMessage};
+use crate::mock::MockBus;
+
+fn create_cpu() -> CPU<Vec<u8>> {
+    let mut cpu = create_ram_cpu_with_program(&[]);
+    cpu.bus[0x0200] = 0x81;
+    cpu.bus[0xFFFC] = 0x34;
+    cpu.bus[0xFFFD] = 0x12;
+    cpu.registers.a = 0xFF;
+    cpu.registers.x = 4;
+    cpu.registers.pc = 0x8000;
+    cpu.registers.status.carry = true;
+    cpu.set_cycles(100_001);
+    cpu
+}
+
+fn eval(source: &str) -> i64 {
+    Expr::parse(source).unwrap().eval(&create_cpu(), 5).unwrap()
+}
+
+#[test]
+fn test_registers_flags_and_counters() {
+    assert_eq!(eval("A == $FF && X > 3"), 1);
+    assert_eq!(eval("a == 0xff && x > 4"), 0);
+    assert_eq!(eval("PC"), 0x8000);
+    assert_eq!(eval("C && !Z"), 1);
+    assert_eq!(eval("P & 1"), 1);
+    assert_eq!(eval("cycles > 100000"), 1);
+    assert_eq!(eval("hits == 5"), 1);
+}
+
+#[test]
+fn test_memory_operands() {
+    assert_eq!(eval("[$0200] & $80"), 0x80);
+    assert_eq!(eval("[$01FF + 1]"), 0x81);
+    assert_eq!(eval("w[$FFFC]"), 0x1234);
+    assert_eq!(eval("W[$FFFC] == $1234"), 1);
+}
+
+#[test]
+fn test_precedence() {
+    assert_eq!(eval("1 + 2 * 3"), 7);
+    assert_eq!(eval("(1 + 2) * 3"), 9);
+    assert_eq!(eval("1 << 2 + 1"), 8);
+    assert_eq!(eval("%1100 | %0011 & 1"), 13);
+    assert_eq!(eval("0 || 1 && 0"), 0);
+    assert_eq!(eval("-1 < 0"), 1);
+    assert_eq!(eval("~0 & 0b1111"), 15);
+    assert_eq!(eval("7 % 4 == 3 == 1"), 1);
+    // `%` after a value is the operator, not a binary prefix
+    assert_eq!(eval("7%10"), 7);
+    assert_eq!(eval("(7)%10 + [$0000]%11"), 7);
+    assert_eq!(eval("7 % %10"), 1);
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = Expr::parse("A == ").unwrap_err();
+    assert_eq!(err.to_string(), "expected a value at column 6");
+    let err = Expr::parse("A == Q").unwrap_err();
+    assert_eq!(err.to_string(), "unknown name 'q' at column 6");
+    assert_eq!(Expr::parse("(A").unwrap_err().message, "expected ')'");
+    assert_eq!(Expr::parse("A X").unwrap_err().position, 2);
+    assert_eq!(Expr::parse("$G").unwrap_err().message, "invalid number");
+    assert_eq!(Expr::parse("A # 1").unwrap_err().position, 2);
+}
+
+#[test]
+fn test_eval_errors() {
+    let cpu = CPU::new(OverlayBus::new(
+        vec![0u8; 0x10000],
+        0x6000..=0x600F,
+        MockBus::new(),
+    ));
+    let expr: Expr = "[$6000] == 0".parse().unwrap();
+    assert_eq!(
+        expr.eval(&cpu, 0),
+        Err(EvalError::Unreadable { addr: 0x6000 })
+    );
+    assert_eq!(
+        Expr::parse("1 / (X - X)").unwrap().eval(&cpu, 0),
+        Err(EvalError::DivisionByZero)
+    );
+    // Short-circuiting skips the failing operand
+    assert_eq!(Expr::parse("0 && [$6000]").unwrap().eval(&cpu, 0), Ok(0));
+}
+
+#[test]
+fn test_log_message() {
+    let cpu = create_cpu();
+    let message = LogMessage::parse("A={A:02X} X={x} P={P:08b} {{hits}} #{hits:3}").unwrap();
+    assert_eq!(message.format(&cpu, 7), "A=FF X=4 P=00100001 {hits} #  7");
+    let message = LogMessage::parse("{1 / 0}").unwrap();
+    assert_eq!(message.format(&cpu, 0), "<division by zero>");
+
+    assert_eq!(LogMessage::parse("A={A").unwrap_err().position, 2);
+    assert_eq!(LogMessage::parse("A}").unwrap_err().position, 1);
+    assert_eq!(LogMessage::parse("A={Q}").unwrap_err().position, 3);
+    assert_eq!(
+        LogMessage::parse("{A:q}").unwrap_err().message,
+        "invalid format 'q'"
+    );
+}
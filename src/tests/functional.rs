@@ -0,0 +1,265 @@
+// src/tests/functional.rs
+
+use crate::cpu::CPU;
+use crate::functional::{Outcome, Success, Suite, DECIMAL, FUNCTIONAL, INTERRUPT};
+use crate::registers::Registers;
+
+fn suite(success: Success, feedback: Option<u16>) -> Suite {
+    Suite {
+        name: "test",
+        file: "test.bin",
+        load: 0x0400,
+        start: 0x0400,
+        success,
+        feedback,
+    }
+}
+
+fn to_bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+/// Executes `opcode #operand` in decimal mode, returning the registers
+/// after it.
+fn decimal(cpu: &mut CPU<Vec<u8>>, opcode: u8, a: u8, operand: u8, carry: bool) -> Registers {
+    cpu.bus[0x0200..0x0202].copy_from_slice(&[opcode, operand]);
+    cpu.registers.pc = 0x0200;
+    cpu.registers.a = a;
+    cpu.registers.status.carry = carry;
+    cpu.registers.status.decimal_mode = true;
+    cpu.step();
+    cpu.registers
+}
+
+#[test]
+fn test_decimal_arithmetic() {
+    let mut cpu = CPU::new(vec![0u8; 0x10000]);
+    for a in 0..100 {
+        for b in 0..100 {
+            for carry in [false, true] {
+                let c = carry as u32;
+                let (bcd_a, bcd_b) = (to_bcd(a), to_bcd(b));
+
+                let registers = decimal(&mut cpu, 0x69, bcd_a, bcd_b, carry);
+                let sum = a + b + c;
+                assert_eq!(registers.a, to_bcd(sum % 100), "{a} + {b} + {c}");
+                assert_eq!(registers.status.carry, sum >= 100);
+                // Z comes from the binary sum
+                let binary = bcd_a as u32 + bcd_b as u32 + c;
+                assert_eq!(registers.status.zero, binary & 0xFF == 0);
+
+                let registers = decimal(&mut cpu, 0xE9, bcd_a, bcd_b, carry);
+                let difference = a as i32 - b as i32 - (1 - c as i32);
+                assert_eq!(
+                    registers.a,
+                    to_bcd(difference.rem_euclid(100) as u32),
+                    "{a} - {b} - {}",
+                    1 - c
+                );
+                assert_eq!(registers.status.carry, difference >= 0);
+                // Every flag comes from the binary difference
+                let binary = (bcd_a as i32 - bcd_b as i32 - (1 - c as i32)) as u8;
+                assert_eq!(registers.status.zero, binary == 0);
+                assert_eq!(registers.status.negative, binary & 0x80 != 0);
+            }
+        }
+    }
+
+    // N and V come from the sum with only the low digit adjusted, $80
+    let registers = decimal(&mut cpu, 0x69, 0x79, 0x00, true);
+    assert_eq!(registers.a, 0x80);
+    assert!(registers.status.negative);
+    assert!(registers.status.overflow);
+    assert!(!registers.status.carry);
+    // Invalid BCD digits are adjusted the same way
+    assert_eq!(decimal(&mut cpu, 0x69, 0x0F, 0x0F, true).a, 0x15);
+    assert_eq!(decimal(&mut cpu, 0xE9, 0x00, 0x0F, true).a, 0x9B);
+    // A binary SBC that borrows wraps around
+    decimal(&mut cpu, 0xE9, 0x00, 0x01, true);
+    cpu.registers.pc = 0x0200;
+    cpu.registers.a = 0x00;
+    cpu.registers.status.carry = true;
+    cpu.registers.status.decimal_mode = false;
+    cpu.step();
+    assert_eq!(cpu.registers.a, 0xFF);
+    assert!(!cpu.registers.status.carry);
+}
+
+/// The NMOS results of a decimal mode ADC or SBC, worked out as in Bruce
+/// Clark's decimal mode test: the accumulator, then N, V, Z and C.
+fn clark_model(sbc: bool, a: u8, b: u8, carry: bool) -> (u8, bool, bool, bool, bool) {
+    let (a16, b16, c) = (a as i16, b as i16, carry as i16);
+    if sbc {
+        // Every flag comes from the binary difference
+        let binary = a16 - b16 + c - 1;
+        let mut al = (a16 & 0x0F) - (b16 & 0x0F) + c - 1;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+        let mut result = (a16 & 0xF0) - (b16 & 0xF0) + al;
+        if result < 0 {
+            result -= 0x60;
+        }
+        let overflow = (binary as i8 as i16) != (a as i8 as i16) - (b as i8 as i16) + c - 1;
+        (
+            result as u8,
+            binary & 0x80 != 0,
+            overflow,
+            binary as u8 == 0,
+            binary >= 0,
+        )
+    } else {
+        let mut al = (a16 & 0x0F) + (b16 & 0x0F) + c;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+        // N and V come from the signed sum with only the low digit adjusted
+        let signed = (a as i8 as i16 & !0x0F) + (b as i8 as i16 & !0x0F) + al;
+        let mut result = (a16 & 0xF0) + (b16 & 0xF0) + al;
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+        (
+            result as u8,
+            signed & 0x80 != 0,
+            !(-128..=127).contains(&signed),
+            (a16 + b16 + c) as u8 == 0,
+            result >= 0x100,
+        )
+    }
+}
+
+#[test]
+fn test_decimal_mode_model() {
+    // Every operand, valid BCD or not, as Bruce Clark's test checks them
+    let mut cpu = CPU::new(vec![0u8; 0x10000]);
+    for sbc in [false, true] {
+        let opcode = if sbc { 0xE9 } else { 0x69 };
+        for a in 0..=0xFF {
+            for b in 0..=0xFF {
+                for carry in [false, true] {
+                    let r = decimal(&mut cpu, opcode, a, b, carry);
+                    let s = r.status;
+                    assert_eq!(
+                        (r.a, s.negative, s.overflow, s.zero, s.carry),
+                        clark_model(sbc, a, b, carry),
+                        "${opcode:02X} A=${a:02X} #${b:02X} C={}",
+                        carry as u8
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_outcomes() {
+    // LDX #$03; loop: DEX; BNE loop; trap: JMP trap
+    let image = [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x4C, 0x05, 0x04];
+    let passing = suite(Success::Trap(0x0405), None);
+    let mut cpu = passing.machine(&image).unwrap();
+    let report = passing.run(&mut cpu, 1000);
+    assert!(report.passed());
+    assert_eq!(report.instructions, 8);
+    assert_eq!(
+        report.to_string(),
+        format!("passed after 8 instructions and {} cycles", report.cycles)
+    );
+
+    let failing = suite(Success::Trap(0x0500), None);
+    let mut cpu = failing.machine(&image).unwrap();
+    let report = failing.run(&mut cpu, 1000);
+    assert_eq!(report.outcome, Outcome::Failed { pc: 0x0405 });
+    assert!(report.to_string().starts_with("failed at $0405 after"));
+
+    // The error byte decides
+    let zero = suite(Success::Zero(0x0010), None);
+    let mut cpu = zero.machine(&image).unwrap();
+    assert!(zero.run(&mut cpu, 1000).passed());
+    let mut cpu = zero.machine(&image).unwrap();
+    cpu.bus[0x0010] = 1;
+    assert_eq!(
+        zero.run(&mut cpu, 1000).outcome,
+        Outcome::Failed { pc: 0x0405 }
+    );
+
+    let mut cpu = passing.machine(&image).unwrap();
+    assert_eq!(passing.run(&mut cpu, 3).outcome, Outcome::TimedOut);
+
+    let mut cpu = passing.machine(&[0xEA, 0x02]).unwrap();
+    let report = passing.run(&mut cpu, 1000);
+    assert_eq!(
+        report.outcome,
+        Outcome::Unimplemented {
+            pc: 0x0401,
+            opcode: 0x02
+        }
+    );
+    assert!(report.to_string().starts_with("opcode $02 at $0401"));
+
+    assert!(passing.machine(&[0; 0xFC01]).is_none());
+}
+
+#[test]
+fn test_feedback_port() {
+    let image = [
+        0x58, // CLI
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0xFC, 0xBF, // STA $BFFC (raise IRQ)
+        0xAD, 0x00, 0x02, // LDA $0200
+        0xF0, 0xFB, // BEQ $0406
+        0xA9, 0x02, // LDA #$02
+        0x8D, 0xFC, 0xBF, // STA $BFFC (raise NMI)
+        0xAD, 0x01, 0x02, // LDA $0201
+        0xF0, 0xFB, // BEQ $0410
+        0x4C, 0x15, 0x04, // JMP $0415
+    ];
+    let run = |feedback| {
+        let suite = suite(Success::Trap(0x0415), feedback);
+        let mut cpu = suite.machine(&image).unwrap();
+        // IRQ: LDA #$00; STA $BFFC; INC $0200; RTI
+        cpu.bus[0x0500..0x0509]
+            .copy_from_slice(&[0xA9, 0x00, 0x8D, 0xFC, 0xBF, 0xEE, 0x00, 0x02, 0x40]);
+        // NMI: INC $0201; RTI, leaving the line high
+        cpu.bus[0x0510..0x0514].copy_from_slice(&[0xEE, 0x01, 0x02, 0x40]);
+        cpu.bus[0xFFFA..0xFFFC].copy_from_slice(&[0x10, 0x05]);
+        cpu.bus[0xFFFE..0x10000].copy_from_slice(&[0x00, 0x05]);
+        let report = suite.run(&mut cpu, 1000);
+        (report, cpu)
+    };
+
+    let (report, cpu) = run(Some(0xBFFC));
+    assert!(report.passed(), "{report}");
+    // The NMI is taken once, on the edge
+    assert_eq!(cpu.bus[0x0200..0x0202], [1, 1]);
+
+    let (report, _) = run(None);
+    assert_eq!(report.outcome, Outcome::TimedOut);
+}
+
+/// Runs a suite from `KLAUS_DIR`.
+fn run_suite(suite: &Suite) {
+    let dir = std::env::var("KLAUS_DIR").expect("KLAUS_DIR is not set");
+    let image = suite.read(std::path::Path::new(&dir)).unwrap();
+    let mut cpu = suite.machine(&image).unwrap();
+    let report = suite.run(&mut cpu, 200_000_000);
+    assert!(report.passed(), "{}: {report}", suite.name);
+}
+
+#[test]
+#[ignore = "needs 6502_functional_test.bin in KLAUS_DIR"]
+fn test_functional_suite() {
+    run_suite(&FUNCTIONAL);
+}
+
+#[test]
+#[ignore = "needs 6502_interrupt_test.bin in KLAUS_DIR"]
+fn test_interrupt_suite() {
+    run_suite(&INTERRUPT);
+}
+
+#[test]
+#[ignore = "needs 6502_decimal_test.bin in KLAUS_DIR"]
+fn test_decimal_suite() {
+    run_suite(&DECIMAL);
+}
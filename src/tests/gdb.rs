@@ -0,0 +1,227 @@
+// src/tests/gdb.rs
+
+use super::create_ram_cpu_with_program;
+use crate::cpu::CPU;
+use crate::debugger::Machine;
+use crate::gdb::GdbStub;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+/// A minimal protocol client, standing in for gdb.
+struct Client {
+    stream: TcpStream,
+    ack: bool,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Self { stream, ack: true }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Sends a packet and returns the reply.
+    fn request(&mut self, data: &str) -> String {
+        self.request_bytes(data.as_bytes())
+    }
+
+    /// Sends a packet that need not be text and returns the reply.
+    fn request_bytes(&mut self, data: &[u8]) -> String {
+        let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        self.stream.write_all(b"$").unwrap();
+        self.stream.write_all(data).unwrap();
+        write!(self.stream, "#{sum:02x}").unwrap();
+        if self.ack {
+            assert_eq!(self.read_byte(), b'+');
+        }
+        self.read_reply()
+    }
+
+    /// Reads a packet from the stub.
+    fn read_reply(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut reply = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => {
+                    sum = sum.wrapping_add(byte);
+                    reply.push(byte);
+                }
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(checksum, sum);
+        if self.ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(reply).unwrap()
+    }
+
+    /// Turns acks off, as gdb does first thing.
+    fn start_no_ack_mode(&mut self) {
+        assert_eq!(self.request("QStartNoAckMode"), "OK");
+        self.ack = false;
+    }
+}
+
+/// Serves `machine` to a client running `script` on another thread.
+fn session<M: Machine, F: FnOnce(&mut Client) + Send + 'static>(
+    stub: &mut GdbStub,
+    machine: &mut M,
+    script: F,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || script(&mut Client::connect(addr)));
+    let (stream, _) = listener.accept().unwrap();
+    stub.serve(machine, stream).unwrap();
+    client.join().unwrap();
+}
+
+/// $8000: LDA #$42 ; STA $0200
+/// $8005: JMP $8005
+fn create_cpu() -> CPU<Vec<u8>> {
+    let mut cpu = create_ram_cpu_with_program(&[0xA9, 0x42, 0x8D, 0x00, 0x02, 0x4C, 0x05, 0x80]);
+    cpu.registers.sp = 0xFF;
+    cpu
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let mut cpu = create_cpu();
+    let p = format!("{:02x}", cpu.registers.status.to_byte());
+    session(&mut GdbStub::new(), &mut cpu, move |client| {
+        assert!(client
+            .request("qSupported:swbreak+;hwbreak+")
+            .contains("qXfer:features:read+"));
+        client.start_no_ack_mode();
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("g"), format!("000000ff0080{p}"));
+        assert_eq!(client.request("P0=7f"), "OK");
+        assert_eq!(client.request("p0"), "7f");
+        assert_eq!(client.request("P4=3412"), "OK");
+        assert_eq!(client.request("p4"), "3412");
+        assert_eq!(client.request("p9"), "");
+        assert_eq!(client.request(&format!("G0102030400c0{p}")), "OK");
+
+        assert_eq!(client.request("M0300,2:abcd"), "OK");
+        assert_eq!(client.request("m0300,3"), "abcd00");
+        // An escaped `}` in binary data
+        assert_eq!(client.request("X0302,1:}]"), "OK");
+        assert_eq!(client.request("m0302,1"), "7d");
+        assert_eq!(client.request_bytes(b"X0303,1:\xff"), "OK");
+        // The address must be text, even where the data need not be
+        assert!(client.request_bytes(b"X\xff:1").starts_with('E'));
+        assert!(client.request("m10000,1").starts_with('E'));
+        // X carries one byte per byte of data, so it allows twice as many
+        let data = "A".repeat(0x900);
+        assert_eq!(client.request(&format!("X1000,900:{data}")), "OK");
+        let hex = "41".repeat(0x900);
+        assert!(client.request(&format!("M1000,900:{hex}")).starts_with('E'));
+        assert_eq!(client.request("qUnknown"), "");
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(cpu.registers.a, 0x01);
+    assert_eq!(cpu.registers.sp, 0x04);
+    assert_eq!(cpu.registers.pc, 0xC000);
+    assert_eq!(cpu.bus[0x0300..0x0304], [0xAB, 0xCD, 0x7D, 0xFF]);
+    assert!(cpu.bus[0x1000..0x1900].iter().all(|&byte| byte == 0x41));
+}
+
+#[test]
+fn test_breakpoints_and_stepping() {
+    let mut cpu = create_cpu();
+    let mut stub = GdbStub::new();
+    session(&mut stub, &mut cpu, |client| {
+        client.start_no_ack_mode();
+        assert_eq!(client.request("Z0,8002,1"), "OK");
+        assert_eq!(client.request("c"), "T05swbreak:;");
+        assert_eq!(client.request("p4"), "0280");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p4"), "0580");
+        assert_eq!(client.request("z0,8002,1"), "OK");
+
+        assert_eq!(client.request("Z1,8005,1"), "OK");
+        // Continuing from a breakpoint runs to its next hit
+        assert_eq!(client.request("c"), "T05hwbreak:;");
+        assert_eq!(client.request("vCont?"), "vCont;c;C;s;S");
+        assert_eq!(client.request("vCont;s:1"), "S05");
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(cpu.bus[0x0200], 0x42);
+    assert_eq!(stub.debugger().breakpoints().count(), 1);
+}
+
+#[test]
+fn test_breakpoint_at_end_of_slice() {
+    // The stub polls for an interrupt every 10000 instructions, and this
+    // breakpoint is reached just as the first slice ends
+    let mut cpu = create_ram_cpu_with_program(&[0xEA; 0x3000]);
+    session(&mut GdbStub::new(), &mut cpu, |client| {
+        client.start_no_ack_mode();
+        assert_eq!(client.request("Z0,a710,1"), "OK");
+        assert_eq!(client.request("c"), "T05swbreak:;");
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(cpu.registers.pc, 0xA710);
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut cpu = create_cpu();
+    let mut stub = GdbStub::new();
+    session(&mut stub, &mut cpu, |client| {
+        client.start_no_ack_mode();
+        assert_eq!(client.request("Z2,0200,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:200;");
+        // The store has finished
+        assert_eq!(client.request("p4"), "0580");
+        assert_eq!(client.request("m0200,1"), "42");
+        assert_eq!(client.request("z2,0200,1"), "OK");
+
+        assert_eq!(client.request("Z4,8006,2"), "OK");
+        assert_eq!(client.request("c"), "T05awatch:8006;");
+        assert_eq!(client.request("z4,8006,2"), "OK");
+
+        // The whole address space, but no more
+        assert_eq!(client.request("Z2,0,10000"), "OK");
+        assert_eq!(client.request("z2,0,10000"), "OK");
+        assert_eq!(client.request("Z2,0,10001"), "E16");
+        assert_eq!(client.request("Z2,10000,1"), "E16");
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(stub.debugger().watchpoints().count(), 0);
+}
+
+#[test]
+fn test_target_description_and_interrupt() {
+    let mut cpu = create_cpu();
+    session(&mut GdbStub::new(), &mut cpu, |client| {
+        client.start_no_ack_mode();
+        let first = client.request("qXfer:features:read:target.xml:0,10");
+        assert_eq!(first, "m<?xml version=\"1");
+        let whole = client.request("qXfer:features:read:target.xml:0,1000");
+        assert!(whole.starts_with("l<?xml"));
+        assert!(whole.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+        assert!(client
+            .request("qXfer:features:read:other.xml:0,10")
+            .starts_with('E'));
+
+        // The loop never ends, so the stub must notice the interrupt
+        client.stream.write_all(b"$c#63").unwrap();
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.read_reply(), "S02");
+        client.stream.write_all(b"$k#6b").unwrap();
+    });
+    assert_eq!(cpu.registers.pc, 0x8005);
+}
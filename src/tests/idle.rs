@@ -0,0 +1,141 @@
+// src/tests/idle.rs
+
+use crate::adapters::{OverlayBus, TracingBus};
+use crate::bus::{Bus, BusAccess};
+use crate::cpu::CPU;
+use crate::idle::IdleDetector;
+use crate::scheduler::{Clock, DeviceContext, Scheduler};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+// wait: LDA $6000 ; BEQ wait ; STA $0200 ; JMP *
+const POLL: [u8; 10] = [0xAD, 0x00, 0x60, 0xF0, 0xFB, 0x8D, 0x00, 0x02, 0x4C, 0x08];
+
+fn create_cpu<B: Bus>(mut bus: B, program: &[u8]) -> CPU<B> {
+    for (i, byte) in program.iter().enumerate() {
+        bus.write(0x8000 + i as u16, *byte);
+    }
+    bus.write(0x8000 + program.len() as u16, 0x80);
+    bus.write(0xFFFC, 0x00);
+    bus.write(0xFFFD, 0x80);
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn test_detects_polling_loop() {
+    let mut cpu = create_cpu(vec![0u8; 0x10000], &POLL);
+    let mut detector = IdleDetector::new();
+    for _ in 0..6 {
+        detector.step(&mut cpu);
+    }
+    let idle = detector.idle_loop().expect("loop should be idle");
+    assert_eq!(idle.head, 0x8000);
+    assert_eq!(idle.period, 4 + 3);
+    assert_eq!(idle.instructions, 2);
+    assert_eq!(idle.polled, vec![0x6000]);
+}
+
+#[test]
+fn test_counting_loop_is_not_idle() {
+    // loop: DEX ; BNE loop
+    let mut cpu = create_cpu(vec![0u8; 0x10000], &[0xCA, 0xD0, 0xFD]);
+    let mut detector = IdleDetector::new();
+    for _ in 0..20 {
+        detector.step(&mut cpu);
+        assert!(detector.idle_loop().is_none());
+    }
+}
+
+#[test]
+fn test_loop_with_write_is_not_idle() {
+    // loop: LDA $6000 ; STA $6001 ; BEQ loop
+    let program = [0xAD, 0x00, 0x60, 0x8D, 0x01, 0x60, 0xF0, 0xF8];
+    let mut cpu = create_cpu(vec![0u8; 0x10000], &program);
+    let mut detector = IdleDetector::new();
+    for _ in 0..20 {
+        detector.step(&mut cpu);
+        assert!(detector.idle_loop().is_none());
+    }
+}
+
+#[test]
+fn test_fast_forward_keeps_cycles_exact() {
+    let mut stepped = create_cpu(vec![0u8; 0x10000], &POLL);
+    let mut skipped = create_cpu(vec![0u8; 0x10000], &POLL);
+    let mut detector = IdleDetector::new();
+
+    let target = 100_003;
+    while stepped.cycles() < target {
+        stepped.step();
+    }
+    let result = detector.run_until(&mut skipped, target);
+    assert_eq!(skipped.cycles(), stepped.cycles());
+    assert_eq!(skipped.registers.pc, stepped.registers.pc);
+    assert!(result.instructions < 20);
+}
+
+#[test]
+fn test_scheduler_skips_to_next_event() {
+    // A device register at $6000 that a timer sets after 1,000,000 cycles
+    let device = Rc::new(RefCell::new(vec![0u8; 0x10000]));
+    let reads = Rc::new(Cell::new(0u64));
+    let counter = reads.clone();
+    let ram = TracingBus::new(vec![0u8; 0x10000], move |_: &BusAccess| {
+        counter.set(counter.get() + 1)
+    });
+    let bus = OverlayBus::new(ram, 0x6000..=0x6000, device.clone());
+    let mut scheduler = Scheduler::new(create_cpu(bus, &POLL));
+    scheduler.set_idle_skipping(true);
+    let timer = scheduler.add_device(
+        move |_tag: u32, _ctx: &mut DeviceContext<'_, _>| device.borrow_mut()[0x6000] = 0x55,
+        Clock::CPU,
+    );
+    scheduler.schedule_at(timer, 1_000_000, 0);
+
+    scheduler.run_until(2_000_000);
+    // The loop saw the device change, stored it and reached the final JMP *
+    assert_eq!(scheduler.cpu.registers.pc, 0x8008);
+    assert_eq!(scheduler.cpu.registers.a, 0x55);
+    assert!(scheduler.cpu.cycles() >= 2_000_000);
+    assert!(scheduler.cpu.cycles() < 2_000_000 + 3);
+    // Both the polling loop and the JMP * were skipped over
+    assert!(reads.get() < 100);
+}
+
+// A free-running counter that advances on every read.
+struct Counter(u8);
+
+impl Bus for Counter {
+    fn read(&mut self, _addr: u16) -> u8 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+
+    fn write(&mut self, _addr: u16, _data: u8) {}
+}
+
+#[test]
+fn test_masked_counter_poll_is_not_idle() {
+    // wait: LDA $D012 ; AND #$80 ; BEQ wait ; JMP *
+    let program = [0xAD, 0x12, 0xD0, 0x29, 0x80, 0xF0, 0xF9, 0x4C, 0x07];
+    let bus = || OverlayBus::new(vec![0u8; 0x10000], 0xD012..=0xD012, Counter(0));
+    let mut stepped = create_cpu(bus(), &program);
+    let mut skipped = create_cpu(bus(), &program);
+    let mut detector = IdleDetector::new();
+
+    // The registers repeat, but the values read do not
+    for _ in 0..30 {
+        detector.step(&mut skipped);
+        assert!(detector.idle_loop().is_none());
+    }
+
+    let target = 5_000;
+    while stepped.cycles() < target {
+        stepped.step();
+    }
+    detector.run_until(&mut skipped, target);
+    assert_eq!(skipped.registers.pc, 0x8007);
+    assert_eq!(skipped.cycles(), stepped.cycles());
+}
@@ -0,0 +1,109 @@
+// src/tests/lockstep.rs
+
+use super::create_ram_cpu_with_program;
+use crate::adapters::WaitStateBus;
+use crate::cpu::CPU;
+use crate::lockstep::{Lockstep, Recorded, Step, StepSource};
+
+fn machine() -> CPU<Vec<u8>> {
+    // loop: INC $10; LDA #$05; INX; JMP loop
+    let mut cpu = create_ram_cpu_with_program(&[0xE6, 0x10, 0xA9, 0x05, 0xE8, 0x4C, 0x00, 0x80]);
+    cpu.bus[0x0010] = 0x01;
+    cpu
+}
+
+#[test]
+fn test_identical_cpus() {
+    let (mut left, mut right) = (machine(), machine());
+    assert_eq!(Lockstep::new().run(&mut left, &mut right, 1000), Ok(1000));
+    assert_eq!(left.registers, right.registers);
+    assert_eq!(left.cycles(), right.cycles());
+}
+
+#[test]
+fn test_register_divergence() {
+    let (mut left, mut right) = (machine(), machine());
+    // The second time round, X differs
+    right.bus[0x8004] = 0xEA;
+    let divergence = Lockstep::new()
+        .with_window(2)
+        .run(&mut left, &mut right, 1000)
+        .unwrap_err();
+    assert_eq!(divergence.step, 2);
+    assert_eq!(divergence.fields, ["X", "bus"]);
+    let pcs: Vec<u16> = divergence.window.iter().map(|step| step.pc).collect();
+    assert_eq!(pcs, [0x8000, 0x8002]);
+
+    let report = divergence.to_string();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "diverged at step 2 (X, bus):");
+    assert!(lines[1].starts_with("        8000  INC $10"));
+    assert!(lines[3].starts_with("  left  8004  INX           -> PC:8005 A:05 X:01"));
+    assert!(lines[4].starts_with("  right 8004  NOP           -> PC:8005 A:05 X:00"));
+    assert!(lines[5].starts_with("  left  bus ["));
+}
+
+#[test]
+fn test_cycle_and_bus_divergence() {
+    // Wait states on the zero page change only the cycle count
+    let mut left = machine();
+    let mut right = CPU::new(WaitStateBus::new(machine().bus, 0x0000..=0x00FF, 1));
+    right.registers.pc = 0x8000;
+    let divergence = Lockstep::new()
+        .run(&mut left, &mut right, 1000)
+        .unwrap_err();
+    assert_eq!(divergence.step, 0);
+    assert_eq!(divergence.fields, ["cycles"]);
+
+    // Different data changes only the bus activity
+    let (mut left, mut right) = (machine(), machine());
+    right.bus[0x0010] = 0x02;
+    let divergence = Lockstep::new()
+        .run(&mut left, &mut right, 1000)
+        .unwrap_err();
+    assert_eq!(divergence.step, 0);
+    assert_eq!(divergence.fields, ["bus"]);
+    assert!(divergence.window.is_empty());
+}
+
+#[test]
+fn test_recorded_trace() {
+    let mut recorder = machine();
+    let steps: Vec<Step> = (0..20).filter_map(|_| recorder.next_step()).collect();
+    assert_eq!(
+        Lockstep::new().run(&mut machine(), &mut Recorded::new(steps.clone()), 20),
+        Ok(20)
+    );
+
+    // Traces from elsewhere may only have the registers
+    let bare: Vec<Step> = steps
+        .iter()
+        .map(|step| Step {
+            instruction: None,
+            cycles: None,
+            accesses: None,
+            ..step.clone()
+        })
+        .collect();
+    let mut right = CPU::new(WaitStateBus::new(machine().bus, 0x0000..=0xFFFF, 3));
+    right.registers.pc = 0x8000;
+    assert_eq!(
+        Lockstep::new().run(&mut Recorded::new(bare), &mut right, 20),
+        Ok(20)
+    );
+
+    // A trace that runs out early diverges
+    let divergence = Lockstep::new()
+        .run(&mut machine(), &mut Recorded::new(steps[..5].to_vec()), 20)
+        .unwrap_err();
+    assert_eq!(divergence.step, 5);
+    assert_eq!(divergence.fields, ["end"]);
+    assert!(divergence.right.is_none());
+    assert!(divergence.to_string().ends_with("  right (ended)"));
+
+    // Both sides stopping at an unimplemented opcode is not a divergence
+    let (mut left, mut right) = (machine(), machine());
+    left.bus[0x8005] = 0x02;
+    right.bus[0x8005] = 0x02;
+    assert_eq!(Lockstep::new().run(&mut left, &mut right, 20), Ok(3));
+}
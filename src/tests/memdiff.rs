@@ -0,0 +1,102 @@
+// src/tests/memdiff.rs
+
+use crate::adapters::{OverlayBus, TracingBus};
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::memdiff::{MemorySnapshot, Region};
+use crate::mock::MockBus;
+use std::cell::Cell;
+
+#[test]
+fn test_no_changes() {
+    let bus = vec![0u8; 0x10000];
+    let diff = MemorySnapshot::capture(&bus).diff_live(&bus);
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "no changes\n");
+}
+
+#[test]
+fn test_ranges_are_split_at_region_boundaries() {
+    let mut bus = vec![0u8; 0x10000];
+    let before = MemorySnapshot::capture(&bus);
+    for addr in 0x00FE..=0x0201 {
+        bus.write(addr, 0xFF);
+    }
+    let diff = before.diff(&MemorySnapshot::capture(&bus));
+    let ranges: Vec<_> = diff
+        .ranges
+        .iter()
+        .map(|range| (range.start, range.end(), range.region))
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![
+            (0x00FE, 0x00FF, Region::ZeroPage),
+            (0x0100, 0x01FF, Region::Stack),
+            (0x0200, 0x0201, Region::Other),
+        ]
+    );
+    assert_eq!(diff.changed_bytes(), 260);
+    assert_eq!(diff.in_region(Region::Stack).count(), 1);
+}
+
+#[test]
+fn test_long_ranges_wrap() {
+    let before = MemorySnapshot::from_bytes(&[0u8; 0x10000]);
+    let mut image = vec![0u8; 0x10000];
+    for (i, byte) in image[0x0300..0x030A].iter_mut().enumerate() {
+        *byte = i as u8 + 1;
+    }
+    image[0x0310] = 0xAA;
+    let diff = before.diff(&MemorySnapshot::from_bytes(&image));
+    assert_eq!(
+        diff.to_string(),
+        "other:\n  \
+         $0300-$0307: 00 00 00 00 00 00 00 00 -> 01 02 03 04 05 06 07 08\n  \
+         $0308-$0309: 00 00 -> 09 0A\n  \
+         $0310: 00 -> AA\n"
+    );
+}
+
+#[test]
+fn test_live_diff_has_no_side_effects() {
+    let reads = Cell::new(0);
+    let mut bus = TracingBus::new(vec![0u8; 0x10000], |_| reads.set(reads.get() + 1));
+    let before = MemorySnapshot::capture(&bus);
+    bus.write(0x4000, 1);
+    let diff = before.diff_live(&bus);
+    assert_eq!(diff.ranges.len(), 1);
+    assert_eq!(reads.get(), 1);
+}
+
+#[test]
+fn test_unpeekable_addresses_are_skipped() {
+    // The mock panics on any access it does not expect, so peeking it must
+    // not turn into reads
+    let bus = OverlayBus::new(vec![0u8; 0x10000], 0x6000..=0x600F, MockBus::new());
+    let snapshot = MemorySnapshot::capture(&bus);
+    assert_eq!(snapshot.get(0x6000), None);
+    assert_eq!(snapshot.get(0x5FFF), Some(0));
+    assert!(snapshot.diff_live(&bus).is_empty());
+
+    let short = MemorySnapshot::from_bytes(&[1, 2, 3]);
+    assert_eq!(short.get(0x0002), Some(3));
+    assert_eq!(short.get(0x0003), None);
+}
+
+#[test]
+fn test_diff_of_routine() {
+    // LDA #$42 ; STA $10 ; PHA ; STA $0300
+    let mut bus = vec![0u8; 0x10000];
+    bus[0x8000..0x8008].copy_from_slice(&[0xA9, 0x42, 0x85, 0x10, 0x48, 0x8D, 0x00, 0x03]);
+    bus[0xFFFD] = 0x80;
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+    cpu.registers.sp = 0xFF;
+    let before = MemorySnapshot::capture(&cpu.bus);
+    cpu.run_instructions(4);
+    assert_eq!(
+        before.diff_live(&cpu.bus).to_string(),
+        "zero page:\n  $0010: 00 -> 42\nstack:\n  $01FF: 00 -> 42\nother:\n  $0300: 00 -> 42\n"
+    );
+}
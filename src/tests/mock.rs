@@ -0,0 +1,66 @@
+// src/tests/mock.rs
+
+use crate::adapters::OverlayBus;
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::mock::MockBus;
+
+fn create_cpu_with_device(program: &[u8], mock: MockBus) -> CPU<OverlayBus<Vec<u8>, MockBus>> {
+    let mut ram = vec![0u8; 0x10000];
+    ram[0x8000..0x8000 + program.len()].copy_from_slice(program);
+    ram[0xFFFC] = 0x00;
+    ram[0xFFFD] = 0x80;
+    let mut cpu = CPU::new(OverlayBus::new(ram, 0x6000..=0x60FF, mock));
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn test_mock_bus_scripted_device() {
+    let program = [
+        0xAD, 0x01, 0x60, // LDA $6001
+        0xA9, 0x41, // LDA #$41
+        0x8D, 0x00, 0x60, // STA $6000
+    ];
+    let mut mock = MockBus::new();
+    mock.expect_read(0x6001, 0x80).expect_write(0x6000, 0x41);
+    let mut cpu = create_cpu_with_device(&program, mock);
+
+    cpu.step();
+    assert_eq!(cpu.registers.a, 0x80);
+    assert_eq!(cpu.registers.status.negative, true);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.bus.overlay().history().len(), 2);
+    cpu.bus.overlay().verify();
+}
+
+#[test]
+#[should_panic(expected = "expected: write $6000 <- $41")]
+fn test_mock_bus_wrong_value() {
+    let mut mock = MockBus::new();
+    mock.expect_write(0x6000, 0x41);
+    mock.write(0x6000, 0x42);
+}
+
+#[test]
+#[should_panic(expected = "actual:   read  $6000")]
+fn test_mock_bus_out_of_order() {
+    let mut mock = MockBus::new();
+    mock.expect_read(0x6001, 0x00).expect_read(0x6000, 0x00);
+    mock.read(0x6000);
+}
+
+#[test]
+#[should_panic(expected = "expected accesses did not happen")]
+fn test_mock_bus_unmet_expectation_on_drop() {
+    let mut mock = MockBus::new();
+    mock.expect_read(0x6001, 0x80);
+}
+
+#[test]
+#[should_panic(expected = "unexpected access after all expectations were met")]
+fn test_mock_bus_unexpected_access() {
+    let mut mock = MockBus::new();
+    mock.write(0x6000, 0x00);
+}
@@ -1,9 +1,40 @@
 // src/tests/mod.rs
 
+#![allow(clippy::bool_assert_comparison)]
+
 use crate::bus::Bus;
 use crate::cpu::CPU;
 use crate::registers::StatusFlags;
 
+mod adapters;
+#[cfg(feature = "serde")]
+mod dap;
+mod dbginfo;
+mod debugger;
+mod disasm;
+mod expr;
+mod functional;
+mod gdb;
+mod idle;
+mod lockstep;
+mod memdiff;
+mod mock;
+mod monitor;
+mod nestest;
+mod replay;
+mod rewind;
+mod run;
+#[cfg(feature = "serde")]
+mod savestate;
+mod scheduler;
+mod singlestep;
+mod state;
+mod throttle;
+mod trace;
+mod tui;
+mod vice;
+mod wait_states;
+
 struct TestBus {
     memory: [u8; 0x10000], // 64KB memory
 }
@@ -45,6 +76,20 @@ fn create_cpu_with_program(program: &[u8]) -> CPU<TestBus> {
     cpu
 }
 
+// Helper function to create a CPU with 64KB of RAM in a Vec<u8>, the program
+// at 0x8000 and the reset vector pointing to it
+fn create_ram_cpu_with_program(program: &[u8]) -> CPU<Vec<u8>> {
+    let mut memory = vec![0u8; 0x10000];
+    memory[0x8000..0x8000 + program.len()].copy_from_slice(program);
+    // Set reset vector to 0x8000
+    memory[0xFFFC] = 0x00;
+    memory[0xFFFD] = 0x80;
+
+    let mut cpu = CPU::new(memory);
+    cpu.reset();
+    cpu
+}
+
 #[cfg(test)]
 mod instruction_tests {
     use super::*;
@@ -160,8 +205,10 @@ mod instruction_tests {
         cpu.step();
         assert_eq!(cpu.registers.a, 0x00);
         assert_eq!(cpu.registers.status.carry, true);
-        assert_eq!(cpu.registers.status.zero, true);
-        assert_eq!(cpu.registers.status.negative, false);
+        // The NMOS 6502 sets Z from the binary sum, $9A, and N from the sum
+        // with only the low digit adjusted, $A0
+        assert_eq!(cpu.registers.status.zero, false);
+        assert_eq!(cpu.registers.status.negative, true);
     }
 
     #[test]
@@ -1661,5 +1708,178 @@ mod instruction_tests {
         assert_eq!(cpu.registers.status.zero, false);
         assert_eq!(cpu.registers.status.negative, false);
     }
+
+    #[test]
+    fn test_every_opcode_but_jam_is_implemented() {
+        let cpu = create_cpu_with_program(&[]);
+        let missing: Vec<u8> = (0..=0xFF).filter(|&op| !cpu.is_implemented(op)).collect();
+        assert_eq!(
+            missing,
+            [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2]
+        );
+    }
+
+    #[test]
+    fn test_lax_and_sax() {
+        let program = vec![
+            0xA7, 0x10, // LAX $10
+            0xA9, 0xF0, // LDA #$F0
+            0x87, 0x11, // SAX $11
+        ];
+        let mut cpu = create_cpu_with_program(&program);
+        cpu.bus.memory[0x10] = 0x8F;
+
+        // Execute LAX $10
+        cpu.step();
+        assert_eq!(cpu.registers.a, 0x8F);
+        assert_eq!(cpu.registers.x, 0x8F);
+        assert_eq!(cpu.registers.status.negative, true);
+
+        // Execute LDA #$F0 and SAX $11
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.bus.memory[0x11], 0x80);
+        assert_eq!(cpu.cycles(), 3 + 2 + 3);
+    }
+
+    #[test]
+    fn test_slo_rra_dcp_isb() {
+        let program = vec![
+            0xA9, 0x02, // LDA #$02
+            0x07, 0x10, // SLO $10
+            0x18, // CLC
+            0xA9, 0x10, // LDA #$10
+            0x67, 0x11, // RRA $11
+            0xA9, 0x05, // LDA #$05
+            0xC7, 0x12, // DCP $12
+            0xE7, 0x13, // ISB $13
+        ];
+        let mut cpu = create_cpu_with_program(&program);
+        cpu.bus.memory[0x10..0x14].copy_from_slice(&[0xC1, 0x03, 0x06, 0x01]);
+
+        // SLO shifts $C1 into $82 and ORs it into A
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.bus.memory[0x10], 0x82);
+        assert_eq!(cpu.registers.a, 0x82);
+        assert_eq!(cpu.registers.status.carry, true);
+        assert_eq!(cpu.registers.status.negative, true);
+
+        // RRA rotates $03 into $01, carrying out 1, and adds both
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.bus.memory[0x11], 0x01);
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.registers.status.carry, false);
+
+        // DCP decrements $06 to $05 and compares it with A
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.bus.memory[0x12], 0x05);
+        assert_eq!(cpu.registers.a, 0x05);
+        assert_eq!(cpu.registers.status.zero, true);
+        assert_eq!(cpu.registers.status.carry, true);
+
+        // ISB increments $01 to $02 and subtracts it from A
+        cpu.step();
+        assert_eq!(cpu.bus.memory[0x13], 0x02);
+        assert_eq!(cpu.registers.a, 0x03);
+        assert_eq!(cpu.registers.status.carry, true);
+    }
+
+    #[test]
+    fn test_immediate_undocumented() {
+        let program = vec![
+            0xA9, 0xFF, // LDA #$FF
+            0x38, // SEC
+            0x6B, 0xC0, // ARR #$C0
+            0xA9, 0xFF, // LDA #$FF
+            0x18, // CLC
+            0xF8, // SED
+            0x6B, 0x66, // ARR #$66
+            0xD8, // CLD
+            0xA9, 0x0F, // LDA #$0F
+            0xA2, 0xFC, // LDX #$FC
+            0xCB, 0x02, // AXS #$02
+            0x0B, 0x80, // ANC #$80
+        ];
+        let mut cpu = create_cpu_with_program(&program);
+
+        // ARR ANDs to $C0 and rotates the carry in
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.a, 0xE0);
+        assert_eq!(cpu.registers.status.negative, true);
+        assert_eq!(cpu.registers.status.carry, true);
+        assert_eq!(cpu.registers.status.overflow, false);
+
+        // In decimal mode both digits of $33 are adjusted
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.registers.a, 0x99);
+        assert_eq!(cpu.registers.status.carry, true);
+        assert_eq!(cpu.registers.status.overflow, true);
+        assert_eq!(cpu.registers.status.negative, false);
+
+        // AXS sets X to (A & X) - $02
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.registers.x, 0x0A);
+        assert_eq!(cpu.registers.status.carry, true);
+
+        // ANC copies N into C
+        cpu.step();
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(cpu.registers.status.zero, true);
+        assert_eq!(cpu.registers.status.carry, false);
+    }
+
+    #[test]
+    fn test_shx_corrupts_address_on_page_cross() {
+        let program = vec![
+            0xA2, 0x05, // LDX #$05
+            0xA0, 0x01, // LDY #$01
+            0x9E, 0x00, 0x12, // SHX $1200,Y
+            0x9E, 0xFF, 0x12, // SHX $12FF,Y
+        ];
+        let mut cpu = create_cpu_with_program(&program);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        // X & ($12 + 1)
+        assert_eq!(cpu.bus.memory[0x1201], 0x01);
+        assert_eq!(cpu.cycles(), 2 + 2 + 5);
+
+        // The stored value becomes the high byte of the address
+        cpu.step();
+        assert_eq!(cpu.bus.memory[0x0100], 0x01);
+        assert_eq!(cpu.bus.memory[0x1300], 0x00);
+        assert_eq!(cpu.cycles(), 2 + 2 + 5 + 5);
+    }
+
+    #[test]
+    fn test_indexed_write_cycles() {
+        let program = vec![
+            0xA2, 0x01, // LDX #$01
+            0x1F, 0xFF, 0x10, // SLO $10FF,X
+            0x9D, 0xFF, 0x10, // STA $10FF,X
+            0x1C, 0xFF, 0x10, // NOP $10FF,X
+        ];
+        let mut cpu = create_cpu_with_program(&program);
+        cpu.step();
+        cpu.step();
+        // Stores and read-modify-writes take the same time either way
+        assert_eq!(cpu.cycles(), 2 + 7);
+        cpu.step();
+        assert_eq!(cpu.cycles(), 2 + 7 + 5);
+        // Reads take one more when the index crosses a page
+        cpu.step();
+        assert_eq!(cpu.cycles(), 2 + 7 + 5 + 5);
+    }
+
     // You can add more tests for different addressing modes and edge cases
 }
@@ -0,0 +1,190 @@
+// src/tests/monitor.rs
+
+use super::create_ram_cpu_with_program;
+use crate::cpu::CPU;
+use crate::monitor::Monitor;
+
+fn machine() -> CPU<Vec<u8>> {
+    // LDA #$42; STA $0200; JSR $8010; BRK
+    let mut cpu =
+        create_ram_cpu_with_program(&[0xA9, 0x42, 0x8D, 0x00, 0x02, 0x20, 0x10, 0x80, 0x00]);
+    // INX; RTS
+    cpu.bus[0x8010..0x8012].copy_from_slice(&[0xE8, 0x60]);
+    cpu
+}
+
+#[test]
+fn test_disassemble_and_dump() {
+    let mut cpu = machine();
+    let mut monitor = Monitor::new();
+    monitor.add_label("sub", 0x8010);
+    assert_eq!(
+        monitor.execute(&mut cpu, "d 8005 8008").unwrap(),
+        ".8005  20 10 80  JSR sub\n.8008  00        BRK\n"
+    );
+    let output = monitor.execute(&mut cpu, "d 8000").unwrap();
+    // Labels get a line of their own
+    assert_eq!(
+        output.lines().filter(|line| line.starts_with('.')).count(),
+        16
+    );
+    assert!(output.contains("\nsub:\n.8010  E8        INX\n"));
+    assert!(output.starts_with(".8000  A9 42     LDA #$42\n"));
+    // An empty line continues where the last command left off
+    let output = monitor.execute(&mut cpu, "").unwrap();
+    assert!(output.starts_with(".8015"), "{output}");
+
+    monitor.execute(&mut cpu, "> 0200 41 \"BC\" 00").unwrap();
+    assert_eq!(
+        monitor.execute(&mut cpu, "m 0200 0203").unwrap(),
+        format!(">0200  41 42 43 00{}  ABC.\n", " ".repeat(36))
+    );
+    let output = monitor.execute(&mut cpu, "m 0200").unwrap();
+    assert_eq!(output.lines().count(), 8);
+    assert!(monitor.execute(&mut cpu, "").unwrap().starts_with(">0280"));
+}
+
+#[test]
+fn test_registers_and_execution() {
+    let mut cpu = machine();
+    let mut monitor = Monitor::new();
+    assert_eq!(
+        monitor.execute(&mut cpu, "r a=1, x = ff").unwrap(),
+        "  ADDR A  X  Y  SP NV-BDIZC CYCLES\n.;8000 01 FF 00 FD 00100000 0\n"
+    );
+    assert!(monitor.execute(&mut cpu, "r q=1").is_err());
+    assert!(monitor.execute(&mut cpu, "r a=100").is_err());
+
+    assert_eq!(
+        monitor.execute(&mut cpu, "z 2").unwrap(),
+        ".8002  8D 00 02  STA $0200\n.8005  20 10 80  JSR $8010\n"
+    );
+    assert_eq!(
+        monitor.execute(&mut cpu, "n").unwrap(),
+        ".8008  00        BRK\n"
+    );
+    assert_eq!(cpu.registers.x, 0);
+    assert_eq!(cpu.bus[0x0200], 0x42);
+
+    monitor.execute(&mut cpu, "b 8011 if x == 1").unwrap();
+    assert_eq!(
+        monitor.execute(&mut cpu, "b").unwrap(),
+        "8011  hits 0  if x == 1\n"
+    );
+    let output = monitor.execute(&mut cpu, "g 8000").unwrap();
+    assert!(
+        output.starts_with("stopped: breakpoint at $8011\n"),
+        "{output}"
+    );
+    assert!(output.ends_with(".8011  60        RTS\n"));
+    monitor.execute(&mut cpu, "bc 8011").unwrap();
+    assert!(monitor.execute(&mut cpu, "bc 8011").is_err());
+}
+
+#[test]
+fn test_memory_commands() {
+    let mut cpu = machine();
+    let mut monitor = Monitor::new();
+    monitor.execute(&mut cpu, "f 1000 1007 aa bb").unwrap();
+    assert_eq!(
+        cpu.bus[0x1000..0x1008],
+        [0xAA, 0xBB, 0xAA, 0xBB, 0xAA, 0xBB, 0xAA, 0xBB]
+    );
+    assert_eq!(
+        monitor.execute(&mut cpu, "h 1000 1007 bb aa").unwrap(),
+        "1001 1003 1005\n"
+    );
+    monitor.execute(&mut cpu, "t 1000 1007 1002").unwrap();
+    assert_eq!(
+        cpu.bus[0x1002..0x100A],
+        [0xAA, 0xBB, 0xAA, 0xBB, 0xAA, 0xBB, 0xAA, 0xBB]
+    );
+    monitor.execute(&mut cpu, "> 2003 01").unwrap();
+    monitor.execute(&mut cpu, "t 1000 1007 2000").unwrap();
+    cpu.bus[0x2005] = 0;
+    assert_eq!(
+        monitor.execute(&mut cpu, "c 1000 1007 2000").unwrap(),
+        "1005\n"
+    );
+    assert!(monitor.execute(&mut cpu, "f 1007 1000 00").is_err());
+}
+
+#[test]
+fn test_labels_and_history() {
+    let mut cpu = machine();
+    let mut monitor = Monitor::new();
+    monitor.execute(&mut cpu, "al 0200 .add").unwrap();
+    // "add" reads as hex, so the label needs its dot
+    monitor.execute(&mut cpu, "> .add+1 07").unwrap();
+    monitor.execute(&mut cpu, "> add 09").unwrap();
+    assert_eq!(cpu.bus[0x0201], 0x07);
+    assert_eq!(cpu.bus[0x0ADD], 0x09);
+    assert_eq!(
+        monitor.execute(&mut cpu, "d 8002 8002").unwrap(),
+        ".8002  8D 00 02  STA add\n"
+    );
+    assert_eq!(monitor.execute(&mut cpu, "shl").unwrap(), "0200 .add\n");
+    assert!(monitor.execute(&mut cpu, "m .nowhere").is_err());
+
+    monitor.execute(&mut cpu, "r").unwrap();
+    assert_eq!(
+        monitor.execute(&mut cpu, "!al").unwrap(),
+        monitor.execute(&mut cpu, "!!").unwrap()
+    );
+    assert_eq!(
+        monitor.history()[6..],
+        ["r", "al 0200 .add", "al 0200 .add"]
+    );
+    assert!(monitor.execute(&mut cpu, "!99").is_err());
+    assert!(monitor
+        .execute(&mut cpu, "history")
+        .unwrap()
+        .contains("   2  > .add+1 07\n"));
+
+    assert!(!monitor.is_done());
+    monitor.execute(&mut cpu, "x").unwrap();
+    assert!(monitor.is_done());
+}
+
+#[test]
+fn test_files() {
+    let dir = std::env::temp_dir().join(format!("lib6502-monitor-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut cpu = machine();
+    let mut monitor = Monitor::new();
+
+    let bin = dir.join("code.bin");
+    let command = format!("s \"{}\" 8000 8004", bin.display());
+    assert_eq!(
+        monitor.execute(&mut cpu, &command).unwrap(),
+        "saved $8000-$8004\n"
+    );
+    assert_eq!(std::fs::read(&bin).unwrap(), [0xA9, 0x42, 0x8D, 0x00, 0x02]);
+    let command = format!("l \"{}\" 9000", bin.display());
+    assert_eq!(
+        monitor.execute(&mut cpu, &command).unwrap(),
+        "loaded $9000-$9004\n"
+    );
+    assert_eq!(
+        cpu.bus[0x9000..0x9005],
+        cpu.bus[0x8000..0x8005].to_vec()[..]
+    );
+
+    let prg = dir.join("code.prg");
+    std::fs::write(&prg, [0x01, 0x08, 0xEA, 0xEA]).unwrap();
+    assert_eq!(monitor.load(&mut cpu, &prg, None).unwrap(), 0x0801..=0x0802);
+    assert!(monitor.load(&mut cpu, &prg, Some(0xFFFF)).is_err());
+
+    let labels = dir.join("labels.lbl");
+    std::fs::write(&labels, "al C:8010 .sub\nal 0200 .result\n").unwrap();
+    let command = format!("ll \"{}\"", labels.display());
+    assert_eq!(
+        monitor.execute(&mut cpu, &command).unwrap(),
+        "loaded 2 labels\n"
+    );
+    assert_eq!(monitor.label_at(0x8010), Some("sub"));
+    std::fs::write(&labels, "al nowhere\n").unwrap();
+    assert!(monitor.execute(&mut cpu, &command).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,147 @@
+// src/tests/nestest.rs
+
+use crate::nestest::{compare, machine, Comparison, NestestError, START};
+use crate::trace::{Nestest, TraceFormat, TraceRecord};
+
+/// An iNES file with one 16KB bank of PRG ROM.
+fn rom() -> Vec<u8> {
+    let mut rom = b"NES\x1A\x01\x00\x00\x00".to_vec();
+    rom.resize(16 + 0x4000, 0);
+    // LDX #$01; INX; STX $02; NOP $A9 (undocumented); JAM
+    rom[16..24].copy_from_slice(&[0xA2, 0x01, 0xE8, 0x86, 0x02, 0x04, 0xA9, 0x02]);
+    rom
+}
+
+const LOG: &str = "\
+C000  A2 01     LDX #$01                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C002  E8        INX                             A:00 X:01 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9
+C003  86 02     STX $02 = 00                    A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 33 CYC:11
+C005  04 A9    *NOP $A9 = 00                    A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 42 CYC:14
+C007  02       *JAM                             A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 51 CYC:17
+";
+
+#[test]
+fn test_machine() {
+    let cpu = machine(&rom()).unwrap();
+    assert_eq!(cpu.registers.pc, START);
+    assert_eq!(cpu.registers.status.to_byte(), 0x24);
+    assert_eq!(cpu.cycles(), 7);
+    // One bank is mirrored into both halves
+    assert_eq!(cpu.bus[0x8000..0x8002], [0xA2, 0x01]);
+    assert_eq!(cpu.bus[0xC000..0xC002], [0xA2, 0x01]);
+
+    assert_eq!(machine(b"NES").err(), Some(NestestError::NotInes));
+    let mut mapped = rom();
+    mapped[6] = 0x10;
+    assert_eq!(
+        machine(&mapped).err(),
+        Some(NestestError::UnsupportedMapper(1))
+    );
+    assert_eq!(
+        machine(&rom()[..0x100]).err(),
+        Some(NestestError::Truncated)
+    );
+}
+
+#[test]
+fn test_compare() {
+    let lines: Vec<&str> = LOG.lines().collect();
+    let mut cpu = machine(&rom()).unwrap();
+    assert_eq!(
+        compare(&mut cpu, &lines[..3].join("\n")),
+        Ok(Comparison {
+            matched: 3,
+            stopped_at: None
+        })
+    );
+    assert_eq!(cpu.registers.pc, 0xC005);
+    assert_eq!(cpu.bus[0x02], 0x02);
+
+    // The undocumented NOP runs, and the comparison stops at the JAM, the
+    // only kind of undocumented opcode the CPU does not implement
+    let mut cpu = machine(&rom()).unwrap();
+    assert_eq!(
+        compare(&mut cpu, LOG),
+        Ok(Comparison {
+            matched: 4,
+            stopped_at: Some(5)
+        })
+    );
+    assert_eq!(cpu.registers.pc, 0xC007);
+    // Traced, the NOP matches the log but for the PPU column
+    let mut cpu = machine(&rom()).unwrap();
+    for _ in 0..3 {
+        cpu.step();
+    }
+    let mut line = Vec::new();
+    Nestest
+        .write(&TraceRecord::capture(&cpu, 3).unwrap(), &mut line)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(line).unwrap(),
+        "C005  04 A9    *NOP $A9 = 00                    A:00 X:02 Y:00 P:24 SP:FD CYC:14\n"
+    );
+
+    // An opcode the CPU does not implement is a divergence if the log does
+    // not mark it as undocumented
+    let mut cpu = machine(&rom()).unwrap();
+    let documented = LOG.replace("   *JAM", "    JAM");
+    let Err(NestestError::Diverged(divergence)) = compare(&mut cpu, &documented) else {
+        panic!("expected a divergence");
+    };
+    assert_eq!(divergence.line, 5);
+    assert_eq!(divergence.fields, ["opcode"]);
+    assert_eq!(divergence.context, lines[..4]);
+    assert_eq!(divergence.expected, documented.lines().nth(4).unwrap());
+    assert!(divergence
+        .actual
+        .ends_with("(opcode $02 is not implemented)"));
+
+    let mut cpu = machine(&rom()).unwrap();
+    let wrong = LOG.replace(
+        "X:01 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9",
+        "X:01 Y:00 P:A4 SP:FD PPU:  0, 27 CYC:10",
+    );
+    let Err(NestestError::Diverged(divergence)) = compare(&mut cpu, &wrong) else {
+        panic!("expected a divergence");
+    };
+    assert_eq!(divergence.line, 2);
+    assert_eq!(divergence.fields, ["P", "CYC"]);
+    assert_eq!(
+        divergence.actual,
+        "C002  E8        INX                             A:00 X:01 Y:00 P:24 SP:FD CYC:9"
+    );
+    let report = divergence.to_string();
+    assert!(report.starts_with("diverged from the log at line 2 (P, CYC):\n  C000"));
+    assert!(report.contains("\n- C002  E8        INX"));
+    assert!(report.contains("\n+ C002  E8        INX"));
+
+    let mut cpu = machine(&rom()).unwrap();
+    assert_eq!(
+        compare(&mut cpu, "C000  A2 01     LDX #$01"),
+        Err(NestestError::BadLogLine { line: 1 })
+    );
+}
+
+/// Runs the real nestest ROM against its whole log, undocumented opcodes
+/// included, from `NESTEST_DIR`.
+#[test]
+#[ignore = "needs nestest.nes and nestest.log in NESTEST_DIR"]
+fn test_nestest_rom() {
+    let dir = std::env::var("NESTEST_DIR").expect("NESTEST_DIR is not set");
+    let dir = std::path::Path::new(&dir);
+    let rom = std::fs::read(dir.join("nestest.nes")).unwrap();
+    let log = std::fs::read_to_string(dir.join("nestest.log")).unwrap();
+    let mut cpu = machine(&rom).unwrap();
+    let comparison = compare(&mut cpu, &log).unwrap_or_else(|err| panic!("{err}"));
+    assert_eq!(
+        comparison,
+        Comparison {
+            matched: log.lines().filter(|l| !l.trim().is_empty()).count(),
+            stopped_at: None
+        }
+    );
+    // nestest leaves the error codes of the documented and undocumented
+    // opcodes at $02 and $03
+    assert_eq!(cpu.bus[0x02..0x04], [0, 0]);
+}
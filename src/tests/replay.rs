@@ -0,0 +1,188 @@
+// src/tests/replay.rs
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::replay::{Input, InputEvent, InputLog, InputRecorder, ReplayBus};
+use crate::scheduler::{Clock, DeviceContext, Scheduler};
+
+// CLI ; loop: LDA $6000 ; CLC ; ADC $0200 ; STA $0200 ; JMP loop
+const PROGRAM: [u8; 14] = [
+    0x58, 0xAD, 0x00, 0x60, 0x18, 0x6D, 0x00, 0x02, 0x8D, 0x00, 0x02, 0x4C, 0x01, 0x80,
+];
+
+fn create_memory() -> Vec<u8> {
+    let mut memory = vec![0u8; 0x10000];
+    memory[0x8000..0x8000 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+    memory[0xFFFC] = 0x00;
+    memory[0xFFFD] = 0x80;
+    // IRQ handler: INC $0201 ; RTI
+    memory[0x9000..0x9004].copy_from_slice(&[0xEE, 0x01, 0x02, 0x40]);
+    memory[0xFFFE] = 0x00;
+    memory[0xFFFF] = 0x90;
+    memory
+}
+
+/// Runs a session with changing device input, interrupts and host input.
+fn record_session() -> (CPU<Vec<u8>>, InputLog) {
+    let mut cpu = CPU::new(create_memory());
+    cpu.reset();
+    let mut recorder = InputRecorder::new(vec![0x6000..=0x600F]);
+    for i in 0..300u32 {
+        if i % 10 == 0 {
+            cpu.bus.write(0x6000, (i / 10) as u8 * 3);
+        }
+        if i % 37 == 0 {
+            recorder.irq(&mut cpu);
+        }
+        if i % 50 == 0 {
+            recorder.host_input(&mut cpu, 1, i as u8);
+            cpu.bus.write(0x7000, i as u8);
+        }
+        recorder.step(&mut cpu);
+    }
+    (cpu, recorder.into_log())
+}
+
+#[test]
+fn test_replay_reproduces_session() {
+    let (recorded, log) = record_session();
+    assert!(log.events.iter().any(|e| e.input == Input::Irq));
+
+    let mut bus = ReplayBus::new(create_memory(), log);
+    bus.on_host_input(|memory: &mut Vec<u8>, channel, data| {
+        assert_eq!(channel, 1);
+        memory[0x7000] = data;
+    });
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+    for _ in 0..300 {
+        cpu.replay_step().unwrap();
+    }
+    assert!(cpu.bus.is_finished());
+    assert_eq!(cpu.snapshot(), recorded.snapshot());
+    // The device registers were never read during replay, so only compare RAM
+    assert_eq!(cpu.bus.inner()[..0x6000], recorded.bus[..0x6000]);
+    assert_eq!(cpu.bus.inner()[0x7000], recorded.bus[0x7000]);
+}
+
+#[test]
+fn test_reads_are_stamped_with_instruction_start() {
+    let (_, log) = record_session();
+    let first_read = log
+        .events
+        .iter()
+        .find(|e| matches!(e.input, Input::Read { .. }))
+        .unwrap();
+    // IRQ at cycle 0 takes 7 cycles, INC and RTI in the handler take 12,
+    // then CLI takes 2
+    assert_eq!(
+        *first_read,
+        InputEvent {
+            cycle: 21,
+            input: Input::Read {
+                addr: 0x6000,
+                data: 0
+            }
+        }
+    );
+    assert_eq!(
+        log.events[0],
+        InputEvent {
+            cycle: 0,
+            input: Input::Irq
+        }
+    );
+}
+
+#[test]
+fn test_divergence_is_reported() {
+    let (_, log) = record_session();
+    let mut memory = create_memory();
+    // Read a different input register than the recorded program
+    memory[0x8002] = 0x01;
+    let mut cpu = CPU::new(ReplayBus::new(memory, log));
+    cpu.reset();
+    let divergence = (0..300)
+        .find_map(|_| cpu.replay_step().err())
+        .expect("replay should diverge");
+    assert_eq!(divergence.cycle, 21);
+    assert_eq!(
+        divergence.found,
+        Some(Input::Read {
+            addr: 0x6001,
+            data: 0
+        })
+    );
+    assert_eq!(
+        divergence.to_string(),
+        "replay diverged at cycle 21: expected read $6000 = $00 at cycle 21, found read $6001 = $00"
+    );
+}
+
+#[test]
+fn test_missed_interrupt_is_reported() {
+    let mut log = InputLog {
+        ranges: vec![0x6000..=0x600F],
+        events: Vec::new(),
+    };
+    log.events.push(InputEvent {
+        cycle: 1,
+        input: Input::Nmi,
+    });
+    let mut cpu = CPU::new(ReplayBus::new(create_memory(), log));
+    cpu.reset();
+    let divergence = cpu.replay_step().unwrap_err();
+    assert_eq!(divergence.cycle, 2);
+    assert_eq!(divergence.found, None);
+    assert_eq!(
+        divergence.to_string(),
+        "replay diverged at cycle 2: expected NMI at cycle 1, found nothing"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_log_round_trips_through_json() {
+    let (_, log) = record_session();
+    let json = serde_json::to_string(&log).unwrap();
+    let loaded: InputLog = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, log);
+}
+
+#[test]
+fn test_scheduler_interrupts_are_recorded() {
+    let mut memory = create_memory();
+    // NMI handler: the same as the IRQ handler
+    memory[0xFFFA] = 0x00;
+    memory[0xFFFB] = 0x90;
+    let mut cpu = CPU::new(memory.clone());
+    cpu.reset();
+    let mut scheduler = Scheduler::new(cpu);
+    // A device that changes its register and raises an NMI every 100 cycles
+    let device = scheduler.add_device(
+        |_tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| {
+            ctx.cpu.bus[0x6000] = ctx.cpu.bus[0x6000].wrapping_add(7);
+            ctx.trigger_nmi();
+            ctx.schedule_in(100, 0);
+        },
+        Clock::CPU,
+    );
+    scheduler.schedule_at(device, 100, 0);
+    scheduler.set_recorder(InputRecorder::new(vec![0x6000..=0x600F]));
+    scheduler.run_until(2_000);
+    let log = scheduler.take_recorder().unwrap().into_log();
+    assert_eq!(
+        log.events.iter().filter(|e| e.input == Input::Nmi).count(),
+        19
+    );
+
+    // The device is not there on replay; the log stands in for it
+    let mut replay = CPU::new(ReplayBus::new(memory, log));
+    replay.reset();
+    while replay.cycles() < scheduler.cpu.cycles() {
+        replay.replay_step().unwrap();
+    }
+    assert!(replay.bus.is_finished());
+    assert_eq!(replay.snapshot(), scheduler.cpu.snapshot());
+    assert_eq!(replay.bus.inner()[..0x6000], scheduler.cpu.bus[..0x6000]);
+}
@@ -0,0 +1,168 @@
+// src/tests/rewind.rs
+
+use super::create_ram_cpu_with_program;
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::rewind::Rewind;
+
+// LDX #0 ; loop: INX ; STX $0200 ; JMP loop
+const COUNTER: [u8; 9] = [0xA2, 0x00, 0xE8, 0x8E, 0x00, 0x02, 0x4C, 0x02, 0x80];
+
+fn create_cpu() -> CPU<Vec<u8>> {
+    let mut cpu = create_ram_cpu_with_program(&COUNTER);
+    // NMI handler: INC $0300 ; RTI
+    cpu.bus[0x9000..0x9004].copy_from_slice(&[0xEE, 0x00, 0x03, 0x40]);
+    cpu.bus[0xFFFA] = 0x00;
+    cpu.bus[0xFFFB] = 0x90;
+    cpu
+}
+
+#[test]
+fn test_step_back_restores_cpu_and_memory() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(1_000, 1 << 20);
+    for _ in 0..8 {
+        rewind.step(&mut cpu);
+    }
+    // LDX, then two full iterations, then INX
+    assert_eq!(cpu.registers.x, 3);
+    assert_eq!(cpu.bus.read(0x0200), 2);
+    let before = cpu.snapshot();
+
+    rewind.step(&mut cpu);
+    assert_eq!(cpu.bus.read(0x0200), 3);
+    assert!(rewind.step_back(&mut cpu));
+    assert_eq!(cpu.snapshot(), before);
+    assert_eq!(cpu.bus.read(0x0200), 2);
+    assert_eq!(rewind.position(), 8);
+}
+
+#[test]
+fn test_step_back_without_history() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(1_000, 1 << 20);
+    let before = cpu.snapshot();
+    assert!(!rewind.step_back(&mut cpu));
+    assert_eq!(cpu.snapshot(), before);
+}
+
+#[test]
+fn test_run_back_to_pc() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(20, 1 << 20);
+    for _ in 0..100 {
+        rewind.step(&mut cpu);
+    }
+    let step = rewind.run_back_to_pc(&mut cpu, 0x8003).unwrap();
+    assert_eq!(cpu.registers.pc, 0x8003);
+    assert_eq!(rewind.position(), step);
+    // The store for this iteration has not happened yet
+    assert_eq!(cpu.bus.read(0x0200), cpu.registers.x - 1);
+    assert_eq!(rewind.run_back_to_pc(&mut cpu, 0x1234), None);
+    assert_eq!(rewind.position(), step);
+}
+
+#[test]
+fn test_rewind_cycles_across_checkpoints() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(50, 1 << 20);
+    let mut history = Vec::new();
+    for _ in 0..300 {
+        history.push((cpu.snapshot(), cpu.bus.read(0x0200)));
+        rewind.step(&mut cpu);
+    }
+    let now = cpu.cycles();
+    let step = rewind.rewind_cycles(&mut cpu, 500).unwrap();
+    assert!(cpu.cycles() <= now - 500);
+    let (state, stored) = history[step as usize];
+    assert_eq!(cpu.snapshot(), state);
+    assert_eq!(cpu.bus.read(0x0200), stored);
+    // The next instruction boundary would not have been far enough back
+    assert!(history[step as usize + 1].0.cycles > now - 500);
+
+    assert_eq!(rewind.rewind_cycles(&mut cpu, 1_000_000), None);
+    assert_eq!(rewind.position(), step);
+}
+
+#[test]
+fn test_execution_continues_after_going_back() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(30, 1 << 20);
+    for _ in 0..50 {
+        rewind.step(&mut cpu);
+    }
+    let end = cpu.snapshot();
+    assert!(rewind.goto(&mut cpu, 10));
+    for _ in 10..50 {
+        rewind.step(&mut cpu);
+    }
+    assert_eq!(cpu.snapshot(), end);
+    assert!(rewind.goto(&mut cpu, 5));
+    assert!(!rewind.goto(&mut cpu, 6));
+}
+
+#[test]
+fn test_interrupts_are_replayed() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(1_000, 1 << 20);
+    for _ in 0..5 {
+        rewind.step(&mut cpu);
+    }
+    cpu.nmi();
+    for _ in 0..5 {
+        rewind.step(&mut cpu);
+    }
+    let end = cpu.snapshot();
+    assert_eq!(cpu.bus.read(0x0300), 1);
+
+    // Going back into the handler replays the interrupt
+    assert!(rewind.goto(&mut cpu, 6));
+    assert_eq!(cpu.registers.pc, 0x9003);
+    assert_eq!(cpu.bus.read(0x0300), 1);
+    for _ in 6..10 {
+        rewind.step(&mut cpu);
+    }
+    assert_eq!(cpu.snapshot(), end);
+
+    assert!(rewind.goto(&mut cpu, 5));
+    assert_eq!(cpu.registers.pc, 0x9000);
+    assert_eq!(cpu.bus.read(0x0300), 0);
+    assert!(rewind.goto(&mut cpu, 4));
+    assert_eq!(cpu.registers.pc, 0x8002);
+}
+
+#[test]
+fn test_budget_drops_old_checkpoints() {
+    let mut cpu = create_cpu();
+    // Room for about three snapshots of the 64K bus
+    let mut rewind = Rewind::new(100, 3 * 0x10000 + 4096);
+    for _ in 0..1_000 {
+        rewind.step(&mut cpu);
+    }
+    assert!(rewind.memory_usage() <= 3 * 0x10000 + 4096);
+    assert!(rewind.oldest() > 0);
+    let position = rewind.position();
+    assert!(!rewind.goto(&mut cpu, 0));
+    assert_eq!(rewind.position(), position);
+    assert!(rewind.goto(&mut cpu, rewind.oldest()));
+}
+
+#[test]
+fn test_write_journal() {
+    let mut cpu = create_cpu();
+    let mut rewind = Rewind::new(1_000, 1 << 20);
+    for _ in 0..10 {
+        rewind.step(&mut cpu);
+    }
+    let write = rewind.last_write_to(0x0200).unwrap();
+    assert_eq!(write.pc, 0x8003);
+    assert_eq!(write.data, 3);
+    assert_eq!(write.step, 8);
+    assert_eq!(rewind.writes().count(), 3);
+    assert_eq!(rewind.last_write_to(0x0201), None);
+
+    // Undo the JMP and the STX
+    rewind.step_back(&mut cpu);
+    rewind.step_back(&mut cpu);
+    assert_eq!(rewind.last_write_to(0x0200).unwrap().data, 2);
+}
@@ -0,0 +1,82 @@
+// src/tests/run.rs
+
+use super::create_ram_cpu_with_program;
+use crate::run::{RunResult, StopReason};
+
+// LDX #$03 ; loop: DEX ; BNE loop ; JMP *
+const COUNTDOWN: [u8; 8] = [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x4C, 0x05, 0x80];
+
+#[test]
+fn test_run_instructions() {
+    let mut cpu = create_ram_cpu_with_program(&COUNTDOWN);
+    let result = cpu.run_instructions(3);
+    assert_eq!(
+        result,
+        RunResult {
+            reason: StopReason::InstructionsExecuted,
+            cycles: 2 + 2 + 3,
+            instructions: 3,
+        }
+    );
+    assert_eq!(cpu.registers.x, 2);
+}
+
+#[test]
+fn test_run_cycles() {
+    let mut cpu = create_ram_cpu_with_program(&[0xEA; 16]);
+    let result = cpu.run_cycles(7);
+    assert_eq!(result.reason, StopReason::CyclesElapsed);
+    assert_eq!(result.cycles, 8);
+    assert_eq!(result.instructions, 4);
+}
+
+#[test]
+fn test_run_until_predicate() {
+    let mut cpu = create_ram_cpu_with_program(&COUNTDOWN);
+    let result = cpu.run_until(|cpu| cpu.registers.x == 1);
+    assert_eq!(result.reason, StopReason::Predicate);
+    assert_eq!(cpu.registers.pc, 0x8003);
+}
+
+#[test]
+fn test_run_until_pc() {
+    let mut cpu = create_ram_cpu_with_program(&COUNTDOWN);
+    let result = cpu.run_until_pc(0x8005);
+    assert_eq!(result.reason, StopReason::PcReached);
+    assert_eq!(cpu.registers.x, 0);
+    assert_eq!(cpu.run_until_pc(0x8005).instructions, 0);
+}
+
+#[test]
+fn test_run_until_trap_jmp() {
+    let mut cpu = create_ram_cpu_with_program(&COUNTDOWN);
+    let result = cpu.run_until_trap(100);
+    assert_eq!(result.reason, StopReason::Trap { pc: 0x8005 });
+    // LDX, 3x DEX, 2 taken BNE, 1 untaken BNE, JMP
+    assert_eq!(result.instructions, 8);
+    assert_eq!(result.cycles, 2 + 3 * 2 + 2 * 3 + 2 + 3);
+}
+
+#[test]
+fn test_run_until_trap_branch() {
+    // LDA #$01 ; BNE *
+    let mut cpu = create_ram_cpu_with_program(&[0xA9, 0x01, 0xD0, 0xFE]);
+    let result = cpu.run_until_trap(100);
+    assert_eq!(result.reason, StopReason::Trap { pc: 0x8002 });
+}
+
+#[test]
+fn test_run_until_trap_limit() {
+    // A loop that never traps: loop: INX ; JMP loop
+    let mut cpu = create_ram_cpu_with_program(&[0xE8, 0x4C, 0x00, 0x80]);
+    let result = cpu.run_until_trap(10);
+    assert_eq!(
+        result,
+        RunResult {
+            reason: StopReason::InstructionsExecuted,
+            cycles: 5 * (2 + 3),
+            instructions: 10,
+        }
+    );
+    assert_eq!(cpu.registers.x, 5);
+}
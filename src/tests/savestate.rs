@@ -0,0 +1,158 @@
+// src/tests/savestate.rs
+
+use super::create_cpu_with_program;
+use crate::adapters::OverlayBus;
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::savestate::*;
+use serde::{Deserialize, Serialize};
+
+// A device with its own state, saved alongside RAM.
+#[derive(Default)]
+struct Latch {
+    value: u8,
+    writes: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LatchState {
+    value: u8,
+    writes: u32,
+}
+
+impl Bus for Latch {
+    fn read(&mut self, _addr: u16) -> u8 {
+        self.value
+    }
+
+    fn write(&mut self, _addr: u16, data: u8) {
+        self.value = data;
+        self.writes += 1;
+    }
+}
+
+impl SaveState for Latch {
+    type State = LatchState;
+
+    fn save_state(&self) -> LatchState {
+        LatchState {
+            value: self.value,
+            writes: self.writes,
+        }
+    }
+
+    fn load_state(&mut self, state: LatchState) -> Result<(), SaveStateError> {
+        self.value = state.value;
+        self.writes = state.writes;
+        Ok(())
+    }
+}
+
+fn create_machine() -> CPU<OverlayBus<Vec<u8>, Latch>> {
+    let mut ram = vec![0u8; 0x10000];
+    // LDA #$42 ; STA $6000 ; INC $10 ; NOP
+    ram[0x8000..0x8008].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x60, 0xE6, 0x10, 0xEA]);
+    ram[0xFFFD] = 0x80;
+    let mut cpu = CPU::new(OverlayBus::new(ram, 0x6000..=0x6000, Latch::default()));
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let mut cpu = create_machine();
+    cpu.step();
+    cpu.step();
+    cpu.step();
+    let mut file = Vec::new();
+    cpu.save_state(&mut file).unwrap();
+
+    let mut restored = create_machine();
+    restored.load_state(file.as_slice()).unwrap();
+    assert_eq!(restored.snapshot(), cpu.snapshot());
+    assert_eq!(restored.bus.overlay().value, 0x42);
+    assert_eq!(restored.bus.overlay().writes, 1);
+    assert_eq!(restored.bus.inner()[0x10], 1);
+
+    // Both machines carry on identically
+    cpu.step();
+    restored.step();
+    assert_eq!(restored.snapshot(), cpu.snapshot());
+}
+
+#[test]
+fn test_header_is_written() {
+    let cpu = create_cpu_with_program(&[0xEA]);
+    let mut file = Vec::new();
+    crate::savestate::write_state(&mut file, &cpu.snapshot(), &vec![0u8; 4]).unwrap();
+    let text = String::from_utf8(file).unwrap();
+    assert!(text.starts_with(r#"{"magic":"lib6502-savestate","version":1,"variant":"NMOS 6502","#));
+    assert!(text.ends_with(r#""machine":"00000000"}"#));
+}
+
+#[test]
+fn test_newer_version_is_rejected() {
+    let mut cpu = create_machine();
+    let mut file = Vec::new();
+    cpu.save_state(&mut file).unwrap();
+    let text = String::from_utf8(file)
+        .unwrap()
+        .replace(r#""version":1"#, r#""version":99"#);
+    let err = cpu.load_state(text.as_bytes()).unwrap_err();
+    assert!(matches!(
+        err,
+        SaveStateError::UnsupportedVersion {
+            found: 99,
+            oldest: 1,
+            newest: 1
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "save state format version 99 is not supported (this build reads up to version 1)"
+    );
+}
+
+#[test]
+fn test_older_version_is_rejected() {
+    let mut cpu = create_machine();
+    let mut file = Vec::new();
+    cpu.save_state(&mut file).unwrap();
+    let text = String::from_utf8(file)
+        .unwrap()
+        .replace(r#""version":1"#, r#""version":0"#);
+    let err = cpu.load_state(text.as_bytes()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "save state format version 0 is older than the oldest supported version 1"
+    );
+}
+
+#[test]
+fn test_wrong_variant_and_garbage_are_rejected() {
+    let mut cpu = create_machine();
+    let mut file = Vec::new();
+    cpu.save_state(&mut file).unwrap();
+    let text = String::from_utf8(file)
+        .unwrap()
+        .replace("NMOS 6502", "65C02");
+    assert!(matches!(
+        cpu.load_state(text.as_bytes()),
+        Err(SaveStateError::VariantMismatch { .. })
+    ));
+    assert!(matches!(
+        cpu.load_state(&b"{\"hello\":1}"[..]),
+        Err(SaveStateError::NotASaveState)
+    ));
+    assert!(matches!(
+        cpu.load_state(&b"not json"[..]),
+        Err(SaveStateError::Format(_))
+    ));
+}
+
+#[test]
+fn test_flat_memory_rejects_wrong_size() {
+    let mut memory = [0u8; 0x10000];
+    let err = memory.load_state(MemoryImage(vec![0; 16])).unwrap_err();
+    assert!(matches!(err, SaveStateError::InvalidState(_)));
+}
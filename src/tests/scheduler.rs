@@ -0,0 +1,150 @@
+// src/tests/scheduler.rs
+
+use crate::cpu::CPU;
+use crate::scheduler::{Clock, Device, DeviceContext, Scheduler};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Builds a scheduler whose CPU runs NOPs from $8000 with the IRQ handler at $9000.
+fn create_scheduler() -> Scheduler<Vec<u8>> {
+    let mut memory = vec![0xEAu8; 0x10000];
+    memory[0xFFFC] = 0x00;
+    memory[0xFFFD] = 0x80;
+    memory[0xFFFE] = 0x00;
+    memory[0xFFFF] = 0x90;
+    memory[0xFFFA] = 0x00;
+    memory[0xFFFB] = 0xA0;
+    let mut cpu = CPU::new(memory);
+    cpu.reset();
+    Scheduler::new(cpu)
+}
+
+// A free-running timer that raises IRQ every `period` cycles.
+struct Timer {
+    period: u64,
+    fired: Rc<RefCell<Vec<u64>>>,
+}
+
+impl Device<Vec<u8>> for Timer {
+    fn event(&mut self, _tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>) {
+        self.fired.borrow_mut().push(ctx.scheduled());
+        ctx.set_irq(true);
+        ctx.schedule_in(self.period, 0);
+    }
+}
+
+#[test]
+fn test_clock_conversions() {
+    let clock = Clock::new(3, 2);
+    assert_eq!(clock.ticks_at(4), 6);
+    assert_eq!(clock.ticks_at(5), 7);
+    assert_eq!(clock.cycle_of_tick(7), 5);
+    assert_eq!(clock.cycle_of_tick(6), 4);
+    assert_eq!(Clock::CPU.cycle_of_tick(10), 10);
+}
+
+#[test]
+fn test_periodic_event_does_not_drift() {
+    let mut scheduler = create_scheduler();
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let timer = scheduler.add_device(
+        Timer {
+            period: 25,
+            fired: fired.clone(),
+        },
+        Clock::CPU,
+    );
+    // Keep interrupts masked so only the event timing is observed
+    scheduler.cpu.registers.status.interrupt_disable = true;
+    scheduler.schedule_at(timer, 25, 0);
+    scheduler.run_until(101);
+    assert_eq!(*fired.borrow(), vec![25, 50, 75, 100]);
+    assert!(scheduler.irq_asserted());
+}
+
+#[test]
+fn test_irq_taken_when_enabled() {
+    let mut scheduler = create_scheduler();
+    let timer = scheduler.add_device(
+        |_tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| ctx.set_irq(true),
+        Clock::CPU,
+    );
+    scheduler.schedule_at(timer, 10, 0);
+    scheduler.run_until(10);
+    assert!(scheduler.cpu.registers.pc < 0x9000);
+    scheduler.step();
+    assert_eq!(scheduler.cpu.registers.pc, 0x9001);
+    assert!(scheduler.cpu.registers.status.interrupt_disable);
+    // 10 cycles of NOPs, 7 for the interrupt and 2 for the first handler NOP
+    assert_eq!(scheduler.cpu.cycles(), 19);
+}
+
+#[test]
+fn test_nmi_and_device_clock() {
+    let mut scheduler = create_scheduler();
+    // A device at twice the CPU clock that fires after 40 of its own ticks
+    let device = scheduler.add_device(
+        |tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| {
+            if tag == 0 {
+                ctx.schedule_in_ticks(40, 1);
+            } else {
+                ctx.trigger_nmi();
+            }
+        },
+        Clock::new(2, 1),
+    );
+    scheduler.schedule_at(device, 0, 0);
+    scheduler.run_until(20);
+    assert!(scheduler.cpu.registers.pc < 0xA000);
+    scheduler.step();
+    assert_eq!(scheduler.cpu.registers.pc, 0xA001);
+}
+
+#[test]
+fn test_event_order_and_cancel() {
+    let mut scheduler = create_scheduler();
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let log = order.clone();
+    let device = scheduler.add_device(
+        move |tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| {
+            log.borrow_mut().push(tag);
+            if tag == 1 {
+                ctx.cancel(3);
+            }
+        },
+        Clock::CPU,
+    );
+    scheduler.schedule_at(device, 4, 2);
+    scheduler.schedule_at(device, 4, 1);
+    scheduler.schedule_at(device, 2, 0);
+    scheduler.schedule_at(device, 6, 3);
+    assert_eq!(scheduler.next_event_cycle(), Some(2));
+    scheduler.run_until(10);
+    assert_eq!(*order.borrow(), vec![0, 2, 1]);
+    assert_eq!(scheduler.next_event_cycle(), None);
+}
+
+#[test]
+fn test_rescheduling_now_does_not_hang() {
+    let mut scheduler = create_scheduler();
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let log = fired.clone();
+    let device = scheduler.add_device(
+        move |_tag: u32, ctx: &mut DeviceContext<'_, Vec<u8>>| {
+            log.borrow_mut().push(ctx.scheduled());
+            ctx.schedule_in(0, 0);
+        },
+        Clock::CPU,
+    );
+    scheduler.schedule_at(device, 0, 0);
+    scheduler.run_until(10);
+    // Each event is moved to the cycle after the one being handled
+    assert_eq!(*fired.borrow(), (0..=10).collect::<Vec<u64>>());
+    assert_eq!(scheduler.next_event_cycle(), Some(11));
+}
+
+#[test]
+#[should_panic(expected = "unknown device id 3")]
+fn test_set_irq_unknown_device() {
+    create_scheduler().set_irq(3, true);
+}
@@ -0,0 +1,314 @@
+// src/tests/singlestep.rs
+
+use crate::bus::{AccessKind, BusAccess};
+use crate::cpu::CPU;
+use crate::singlestep::{run_case, run_cases, Mismatch, TestCase, TestState};
+
+fn read(addr: u16, data: u8) -> BusAccess {
+    BusAccess {
+        addr,
+        data,
+        kind: AccessKind::Read,
+    }
+}
+
+fn write(addr: u16, data: u8) -> BusAccess {
+    BusAccess {
+        addr,
+        data,
+        kind: AccessKind::Write,
+    }
+}
+
+fn state(pc: u16, a: u8, ram: &[(u16, u8)]) -> TestState {
+    TestState {
+        pc,
+        s: 0xFD,
+        a,
+        x: 0,
+        y: 0,
+        p: 0x24,
+        ram: ram.to_vec(),
+    }
+}
+
+fn fresh() -> CPU<Vec<u8>> {
+    CPU::new(vec![0u8; 0x10000])
+}
+
+/// The JAM opcodes, the only ones this CPU does not implement.
+const JAM: [u8; 12] = [
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+];
+
+/// Returns `true` if a mismatch is one this CPU is known to have, so the
+/// SingleStepTests files can check that nothing else fails. Every
+/// implemented opcode must match the real chip cycle for cycle.
+fn is_known_gap(opcode: u8, mismatch: &Mismatch) -> bool {
+    match mismatch {
+        Mismatch::Unimplemented { .. } => JAM.contains(&opcode),
+        _ => false,
+    }
+}
+
+/// STA $10
+fn store() -> TestCase {
+    TestCase {
+        name: "85 10".to_string(),
+        initial: state(
+            0x0200,
+            0x42,
+            &[(0x0200, 0x85), (0x0201, 0x10), (0x0010, 0x00)],
+        ),
+        final_state: state(
+            0x0202,
+            0x42,
+            &[(0x0200, 0x85), (0x0201, 0x10), (0x0010, 0x42)],
+        ),
+        cycles: vec![read(0x0200, 0x85), read(0x0201, 0x10), write(0x0010, 0x42)],
+    }
+}
+
+#[test]
+fn test_passing_case() {
+    let mut cpu = fresh();
+    assert_eq!(run_case(&mut cpu, &store()), []);
+    assert_eq!(cpu.bus[0x0010], 0x42);
+}
+
+#[test]
+fn test_mismatches() {
+    let mut case = store();
+    case.final_state.a = 0x43;
+    case.final_state.ram[2].1 = 0x41;
+    case.cycles[2] = write(0x0010, 0x41);
+    assert_eq!(
+        run_case(&mut fresh(), &case),
+        [
+            Mismatch::Register {
+                name: "A",
+                expected: 0x43,
+                actual: 0x42
+            },
+            Mismatch::Memory {
+                addr: 0x0010,
+                expected: 0x41,
+                actual: Some(0x42)
+            },
+            Mismatch::Cycle {
+                index: 2,
+                expected: Some(write(0x0010, 0x41)),
+                actual: Some(write(0x0010, 0x42))
+            },
+        ]
+    );
+
+    // INX reads the byte after it in its second cycle
+    let case = TestCase {
+        name: "e8".to_string(),
+        initial: state(0x0200, 0, &[(0x0200, 0xE8), (0x0201, 0x55)]),
+        final_state: TestState {
+            x: 1,
+            ..state(0x0201, 0, &[(0x0200, 0xE8)])
+        },
+        cycles: vec![read(0x0200, 0xE8), read(0x0201, 0x55)],
+    };
+    assert_eq!(run_case(&mut fresh(), &case), []);
+
+    let mut case = case;
+    case.cycles.push(read(0x0202, 0));
+    let mismatches = run_case(&mut fresh(), &case);
+    assert_eq!(
+        mismatches,
+        [
+            Mismatch::CycleCount {
+                expected: 3,
+                actual: 2
+            },
+            Mismatch::Cycle {
+                index: 2,
+                expected: Some(read(0x0202, 0)),
+                actual: None
+            }
+        ]
+    );
+    assert!(!mismatches.iter().any(|m| is_known_gap(0xE8, m)));
+    assert_eq!(
+        mismatches[1].to_string(),
+        "cycle 2 was nothing, expected $0202 read $00"
+    );
+
+    let mut case = store();
+    case.cycles.push(read(0x0202, 0));
+    assert!(
+        run_case(&mut fresh(), &case).contains(&Mismatch::CycleCount {
+            expected: 4,
+            actual: 3
+        })
+    );
+
+    let case = TestCase {
+        name: "02".to_string(),
+        initial: state(0x0200, 0, &[(0x0200, 0x02)]),
+        final_state: state(0x0200, 0, &[]),
+        cycles: Vec::new(),
+    };
+    assert_eq!(
+        run_case(&mut fresh(), &case),
+        [Mismatch::Unimplemented { opcode: 0x02 }]
+    );
+}
+
+#[test]
+fn test_dummy_accesses() {
+    // JSR $1234
+    let jsr = TestCase {
+        name: "20 34 12".to_string(),
+        initial: state(0x0200, 0, &[(0x0200, 0x20), (0x0201, 0x34), (0x0202, 0x12)]),
+        final_state: TestState {
+            s: 0xFB,
+            ..state(0x1234, 0, &[(0x01FD, 0x02), (0x01FC, 0x02)])
+        },
+        cycles: vec![
+            read(0x0200, 0x20),
+            read(0x0201, 0x34),
+            read(0x01FD, 0x00),
+            write(0x01FD, 0x02),
+            write(0x01FC, 0x02),
+            read(0x0202, 0x12),
+        ],
+    };
+    // RTS back to it
+    let rts = TestCase {
+        name: "60".to_string(),
+        initial: TestState {
+            s: 0xFB,
+            ..state(0x0200, 0, &[(0x0200, 0x60), (0x01FC, 0x02), (0x01FD, 0x02)])
+        },
+        final_state: state(0x0203, 0, &[]),
+        cycles: vec![
+            read(0x0200, 0x60),
+            read(0x0201, 0x00),
+            read(0x01FB, 0x00),
+            read(0x01FC, 0x02),
+            read(0x01FD, 0x02),
+            read(0x0202, 0x00),
+        ],
+    };
+    // INC $10FF,X with X = 1
+    let inc = TestCase {
+        name: "fe ff 10".to_string(),
+        initial: TestState {
+            x: 1,
+            ..state(
+                0x0200,
+                0,
+                &[(0x0200, 0xFE), (0x0201, 0xFF), (0x0202, 0x10), (0x1100, 7)],
+            )
+        },
+        final_state: TestState {
+            x: 1,
+            ..state(0x0203, 0, &[(0x1100, 8)])
+        },
+        cycles: vec![
+            read(0x0200, 0xFE),
+            read(0x0201, 0xFF),
+            read(0x0202, 0x10),
+            read(0x1000, 0x00),
+            read(0x1100, 0x07),
+            write(0x1100, 0x07),
+            write(0x1100, 0x08),
+        ],
+    };
+    // BNE to the next page
+    let bne = TestCase {
+        name: "d0 02".to_string(),
+        initial: state(0x02FD, 0, &[(0x02FD, 0xD0), (0x02FE, 0x02)]),
+        final_state: state(0x0301, 0, &[]),
+        cycles: vec![
+            read(0x02FD, 0xD0),
+            read(0x02FE, 0x02),
+            read(0x02FF, 0x00),
+            read(0x0201, 0x00),
+        ],
+    };
+    for case in [jsr, rts, inc, bne] {
+        assert_eq!(run_case(&mut fresh(), &case), [], "{}", case.name);
+    }
+}
+
+#[test]
+fn test_summary() {
+    let mut wrong = store();
+    wrong.name = "wrong".to_string();
+    wrong.final_state.a = 0;
+    let mut slow = store();
+    slow.name = "slow".to_string();
+    slow.cycles.push(read(0x0202, 0));
+    let summary = run_cases(fresh, &[store(), wrong, slow]);
+    assert_eq!(summary.cases, 3);
+    assert_eq!(summary.passed, 1);
+    // Only one gets the registers or memory wrong
+    assert_eq!(summary.wrong_results, 1);
+    assert!(!summary.all_passed());
+    assert_eq!(
+        summary.to_string(),
+        "1 of 3 cases passed, 1 with wrong results; first failure \"wrong\": A is $42, expected $00;"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_read_cases() {
+    let json = r#"[{
+        "name": "85 10",
+        "initial": {"pc": 512, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+                    "ram": [[512, 133], [513, 16], [16, 0]]},
+        "final": {"pc": 514, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+                  "ram": [[512, 133], [513, 16], [16, 66]]},
+        "cycles": [[512, 133, "read"], [513, 16, "read"], [16, 66, "write"]]
+    }]"#;
+    let cases = crate::singlestep::read_cases(json.as_bytes()).unwrap();
+    assert_eq!(cases, [store()]);
+    assert!(crate::singlestep::read_cases(&b"[{}]"[..]).is_err());
+}
+
+/// Runs every file in `SINGLESTEP_DIR`, such as the `6502/v1` directory of
+/// SingleStepTests, and reports the cases that fail, other than those of
+/// the JAM opcodes.
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs the SingleStepTests files in SINGLESTEP_DIR"]
+fn test_singlestep_files() {
+    let dir = std::env::var("SINGLESTEP_DIR").expect("SINGLESTEP_DIR is not set");
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no test files");
+    let mut regressions = Vec::new();
+    for path in paths {
+        let file = std::fs::File::open(&path).unwrap();
+        let cases = crate::singlestep::read_cases(file).unwrap();
+        let summary = run_cases(fresh, &cases);
+        for (name, mismatches) in &summary.failures {
+            // Each case is named after its bytes, opcode first
+            let opcode = u8::from_str_radix(&name[..2], 16).unwrap();
+            let unknown: Vec<String> = mismatches
+                .iter()
+                .filter(|m| !is_known_gap(opcode, m))
+                .map(|m| m.to_string())
+                .collect();
+            if !unknown.is_empty() {
+                regressions.push(format!(
+                    "{}: \"{name}\": {}",
+                    path.display(),
+                    unknown.join("; ")
+                ));
+            }
+        }
+    }
+    assert!(regressions.is_empty(), "{}", regressions.join("\n"));
+}
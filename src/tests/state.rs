@@ -0,0 +1,74 @@
+// src/tests/state.rs
+
+use super::create_cpu_with_program;
+use crate::state::{CpuState, Field, FieldChange};
+
+#[test]
+fn test_snapshot_and_restore() {
+    // LDA #$80 ; SEC ; NOP
+    let mut cpu = create_cpu_with_program(&[0xA9, 0x80, 0x38, 0xEA]);
+    let start = cpu.snapshot();
+    cpu.step();
+    cpu.step();
+    let after = cpu.snapshot();
+    assert_ne!(start, after);
+    assert_eq!(after.registers.a, 0x80);
+    assert_eq!(after.cycles, 4);
+
+    cpu.restore(&start);
+    assert_eq!(cpu.snapshot(), start);
+    assert_eq!(cpu.registers.pc, 0x8000);
+    assert_eq!(cpu.cycles(), 0);
+
+    // Re-executing from the restored state gives the same result
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.snapshot(), after);
+}
+
+#[test]
+fn test_set_cycles() {
+    let mut cpu = create_cpu_with_program(&[0xEA]);
+    cpu.set_cycles(1_000);
+    cpu.step();
+    assert_eq!(cpu.cycles(), 1_002);
+}
+
+#[test]
+fn test_state_diff() {
+    let mut cpu = create_cpu_with_program(&[0xA9, 0x80]);
+    let before = cpu.snapshot();
+    cpu.step();
+    let diff = before.diff(&cpu.snapshot());
+    assert_eq!(
+        diff.changes,
+        vec![
+            FieldChange {
+                field: Field::A,
+                left: 0x00,
+                right: 0x80
+            },
+            FieldChange {
+                field: Field::Pc,
+                left: 0x8000,
+                right: 0x8002
+            },
+            FieldChange {
+                field: Field::Negative,
+                left: 0,
+                right: 1
+            },
+            FieldChange {
+                field: Field::Cycles,
+                left: 0,
+                right: 2
+            },
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "A: $00 -> $80\nPC: $8000 -> $8002\nN: 0 -> 1\ncycles: 0 -> 2\n"
+    );
+    assert!(before.diff(&before).is_empty());
+    assert_eq!(CpuState::default().registers.sp, 0xFD);
+}
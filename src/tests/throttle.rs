@@ -0,0 +1,120 @@
+// src/tests/throttle.rs
+
+use super::create_ram_cpu_with_program;
+use crate::run::StopReason;
+use crate::throttle::{ClockMode, Throttle, TimeSource};
+use std::time::Duration;
+
+// A fake clock where sleeping advances time instantly.
+#[derive(Default)]
+struct FakeTime {
+    now: Duration,
+    slept: Duration,
+}
+
+impl TimeSource for FakeTime {
+    fn now(&mut self) -> Duration {
+        self.now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.now += duration;
+        self.slept += duration;
+    }
+}
+
+// NOPs from 0x8000 up to the reset vector
+static NOPS: [u8; 0x7FFC] = [0xEA; 0x7FFC];
+
+#[test]
+fn test_throttled_run_matches_frequency() {
+    let mut cpu = create_ram_cpu_with_program(&NOPS);
+    let mut throttle =
+        Throttle::with_time_source(ClockMode::Throttled(1_000_000), FakeTime::default());
+    let result = throttle.run_for(&mut cpu, Duration::from_millis(10));
+    assert_eq!(result.reason, StopReason::TimeElapsed);
+    // 10ms at 1 MHz is 10,000 cycles of 2-cycle NOPs
+    assert_eq!(result.cycles, 10_000);
+    assert_eq!(throttle.time_source_mut().now, Duration::from_millis(10));
+}
+
+#[test]
+fn test_slow_clock_steps() {
+    let mut cpu = create_ram_cpu_with_program(&NOPS);
+    let mut throttle = Throttle::with_time_source(ClockMode::Throttled(1), FakeTime::default());
+    throttle.step(&mut cpu);
+    throttle.step(&mut cpu);
+    // The second NOP may only start 2 seconds after the first
+    assert_eq!(throttle.time_source_mut().now, Duration::from_secs(2));
+    let result = throttle.run_for(&mut cpu, Duration::from_secs(3));
+    assert_eq!(result.instructions, 1);
+    assert_eq!(cpu.cycles(), 6);
+}
+
+#[test]
+fn test_catch_up_after_stall() {
+    let mut cpu = create_ram_cpu_with_program(&NOPS);
+    let mut throttle = Throttle::with_time_source(ClockMode::Throttled(1000), FakeTime::default());
+    throttle.step(&mut cpu);
+    // The host stalls for 50ms, within the default 100ms lag
+    throttle.time_source_mut().now += Duration::from_millis(50);
+    throttle.time_source_mut().slept = Duration::ZERO;
+    let result = throttle.run_for(&mut cpu, Duration::from_millis(10));
+    // The missed cycles are run without sleeping, so the CPU is back on
+    // schedule at 60 cycles when the 60ms mark is reached
+    assert_eq!(cpu.cycles(), 60);
+    assert_eq!(result.cycles, 58);
+    assert_eq!(throttle.time_source_mut().slept, Duration::from_millis(10));
+}
+
+#[test]
+fn test_stall_beyond_max_lag_is_dropped() {
+    let mut cpu = create_ram_cpu_with_program(&NOPS);
+    let mut throttle = Throttle::with_time_source(ClockMode::Throttled(1000), FakeTime::default());
+    throttle.step(&mut cpu);
+    throttle.time_source_mut().now += Duration::from_secs(5);
+    let result = throttle.run_for(&mut cpu, Duration::from_millis(10));
+    assert_eq!(result.cycles, 10);
+}
+
+#[test]
+fn test_manual_mode() {
+    let mut cpu = create_ram_cpu_with_program(&NOPS);
+    let mut throttle = Throttle::with_time_source(ClockMode::Manual, FakeTime::default());
+    let result = throttle.run_for(&mut cpu, Duration::from_millis(10));
+    assert_eq!(result.instructions, 0);
+    assert_eq!(throttle.step(&mut cpu).instructions, 0);
+    assert_eq!(throttle.single_step(&mut cpu).cycles, 2);
+    assert_eq!(cpu.registers.pc, 0x8001);
+}
+
+#[test]
+fn test_run_until_predicate() {
+    let mut cpu = create_ram_cpu_with_program(&NOPS);
+    let mut throttle =
+        Throttle::with_time_source(ClockMode::Throttled(1_000_000), FakeTime::default());
+    let result = throttle.run_until(&mut cpu, Duration::from_secs(1), |cpu| {
+        cpu.registers.pc == 0x8010
+    });
+    assert_eq!(result.reason, StopReason::Predicate);
+    assert_eq!(result.instructions, 16);
+}
+
+#[test]
+#[should_panic(expected = "frequency must be non-zero")]
+fn test_zero_frequency_new() {
+    Throttle::new(0);
+}
+
+#[test]
+#[should_panic(expected = "frequency must be non-zero")]
+fn test_zero_frequency_with_time_source() {
+    Throttle::with_time_source(ClockMode::Throttled(0), FakeTime::default());
+}
+
+#[test]
+#[should_panic(expected = "frequency must be non-zero")]
+fn test_zero_frequency_set_mode() {
+    let mut throttle = Throttle::with_time_source(ClockMode::Manual, FakeTime::default());
+    throttle.set_mode(ClockMode::Throttled(0));
+}
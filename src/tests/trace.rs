@@ -0,0 +1,165 @@
+// src/tests/trace.rs
+
+use super::{create_cpu_with_program, create_ram_cpu_with_program};
+use crate::cpu::CPU;
+use crate::debugger::{Debugger, Resume, Stop};
+use crate::trace::{Effective, JsonLines, Nestest, TraceRecord, Traced, Tracer, Vice};
+
+fn machine() -> CPU<Vec<u8>> {
+    // LDX #$04; LDA ($80,X); LDY #$02; LDA ($80),Y; STA $10,X; JMP ($02FF)
+    let mut cpu = create_ram_cpu_with_program(&[
+        0xA2, 0x04, 0xA1, 0x80, 0xA0, 0x02, 0xB1, 0x80, 0x95, 0x10, 0x6C, 0xFF, 0x02, 0x00,
+    ]);
+    cpu.bus[0x0080..0x0082].copy_from_slice(&[0x00, 0x03]);
+    cpu.bus[0x0084..0x0086].copy_from_slice(&[0x00, 0x02]);
+    cpu.bus[0x0200] = 0x5A;
+    cpu.bus[0x0302] = 0x77;
+    // The pointer's high byte comes from $0200, not $0300
+    cpu.bus[0x02FF] = 0x00;
+    cpu.bus[0x0300] = 0x90;
+    cpu
+}
+
+fn lines<F: crate::trace::TraceFormat + 'static>(format: F, count: usize) -> Vec<String> {
+    let mut cpu = machine();
+    let mut tracer = Tracer::new(format, Vec::new());
+    for _ in 0..count {
+        tracer.step(&mut cpu).unwrap();
+    }
+    String::from_utf8(tracer.into_output())
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_effective_addresses() {
+    let mut cpu = machine();
+    let mut records = Vec::new();
+    for index in 0..6 {
+        records.push(TraceRecord::capture(&cpu, index).unwrap());
+        cpu.step();
+    }
+    assert_eq!(records[0].effective, None);
+    assert_eq!(
+        records[1].effective,
+        Some(Effective {
+            pointer: Some(0x84),
+            addr: 0x0200,
+            value: Some(0x5A)
+        })
+    );
+    assert_eq!(records[1].registers.x, 4);
+    assert_eq!(
+        records[3].effective,
+        Some(Effective {
+            pointer: Some(0x80),
+            addr: 0x0302,
+            value: Some(0x77)
+        })
+    );
+    assert_eq!(records[4].effective.unwrap().addr, 0x0014);
+    assert_eq!(
+        records[5].effective,
+        Some(Effective {
+            pointer: Some(0x02FF),
+            addr: 0x5A00,
+            value: None
+        })
+    );
+    assert_eq!(cpu.registers.pc, 0x5A00);
+    assert!(records[5].cycles > records[4].cycles);
+}
+
+#[test]
+fn test_nestest_format() {
+    let lines = lines(Nestest, 6);
+    assert_eq!(
+        lines[0],
+        "8000  A2 04     LDX #$04                        A:00 X:00 Y:00 P:20 SP:FD CYC:0"
+    );
+    assert!(lines[1].starts_with("8002  A1 80     LDA ($80,X) @ 84 = 0200 = 5A    A:00 X:04"));
+    assert!(lines[3].starts_with("8006  B1 80     LDA ($80),Y = 0300 @ 0302 = 77  A:5A"));
+    assert!(lines[4].starts_with("8008  95 10     STA $10,X @ 14 = 00             A:77"));
+    assert!(lines[5].starts_with("800A  6C FF 02  JMP ($02FF) = 5A00              A:77"));
+}
+
+#[test]
+fn test_vice_and_json_formats() {
+    let lines = lines(Vice, 2);
+    assert_eq!(
+        lines[0],
+        ".C:8000  A2 04       LDX #$04       - A:00 X:00 Y:00 SP:fd ..-.....          0"
+    );
+    assert!(lines[1].starts_with(".C:8002  A1 80       LDA ($80,X)    - A:00 X:04"));
+
+    let lines = self::lines(JsonLines, 2);
+    assert_eq!(
+        lines[0],
+        "{\"index\":0,\"pc\":32768,\"bytes\":[162,4],\"disassembly\":\"LDX #$04\",\
+         \"a\":0,\"x\":0,\"y\":0,\"sp\":253,\"p\":32,\"cycles\":0}"
+    );
+    assert!(lines[1].contains("\"disassembly\":\"LDA ($80,X)\",\"addr\":512,\"value\":90,"));
+}
+
+#[test]
+fn test_filters() {
+    let mut cpu = machine();
+    let mut tracer = Tracer::new(Nestest, Vec::new());
+    tracer.add_range(0x8004..=0x8005);
+    tracer.add_range(0x800A..=0x800A);
+    for _ in 0..6 {
+        tracer.step(&mut cpu).unwrap();
+    }
+    let log = String::from_utf8(tracer.output().clone()).unwrap();
+    let pcs: Vec<&str> = log.lines().map(|line| &line[..4]).collect();
+    assert_eq!(pcs, ["8004", "800A"]);
+
+    let mut cpu = machine();
+    let mut tracer = Tracer::new(JsonLines, Vec::new());
+    tracer.set_window(2..4);
+    for _ in 0..6 {
+        tracer.step(&mut cpu).unwrap();
+    }
+    assert_eq!(tracer.executed(), 6);
+    let log = String::from_utf8(tracer.into_output()).unwrap();
+    let indexes: Vec<&str> = log.lines().map(|line| &line[..11]).collect();
+    assert_eq!(indexes, ["{\"index\":2,", "{\"index\":3,"]);
+}
+
+#[test]
+fn test_unpeekable_bus() {
+    // The same program on a bus that cannot peek
+    let ram = machine();
+    let mut cpu = create_cpu_with_program(&[]);
+    cpu.bus.memory.copy_from_slice(&ram.bus);
+    let mut tracer = Tracer::new(Nestest, Vec::new());
+    for _ in 0..6 {
+        tracer.step(&mut cpu).unwrap();
+    }
+    let log = String::from_utf8(tracer.into_output()).unwrap();
+    let expected: Vec<String> = lines(Nestest, 6)
+        .into_iter()
+        // A store does not read the value it replaces
+        .map(|line| line.replace("@ 14 = 00", "@ 14 = ??"))
+        .collect();
+    assert_eq!(log.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn test_traced_machine() {
+    let mut machine = Traced::new(machine(), Tracer::new(Nestest, Vec::new()));
+    let mut debugger = Debugger::new();
+    debugger.set_breakpoint(0x8008);
+    assert_eq!(
+        debugger.resume(&mut machine, Resume::Continue, 100),
+        Stop::Breakpoint { pc: 0x8008 }
+    );
+    assert!(machine.take_error().is_none());
+    let (cpu, tracer) = machine.into_parts();
+    assert_eq!(cpu.registers.pc, 0x8008);
+    let log = String::from_utf8(tracer.into_output()).unwrap();
+    assert_eq!(log.lines().count(), 4);
+    assert!(log.lines().last().unwrap().starts_with("8006"));
+}
@@ -0,0 +1,222 @@
+// src/tests/tui.rs
+
+use super::create_ram_cpu_with_program;
+use crate::cpu::CPU;
+use crate::tui::{decode_keys, Key, Tui};
+
+fn machine() -> CPU<Vec<u8>> {
+    // LDX #$05; loop: DEX; BNE loop; JSR $8010; JMP $8000
+    let mut cpu = create_ram_cpu_with_program(&[
+        0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x20, 0x10, 0x80, 0x4C, 0x00, 0x80,
+    ]);
+    // LDA #$41; PHA; PLA; RTS
+    cpu.bus[0x8010..0x8015].copy_from_slice(&[0xA9, 0x41, 0x48, 0x68, 0x60]);
+    cpu
+}
+
+/// Returns the rows of a frame without escape codes.
+fn screen(frame: &str) -> Vec<String> {
+    let mut text = String::new();
+    let mut chars = frame.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text.split("\r\n").map(str::to_string).collect()
+}
+
+fn keys(tui: &mut Tui, cpu: &mut CPU<Vec<u8>>, text: &str) {
+    for key in decode_keys(text.as_bytes()) {
+        tui.handle_key(cpu, key);
+    }
+}
+
+#[test]
+fn test_decode_keys() {
+    assert_eq!(
+        decode_keys(b"s\x1b[A\x1b[B\x1b[5~\x1b[6~\x1b[21~\x1b[23;2~\x1bOP"),
+        [
+            Key::Char('s'),
+            Key::Up,
+            Key::Down,
+            Key::PageUp,
+            Key::PageDown,
+            Key::F(10),
+            Key::ShiftF(11),
+            Key::F(1),
+        ]
+    );
+    assert_eq!(
+        decode_keys(b"\r\x7f\x03\x1b"),
+        [Key::Enter, Key::Backspace, Key::Interrupt, Key::Escape]
+    );
+    // Unknown sequences and control characters are dropped
+    assert_eq!(decode_keys(b"\x1b[99x\x01a"), [Key::Char('a')]);
+}
+
+#[test]
+fn test_decode_truncated_keys() {
+    // A read can end partway through an escape sequence
+    assert_eq!(decode_keys(b"\x1b["), []);
+    assert_eq!(decode_keys(b"\x1bO"), []);
+    assert_eq!(decode_keys(b"a\x1b[2"), [Key::Char('a')]);
+    assert_eq!(decode_keys(b"\x1b[23;"), []);
+    // Cut off after a multi-byte character
+    assert_eq!(decode_keys(b"\x1b[\xc3\xa9"), []);
+    assert_eq!(decode_keys(b"\x1bO\xc3"), []);
+}
+
+#[test]
+fn test_layout() {
+    let mut cpu = machine();
+    let mut tui = Tui::new();
+    tui.monitor_mut().add_label("sub", 0x8010);
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert_eq!(rows.len(), 24);
+    assert!(rows[1..].iter().all(|row| row.chars().count() == 80));
+    assert!(rows[0].starts_with(" lib6502  stopped"));
+    assert!(rows[0].ends_with("cycles 0 "));
+    assert!(rows[1].contains("Disassembly") && rows[1].contains("Registers"));
+    assert!(rows[2].trim_end().ends_with("| PC 8000    SP FD"));
+    // The PC line comes after a few lines of context
+    assert!(rows[5].starts_with("  8000  A2 05     LDX #$05"));
+    assert!(rows.iter().any(|row| row.contains("JSR sub")));
+    assert!(rows
+        .iter()
+        .any(|row| row.trim_end().ends_with("| NV-BDIZC")));
+    assert!(rows.iter().any(|row| row.contains("Memory")));
+    assert!(rows.iter().any(|row| row.contains(" 0000  00 00 00")));
+    assert!(rows
+        .iter()
+        .any(|row| row.trim_end().ends_with("| 01FE  00")));
+    assert!(rows[22].starts_with("s step  n next"));
+
+    cpu.registers.sp = 0xFF;
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows.iter().any(|row| row.trim_end().ends_with("| (empty)")));
+    cpu.registers.sp = 0xFD;
+
+    // Narrow terminals still get whole rows
+    let rows = screen(&tui.render(&cpu, 40, 10));
+    assert_eq!(rows.len(), 10);
+    assert!(rows.iter().all(|row| row.chars().count() == 40));
+
+    // Stepping into the subroutine shows what it pushed
+    keys(&mut tui, &mut cpu, "n");
+    cpu.registers.pc = 0x8005;
+    keys(&mut tui, &mut cpu, "ss");
+    // The accumulator the last step changed is highlighted
+    assert!(tui.render(&cpu, 80, 24).contains("\x1b[1;33m41"));
+    keys(&mut tui, &mut cpu, "s");
+    assert_eq!(cpu.registers.pc, 0x8013);
+    assert!(!tui.render(&cpu, 80, 24).contains("\x1b[1;33m41"));
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows
+        .iter()
+        .any(|row| row.trim_end().ends_with("| 01FB  41")));
+    assert!(rows
+        .iter()
+        .any(|row| row.trim_end().ends_with("| 01FC  07")));
+    assert!(rows
+        .iter()
+        .any(|row| row.trim_end().ends_with("| 01FD  80")));
+}
+
+#[test]
+fn test_stepping_and_breakpoints() {
+    let mut cpu = machine();
+    let mut tui = Tui::new();
+    keys(&mut tui, &mut cpu, "s");
+    assert_eq!(cpu.registers.pc, 0x8002);
+    keys(&mut tui, &mut cpu, "\x1b[21~");
+    assert_eq!(cpu.registers.pc, 0x8003);
+
+    // Move the cursor down to the JSR and break there
+    keys(&mut tui, &mut cpu, "\x1b[Bb");
+    assert!(tui.monitor().debugger().breakpoint(0x8005).is_some());
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows.iter().any(|row| row.starts_with("* 8005")));
+
+    keys(&mut tui, &mut cpu, "c");
+    assert!(tui.is_running());
+    tui.run(&mut cpu, || true);
+    assert!(!tui.is_running());
+    assert_eq!(cpu.registers.pc, 0x8005);
+    assert_eq!(cpu.registers.x, 0);
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows[0].contains("breakpoint at $8005"));
+    assert!(rows.iter().any(|row| row.contains("| * 8005  1")));
+
+    // Stepping over the call runs all of it
+    keys(&mut tui, &mut cpu, "n");
+    assert_eq!(cpu.registers.pc, 0x8008);
+    keys(&mut tui, &mut cpu, "b");
+    assert!(tui.monitor().debugger().breakpoint(0x8008).is_some());
+    keys(&mut tui, &mut cpu, "b");
+    assert!(tui.monitor().debugger().breakpoint(0x8008).is_none());
+
+    // Nothing stops a run but a pause
+    tui.monitor_mut().debugger_mut().clear_breakpoints();
+    keys(&mut tui, &mut cpu, "c");
+    tui.run(&mut cpu, || false);
+    assert!(tui.is_running());
+    keys(&mut tui, &mut cpu, "p");
+    assert!(!tui.is_running());
+    assert!(screen(&tui.render(&cpu, 80, 24))[0].contains("paused"));
+}
+
+#[test]
+fn test_prompts() {
+    let mut cpu = machine();
+    let mut tui = Tui::new();
+    keys(&mut tui, &mut cpu, ":r a=4x\x7f2");
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert_eq!(rows[23].trim_end(), ":r a=42_");
+    keys(&mut tui, &mut cpu, "\r");
+    assert_eq!(cpu.registers.a, 0x42);
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows.iter().any(|row| row.starts_with(": r a=42")));
+    assert!(rows.iter().any(|row| row.starts_with(".;8000 42 00")));
+
+    keys(&mut tui, &mut cpu, ":bogus\r");
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows.iter().any(|row| row.starts_with("? unknown command")));
+
+    keys(&mut tui, &mut cpu, "m8010\r");
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows
+        .iter()
+        .any(|row| row.starts_with(" 8010  A9 41 48 68 60")));
+    keys(&mut tui, &mut cpu, "\x1b[6~");
+    let rows = screen(&tui.render(&cpu, 80, 24));
+    assert!(rows.iter().any(|row| row.starts_with(" 8110  00")));
+
+    // Escape abandons a prompt, and keys work again after it
+    keys(&mut tui, &mut cpu, ":q\x1b");
+    assert!(!tui.is_done());
+    keys(&mut tui, &mut cpu, "q");
+    assert!(tui.is_done());
+}
+
+#[test]
+fn test_breakpoint_at_end_of_frame() {
+    let mut cpu = create_ram_cpu_with_program(&[0xEA; 0x3000]);
+    let mut tui = Tui::new();
+    tui.monitor_mut().debugger_mut().set_breakpoint(0xA710);
+    keys(&mut tui, &mut cpu, "c");
+    // The first slice ends at the breakpoint, and a frame is drawn there
+    tui.run(&mut cpu, || false);
+    assert!(tui.is_running());
+    assert_eq!(cpu.registers.pc, 0xA710);
+    tui.run(&mut cpu, || true);
+    assert!(!tui.is_running());
+    assert_eq!(cpu.registers.pc, 0xA710);
+    assert!(screen(&tui.render(&cpu, 80, 24))[0].contains("breakpoint at $A710"));
+}
@@ -0,0 +1,327 @@
+// src/tests/vice.rs
+
+use super::create_ram_cpu_with_program;
+use crate::cpu::CPU;
+use crate::vice::ViceMonitor;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+const EVENT: u32 = 0xFFFF_FFFF;
+
+/// A response or event from the monitor.
+#[derive(Debug)]
+struct Message {
+    kind: u8,
+    error: u8,
+    id: u32,
+    body: Vec<u8>,
+}
+
+/// A minimal protocol client, standing in for a VICE tool.
+struct Client {
+    stream: TcpStream,
+    next_id: u32,
+    events: VecDeque<Message>,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Self {
+            stream,
+            next_id: 1,
+            events: VecDeque::new(),
+        }
+    }
+
+    fn read_message(&mut self) -> Message {
+        let mut header = [0; 12];
+        self.stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[..2], [0x02, 0x02]);
+        let len = u32::from_le_bytes(header[2..6].try_into().unwrap());
+        let mut body = vec![0; len as usize];
+        self.stream.read_exact(&mut body).unwrap();
+        Message {
+            kind: header[6],
+            error: header[7],
+            id: u32::from_le_bytes(header[8..12].try_into().unwrap()),
+            body,
+        }
+    }
+
+    /// Sends a command and returns its response, queueing the events and
+    /// extra responses that come before it.
+    fn request(&mut self, command: u8, body: &[u8]) -> Message {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut message = vec![0x02, 0x02];
+        message.extend((body.len() as u32).to_le_bytes());
+        message.extend(id.to_le_bytes());
+        message.push(command);
+        message.extend(body);
+        self.stream.write_all(&message).unwrap();
+        loop {
+            let message = self.read_message();
+            if message.id == id && message.kind == response_kind(command) {
+                return message;
+            }
+            self.events.push_back(message);
+        }
+    }
+
+    /// Returns the next event, or extra response.
+    fn event(&mut self) -> Message {
+        self.events
+            .pop_front()
+            .unwrap_or_else(|| self.read_message())
+    }
+
+    /// Checks that the next events announce a stop at `pc`.
+    fn expect_stop(&mut self, pc: u16) {
+        let registers = self.event();
+        assert_eq!((registers.kind, registers.id), (0x31, EVENT));
+        let stopped = self.event();
+        assert_eq!((stopped.kind, stopped.id), (0x62, EVENT));
+        assert_eq!(stopped.body, pc.to_le_bytes());
+    }
+
+    /// Stops the machine with a ping.
+    fn stop(&mut self) -> u16 {
+        assert_eq!(self.request(0x81, &[]).error, 0);
+        let registers = self.event();
+        assert_eq!(registers.kind, 0x31);
+        let stopped = self.event();
+        assert_eq!(stopped.kind, 0x62);
+        u16::from_le_bytes([stopped.body[0], stopped.body[1]])
+    }
+
+    /// Resumes the machine.
+    fn exit(&mut self) {
+        assert_eq!(self.request(0xAA, &[]).error, 0);
+        assert_eq!(self.event().kind, 0x63);
+    }
+
+    fn set_checkpoint(&mut self, start: u16, end: u16, stop: bool, operation: u8) -> u32 {
+        let mut body = start.to_le_bytes().to_vec();
+        body.extend(end.to_le_bytes());
+        body.extend([stop as u8, 1, operation, 0]);
+        let response = self.request(0x12, &body);
+        assert_eq!((response.kind, response.error), (0x11, 0));
+        u32::from_le_bytes(response.body[..4].try_into().unwrap())
+    }
+
+    fn registers(&mut self) -> Vec<(u8, u16)> {
+        let response = self.request(0x31, &[0]);
+        parse_registers(&response.body)
+    }
+}
+
+/// Returns the response type of a command.
+fn response_kind(command: u8) -> u8 {
+    match command {
+        0x12 => 0x11,
+        0x32 => 0x31,
+        other => other,
+    }
+}
+
+fn parse_registers(body: &[u8]) -> Vec<(u8, u16)> {
+    let count = u16::from_le_bytes([body[0], body[1]]) as usize;
+    body[2..]
+        .chunks(4)
+        .take(count)
+        .map(|item| (item[1], u16::from_le_bytes([item[2], item[3]])))
+        .collect()
+}
+
+fn memory_args(start: u16, end: u16) -> Vec<u8> {
+    let mut body = vec![0];
+    body.extend(start.to_le_bytes());
+    body.extend(end.to_le_bytes());
+    body.extend([0, 0, 0]);
+    body
+}
+
+/// A loop that stores to $0300: LDA #$01; STA $0300; JMP $8000
+const STORE_LOOP: [u8; 8] = [0xA9, 0x01, 0x8D, 0x00, 0x03, 0x4C, 0x00, 0x80];
+
+/// Serves `cpu` to a client running `script` on another thread.
+fn session<F: FnOnce(&mut Client) + Send + 'static>(
+    monitor: &mut ViceMonitor,
+    cpu: &mut CPU<Vec<u8>>,
+    script: F,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || script(&mut Client::connect(addr)));
+    let (stream, _) = listener.accept().unwrap();
+    monitor.serve(cpu, stream).unwrap();
+    client.join().unwrap();
+}
+
+#[test]
+fn test_memory_and_registers() {
+    let mut cpu = create_ram_cpu_with_program(&STORE_LOOP);
+    session(&mut ViceMonitor::new(), &mut cpu, |client| {
+        let pc = client.stop();
+        assert!((0x8000..0x8008).contains(&pc));
+
+        let mut body = memory_args(0x0200, 0x0202);
+        body.extend([1, 2, 3]);
+        assert_eq!(client.request(0x02, &body).error, 0);
+        let response = client.request(0x01, &memory_args(0x01FF, 0x0203));
+        assert_eq!(response.body, [5, 0, 0, 1, 2, 3, 0]);
+
+        // A=$42, PC=$8002
+        let body = [0, 2, 0, 3, 0x00, 0x42, 0x00, 3, 0x03, 0x02, 0x80];
+        let response = client.request(0x32, &body);
+        assert_eq!(response.kind, 0x31);
+        assert_eq!(parse_registers(&response.body)[0], (0x00, 0x42));
+        let registers = client.registers();
+        assert_eq!(registers.len(), 6);
+        assert_eq!(registers[3], (0x03, 0x8002));
+        assert_eq!(registers[5], (0x05, 0x20));
+
+        let response = client.request(0x83, &[0]);
+        assert_eq!(response.body[..2], [6, 0]);
+        assert_eq!(response.body[2..7], [4, 0x00, 8, 1, b'A']);
+        let response = client.request(0x82, &[]);
+        assert_eq!(response.body, [1, 0, 6, 0, 0, 3, b'c', b'p', b'u']);
+
+        assert_eq!(client.request(0x01, &[0, 0, 0, 1, 0, 1, 0, 0]).error, 0x02);
+        assert_eq!(client.request(0x01, &[0]).error, 0x80);
+        assert_eq!(client.request(0xDD, &[]).error, 0x83);
+        assert_eq!(client.request(0xBB, &[]).error, 0);
+    });
+    assert_eq!(cpu.bus[0x0200..0x0203], [1, 2, 3]);
+    assert_eq!(cpu.registers.a, 0x42);
+}
+
+#[test]
+fn test_checkpoints() {
+    let mut cpu = create_ram_cpu_with_program(&STORE_LOOP);
+    session(&mut ViceMonitor::new(), &mut cpu, |client| {
+        client.stop();
+        let exec = client.set_checkpoint(0x8002, 0x8002, true, 0x04);
+        assert_eq!(exec, 1);
+        client.exit();
+        let info = client.event();
+        assert_eq!((info.kind, info.id), (0x11, EVENT));
+        assert_eq!(info.body[..5], [1, 0, 0, 0, 1]);
+        assert_eq!(info.body[13..17], 1u32.to_le_bytes());
+        client.expect_stop(0x8002);
+
+        // A store checkpoint stops after the instruction
+        let store = client.set_checkpoint(0x0300, 0x0300, true, 0x02);
+        assert_eq!(client.request(0x13, &exec.to_le_bytes()).error, 0);
+        assert_eq!(client.request(0x13, &exec.to_le_bytes()).error, 0x01);
+        client.exit();
+        assert_eq!(client.event().kind, 0x11);
+        client.expect_stop(0x8005);
+
+        // A condition that never holds, then a tracing checkpoint
+        let mut body = store.to_le_bytes().to_vec();
+        body.push(6);
+        body.extend(b"a == 2");
+        assert_eq!(client.request(0x22, &body).error, 0);
+        let trace = client.set_checkpoint(0x8000, 0x8000, false, 0x04);
+        client.exit();
+        assert_eq!(client.event().body[..4], trace.to_le_bytes());
+        assert_eq!(client.event().body[..4], trace.to_le_bytes());
+        let pc = client.stop();
+        assert!((0x8000..0x8008).contains(&pc));
+
+        assert_eq!(client.request(0x15, &[2, 0, 0, 0, 0]).error, 0);
+        let response = client.request(0x14, &[]);
+        assert_eq!(response.body, 2u32.to_le_bytes());
+        let listed: Vec<_> = (0..2).map(|_| client.event()).collect();
+        assert_eq!(listed[0].id, response.id);
+        assert_eq!(listed[0].body[10], 0, "disabled");
+        assert_eq!(listed[0].body[21], 1, "has a condition");
+        assert_eq!(listed[1].body[..4], trace.to_le_bytes());
+        assert_eq!(client.request(0x11, &[9, 0, 0, 0]).error, 0x01);
+        assert_eq!(client.request(0xBB, &[]).error, 0);
+    });
+}
+
+#[test]
+fn test_advance_and_jam() {
+    let mut cpu = create_ram_cpu_with_program(&STORE_LOOP);
+    cpu.bus[0x9000] = 0x02;
+    session(&mut ViceMonitor::new(), &mut cpu, |client| {
+        client.stop();
+        // PC=$8000
+        let body = [0, 1, 0, 3, 0x03, 0x00, 0x80];
+        assert_eq!(client.request(0x32, &body).error, 0);
+        assert_eq!(client.request(0x71, &[0, 2, 0]).error, 0);
+        client.expect_stop(0x8005);
+        assert_eq!(client.request(0x71, &[1, 1, 0]).error, 0);
+        client.expect_stop(0x8000);
+
+        let body = [0, 1, 0, 3, 0x03, 0x00, 0x90];
+        assert_eq!(client.request(0x32, &body).error, 0);
+        client.exit();
+        assert_eq!(client.event().kind, 0x31);
+        let jam = client.event();
+        assert_eq!((jam.kind, jam.body.as_slice()), (0x61, &[0x00, 0x90][..]));
+        assert_eq!(client.request(0xBB, &[]).error, 0);
+    });
+    assert_eq!(cpu.registers.pc, 0x9000);
+}
+
+#[test]
+fn test_stop_an_unfinished_step() {
+    let mut cpu = create_ram_cpu_with_program(&STORE_LOOP);
+    session(&mut ViceMonitor::new(), &mut cpu, |client| {
+        client.stop();
+        // The loop never returns, so only another command stops this
+        assert_eq!(client.request(0x73, &[]).error, 0);
+        let pc = client.stop();
+        assert!((0x8000..0x8008).contains(&pc));
+        assert_eq!(client.request(0xBB, &[]).error, 0);
+    });
+}
+
+#[test]
+fn test_checkpoint_at_end_of_slice() {
+    let mut cpu = create_ram_cpu_with_program(&[0xEA; 0x3000]);
+    session(&mut ViceMonitor::new(), &mut cpu, |client| {
+        client.stop();
+        // PC=$8000
+        let body = [0, 1, 0, 3, 0x03, 0x00, 0x80];
+        assert_eq!(client.request(0x32, &body).error, 0);
+        // The monitor polls the client every 10000 instructions, and this
+        // checkpoint is reached just as the first slice ends
+        client.set_checkpoint(0xA710, 0xA710, true, 0x04);
+        client.exit();
+        assert_eq!(client.event().kind, 0x11);
+        client.expect_stop(0xA710);
+        assert_eq!(client.request(0xBB, &[]).error, 0);
+    });
+    assert_eq!(cpu.registers.pc, 0xA710);
+}
+
+#[test]
+fn test_oversized_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let mut client = Client::connect(addr);
+        client.stop();
+        // A memory set claiming 4GB of data
+        let mut message = vec![0x02, 0x02];
+        message.extend(u32::MAX.to_le_bytes());
+        message.extend(2u32.to_le_bytes());
+        message.push(0x02);
+        client.stream.write_all(&message).unwrap();
+    });
+    let (stream, _) = listener.accept().unwrap();
+    let err = ViceMonitor::new()
+        .serve(&mut create_ram_cpu_with_program(&STORE_LOOP), stream)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    client.join().unwrap();
+}
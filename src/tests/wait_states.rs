@@ -0,0 +1,60 @@
+// src/tests/wait_states.rs
+
+use crate::adapters::{OverlayBus, WaitStateBus};
+use crate::bus::Bus;
+use crate::cpu::CPU;
+
+fn create_cpu<B: Bus>(mut bus: B, program: &[u8]) -> CPU<B> {
+    for (i, byte) in program.iter().enumerate() {
+        bus.write(0x8000 + i as u16, *byte);
+    }
+    bus.write(0xFFFC, 0x00);
+    bus.write(0xFFFD, 0x80);
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn test_no_wait_states_by_default() {
+    // LDA $6000
+    let mut cpu = create_cpu(vec![0u8; 0x10000], &[0xAD, 0x00, 0x60]);
+    cpu.step();
+    assert_eq!(cpu.cycles(), 4);
+}
+
+#[test]
+fn test_wait_states_on_slow_device() {
+    // LDA $6000 ; STA $6001 ; LDA $0200
+    let program = [0xAD, 0x00, 0x60, 0x8D, 0x01, 0x60, 0xAD, 0x00, 0x02];
+    let bus = WaitStateBus::with_read_write(vec![0u8; 0x10000], 0x6000..=0x60FF, 1, 3);
+    let mut cpu = create_cpu(bus, &program);
+
+    cpu.step();
+    assert_eq!(cpu.cycles(), 4 + 1);
+    cpu.step();
+    assert_eq!(cpu.cycles(), 4 + 1 + 4 + 3);
+    cpu.step();
+    assert_eq!(cpu.cycles(), 4 + 1 + 4 + 3 + 4);
+}
+
+#[test]
+fn test_wait_states_on_instruction_fetch() {
+    // Two NOPs fetched from a ROM that costs one wait state per read. Each
+    // also reads the byte after it.
+    let bus = WaitStateBus::new(vec![0u8; 0x10000], 0x8000..=0xFFFF, 1);
+    let mut cpu = create_cpu(bus, &[0xEA, 0xEA]);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.cycles(), 2 * (2 + 2));
+}
+
+#[test]
+fn test_wait_states_through_overlay() {
+    let device = WaitStateBus::new(vec![0u8; 0x10000], 0x0000..=0xFFFF, 2);
+    let bus = OverlayBus::new(vec![0u8; 0x10000], 0x6000..=0x6000, device);
+    // LDA $6000
+    let mut cpu = create_cpu(bus, &[0xAD, 0x00, 0x60]);
+    cpu.step();
+    assert_eq!(cpu.cycles(), 4 + 2);
+}
@@ -0,0 +1,316 @@
+//! The `throttle` module paces a `CPU` against wall-clock time.
+//!
+//! A `Throttle` runs instructions at a target clock frequency, such as 1 MHz
+//! for a Ben Eater breadboard computer or an Apple I. It sleeps when the CPU
+//! gets ahead of the wall clock and runs flat out when it falls behind, for
+//! example after the host was busy. If it falls too far behind, the debt is
+//! dropped instead of running a long burst at full speed.
+//!
+//! Like Ben Eater's clock module, a throttle can also run at very slow
+//! frequencies such as 1 Hz, or be switched to manual mode where instructions
+//! only execute when `single_step` is called.
+//!
+//! ```no_run
+//! use lib6502::cpu::CPU;
+//! use lib6502::throttle::Throttle;
+//! use std::time::Duration;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! cpu.reset();
+//! let mut throttle = Throttle::new(1_000_000);
+//! loop {
+//!     // One 60 Hz frame's worth of 1 MHz execution
+//!     throttle.run_for(&mut cpu, Duration::from_micros(16_667));
+//!     // ... update the display here ...
+//! }
+//! ```
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::run::{RunResult, StopReason};
+use std::time::{Duration, Instant};
+
+/// A source of wall-clock time for a `Throttle`.
+///
+/// `StdTime` is the real implementation. Tests can supply a fake one.
+pub trait TimeSource {
+    /// Returns the time elapsed since some fixed starting point.
+    fn now(&mut self) -> Duration;
+
+    /// Blocks for the given duration.
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The host's monotonic clock.
+pub struct StdTime {
+    origin: Instant,
+}
+
+impl StdTime {
+    /// Creates a new `StdTime` starting from the current instant.
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for StdTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for StdTime {
+    fn now(&mut self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// How a `Throttle` paces execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMode {
+    /// Run at the given frequency in Hz.
+    Throttled(u64),
+    /// Run as fast as the host allows.
+    Unthrottled,
+    /// Only execute instructions through `Throttle::single_step`.
+    Manual,
+}
+
+/// Panics if `mode` is throttled to zero Hz, which cannot be paced.
+fn check_mode(mode: ClockMode) {
+    if let ClockMode::Throttled(frequency_hz) = mode {
+        assert!(frequency_hz > 0, "frequency must be non-zero");
+    }
+}
+
+/// Paces a `CPU` to a target clock frequency.
+pub struct Throttle<T: TimeSource = StdTime> {
+    time: T,
+    mode: ClockMode,
+    /// The wall-clock time at which `base_cycles` was reached.
+    base_time: Duration,
+    /// The CPU cycle count at `base_time`.
+    base_cycles: u64,
+    /// Set when the timeline must be re-anchored to the CPU before the next run.
+    needs_resync: bool,
+    /// How far behind the throttle may fall before it stops catching up.
+    max_lag: Duration,
+    /// How far ahead the CPU must get before the throttle sleeps.
+    min_sleep: Duration,
+}
+
+impl Throttle<StdTime> {
+    /// Creates a new `Throttle` running at `frequency_hz` against the host clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frequency_hz` is zero.
+    pub fn new(frequency_hz: u64) -> Self {
+        Self::with_time_source(ClockMode::Throttled(frequency_hz), StdTime::new())
+    }
+}
+
+impl<T: TimeSource> Throttle<T> {
+    /// Creates a new `Throttle` with the given mode and time source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mode` is throttled to zero Hz.
+    pub fn with_time_source(mode: ClockMode, time: T) -> Self {
+        check_mode(mode);
+        Self {
+            time,
+            mode,
+            base_time: Duration::ZERO,
+            base_cycles: 0,
+            needs_resync: true,
+            max_lag: Duration::from_millis(100),
+            min_sleep: Duration::from_millis(1),
+        }
+    }
+
+    /// Returns the current clock mode.
+    pub fn mode(&self) -> ClockMode {
+        self.mode
+    }
+
+    /// Changes the clock mode. Pacing restarts from the current instant.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mode` is throttled to zero Hz.
+    pub fn set_mode(&mut self, mode: ClockMode) {
+        check_mode(mode);
+        self.mode = mode;
+        self.needs_resync = true;
+    }
+
+    /// Changes the target frequency and switches to throttled mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frequency_hz` is zero.
+    pub fn set_frequency(&mut self, frequency_hz: u64) {
+        self.set_mode(ClockMode::Throttled(frequency_hz));
+    }
+
+    /// Sets how far behind real time the throttle may fall before it gives up
+    /// catching up. The default is 100ms.
+    pub fn set_max_lag(&mut self, max_lag: Duration) {
+        self.max_lag = max_lag;
+    }
+
+    /// Sets the smallest lead over real time worth sleeping for. The default
+    /// is 1ms, which keeps the number of sleeps per second low at MHz speeds.
+    pub fn set_min_sleep(&mut self, min_sleep: Duration) {
+        self.min_sleep = min_sleep;
+    }
+
+    /// Forgets any lead or lag, so pacing restarts from the current instant.
+    pub fn resync(&mut self) {
+        self.needs_resync = true;
+    }
+
+    /// Returns a mutable reference to the time source.
+    pub fn time_source_mut(&mut self) -> &mut T {
+        &mut self.time
+    }
+
+    /// Executes one instruction immediately, whatever the mode.
+    ///
+    /// This is the equivalent of the step button on Ben Eater's clock module,
+    /// at instruction rather than cycle granularity.
+    pub fn single_step<B: Bus>(&mut self, cpu: &mut CPU<B>) -> RunResult {
+        let start = cpu.cycles();
+        cpu.step();
+        self.needs_resync = true;
+        RunResult {
+            reason: StopReason::InstructionsExecuted,
+            cycles: cpu.cycles() - start,
+            instructions: 1,
+        }
+    }
+
+    /// Executes one instruction once it is due in real time.
+    ///
+    /// In manual mode nothing is executed and no time passes.
+    pub fn step<B: Bus>(&mut self, cpu: &mut CPU<B>) -> RunResult {
+        let start = cpu.cycles();
+        let mut instructions = 0;
+        if self.mode != ClockMode::Manual {
+            self.pace(cpu.cycles(), None);
+            cpu.step();
+            instructions = 1;
+        }
+        RunResult {
+            reason: StopReason::InstructionsExecuted,
+            cycles: cpu.cycles() - start,
+            instructions,
+        }
+    }
+
+    /// Runs paced instructions for `duration` of wall-clock time.
+    ///
+    /// In manual mode this just waits for `duration`.
+    pub fn run_for<B: Bus>(&mut self, cpu: &mut CPU<B>, duration: Duration) -> RunResult {
+        let deadline = self.time.now() + duration;
+        self.run_until_deadline(cpu, deadline, |_| false)
+    }
+
+    /// Runs paced instructions until `predicate` returns `true` or `duration`
+    /// of wall-clock time has passed, whichever comes first.
+    ///
+    /// The predicate is checked before each instruction.
+    pub fn run_until<B: Bus, F: FnMut(&CPU<B>) -> bool>(
+        &mut self,
+        cpu: &mut CPU<B>,
+        duration: Duration,
+        predicate: F,
+    ) -> RunResult {
+        let deadline = self.time.now() + duration;
+        self.run_until_deadline(cpu, deadline, predicate)
+    }
+
+    /// The shared loop behind `run_for` and `run_until`.
+    fn run_until_deadline<B: Bus, F: FnMut(&CPU<B>) -> bool>(
+        &mut self,
+        cpu: &mut CPU<B>,
+        deadline: Duration,
+        mut predicate: F,
+    ) -> RunResult {
+        let start = cpu.cycles();
+        let mut instructions = 0;
+        let reason = loop {
+            if predicate(cpu) {
+                break StopReason::Predicate;
+            }
+            if self.mode == ClockMode::Manual {
+                let now = self.time.now();
+                if now < deadline {
+                    self.time.sleep(deadline - now);
+                }
+                break StopReason::TimeElapsed;
+            }
+            if !self.pace(cpu.cycles(), Some(deadline)) {
+                break StopReason::TimeElapsed;
+            }
+            cpu.step();
+            instructions += 1;
+        };
+        RunResult {
+            reason,
+            cycles: cpu.cycles() - start,
+            instructions,
+        }
+    }
+
+    /// Waits until the instruction starting at `cycles` is due.
+    ///
+    /// Returns `false` without waiting past `deadline` if the instruction is
+    /// not due before it.
+    fn pace(&mut self, cycles: u64, deadline: Option<Duration>) -> bool {
+        let now = self.time.now();
+        if self.needs_resync {
+            self.rebase(now, cycles);
+        }
+        let frequency = match self.mode {
+            ClockMode::Throttled(frequency) => frequency,
+            _ => return deadline.is_none_or(|deadline| now < deadline),
+        };
+        let elapsed = cycles.saturating_sub(self.base_cycles) as u128;
+        let mut due = self.base_time
+            + Duration::from_nanos((elapsed * 1_000_000_000 / frequency as u128) as u64);
+        if now > due + self.max_lag {
+            // Too far behind to catch up, so drop the debt
+            self.rebase(now, cycles);
+            due = now;
+        }
+        if let Some(deadline) = deadline {
+            if due >= deadline {
+                if now < deadline {
+                    self.time.sleep(deadline - now);
+                }
+                return false;
+            }
+        }
+        // Small leads are allowed to build up during a run so the host is not
+        // asked to sleep for a few microseconds at a time
+        if due > now && (deadline.is_none() || due - now >= self.min_sleep) {
+            self.time.sleep(due - now);
+        }
+        true
+    }
+
+    /// Anchors the timeline so that `cycles` is due at `now`.
+    fn rebase(&mut self, now: Duration, cycles: u64) {
+        self.base_time = now;
+        self.base_cycles = cycles;
+        self.needs_resync = false;
+    }
+}
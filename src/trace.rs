@@ -0,0 +1,552 @@
+//! The `trace` module records every instruction a CPU executes.
+//!
+//! A `Tracer` looks at the CPU before each instruction and, if the
+//! instruction passes its filters, writes a `TraceRecord` in some
+//! `TraceFormat`. Three formats are provided: `Nestest`, the log format of
+//! Nintendulator that the nestest ROM's reference log uses, `Vice`, the
+//! format of VICE's CPU history, and `JsonLines`, for other tools.
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::trace::{Nestest, Tracer};
+//!
+//! let mut cpu = CPU::new(vec![0u8; 0x10000]);
+//! // LDA #$42; STA $0200
+//! cpu.bus[0x8000..0x8005].copy_from_slice(&[0xA9, 0x42, 0x8D, 0x00, 0x02]);
+//! cpu.registers.pc = 0x8000;
+//! let mut tracer = Tracer::new(Nestest, Vec::new());
+//! tracer.step(&mut cpu).unwrap();
+//! tracer.step(&mut cpu).unwrap();
+//! let log = String::from_utf8(tracer.into_output()).unwrap();
+//! assert_eq!(
+//!     log.lines().nth(1),
+//!     Some("8002  8D 00 02  STA $0200 = 00                  A:42 X:00 Y:00 P:20 SP:FD CYC:2")
+//! );
+//! ```
+//!
+//! To trace a machine driven by something else, such as a `Debugger` or a
+//! frontend, wrap it in a `Traced`, which traces every instruction the
+//! machine executes.
+//!
+//! Records are made with `Bus::peek`. Where the bus cannot peek the
+//! opcode, the record is made from the bus accesses the instruction made
+//! once it has executed instead, and values it did not read are unknown.
+
+use crate::bus::{AccessKind, Bus, BusAccess};
+use crate::cpu::CPU;
+use crate::debugger::Machine;
+use crate::disasm::{disassemble, is_documented, Disassembled, Mode};
+use crate::registers::Registers;
+use std::io::{self, Write};
+use std::ops::{Range, RangeInclusive};
+
+/// JSR and JMP absolute jump to their operand rather than accessing it.
+const JSR: u8 = 0x20;
+const JMP: u8 = 0x4C;
+
+/// The memory an instruction accesses, as it was before the instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effective {
+    /// For the indirect modes, the address the pointer was read from.
+    pub pointer: Option<u16>,
+    /// The effective address: the data accessed, or the target of an
+    /// indirect `JMP`.
+    pub addr: u16,
+    /// The byte at the effective address, if it could be peeked. `None` for
+    /// an indirect `JMP`.
+    pub value: Option<u8>,
+}
+
+/// One executed instruction, as seen before it executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The number of instructions executed before this one since the
+    /// tracer was created, whether traced or not.
+    pub index: u64,
+    /// The instruction, with its address and bytes.
+    pub instruction: Disassembled,
+    /// The memory the instruction accesses, for the modes that access any.
+    pub effective: Option<Effective>,
+    /// The registers.
+    pub registers: Registers,
+    /// The cycle count.
+    pub cycles: u64,
+}
+
+impl TraceRecord {
+    /// Captures the instruction the CPU is about to execute.
+    ///
+    /// # Returns
+    ///
+    /// The record, or `None` if the opcode cannot be peeked.
+    pub fn capture<B: Bus>(cpu: &CPU<B>, index: u64) -> Option<Self> {
+        let instruction = disassemble(&cpu.bus, cpu.registers.pc)?;
+        let effective = effective(&cpu.bus, &cpu.registers, &instruction);
+        Some(Self {
+            index,
+            instruction,
+            effective,
+            registers: cpu.registers,
+            cycles: cpu.cycles(),
+        })
+    }
+}
+
+/// Works out the memory an instruction accesses, with `Bus::peek`.
+fn effective<B: Bus + ?Sized>(
+    bus: &B,
+    registers: &Registers,
+    instruction: &Disassembled,
+) -> Option<Effective> {
+    let (x, y) = (registers.x, registers.y);
+    let operand = instruction.operand?;
+    // Pointers in the zero page wrap around within it
+    let zero_page_word = |ptr: u8| {
+        let lo = bus.peek(ptr as u16)?;
+        let hi = bus.peek(ptr.wrapping_add(1) as u16)?;
+        Some(u16::from_le_bytes([lo, hi]))
+    };
+    let data = |addr: u16, pointer: Option<u16>| {
+        Some(Effective {
+            pointer,
+            addr,
+            value: bus.peek(addr),
+        })
+    };
+    match instruction.mode? {
+        Mode::Implied | Mode::Accumulator | Mode::Immediate | Mode::Relative => None,
+        Mode::Absolute if matches!(instruction.bytes[0], JSR | JMP) => None,
+        Mode::ZeroPage | Mode::Absolute => data(operand, None),
+        Mode::ZeroPageX => data((operand as u8).wrapping_add(x) as u16, None),
+        Mode::ZeroPageY => data((operand as u8).wrapping_add(y) as u16, None),
+        Mode::AbsoluteX => data(operand.wrapping_add(x as u16), None),
+        Mode::AbsoluteY => data(operand.wrapping_add(y as u16), None),
+        Mode::Indirect => {
+            // The high byte comes from the same page, as on the real chip
+            let lo = bus.peek(operand)?;
+            let hi = bus.peek((operand & 0xFF00) | (operand.wrapping_add(1) & 0x00FF))?;
+            Some(Effective {
+                pointer: Some(operand),
+                addr: u16::from_le_bytes([lo, hi]),
+                value: None,
+            })
+        }
+        Mode::IndirectX => {
+            let pointer = (operand as u8).wrapping_add(x);
+            data(zero_page_word(pointer)?, Some(pointer as u16))
+        }
+        Mode::IndirectY => {
+            let base = zero_page_word(operand as u8)?;
+            data(base.wrapping_add(y as u16), Some(operand))
+        }
+    }
+}
+
+/// The bytes an instruction read, as a bus that can peek only those.
+///
+/// The first read of each address is the value it had before the
+/// instruction, since nothing writes before reading the same address.
+struct Fetched<'a>(&'a [BusAccess]);
+
+impl Bus for Fetched<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr).unwrap_or(0)
+    }
+
+    fn write(&mut self, _addr: u16, _data: u8) {}
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.0
+            .iter()
+            .find(|access| access.addr == addr && access.kind == AccessKind::Read)
+            .map(|access| access.data)
+    }
+}
+
+/// An instruction whose opcode could not be peeked, waiting to execute.
+struct Pending {
+    index: u64,
+    registers: Registers,
+    cycles: u64,
+}
+
+/// A way of writing trace records.
+pub trait TraceFormat {
+    /// Writes one record, as a line.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error writing to `output`.
+    fn write(&mut self, record: &TraceRecord, output: &mut dyn Write) -> io::Result<()>;
+}
+
+/// The Nintendulator log format, as in the nestest reference log.
+///
+/// Memory operands show the value before the instruction, as in
+/// `STA $0200 = 00`, and indexed and indirect ones the address worked out,
+/// as in `LDA ($80),Y = 0200 @ 0204 = 5A`. There is no PPU, so the PPU
+/// column of the reference log is left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nestest;
+
+impl TraceFormat for Nestest {
+    fn write(&mut self, record: &TraceRecord, output: &mut dyn Write) -> io::Result<()> {
+        let instruction = &record.instruction;
+        let registers = &record.registers;
+        let value = |value: Option<u8>| value.map_or("??".to_string(), |v| format!("{v:02X}"));
+        let mut text = instruction.to_string();
+        if let (Some(mode), Some(effective)) = (instruction.mode, record.effective) {
+            let addr = effective.addr;
+            let pointer = effective.pointer.unwrap_or_default();
+            let suffix = match mode {
+                Mode::ZeroPage | Mode::Absolute => format!(" = {}", value(effective.value)),
+                Mode::ZeroPageX | Mode::ZeroPageY => {
+                    format!(" @ {addr:02X} = {}", value(effective.value))
+                }
+                Mode::AbsoluteX | Mode::AbsoluteY => {
+                    format!(" @ {addr:04X} = {}", value(effective.value))
+                }
+                Mode::Indirect => format!(" = {addr:04X}"),
+                Mode::IndirectX => {
+                    format!(" @ {pointer:02X} = {addr:04X} = {}", value(effective.value))
+                }
+                Mode::IndirectY => format!(
+                    " = {:04X} @ {addr:04X} = {}",
+                    addr.wrapping_sub(registers.y as u16),
+                    value(effective.value)
+                ),
+                _ => String::new(),
+            };
+            text.push_str(&suffix);
+        }
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        // Nintendulator marks undocumented opcodes with a star
+        let marker = if is_documented(instruction.bytes[0]) {
+            ' '
+        } else {
+            '*'
+        };
+        writeln!(
+            output,
+            "{:04X}  {:<9}{marker}{text:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            instruction.addr,
+            bytes.join(" "),
+            registers.a,
+            registers.x,
+            registers.y,
+            registers.status.to_byte(),
+            registers.sp,
+            record.cycles
+        )
+    }
+}
+
+/// The format of VICE's CPU history, as shown by its monitor's `chis`
+/// command.
+///
+/// The flags are shown as letters, with a dot for each clear one, and the
+/// effective address is not shown.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vice;
+
+impl TraceFormat for Vice {
+    fn write(&mut self, record: &TraceRecord, output: &mut dyn Write) -> io::Result<()> {
+        let instruction = &record.instruction;
+        let registers = &record.registers;
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let status = registers.status.to_byte();
+        let flags: String = "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(index, name)| {
+                if name == '-' || status & (0x80 >> index) != 0 {
+                    name
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            output,
+            ".C:{:04x}  {:<12}{:<15}- A:{:02X} X:{:02X} Y:{:02X} SP:{:02x} {flags} {:>10}",
+            instruction.addr,
+            bytes.join(" "),
+            instruction.to_string(),
+            registers.a,
+            registers.x,
+            registers.y,
+            registers.sp,
+            record.cycles
+        )
+    }
+}
+
+/// JSON Lines: one JSON object per instruction.
+///
+/// Each object has `index`, `pc`, `bytes`, `disassembly`, `a`, `x`, `y`,
+/// `sp`, `p` and `cycles`, and `addr` and `value` for instructions that
+/// access memory, all numbers but the disassembly. A value that cannot be
+/// peeked is `null`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLines;
+
+impl TraceFormat for JsonLines {
+    fn write(&mut self, record: &TraceRecord, output: &mut dyn Write) -> io::Result<()> {
+        let instruction = &record.instruction;
+        let registers = &record.registers;
+        let bytes: Vec<String> = instruction.bytes.iter().map(u8::to_string).collect();
+        let mut line = format!(
+            "{{\"index\":{},\"pc\":{},\"bytes\":[{}],\"disassembly\":\"{instruction}\"",
+            record.index,
+            instruction.addr,
+            bytes.join(",")
+        );
+        if let Some(effective) = record.effective {
+            let value = effective
+                .value
+                .map_or("null".to_string(), |v| v.to_string());
+            line.push_str(&format!(",\"addr\":{},\"value\":{value}", effective.addr));
+        }
+        writeln!(
+            output,
+            "{line},\"a\":{},\"x\":{},\"y\":{},\"sp\":{},\"p\":{},\"cycles\":{}}}",
+            registers.a,
+            registers.x,
+            registers.y,
+            registers.sp,
+            registers.status.to_byte(),
+            record.cycles
+        )
+    }
+}
+
+/// Writes a trace of the instructions a CPU executes.
+pub struct Tracer<W: Write> {
+    format: Box<dyn TraceFormat>,
+    output: W,
+    /// Only instructions at these addresses are traced, if there are any.
+    ranges: Vec<RangeInclusive<u16>>,
+    /// Only instructions with an index in this window are traced.
+    window: Range<u64>,
+    executed: u64,
+    pending: Option<Pending>,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Creates a new `Tracer` that traces every instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - How to write each record.
+    /// * `output` - Where to write them.
+    pub fn new<F: TraceFormat + 'static>(format: F, output: W) -> Self {
+        Self {
+            format: Box::new(format),
+            output,
+            ranges: Vec::new(),
+            window: 0..u64::MAX,
+            executed: 0,
+            pending: None,
+        }
+    }
+
+    /// Only traces instructions in `range` and any other ranges added.
+    pub fn add_range(&mut self, range: RangeInclusive<u16>) {
+        self.ranges.push(range);
+    }
+
+    /// Only traces the instructions whose index, counting from 0 for the
+    /// first instruction seen, is in `window`.
+    pub fn set_window(&mut self, window: Range<u64>) {
+        self.window = window;
+    }
+
+    /// Returns the number of instructions seen, traced or not.
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Returns the output.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Returns the output mutably.
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Consumes the tracer, returning its output.
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// Traces the instruction the CPU is about to execute, if it passes the
+    /// filters. Call this before every instruction.
+    ///
+    /// If the opcode cannot be peeked, the instruction is traced by
+    /// `finish` once it has executed instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error writing the output.
+    pub fn trace<B: Bus>(&mut self, cpu: &CPU<B>) -> io::Result<()> {
+        let index = self.executed;
+        self.executed += 1;
+        self.pending = None;
+        let pc = cpu.registers.pc;
+        let wanted = self.window.contains(&index)
+            && (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc)));
+        if !wanted {
+            return Ok(());
+        }
+        match TraceRecord::capture(cpu, index) {
+            Some(record) => self.format.write(&record, &mut self.output),
+            None => {
+                self.pending = Some(Pending {
+                    index,
+                    registers: cpu.registers,
+                    cycles: cpu.cycles(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Traces the instruction that just executed, if `trace` could not
+    /// peek its opcode, from the bus accesses it made. Call this after
+    /// every instruction, with access recording enabled on the CPU.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error writing the output.
+    pub fn finish<B: Bus>(&mut self, cpu: &CPU<B>) -> io::Result<()> {
+        let Some(pending) = self.pending.take() else {
+            return Ok(());
+        };
+        let fetched = Fetched(cpu.accesses());
+        let Some(instruction) = disassemble(&fetched, pending.registers.pc) else {
+            return Ok(());
+        };
+        let record = TraceRecord {
+            index: pending.index,
+            effective: effective(&fetched, &pending.registers, &instruction),
+            instruction,
+            registers: pending.registers,
+            cycles: pending.cycles,
+        };
+        self.format.write(&record, &mut self.output)
+    }
+
+    /// Traces the next instruction and executes it.
+    ///
+    /// Access recording is enabled on the CPU if the bus cannot peek the
+    /// opcode, so that the instruction can still be traced.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error writing the output, after executing the
+    /// instruction.
+    pub fn step<B: Bus>(&mut self, cpu: &mut CPU<B>) -> io::Result<()> {
+        record_unpeekable(cpu);
+        let result = self.trace(cpu);
+        cpu.step();
+        result.and(self.finish(cpu))
+    }
+}
+
+/// A machine whose instructions are traced.
+///
+/// The trace is written before each instruction executes, or just after it
+/// if the bus cannot peek its opcode. Writing errors
+/// do not stop the machine; the first one is kept for `take_error`.
+pub struct Traced<M: Machine, W: Write> {
+    machine: M,
+    tracer: Tracer<W>,
+    error: Option<io::Error>,
+}
+
+impl<M: Machine, W: Write> Traced<M, W> {
+    /// Wraps `machine` so that `tracer` sees every instruction it executes.
+    pub fn new(machine: M, tracer: Tracer<W>) -> Self {
+        Self {
+            machine,
+            tracer,
+            error: None,
+        }
+    }
+
+    /// Returns the machine.
+    pub fn machine(&self) -> &M {
+        &self.machine
+    }
+
+    /// Returns the machine mutably.
+    pub fn machine_mut(&mut self) -> &mut M {
+        &mut self.machine
+    }
+
+    /// Returns the tracer.
+    pub fn tracer(&self) -> &Tracer<W> {
+        &self.tracer
+    }
+
+    /// Returns the tracer mutably, for example to change its filters.
+    pub fn tracer_mut(&mut self) -> &mut Tracer<W> {
+        &mut self.tracer
+    }
+
+    /// Returns the first error writing the trace since the last call, if
+    /// any.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Consumes the wrapper, returning the machine and the tracer.
+    pub fn into_parts(self) -> (M, Tracer<W>) {
+        (self.machine, self.tracer)
+    }
+}
+
+impl<M: Machine, W: Write> Machine for Traced<M, W> {
+    type Bus = M::Bus;
+
+    fn cpu(&self) -> &CPU<M::Bus> {
+        self.machine.cpu()
+    }
+
+    fn cpu_mut(&mut self) -> &mut CPU<M::Bus> {
+        self.machine.cpu_mut()
+    }
+
+    fn before_instruction(&mut self) {
+        self.machine.before_instruction();
+    }
+
+    fn execute(&mut self) {
+        record_unpeekable(self.machine.cpu_mut());
+        if let Err(err) = self.tracer.trace(self.machine.cpu()) {
+            self.error.get_or_insert(err);
+        }
+        self.machine.execute();
+        if let Err(err) = self.tracer.finish(self.machine.cpu()) {
+            self.error.get_or_insert(err);
+        }
+    }
+}
+
+/// Enables access recording if the opcode at the PC cannot be peeked, since
+/// the accesses are then the only way to see the instruction.
+fn record_unpeekable<B: Bus>(cpu: &mut CPU<B>) {
+    if cpu.bus.peek(cpu.registers.pc).is_none() && !cpu.is_recording_accesses() {
+        cpu.set_access_recording(true);
+    }
+}
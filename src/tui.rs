@@ -0,0 +1,673 @@
+//! The `tui` module implements a full-screen terminal debugger.
+//!
+//! A `Tui` draws the disassembly around the PC, the registers and flags,
+//! the stack, a memory view, the breakpoints and the output of commands as
+//! a frame of plain text and ANSI escape codes, and reacts to keys. It does
+//! no I/O itself, so the `tui6502` binary supplies the terminal:
+//!
+//! ```
+//! use lib6502::cpu::CPU;
+//! use lib6502::tui::{Key, Tui};
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! cpu.registers.pc = 0x8000;
+//! let mut tui = Tui::new();
+//! tui.handle_key(&mut cpu, Key::Char('s'));
+//! assert_eq!(cpu.registers.pc, 0x8001);
+//! let frame = tui.render(&cpu, 80, 24);
+//! assert!(frame.contains("8001  EA        NOP"));
+//! ```
+//!
+//! The keys are those of most debuggers:
+//!
+//! | Key             | Action                                      |
+//! |-----------------|---------------------------------------------|
+//! | F11, `s`        | step into                                   |
+//! | F10, `n`        | step over                                   |
+//! | Shift-F11, `o`  | step out                                    |
+//! | F5, `c`         | continue, or pause while running            |
+//! | `p`, Esc, Ctrl-C| pause                                       |
+//! | Up, Down        | move the disassembly cursor                 |
+//! | F9, `b`         | toggle a breakpoint at the cursor           |
+//! | PgUp, PgDn      | scroll the memory view                      |
+//! | `m`             | show memory at an address                   |
+//! | `:`             | enter a `Monitor` command                   |
+//! | `q`             | quit                                        |
+
+use crate::bus::Bus;
+use crate::debugger::{Machine, Resume, Stop};
+use crate::disasm::{back_up, disassemble, next_addr};
+use crate::monitor::Monitor;
+use crate::registers::Registers;
+
+/// The instructions executed by `Tui::run` between calls to its
+/// `keep_going`.
+const SLICE: u64 = 10_000;
+
+/// The most instructions a step over or out executes before giving up.
+const STEP_LIMIT: u64 = 10_000_000;
+
+/// The width of the right-hand column.
+const RIGHT_WIDTH: usize = 26;
+
+/// The rows of the memory view, the command output and the breakpoint
+/// list.
+const MEMORY_ROWS: usize = 6;
+const OUTPUT_ROWS: usize = 4;
+const BREAKPOINT_ROWS: usize = 5;
+
+/// The disassembly lines shown above the cursor.
+const CONTEXT_LINES: usize = 3;
+
+/// The output lines kept.
+const OUTPUT_LIMIT: usize = 100;
+
+/// The key help shown at the bottom of the screen.
+const HELP: &str = "s step  n next  o out  c continue  b break  m memory  : command  q quit";
+
+/// SGR parameters of the styles used.
+const PLAIN: &str = "";
+const REVERSE: &str = "7";
+const HEADER: &str = "1;4";
+const CHANGED: &str = "1;33";
+const BREAKPOINT: &str = "1;31";
+const DIM: &str = "2";
+
+/// A key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A printable character.
+    Char(char),
+    /// Enter or Return.
+    Enter,
+    /// Backspace.
+    Backspace,
+    /// Escape, on its own.
+    Escape,
+    /// Ctrl-C.
+    Interrupt,
+    /// The up arrow.
+    Up,
+    /// The down arrow.
+    Down,
+    /// Page Up.
+    PageUp,
+    /// Page Down.
+    PageDown,
+    /// A function key, F1 to F12.
+    F(u8),
+    /// Shift and a function key.
+    ShiftF(u8),
+}
+
+/// Decodes the bytes a terminal sends for key presses.
+///
+/// Unknown escape sequences are dropped.
+pub fn decode_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+    while let Some((&byte, after)) = rest.split_first() {
+        rest = after;
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            0x7F | 0x08 => Key::Backspace,
+            0x03 => Key::Interrupt,
+            0x1B => match rest {
+                [b'[' | b'O', ..] => {
+                    // CSI and SS3 sequences end with a byte from @ to ~
+                    let end = rest[1..]
+                        .iter()
+                        .position(|byte| (0x40..=0x7E).contains(byte))
+                        .map_or(rest.len(), |end| end + 2);
+                    let sequence = &rest[..end];
+                    rest = &rest[end..];
+                    match escape_key(sequence) {
+                        Some(key) => key,
+                        None => continue,
+                    }
+                }
+                _ => Key::Escape,
+            },
+            byte if byte.is_ascii_graphic() || byte == b' ' => Key::Char(byte as char),
+            _ => continue,
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// Decodes an escape sequence, without its leading Escape.
+fn escape_key(sequence: &[u8]) -> Option<Key> {
+    // A bare `[` or `O`, or a sequence cut off at the end of a read before
+    // its final byte, is dropped
+    let (&last, body) = sequence.split_last()?;
+    if body.is_empty() || !(0x40..=0x7E).contains(&last) {
+        return None;
+    }
+    let body = std::str::from_utf8(&body[1..]).ok()?;
+    let (params, shift) = match body.split_once(';') {
+        Some((params, "2")) => (params, true),
+        Some((params, _)) => (params, false),
+        None => (body, false),
+    };
+    let function = |n| if shift { Key::ShiftF(n) } else { Key::F(n) };
+    Some(match (params, last) {
+        (_, b'A') => Key::Up,
+        (_, b'B') => Key::Down,
+        (_, b'P') => function(1),
+        (_, b'Q') => function(2),
+        (_, b'R') => function(3),
+        (_, b'S') => function(4),
+        ("5", b'~') => Key::PageUp,
+        ("6", b'~') => Key::PageDown,
+        ("15", b'~') => function(5),
+        ("17", b'~') => function(6),
+        ("18", b'~') => function(7),
+        ("19", b'~') => function(8),
+        ("20", b'~') => function(9),
+        ("21", b'~') => function(10),
+        ("23", b'~') => function(11),
+        ("24", b'~') => function(12),
+        _ => return None,
+    })
+}
+
+/// What the bottom line is reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    /// A monitor command.
+    Command,
+    /// The address of the memory view.
+    Memory,
+}
+
+/// A line of the screen, as pieces of text and their SGR parameters.
+type Line = Vec<(String, &'static str)>;
+
+/// A full-screen terminal debugger.
+pub struct Tui {
+    monitor: Monitor,
+    /// The first address of the memory view.
+    memory: u16,
+    /// The address the disassembly cursor is on, if moved off the PC.
+    cursor: Option<u16>,
+    prompt: Option<(Prompt, String)>,
+    output: Vec<String>,
+    status: String,
+    /// The registers before the last step or run, to highlight changes.
+    previous: Option<Registers>,
+    running: bool,
+    done: bool,
+}
+
+impl Tui {
+    /// Creates a new `Tui` with a fresh `Monitor`.
+    pub fn new() -> Self {
+        Self::with_monitor(Monitor::new())
+    }
+
+    /// Creates a new `Tui` around an existing monitor, for example one that
+    /// has labels loaded.
+    pub fn with_monitor(monitor: Monitor) -> Self {
+        Self {
+            monitor,
+            memory: 0,
+            cursor: None,
+            prompt: None,
+            output: Vec::new(),
+            status: "stopped".to_string(),
+            previous: None,
+            running: false,
+            done: false,
+        }
+    }
+
+    /// Returns the monitor, which holds the debugger and the labels.
+    pub fn monitor(&self) -> &Monitor {
+        &self.monitor
+    }
+
+    /// Returns the monitor mutably.
+    pub fn monitor_mut(&mut self) -> &mut Monitor {
+        &mut self.monitor
+    }
+
+    /// Returns `true` while the machine is running.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Returns `true` once the user has quit.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Shows memory from `addr` in the memory view.
+    pub fn show_memory(&mut self, addr: u16) {
+        self.memory = addr;
+    }
+
+    /// Runs the machine if it is running, until it stops or `keep_going`
+    /// returns `false`, and notes why it stopped if it did.
+    ///
+    /// `keep_going` is asked every few thousand instructions. Return
+    /// `false` from it to draw a frame or handle a key, then call this
+    /// again.
+    pub fn run<M: Machine, F: FnMut() -> bool>(&mut self, machine: &mut M, keep_going: F) {
+        if !self.running {
+            return;
+        }
+        let stop = self.monitor.debugger_mut().resume_polling(
+            machine,
+            Resume::Continue,
+            SLICE,
+            keep_going,
+        );
+        if stop != Stop::Limit {
+            self.running = false;
+            self.status = stop.to_string();
+        }
+    }
+
+    /// Reacts to a key press.
+    pub fn handle_key<M: Machine>(&mut self, machine: &mut M, key: Key) {
+        if let Some((prompt, text)) = &mut self.prompt {
+            match key {
+                Key::Char(c) => text.push(c),
+                Key::Backspace => {
+                    text.pop();
+                }
+                Key::Enter => {
+                    let (prompt, text) = (*prompt, std::mem::take(text));
+                    self.prompt = None;
+                    self.submit(machine, prompt, &text);
+                }
+                Key::Escape | Key::Interrupt => self.prompt = None,
+                _ => {}
+            }
+            return;
+        }
+        if self.running {
+            match key {
+                Key::Char('p' | 'c') | Key::F(5) | Key::Escape | Key::Interrupt => {
+                    self.running = false;
+                    self.status = "paused".to_string();
+                }
+                Key::Char('q') => self.done = true,
+                _ => {}
+            }
+            return;
+        }
+        let bus = &machine.cpu().bus;
+        let cursor = self.cursor.unwrap_or(machine.cpu().registers.pc);
+        match key {
+            Key::Char('s') | Key::F(11) => self.resume(machine, Resume::StepInto),
+            Key::Char('n') | Key::F(10) => self.resume(machine, Resume::StepOver),
+            Key::Char('o') | Key::ShiftF(11) => self.resume(machine, Resume::StepOut),
+            Key::Char('c') | Key::F(5) => self.resume(machine, Resume::Continue),
+            Key::Up => self.cursor = Some(back_up(bus, cursor, 1)),
+            Key::Down => self.cursor = Some(next_addr(bus, cursor)),
+            Key::Char('b') | Key::F(9) => {
+                let debugger = self.monitor.debugger_mut();
+                if !debugger.remove_breakpoint(cursor) {
+                    debugger.set_breakpoint(cursor);
+                }
+            }
+            Key::PageUp => self.memory = self.memory.wrapping_sub(0x100),
+            Key::PageDown => self.memory = self.memory.wrapping_add(0x100),
+            Key::Char('m') => self.prompt = Some((Prompt::Memory, String::new())),
+            Key::Char(':') => self.prompt = Some((Prompt::Command, String::new())),
+            Key::Char('q') => self.done = true,
+            _ => {}
+        }
+    }
+
+    /// Steps, or starts running.
+    fn resume<M: Machine>(&mut self, machine: &mut M, how: Resume) {
+        self.previous = Some(machine.cpu().registers);
+        self.cursor = None;
+        if how == Resume::Continue {
+            self.running = true;
+            self.status = "running".to_string();
+            return;
+        }
+        let stop = self.monitor.debugger_mut().resume(machine, how, STEP_LIMIT);
+        self.status = match stop {
+            Stop::StepComplete => "stopped".to_string(),
+            Stop::Limit => format!("step did not finish within {STEP_LIMIT} instructions"),
+            stop => stop.to_string(),
+        };
+    }
+
+    /// Acts on a line typed at the prompt.
+    fn submit<M: Machine>(&mut self, machine: &mut M, prompt: Prompt, text: &str) {
+        match prompt {
+            Prompt::Memory => match self.monitor.parse_addr(text.trim()) {
+                Ok(addr) => self.memory = addr,
+                Err(err) => self.print(&format!("? {err}")),
+            },
+            Prompt::Command => {
+                self.print(&format!(": {text}"));
+                let pc = machine.cpu().registers.pc;
+                match self.monitor.execute(machine, text) {
+                    Ok(output) => output.lines().for_each(|line| self.print(line)),
+                    Err(err) => self.print(&format!("? {err}")),
+                }
+                if self.monitor.is_done() {
+                    self.done = true;
+                }
+                if machine.cpu().registers.pc != pc {
+                    self.cursor = None;
+                }
+            }
+        }
+    }
+
+    /// Adds a line to the command output.
+    fn print(&mut self, line: &str) {
+        self.output.push(line.to_string());
+        if self.output.len() > OUTPUT_LIMIT {
+            self.output.remove(0);
+        }
+    }
+
+    /// Draws the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to show.
+    /// * `width` - The width of the terminal, in columns.
+    /// * `height` - The height of the terminal, in rows.
+    ///
+    /// # Returns
+    ///
+    /// The frame: an escape code moving the cursor home, then every row of
+    /// the screen, padded to the full width.
+    pub fn render<M: Machine>(&self, machine: &M, width: usize, height: usize) -> String {
+        let cpu = machine.cpu();
+        let body = height.saturating_sub(3);
+        let left_width = width.saturating_sub(RIGHT_WIDTH + 1);
+        let disassembly_rows = body.saturating_sub(MEMORY_ROWS + OUTPUT_ROWS + 3);
+
+        let mut left = vec![header("Disassembly")];
+        left.extend(self.disassembly(&cpu.bus, cpu.registers.pc, disassembly_rows));
+        left.push(header("Memory"));
+        left.extend(self.memory_view(&cpu.bus, left_width));
+        left.push(header("Output"));
+        let skip = self.output.len().saturating_sub(OUTPUT_ROWS);
+        left.extend(self.output[skip..].iter().map(|line| plain(line)));
+
+        let mut right = vec![header("Registers")];
+        right.extend(self.registers(&cpu.registers));
+        right.push(header("Stack"));
+        let stack_rows = body.saturating_sub(right.len() + BREAKPOINT_ROWS + 2);
+        right.extend(stack(&cpu.bus, cpu.registers.sp, stack_rows));
+        right.push(header("Breakpoints"));
+        right.extend(self.breakpoints(body.saturating_sub(right.len())));
+
+        let state = if self.running {
+            "running"
+        } else {
+            &self.status
+        };
+        let cycles = format!("cycles {} ", cpu.cycles());
+        let title = format!(" lib6502  {state}");
+        let gap = width.saturating_sub(title.chars().count() + cycles.len());
+        let mut frame = String::from("\x1b[H");
+        frame.push_str(&draw(
+            &vec![(format!("{title}{}{cycles}", " ".repeat(gap)), REVERSE)],
+            width,
+        ));
+        for row in 0..body {
+            frame.push_str("\r\n");
+            frame.push_str(&draw(left.get(row).unwrap_or(&Vec::new()), left_width));
+            frame.push_str(&draw(&vec![("|".to_string(), DIM)], 1));
+            frame.push_str(&draw(right.get(row).unwrap_or(&Vec::new()), RIGHT_WIDTH));
+        }
+        frame.push_str("\r\n");
+        frame.push_str(&draw(&vec![(HELP.to_string(), DIM)], width));
+        frame.push_str("\r\n");
+        let bottom = match &self.prompt {
+            Some((Prompt::Command, text)) => format!(":{text}_"),
+            Some((Prompt::Memory, text)) => format!("memory at: {text}_"),
+            None => String::new(),
+        };
+        frame.push_str(&draw(&plain(&bottom), width));
+        frame
+    }
+
+    /// Returns the disassembly lines around the cursor.
+    fn disassembly<B: Bus>(&self, bus: &B, pc: u16, rows: usize) -> Vec<Line> {
+        let cursor = self.cursor.unwrap_or(pc);
+        let mut addr = back_up(bus, cursor, CONTEXT_LINES.min(rows / 2));
+        let mut lines = Vec::with_capacity(rows);
+        while lines.len() < rows {
+            if let Some(name) = self.monitor.label_at(addr) {
+                lines.push(plain(&format!("        {name}:")));
+                if lines.len() == rows {
+                    break;
+                }
+            }
+            let marker = if self.monitor.debugger().breakpoint(addr).is_some() {
+                ("*".to_string(), BREAKPOINT)
+            } else {
+                (" ".to_string(), PLAIN)
+            };
+            let text = match disassemble(bus, addr) {
+                Some(instruction) => {
+                    let bytes: Vec<String> = instruction
+                        .bytes
+                        .iter()
+                        .map(|byte| format!("{byte:02X}"))
+                        .collect();
+                    let operand = instruction
+                        .operand_text(|addr| self.monitor.label_at(addr).map(str::to_string));
+                    let text = format!("{} {operand}", instruction.mnemonic);
+                    format!(" {addr:04X}  {:<9} {}", bytes.join(" "), text.trim_end())
+                }
+                None => format!(" {addr:04X}  ??"),
+            };
+            let style = if addr == pc {
+                REVERSE
+            } else if addr == cursor {
+                HEADER
+            } else {
+                PLAIN
+            };
+            lines.push(vec![marker, (text, style)]);
+            let next = next_addr(bus, addr);
+            if next <= addr {
+                break;
+            }
+            addr = next;
+        }
+        lines
+    }
+
+    /// Returns the lines of the memory view.
+    fn memory_view<B: Bus>(&self, bus: &B, width: usize) -> Vec<Line> {
+        // Each byte takes 3 columns of hex and one of text
+        let per_row = [16u16, 8, 4]
+            .into_iter()
+            .find(|&n| 4 * n as usize + 7 <= width)
+            .unwrap_or(4);
+        (0..MEMORY_ROWS as u16)
+            .map(|row| {
+                let start = self.memory.wrapping_add(row * per_row);
+                let bytes: Vec<Option<u8>> = (0..per_row)
+                    .map(|offset| bus.peek(start.wrapping_add(offset)))
+                    .collect();
+                let hex: Vec<String> = bytes
+                    .iter()
+                    .map(|byte| byte.map_or("??".to_string(), |byte| format!("{byte:02X}")))
+                    .collect();
+                let text: String = bytes
+                    .iter()
+                    .map(|byte| match byte {
+                        Some(byte @ 0x20..=0x7E) => *byte as char,
+                        _ => '.',
+                    })
+                    .collect();
+                plain(&format!(" {start:04X}  {}  {text}", hex.join(" ")))
+            })
+            .collect()
+    }
+
+    /// Returns the register lines, with changed registers highlighted.
+    fn registers(&self, registers: &Registers) -> Vec<Line> {
+        let previous = self.previous.unwrap_or(*registers);
+        let field = |name: &str, value: String, changed: bool| {
+            vec![
+                (format!(" {name} "), PLAIN),
+                (value, if changed { CHANGED } else { PLAIN }),
+            ]
+        };
+        let pair = |first: Line, second: Line| {
+            let mut line = first;
+            line.push(("   ".to_string(), PLAIN));
+            line.extend(second);
+            line
+        };
+        let flags = registers.status.to_byte();
+        let changed_flags = flags ^ previous.status.to_byte();
+        let mut bits = vec![(" ".to_string(), PLAIN)];
+        for bit in (0..8).rev() {
+            let set = flags & (1 << bit) != 0;
+            let style = if changed_flags & (1 << bit) != 0 {
+                CHANGED
+            } else {
+                PLAIN
+            };
+            bits.push(((if set { "1" } else { "0" }).to_string(), style));
+        }
+        vec![
+            pair(
+                field(
+                    "PC",
+                    format!("{:04X}", registers.pc),
+                    registers.pc != previous.pc,
+                ),
+                field(
+                    "SP",
+                    format!("{:02X}", registers.sp),
+                    registers.sp != previous.sp,
+                ),
+            ),
+            pair(
+                field(
+                    "A ",
+                    format!("{:02X}  ", registers.a),
+                    registers.a != previous.a,
+                ),
+                field(
+                    "X ",
+                    format!("{:02X}", registers.x),
+                    registers.x != previous.x,
+                ),
+            ),
+            pair(
+                field(
+                    "Y ",
+                    format!("{:02X}  ", registers.y),
+                    registers.y != previous.y,
+                ),
+                field("P ", format!("{flags:02X}"), changed_flags != 0),
+            ),
+            plain(" NV-BDIZC"),
+            bits,
+        ]
+    }
+
+    /// Returns the breakpoint lines.
+    fn breakpoints(&self, rows: usize) -> Vec<Line> {
+        let breakpoints: Vec<_> = self.monitor.debugger().breakpoints().collect();
+        let shown = if breakpoints.len() > rows {
+            rows.saturating_sub(1)
+        } else {
+            rows
+        };
+        let mut lines: Vec<Line> = breakpoints
+            .iter()
+            .take(shown)
+            .map(|breakpoint| {
+                let (marker, style) = if breakpoint.enabled {
+                    ("*", BREAKPOINT)
+                } else {
+                    ("-", DIM)
+                };
+                let name = self
+                    .monitor
+                    .label_at(breakpoint.addr)
+                    .map_or(String::new(), |name| format!(" {name}"));
+                let condition = if breakpoint.condition.is_some() {
+                    " if"
+                } else {
+                    ""
+                };
+                vec![
+                    (format!(" {marker} "), style),
+                    (
+                        format!(
+                            "{:04X}{name}{condition}  {}",
+                            breakpoint.addr, breakpoint.hits
+                        ),
+                        PLAIN,
+                    ),
+                ]
+            })
+            .collect();
+        if breakpoints.len() > shown {
+            lines.push(plain(&format!(" ... {} more", breakpoints.len() - shown)));
+        }
+        lines
+    }
+}
+
+impl Default for Tui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the lines of the stack view: the bytes pushed, most recent
+/// first.
+fn stack<B: Bus>(bus: &B, sp: u8, rows: usize) -> Vec<Line> {
+    if sp == 0xFF {
+        return vec![vec![(" (empty)".to_string(), DIM)]];
+    }
+    (sp as u16 + 1..=0xFF)
+        .take(rows)
+        .map(|offset| {
+            let addr = 0x0100 + offset;
+            let value = bus
+                .peek(addr)
+                .map_or("??".to_string(), |byte| format!("{byte:02X}"));
+            plain(&format!(" {addr:04X}  {value}"))
+        })
+        .collect()
+}
+
+fn plain(text: &str) -> Line {
+    vec![(text.to_string(), PLAIN)]
+}
+
+fn header(title: &str) -> Line {
+    vec![(format!(" {title}"), HEADER)]
+}
+
+/// Draws a line, cut or padded to exactly `width` columns.
+fn draw(line: &Line, width: usize) -> String {
+    let mut output = String::new();
+    let mut used = 0;
+    for (text, style) in line {
+        let text: String = text.chars().take(width - used).collect();
+        used += text.chars().count();
+        if style.is_empty() {
+            output.push_str(&text);
+        } else {
+            output.push_str(&format!("\x1b[{style}m{text}\x1b[0m"));
+        }
+    }
+    output.push_str(&" ".repeat(width - used));
+    output
+}
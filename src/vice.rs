@@ -0,0 +1,790 @@
+//! The `vice` module implements the VICE binary monitor protocol.
+//!
+//! A `ViceMonitor` lets tools written for the VICE emulator's remote
+//! monitor, such as IDE plugins and C64 debuggers, drive a machine over
+//! TCP. Like VICE, the machine runs until a command arrives, stays stopped
+//! while the client inspects it, and runs again on `exit`:
+//!
+//! ```no_run
+//! use lib6502::cpu::CPU;
+//! use lib6502::vice::ViceMonitor;
+//! use std::net::TcpListener;
+//!
+//! let mut cpu = CPU::new(vec![0xEAu8; 0x10000]);
+//! let listener = TcpListener::bind("127.0.0.1:6502").unwrap();
+//! let (stream, _) = listener.accept().unwrap();
+//! ViceMonitor::new().serve(&mut cpu, stream).unwrap();
+//! ```
+//!
+//! The supported commands are memory get and set, checkpoints (with
+//! conditions in the expression language of the `expr` module), registers
+//! get and set, advancing instructions, executing until return, ping,
+//! the bank, register and VICE info queries, reset, exit and quit. Other
+//! commands are answered with the "invalid command" error.
+//!
+//! Only the main memory space exists. Memory reads without side effects use
+//! `Bus::peek`, and bytes that cannot be peeked read as `$FF`. Load
+//! checkpoints also see instruction fetches, as every read the CPU makes is
+//! a load to the debugger. The monitor owns the breakpoints and watchpoints
+//! of its debugger, replacing any set by the host.
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::debugger::{BreakOn, Debugger, Machine, Resume, Stop, WatchKind, WatchpointId};
+use crate::expr::Expr;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// The byte every message starts with.
+const STX: u8 = 0x02;
+
+/// The version of the protocol spoken.
+const API_VERSION: u8 = 0x02;
+
+/// The request id of events, which answer no request.
+const EVENT: u32 = 0xFFFF_FFFF;
+
+/// The instructions executed between checks for a command.
+const SLICE: u64 = 10_000;
+
+/// The value read for bytes that cannot be peeked.
+const UNREADABLE: u8 = 0xFF;
+
+/// The longest request body, a memory set of all 64KB after its 8 bytes of
+/// arguments.
+const MAX_BODY: u32 = 8 + 0x10000;
+
+/// The command and response types.
+mod command {
+    pub const MEMORY_GET: u8 = 0x01;
+    pub const MEMORY_SET: u8 = 0x02;
+    pub const CHECKPOINT_INFO: u8 = 0x11;
+    pub const CHECKPOINT_SET: u8 = 0x12;
+    pub const CHECKPOINT_DELETE: u8 = 0x13;
+    pub const CHECKPOINT_LIST: u8 = 0x14;
+    pub const CHECKPOINT_TOGGLE: u8 = 0x15;
+    pub const CONDITION_SET: u8 = 0x22;
+    pub const REGISTERS_GET: u8 = 0x31;
+    pub const REGISTERS_SET: u8 = 0x32;
+    pub const ADVANCE_INSTRUCTIONS: u8 = 0x71;
+    pub const EXECUTE_UNTIL_RETURN: u8 = 0x73;
+    pub const PING: u8 = 0x81;
+    pub const BANKS_AVAILABLE: u8 = 0x82;
+    pub const REGISTERS_AVAILABLE: u8 = 0x83;
+    pub const VICE_INFO: u8 = 0x85;
+    pub const EXIT: u8 = 0xAA;
+    pub const QUIT: u8 = 0xBB;
+    pub const RESET: u8 = 0xCC;
+    pub const JAM: u8 = 0x61;
+    pub const STOPPED: u8 = 0x62;
+    pub const RESUMED: u8 = 0x63;
+}
+
+/// The error codes of responses.
+mod error {
+    pub const OK: u8 = 0x00;
+    pub const NOT_FOUND: u8 = 0x01;
+    pub const INVALID_MEMSPACE: u8 = 0x02;
+    pub const INVALID_LENGTH: u8 = 0x80;
+    pub const INVALID_PARAMETER: u8 = 0x81;
+    pub const INVALID_API_VERSION: u8 = 0x82;
+    pub const INVALID_COMMAND: u8 = 0x83;
+}
+
+/// The register ids, as VICE numbers them for the 6502.
+const REGISTERS: [(u8, &str, u8); 6] = [
+    (0x00, "A", 8),
+    (0x01, "X", 8),
+    (0x02, "Y", 8),
+    (0x03, "PC", 16),
+    (0x04, "SP", 8),
+    (0x05, "FL", 8),
+];
+
+/// The operations a checkpoint can watch, as a bit mask.
+const LOAD: u8 = 0x01;
+const STORE: u8 = 0x02;
+const EXEC: u8 = 0x04;
+
+/// The version reported by the VICE info command: the VICE release whose
+/// protocol this follows.
+const VICE_VERSION: [u8; 4] = [3, 7, 0, 0];
+
+/// A checkpoint set by the client.
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    start: u16,
+    end: u16,
+    stop: bool,
+    enabled: bool,
+    operation: u8,
+    temporary: bool,
+    hits: u32,
+    condition: Option<Expr>,
+    /// The debugger watchpoint for load and store checkpoints.
+    watchpoint: Option<WatchpointId>,
+}
+
+impl Checkpoint {
+    /// Returns the body of a checkpoint info response.
+    fn info(&self, id: u32, hit: bool) -> Vec<u8> {
+        let mut body = id.to_le_bytes().to_vec();
+        body.push(hit as u8);
+        body.extend(self.start.to_le_bytes());
+        body.extend(self.end.to_le_bytes());
+        body.extend([
+            self.stop as u8,
+            self.enabled as u8,
+            self.operation,
+            self.temporary as u8,
+        ]);
+        body.extend(self.hits.to_le_bytes());
+        // Ignore count
+        body.extend(0u32.to_le_bytes());
+        body.extend([self.condition.is_some() as u8, 0]);
+        body
+    }
+}
+
+/// A request from the client.
+struct Request {
+    id: u32,
+    command: u8,
+    api_version: u8,
+    body: Vec<u8>,
+}
+
+/// What to do after handling a request.
+enum Next {
+    /// Stay in the monitor.
+    Stay,
+    /// Run the machine.
+    Run,
+    /// End the session.
+    Quit,
+}
+
+/// A VICE binary monitor protocol server for one machine.
+pub struct ViceMonitor {
+    debugger: Debugger,
+    checkpoints: BTreeMap<u32, Checkpoint>,
+    next_checkpoint: u32,
+}
+
+impl ViceMonitor {
+    /// Creates a new `ViceMonitor` with a fresh `Debugger` that stops on
+    /// the JAM opcodes the CPU does not implement, which are reported to the
+    /// client as a JAM.
+    pub fn new() -> Self {
+        let mut debugger = Debugger::new();
+        debugger.set_break_on(BreakOn {
+            undocumented: true,
+            ..BreakOn::default()
+        });
+        Self::with_debugger(debugger)
+    }
+
+    /// Creates a new `ViceMonitor` around an existing debugger, for example
+    /// one set up to break on `BRK`.
+    pub fn with_debugger(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            checkpoints: BTreeMap::new(),
+            next_checkpoint: 1,
+        }
+    }
+
+    /// Returns the debugger.
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Returns the debugger mutably.
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Serves one client until it quits or disconnects.
+    ///
+    /// The machine runs from the start of the session until the first
+    /// command arrives, as it does in VICE.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - The machine to debug.
+    /// * `stream` - The connection to the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error on the connection, and `InvalidData` for a
+    /// request that is malformed or longer than any command.
+    pub fn serve<M: Machine>(&mut self, machine: &mut M, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut running = true;
+        loop {
+            if running {
+                if !self.run(machine, &mut stream)? {
+                    return Ok(());
+                }
+                running = false;
+            }
+            let Some(request) = read_request(&mut stream)? else {
+                return Ok(());
+            };
+            match self.handle(machine, &mut stream, request)? {
+                Next::Stay => {}
+                Next::Run => {
+                    let pc = machine.cpu().registers.pc;
+                    send(
+                        &mut stream,
+                        command::RESUMED,
+                        error::OK,
+                        EVENT,
+                        &pc.to_le_bytes(),
+                    )?;
+                    running = true;
+                }
+                Next::Quit => return Ok(()),
+            }
+        }
+    }
+
+    /// Runs the machine until it stops or a command arrives, and tells the
+    /// client it stopped. Returns `false` if the client disconnected.
+    fn run<M: Machine>(&mut self, machine: &mut M, stream: &mut TcpStream) -> io::Result<bool> {
+        loop {
+            // One resume until the client sends something, so that a
+            // checkpoint is not skipped where a slice happens to end
+            let mut polled = Ok(Some(false));
+            let stop = self
+                .debugger
+                .resume_polling(machine, Resume::Continue, SLICE, || {
+                    polled = poll(stream);
+                    matches!(polled, Ok(Some(false)))
+                });
+            if stop != Stop::Limit {
+                if self.stopped(machine, stream, stop)? {
+                    return Ok(true);
+                }
+                // A checkpoint that only traces keeps the machine going,
+                // and the client must still be able to stop it
+                polled = poll(stream);
+            }
+            match polled? {
+                None => return Ok(false),
+                Some(true) => {
+                    send_stopped(stream, machine.cpu(), command::STOPPED)?;
+                    return Ok(true);
+                }
+                Some(false) => {}
+            }
+        }
+    }
+
+    /// Reports the checkpoints hit by `stop`. Returns `true` if one of them,
+    /// or the stop itself, stops the machine.
+    fn stopped<M: Machine>(
+        &mut self,
+        machine: &mut M,
+        stream: &mut TcpStream,
+        stop: Stop,
+    ) -> io::Result<bool> {
+        let hit = match stop {
+            Stop::Breakpoint { pc } | Stop::TemporaryBreakpoint { pc } => {
+                self.hit(machine.cpu(), |checkpoint| {
+                    checkpoint.operation & EXEC != 0
+                        && (checkpoint.start..=checkpoint.end).contains(&pc)
+                })
+            }
+            Stop::Watchpoint { id, .. } => self.hit(machine.cpu(), |checkpoint| {
+                checkpoint.watchpoint == Some(id)
+            }),
+            Stop::Undocumented { .. } => {
+                send_stopped(stream, machine.cpu(), command::JAM)?;
+                return Ok(true);
+            }
+            _ => Vec::new(),
+        };
+        // Other stops, such as on BRK, come from the host's debugger setup
+        let checkpoint = matches!(
+            stop,
+            Stop::Breakpoint { .. } | Stop::TemporaryBreakpoint { .. } | Stop::Watchpoint { .. }
+        );
+        let stops = !checkpoint || hit.iter().any(|(_, checkpoint)| checkpoint.stop);
+        for (id, checkpoint) in &hit {
+            send(
+                stream,
+                command::CHECKPOINT_INFO,
+                error::OK,
+                EVENT,
+                &checkpoint.info(*id, true),
+            )?;
+            if checkpoint.temporary {
+                self.delete_checkpoint(*id);
+            }
+        }
+        if stops {
+            send_stopped(stream, machine.cpu(), command::STOPPED)?;
+        }
+        Ok(stops)
+    }
+
+    /// Counts a hit on every enabled checkpoint matching `matches` whose
+    /// condition holds, and returns them.
+    fn hit<B: Bus, F: Fn(&Checkpoint) -> bool>(
+        &mut self,
+        cpu: &CPU<B>,
+        matches: F,
+    ) -> Vec<(u32, Checkpoint)> {
+        let mut hit = Vec::new();
+        for (&id, checkpoint) in self.checkpoints.iter_mut() {
+            if !checkpoint.enabled || !matches(checkpoint) {
+                continue;
+            }
+            let hits = checkpoint.hits as u64 + 1;
+            let holds = checkpoint
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.eval(cpu, hits) != Ok(0));
+            if holds {
+                checkpoint.hits += 1;
+                hit.push((id, checkpoint.clone()));
+            }
+        }
+        hit
+    }
+
+    /// Handles one request.
+    fn handle<M: Machine>(
+        &mut self,
+        machine: &mut M,
+        stream: &mut TcpStream,
+        request: Request,
+    ) -> io::Result<Next> {
+        let id = request.id;
+        if request.api_version != 1 && request.api_version != API_VERSION {
+            send(stream, request.command, error::INVALID_API_VERSION, id, &[])?;
+            return Ok(Next::Stay);
+        }
+        let body = &request.body;
+        let mut next = Next::Stay;
+        let result: Result<Vec<u8>, u8> = match request.command {
+            command::MEMORY_GET => memory_get(machine.cpu_mut(), body),
+            command::MEMORY_SET => memory_set(machine.cpu_mut(), body),
+            command::CHECKPOINT_SET => self.set_checkpoint(body).map(|id| {
+                // Answered with the info of the new checkpoint
+                self.checkpoints[&id].info(id, false)
+            }),
+            command::CHECKPOINT_INFO => checkpoint_id(body).and_then(|checkpoint| {
+                self.checkpoints
+                    .get(&checkpoint)
+                    .map(|found| found.info(checkpoint, false))
+                    .ok_or(error::NOT_FOUND)
+            }),
+            command::CHECKPOINT_DELETE => checkpoint_id(body).and_then(|checkpoint| {
+                if self.delete_checkpoint(checkpoint) {
+                    Ok(Vec::new())
+                } else {
+                    Err(error::NOT_FOUND)
+                }
+            }),
+            command::CHECKPOINT_LIST => {
+                for (&checkpoint, found) in &self.checkpoints {
+                    let info = found.info(checkpoint, false);
+                    send(stream, command::CHECKPOINT_INFO, error::OK, id, &info)?;
+                }
+                Ok((self.checkpoints.len() as u32).to_le_bytes().to_vec())
+            }
+            command::CHECKPOINT_TOGGLE => self.toggle_checkpoint(body),
+            command::CONDITION_SET => self.set_condition(body),
+            command::REGISTERS_GET => memspace(body, 0).map(|()| registers(machine.cpu())),
+            command::REGISTERS_SET => set_registers(machine.cpu_mut(), body),
+            command::ADVANCE_INSTRUCTIONS | command::EXECUTE_UNTIL_RETURN => {
+                let (how, count) = match (request.command, body.as_slice()) {
+                    (command::EXECUTE_UNTIL_RETURN, _) => (Resume::StepOut, 1),
+                    (_, [over, lo, hi, ..]) => (
+                        if *over != 0 {
+                            Resume::StepOver
+                        } else {
+                            Resume::StepInto
+                        },
+                        u16::from_le_bytes([*lo, *hi]),
+                    ),
+                    _ => {
+                        return send(stream, request.command, error::INVALID_LENGTH, id, &[])
+                            .map(|()| Next::Stay)
+                    }
+                };
+                send(stream, request.command, error::OK, id, &[])?;
+                // A step over or out may never finish, so the client can
+                // stop it by sending another command
+                let mut polled = Ok(Some(false));
+                for _ in 0..count {
+                    let stop = self.debugger.resume_polling(machine, how, SLICE, || {
+                        polled = poll(stream);
+                        matches!(polled, Ok(Some(false)))
+                    });
+                    if stop != Stop::StepComplete {
+                        break;
+                    }
+                }
+                if polled?.is_none() {
+                    return Ok(Next::Quit);
+                }
+                send_stopped(stream, machine.cpu(), command::STOPPED)?;
+                return Ok(Next::Stay);
+            }
+            command::PING => Ok(Vec::new()),
+            command::BANKS_AVAILABLE => {
+                let mut body = 1u16.to_le_bytes().to_vec();
+                body.extend([6, 0, 0, 3]);
+                body.extend(b"cpu");
+                Ok(body)
+            }
+            command::REGISTERS_AVAILABLE => memspace(body, 0).map(|()| {
+                let mut body = (REGISTERS.len() as u16).to_le_bytes().to_vec();
+                for (register, name, bits) in REGISTERS {
+                    body.extend([3 + name.len() as u8, register, bits, name.len() as u8]);
+                    body.extend(name.bytes());
+                }
+                body
+            }),
+            command::VICE_INFO => {
+                let mut body = vec![4];
+                body.extend(VICE_VERSION);
+                body.push(4);
+                body.extend(0u32.to_le_bytes());
+                Ok(body)
+            }
+            command::RESET => {
+                machine.cpu_mut().reset();
+                Ok(Vec::new())
+            }
+            command::EXIT => {
+                next = Next::Run;
+                Ok(Vec::new())
+            }
+            command::QUIT => {
+                next = Next::Quit;
+                Ok(Vec::new())
+            }
+            _ => Err(error::INVALID_COMMAND),
+        };
+        // Commands answered with a checkpoint or the registers use the
+        // response type of the matching get command
+        let kind = match request.command {
+            command::CHECKPOINT_SET => command::CHECKPOINT_INFO,
+            command::REGISTERS_SET => command::REGISTERS_GET,
+            other => other,
+        };
+        match result {
+            Ok(response) => send(stream, kind, error::OK, id, &response)?,
+            Err(code) => send(stream, kind, code, id, &[])?,
+        }
+        Ok(next)
+    }
+
+    /// Handles checkpoint set, returning the id of the new checkpoint.
+    fn set_checkpoint(&mut self, body: &[u8]) -> Result<u32, u8> {
+        let [start_lo, start_hi, end_lo, end_hi, stop, enabled, operation, temporary, ref rest @ ..] =
+            *body
+        else {
+            return Err(error::INVALID_LENGTH);
+        };
+        memspace(rest, 0)?;
+        let (start, end) = (
+            u16::from_le_bytes([start_lo, start_hi]),
+            u16::from_le_bytes([end_lo, end_hi]),
+        );
+        if end < start || operation & (LOAD | STORE | EXEC) == 0 {
+            return Err(error::INVALID_PARAMETER);
+        }
+        let id = self.next_checkpoint;
+        self.next_checkpoint += 1;
+        self.checkpoints.insert(
+            id,
+            Checkpoint {
+                start,
+                end,
+                stop: stop != 0,
+                enabled: enabled != 0,
+                operation,
+                temporary: temporary != 0,
+                hits: 0,
+                condition: None,
+                watchpoint: None,
+            },
+        );
+        self.sync(id);
+        Ok(id)
+    }
+
+    /// Handles checkpoint toggle.
+    fn toggle_checkpoint(&mut self, body: &[u8]) -> Result<Vec<u8>, u8> {
+        let id = checkpoint_id(body)?;
+        let enabled = *body.get(4).ok_or(error::INVALID_LENGTH)? != 0;
+        self.checkpoints
+            .get_mut(&id)
+            .ok_or(error::NOT_FOUND)?
+            .enabled = enabled;
+        self.sync(id);
+        Ok(Vec::new())
+    }
+
+    /// Handles condition set.
+    fn set_condition(&mut self, body: &[u8]) -> Result<Vec<u8>, u8> {
+        let id = checkpoint_id(body)?;
+        let len = *body.get(4).ok_or(error::INVALID_LENGTH)? as usize;
+        let text = body.get(5..5 + len).ok_or(error::INVALID_LENGTH)?;
+        let condition = std::str::from_utf8(text)
+            .ok()
+            .and_then(|text| Expr::parse(text).ok())
+            .ok_or(error::INVALID_PARAMETER)?;
+        self.checkpoints
+            .get_mut(&id)
+            .ok_or(error::NOT_FOUND)?
+            .condition = Some(condition);
+        Ok(Vec::new())
+    }
+
+    /// Removes a checkpoint. Returns `false` if there was none with that id.
+    fn delete_checkpoint(&mut self, id: u32) -> bool {
+        let Some(checkpoint) = self.checkpoints.remove(&id) else {
+            return false;
+        };
+        if let Some(watchpoint) = checkpoint.watchpoint {
+            self.debugger.remove_watchpoint(watchpoint);
+        }
+        self.sync_breakpoints();
+        true
+    }
+
+    /// Brings the debugger up to date with checkpoint `id`.
+    fn sync(&mut self, id: u32) {
+        let checkpoint = self.checkpoints.get_mut(&id).expect("checkpoint exists");
+        let kind = match checkpoint.operation & (LOAD | STORE) {
+            LOAD => Some(WatchKind::Read),
+            STORE => Some(WatchKind::Write),
+            0 => None,
+            _ => Some(WatchKind::Access),
+        };
+        if let Some(kind) = kind {
+            let watchpoint = *checkpoint.watchpoint.get_or_insert_with(|| {
+                self.debugger
+                    .add_watchpoint(checkpoint.start..=checkpoint.end, kind)
+            });
+            self.debugger
+                .watchpoint_mut(watchpoint)
+                .expect("watchpoint exists")
+                .enabled = checkpoint.enabled;
+        }
+        self.sync_breakpoints();
+    }
+
+    /// Sets a debugger breakpoint on every address covered by an enabled
+    /// exec checkpoint, and on no other.
+    fn sync_breakpoints(&mut self) {
+        self.debugger.clear_breakpoints();
+        for checkpoint in self.checkpoints.values() {
+            if checkpoint.enabled && checkpoint.operation & EXEC != 0 {
+                for addr in checkpoint.start..=checkpoint.end {
+                    self.debugger.set_breakpoint(addr);
+                }
+            }
+        }
+    }
+}
+
+impl Default for ViceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Checks, without blocking, whether a command has arrived. Returns `None`
+/// if the client disconnected.
+fn poll(stream: &TcpStream) -> io::Result<Option<bool>> {
+    stream.set_nonblocking(true)?;
+    let result = stream.peek(&mut [0]);
+    stream.set_nonblocking(false)?;
+    match result {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(true)),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(Some(false)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Reads a request. Returns `None` at the end of the stream.
+fn read_request(stream: &mut TcpStream) -> io::Result<Option<Request>> {
+    let mut header = [0; 11];
+    match stream.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    if header[0] != STX {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request does not start with STX",
+        ));
+    }
+    let len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+    if len > MAX_BODY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request is longer than any command",
+        ));
+    }
+    let mut body = vec![0; len as usize];
+    stream.read_exact(&mut body)?;
+    Ok(Some(Request {
+        id: u32::from_le_bytes([header[6], header[7], header[8], header[9]]),
+        command: header[10],
+        api_version: header[1],
+        body,
+    }))
+}
+
+/// Sends a response or event.
+fn send(stream: &mut TcpStream, kind: u8, error: u8, id: u32, body: &[u8]) -> io::Result<()> {
+    let mut message = vec![STX, API_VERSION];
+    message.extend((body.len() as u32).to_le_bytes());
+    message.extend([kind, error]);
+    message.extend(id.to_le_bytes());
+    message.extend(body);
+    stream.write_all(&message)
+}
+
+/// Sends the registers and then a stopped or JAM event, as VICE does when
+/// the monitor opens.
+fn send_stopped<B: Bus>(stream: &mut TcpStream, cpu: &CPU<B>, kind: u8) -> io::Result<()> {
+    send(
+        stream,
+        command::REGISTERS_GET,
+        error::OK,
+        EVENT,
+        &registers(cpu),
+    )?;
+    let pc = cpu.registers.pc;
+    send(stream, kind, error::OK, EVENT, &pc.to_le_bytes())
+}
+
+/// Returns the id at the start of a checkpoint command.
+fn checkpoint_id(body: &[u8]) -> Result<u32, u8> {
+    match body {
+        [a, b, c, d, ..] => Ok(u32::from_le_bytes([*a, *b, *c, *d])),
+        _ => Err(error::INVALID_LENGTH),
+    }
+}
+
+/// Checks the memspace byte at `index`, if present. Only the main memory
+/// space, 0, exists.
+fn memspace(body: &[u8], index: usize) -> Result<(), u8> {
+    match body.get(index) {
+        None | Some(0) => Ok(()),
+        Some(_) => Err(error::INVALID_MEMSPACE),
+    }
+}
+
+/// Returns the body of a registers response.
+fn registers<B: Bus>(cpu: &CPU<B>) -> Vec<u8> {
+    let registers = &cpu.registers;
+    let mut body = (REGISTERS.len() as u16).to_le_bytes().to_vec();
+    for (register, _, _) in REGISTERS {
+        let value = match register {
+            0x00 => registers.a as u16,
+            0x01 => registers.x as u16,
+            0x02 => registers.y as u16,
+            0x03 => registers.pc,
+            0x04 => registers.sp as u16,
+            _ => registers.status.to_byte() as u16,
+        };
+        body.extend([3, register]);
+        body.extend(value.to_le_bytes());
+    }
+    body
+}
+
+/// Handles registers set, answering with all the registers.
+fn set_registers<B: Bus>(cpu: &mut CPU<B>, body: &[u8]) -> Result<Vec<u8>, u8> {
+    let [space, lo, hi, ref items @ ..] = *body else {
+        return Err(error::INVALID_LENGTH);
+    };
+    memspace(&[space], 0)?;
+    let count = u16::from_le_bytes([lo, hi]) as usize;
+    let mut items = items;
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+        let size = *items.first().ok_or(error::INVALID_LENGTH)? as usize;
+        let item = items.get(1..1 + size).ok_or(error::INVALID_LENGTH)?;
+        let [register, lo, hi, ..] = *item else {
+            return Err(error::INVALID_LENGTH);
+        };
+        if !REGISTERS.iter().any(|(known, _, _)| *known == register) {
+            return Err(error::INVALID_PARAMETER);
+        }
+        values.push((register, u16::from_le_bytes([lo, hi])));
+        items = &items[1 + size..];
+    }
+    let registers = &mut cpu.registers;
+    for (register, value) in values {
+        match register {
+            0x00 => registers.a = value as u8,
+            0x01 => registers.x = value as u8,
+            0x02 => registers.y = value as u8,
+            0x03 => registers.pc = value,
+            0x04 => registers.sp = value as u8,
+            _ => registers.status.from_byte(value as u8),
+        }
+    }
+    Ok(self::registers(cpu))
+}
+
+/// Parses the side effects flag, range and memspace of a memory command,
+/// returning the rest of the body.
+fn memory_args(body: &[u8]) -> Result<(bool, u16, u16, &[u8]), u8> {
+    let [side_effects, start_lo, start_hi, end_lo, end_hi, space, _, _, ref rest @ ..] = *body
+    else {
+        return Err(error::INVALID_LENGTH);
+    };
+    memspace(&[space], 0)?;
+    let (start, end) = (
+        u16::from_le_bytes([start_lo, start_hi]),
+        u16::from_le_bytes([end_lo, end_hi]),
+    );
+    if end < start {
+        return Err(error::INVALID_PARAMETER);
+    }
+    Ok((side_effects != 0, start, end, rest))
+}
+
+/// Handles memory get. With side effects, the bytes are read as the CPU
+/// would read them.
+fn memory_get<B: Bus>(cpu: &mut CPU<B>, body: &[u8]) -> Result<Vec<u8>, u8> {
+    let (side_effects, start, end, _) = memory_args(body)?;
+    let len = (end - start) as usize + 1;
+    // A length of 0 stands for the whole 64KB
+    let mut response = (len as u16).to_le_bytes().to_vec();
+    for addr in start..=end {
+        response.push(if side_effects {
+            cpu.bus.read(addr)
+        } else {
+            cpu.bus.peek(addr).unwrap_or(UNREADABLE)
+        });
+    }
+    Ok(response)
+}
+
+/// Handles memory set, writing through the bus.
+fn memory_set<B: Bus>(cpu: &mut CPU<B>, body: &[u8]) -> Result<Vec<u8>, u8> {
+    let (_, start, end, data) = memory_args(body)?;
+    if data.len() != (end - start) as usize + 1 {
+        return Err(error::INVALID_LENGTH);
+    }
+    for (addr, &byte) in (start..=end).zip(data) {
+        cpu.bus.write(addr, byte);
+    }
+    Ok(Vec::new())
+}